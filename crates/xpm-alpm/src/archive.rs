@@ -0,0 +1,407 @@
+//! Metadata of package archives (`.pkg.tar.*`), read before they are
+//! installed with `pacman -U`.
+
+use crate::cache::signature_path;
+use crate::index::PackageIndex;
+use alpm::{Alpm, SigLevel};
+use flate2::read::GzDecoder;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use xpm_core::{
+    dependency::Dependency,
+    error::{Error, Result},
+    package::{Package, PackageBackend, PackageInfo, PackageStatus, Version},
+};
+
+/// Build environment recorded in `.BUILDINFO`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// Base of a split package.
+    pub pkgbase: Option<String>,
+    /// Tool and version that built the package, e.g. `makepkg 6.1.0`.
+    pub build_tool: Option<String>,
+    /// Directory the package was built in.
+    pub build_dir: Option<String>,
+    /// makepkg `BUILDENV` settings.
+    pub build_env: Vec<String>,
+    /// makepkg `OPTIONS` settings.
+    pub options: Vec<String>,
+    /// Packages installed when it was built, as `name-version-arch`.
+    pub installed: Vec<String>,
+}
+
+/// Result of checking the detached signature of an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Signed by a key trusted by pacman's keyring.
+    Valid,
+    /// There is no `.sig` file next to the archive.
+    Missing,
+    /// The signature does not match or its key is not trusted.
+    Invalid(String),
+    /// The signature could not be checked.
+    Unknown(String),
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Valid => write!(f, "valid"),
+            SignatureStatus::Missing => write!(f, "not signed"),
+            SignatureStatus::Invalid(reason) => write!(f, "invalid: {}", reason),
+            SignatureStatus::Unknown(reason) => write!(f, "not checked: {}", reason),
+        }
+    }
+}
+
+/// A package archive checked against the installed system.
+#[derive(Debug, Clone)]
+pub struct LocalPackage {
+    /// Path of the archive.
+    pub path: String,
+    /// Details from `.PKGINFO`.
+    pub info: PackageInfo,
+    /// Details from `.BUILDINFO`, if the archive has one.
+    pub build: Option<BuildInfo>,
+    /// Version of the package currently installed.
+    pub installed_version: Option<Version>,
+    /// Dependencies neither installed nor available from a repository.
+    pub unsatisfied: Vec<Dependency>,
+    /// Signature check result.
+    pub signature: SignatureStatus,
+}
+
+impl LocalPackage {
+    /// Compares archive metadata with the packages in `index`.
+    pub fn new(
+        path: &str,
+        mut info: PackageInfo,
+        build: Option<BuildInfo>,
+        index: &PackageIndex,
+        signature: SignatureStatus,
+    ) -> Self {
+        let installed_version = index
+            .local(&info.package.name)
+            .map(|pkg| pkg.version().clone());
+        if installed_version.as_ref() == Some(&info.package.version) {
+            info.package.status = PackageStatus::Installed;
+        }
+        let unsatisfied = info
+            .depends
+            .iter()
+            .filter(|dep| index.resolve(dep).is_none())
+            .cloned()
+            .collect();
+
+        Self {
+            path: path.to_string(),
+            info,
+            build,
+            installed_version,
+            unsatisfied,
+            signature,
+        }
+    }
+}
+
+/// Reads `.PKGINFO` and `.BUILDINFO` from the archive at `path`. The
+/// compression is detected from the contents, not the file name.
+pub fn read_archive(path: &Path) -> Result<(PackageInfo, Option<BuildInfo>)> {
+    let io_error = |e: std::io::Error| Error::IoError(format!("{}: {}", path.display(), e));
+    let download_size = path.metadata().map_err(io_error)?.len();
+
+    let mut pkginfo = None;
+    let mut buildinfo = None;
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let name = entry
+            .path()
+            .map_err(io_error)?
+            .to_string_lossy()
+            .into_owned();
+        let target = match name.trim_start_matches("./") {
+            ".PKGINFO" => &mut pkginfo,
+            ".BUILDINFO" => &mut buildinfo,
+            // Metadata comes first; the installed files follow.
+            other if !other.starts_with('.') => break,
+            _ => continue,
+        };
+        let mut text = String::new();
+        entry.read_to_string(&mut text).map_err(io_error)?;
+        *target = Some(text);
+    }
+
+    let pkginfo =
+        pkginfo.ok_or_else(|| Error::Other(format!("{} has no .PKGINFO", path.display())))?;
+    let mut info = parse_pkginfo(&pkginfo)?;
+    info.download_size = download_size;
+    Ok((info, buildinfo.as_deref().map(parse_buildinfo)))
+}
+
+/// Contents of the file at `name`, relative to the root, in the archive at
+/// `path`, or `None` if the package has no such file.
+pub fn read_archive_file(path: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    let io_error = |e: std::io::Error| Error::IoError(format!("{}: {}", path.display(), e));
+    let name = name.trim_start_matches('/');
+
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        if entry.path().map_err(io_error)?.as_os_str() != name {
+            continue;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).map_err(io_error)?;
+        return Ok(Some(contents));
+    }
+    Ok(None)
+}
+
+/// Opens the package archive at `path` for reading, detecting the
+/// compression from the contents.
+fn open_archive(path: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+    let io_error = |e: std::io::Error| Error::IoError(format!("{}: {}", path.display(), e));
+
+    let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+    let mut magic = [0u8; 6];
+    let start = file.fill_buf().map_err(io_error)?;
+    let len = start.len().min(magic.len());
+    magic[..len].copy_from_slice(&start[..len]);
+    let reader: Box<dyn Read> = match &magic[..len] {
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => {
+            Box::new(zstd::Decoder::with_buffer(file).map_err(io_error)?)
+        }
+        [0xfd, b'7', b'z', b'X', b'Z', 0x00] => Box::new(xz2::read::XzDecoder::new(file)),
+        [0x1f, 0x8b, ..] => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// `key = value` lines of `.PKGINFO` and `.BUILDINFO`.
+fn fields(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(" = "))
+}
+
+/// Parses the contents of `.PKGINFO`.
+pub fn parse_pkginfo(text: &str) -> Result<PackageInfo> {
+    let mut name = None;
+    let mut version = None;
+    let mut description = String::new();
+    let mut info = PackageInfo {
+        package: Package::new(
+            "",
+            Version::new(""),
+            "",
+            PackageBackend::Pacman,
+            PackageStatus::Available,
+            "local",
+        ),
+        url: None,
+        licenses: Vec::new(),
+        groups: Vec::new(),
+        depends: Vec::new(),
+        optdepends: Vec::new(),
+        provides: Vec::new(),
+        conflicts: Vec::new(),
+        replaces: Vec::new(),
+        installed_size: 0,
+        download_size: 0,
+        build_date: None,
+        install_date: None,
+        packager: None,
+        arch: "any".to_string(),
+        reason: None,
+    };
+
+    for (key, value) in fields(text) {
+        match key {
+            "pkgname" => name = Some(value.to_string()),
+            "pkgver" => version = Some(value.to_string()),
+            "pkgdesc" => description = value.to_string(),
+            "url" => info.url = Some(value.to_string()),
+            "builddate" => {
+                info.build_date = value
+                    .parse()
+                    .ok()
+                    .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+            }
+            "packager" => info.packager = Some(value.to_string()),
+            "size" => info.installed_size = value.parse().unwrap_or(0),
+            "arch" => info.arch = value.to_string(),
+            "license" => info.licenses.push(value.to_string()),
+            "group" => info.groups.push(value.to_string()),
+            "depend" => info.depends.push(Dependency::parse(value)),
+            "optdepend" => info.optdepends.push(Dependency::parse(value)),
+            "provides" => info.provides.push(Dependency::parse(value)),
+            "conflict" => info.conflicts.push(Dependency::parse(value)),
+            "replaces" => info.replaces.push(Dependency::parse(value)),
+            _ => {}
+        }
+    }
+
+    let (Some(name), Some(version)) = (name, version) else {
+        return Err(Error::Other(".PKGINFO lacks pkgname or pkgver".to_string()));
+    };
+    info.package.name = name;
+    info.package.version = Version::new(&version);
+    info.package.description = description;
+    Ok(info)
+}
+
+/// Parses the contents of `.BUILDINFO`.
+pub fn parse_buildinfo(text: &str) -> BuildInfo {
+    let mut build = BuildInfo::default();
+    let mut tool = None;
+    let mut tool_version = None;
+
+    for (key, value) in fields(text) {
+        match key {
+            "pkgbase" => build.pkgbase = Some(value.to_string()),
+            "buildtool" => tool = Some(value),
+            "buildtoolver" => tool_version = Some(value),
+            "builddir" => build.build_dir = Some(value.to_string()),
+            "buildenv" => build.build_env.push(value.to_string()),
+            "options" => build.options.push(value.to_string()),
+            "installed" => build.installed.push(value.to_string()),
+            _ => {}
+        }
+    }
+
+    build.build_tool = match (tool, tool_version) {
+        (Some(tool), Some(version)) => Some(format!("{} {}", tool, version)),
+        (tool, _) => tool.map(str::to_string),
+    };
+    build
+}
+
+/// Checks the detached signature next to the archive against pacman's
+/// keyring.
+pub fn check_signature(handle: &Alpm, path: &Path) -> SignatureStatus {
+    if !signature_path(path).exists() {
+        return SignatureStatus::Missing;
+    }
+
+    match handle.pkg_load(
+        path.to_string_lossy().into_owned(),
+        false,
+        SigLevel::PACKAGE,
+    ) {
+        Ok(_) => SignatureStatus::Valid,
+        Err(e @ (alpm::Error::PkgInvalidSig | alpm::Error::SigInvalid)) => {
+            SignatureStatus::Invalid(e.to_string())
+        }
+        Err(e) => SignatureStatus::Unknown(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexedPackage;
+    use crate::orphan::OrphanDetector;
+    use flate2::{write::GzEncoder, Compression};
+
+    const PKGINFO: &str = "# Generated by makepkg 6.1.0
+pkgname = demo-tool
+pkgbase = demo
+pkgver = 1:2.0-3
+pkgdesc = A tool = for demos
+url = https://example.org
+builddate = 1700000000
+packager = Demo Packager <demo@example.org>
+size = 4096
+arch = x86_64
+license = MIT
+depend = glibc>=2.38
+depend = libdemo.so=1-64
+optdepend = bash-completion: completions
+provides = demo
+";
+
+    const BUILDINFO: &str = "format = 2
+pkgbase = demo
+buildtool = makepkg
+buildtoolver = 6.1.0
+builddir = /build
+buildenv = !distcc
+options = strip
+installed = glibc-2.39-1-x86_64
+";
+
+    #[test]
+    fn test_parse_metadata() {
+        let info = parse_pkginfo(PKGINFO).unwrap();
+        assert_eq!(info.package.name, "demo-tool");
+        assert_eq!(info.package.version, Version::new("1:2.0-3"));
+        assert_eq!(info.package.description, "A tool = for demos");
+        assert_eq!(info.depends.len(), 2);
+        assert_eq!(info.depends[0].name, "glibc");
+        assert_eq!(info.optdepends[0].name, "bash-completion");
+        assert_eq!(info.installed_size, 4096);
+        assert!(info.build_date.is_some());
+        assert!(parse_pkginfo("pkgname = x\n").is_err());
+
+        let build = parse_buildinfo(BUILDINFO);
+        assert_eq!(build.build_tool.as_deref(), Some("makepkg 6.1.0"));
+        assert_eq!(build.installed, ["glibc-2.39-1-x86_64"]);
+    }
+
+    #[test]
+    fn test_read_archive() {
+        let path = std::env::temp_dir().join(format!("xpm-archive-{}.tar.gz", std::process::id()));
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents) in [
+            (".BUILDINFO", BUILDINFO),
+            (".PKGINFO", PKGINFO),
+            ("usr/bin/demo", "#!/bin/sh\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let (info, build) = read_archive(&path).unwrap();
+        assert_eq!(
+            read_archive_file(&path, "/usr/bin/demo")
+                .unwrap()
+                .as_deref(),
+            Some(&b"#!/bin/sh\n"[..])
+        );
+        assert_eq!(read_archive_file(&path, "etc/demo.conf").unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(info.package.name, "demo-tool");
+        assert!(info.download_size > 0);
+        assert_eq!(build.unwrap().pkgbase.as_deref(), Some("demo"));
+
+        let installed = parse_pkginfo("pkgname = glibc\npkgver = 2.39-1\n").unwrap();
+        let index = PackageIndex::from_packages(
+            vec![IndexedPackage::new(installed, Vec::new(), Vec::new())],
+            Vec::new(),
+            &OrphanDetector::new(),
+        );
+        let local = LocalPackage::new(
+            "demo.pkg.tar.gz",
+            info,
+            None,
+            &index,
+            SignatureStatus::Missing,
+        );
+        assert_eq!(local.installed_version, None);
+        assert_eq!(local.unsatisfied.len(), 1);
+        assert_eq!(local.unsatisfied[0].name, "libdemo.so");
+    }
+}
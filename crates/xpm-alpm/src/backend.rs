@@ -1,71 +1,56 @@
 //! ALPM backend implementation.
 
-use crate::cache::CacheManager;
-use alpm::{Alpm, SigLevel};
+use crate::archive::{LocalPackage, SignatureStatus};
+use crate::cache::{CacheManager, CachedVersion, CleanPolicy, CleanReport};
+use crate::deps::{DependencyNode, ReverseDependencies};
+use crate::files::{FileMatch, PackageFile};
+use crate::index::IndexWorker;
+use crate::orphan::{OrphanDetector, OrphanGroup};
+use crate::pacnew::ConfigFileView;
+pub use crate::config::AlpmConfig;
+use crate::transaction::TransactionHandler;
+use crate::updates::{UpdateCheck, UpdateCheckDb, DEFAULT_MAX_AGE};
+use alpm::Alpm;
 use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tracing::{info, warn};
 use xpm_core::{
+    cancel::CancellationToken,
+    config_file::{ConfigFile, ConfigFileAction},
     error::{Error, Result},
+    event::{PackageAction, TransactionEvent},
     operation::{Operation, OperationKind, OperationResult},
     package::{
-        InstallReason, Package, PackageBackend, PackageInfo, PackageStatus, SearchResult,
-        UpdateInfo, Version,
+        Package, PackageBackend, PackageInfo, PackageStatus, SearchResult, UpdateInfo, Version,
     },
-    source::{PackageSource, ProgressCallback},
+    plan::TransactionPlan,
+    query::SearchQuery,
+    question::{DefaultAnswerer, QuestionAnswerer},
+    source::{EventCallback, PackageSource},
+    verify::{FileIssue, FileProblem, PackageVerification},
 };
 
-/// Default paths for Arch Linux.
-const DEFAULT_ROOT: &str = "/";
-const DEFAULT_DBPATH: &str = "/var/lib/pacman";
-
-/// Configuration for the ALPM backend.
-#[derive(Debug, Clone)]
-pub struct AlpmConfig {
-    /// Root directory for package installation.
-    pub root: String,
-    /// Database path.
-    pub dbpath: String,
-    /// Cache directories.
-    pub cache_dirs: Vec<String>,
-    /// Hook directories.
-    pub hook_dirs: Vec<String>,
-    /// GPG directory.
-    pub gpgdir: String,
-    /// Log file path.
-    pub logfile: String,
-}
-
-impl Default for AlpmConfig {
-    fn default() -> Self {
-        Self {
-            root: DEFAULT_ROOT.to_string(),
-            dbpath: DEFAULT_DBPATH.to_string(),
-            cache_dirs: vec!["/var/cache/pacman/pkg".to_string()],
-            hook_dirs: vec![
-                "/etc/pacman.d/hooks".to_string(),
-                "/usr/share/libalpm/hooks".to_string(),
-            ],
-            gpgdir: "/etc/pacman.d/gnupg".to_string(),
-            logfile: "/var/log/pacman.log".to_string(),
-        }
-    }
-}
-
 /// The pacman/libalpm backend.
 pub struct AlpmBackend {
     config: AlpmConfig,
     cache_manager: CacheManager,
+    index: IndexWorker,
+    orphans: OrphanDetector,
+    update_db: UpdateCheckDb,
 }
 
-// ALPM handle is not Send/Sync, so we create it on demand in blocking tasks.
+// ALPM handle is not Send/Sync, so transactions create one on demand in
+// blocking tasks and queries go through the index.
 unsafe impl Send for AlpmBackend {}
 unsafe impl Sync for AlpmBackend {}
 
 impl AlpmBackend {
-    /// Creates a new ALPM backend with default configuration.
+    /// Creates a new ALPM backend configured from `/etc/pacman.conf`.
     pub fn new() -> Result<Self> {
-        Self::with_config(AlpmConfig::default())
+        Self::with_config(AlpmConfig::system()?)
     }
 
     /// Creates a new ALPM backend with custom configuration.
@@ -80,378 +65,490 @@ impl AlpmBackend {
 
         Ok(Self {
             cache_manager: CacheManager::new(&config.cache_dirs),
+            index: IndexWorker::spawn(config.clone()),
+            orphans: OrphanDetector::new(),
+            update_db: UpdateCheckDb::for_current_user(),
             config,
         })
     }
 
-}
+    /// Uses `detector` to find orphans, e.g. to keep packages back. Package
+    /// statuses in queries follow it too.
+    pub fn with_orphan_detector(mut self, detector: OrphanDetector) -> Self {
+        self.index.set_orphan_detector(detector.clone());
+        self.orphans = detector;
+        self
+    }
 
-#[async_trait]
-impl PackageSource for AlpmBackend {
-    fn source_id(&self) -> &str {
-        "pacman"
+    /// Keeps the databases for update checks in `db` instead of the
+    /// user's cache directory.
+    pub fn with_update_check_db(mut self, db: UpdateCheckDb) -> Self {
+        self.update_db = db;
+        self
     }
 
-    fn display_name(&self) -> &str {
-        "Pacman"
+    /// Lists pending updates from the private update-check databases,
+    /// syncing them first unless that happened within `max_age`.
+    pub async fn check_updates(&self, max_age: Duration) -> Result<UpdateCheck> {
+        let config = self.config.clone();
+        let db = self.update_db.clone();
+
+        tokio::task::spawn_blocking(move || db.check(&config, max_age))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?
     }
 
-    async fn is_available(&self) -> bool {
-        Path::new(&self.config.dbpath).exists()
+    /// When the update-check databases were last synced, if ever.
+    pub fn last_update_check(&self) -> Option<SystemTime> {
+        self.update_db.last_sync()
     }
 
-    async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
-        let config = self.config.clone();
-        let query = query.to_string();
+    /// Rebuilds the package index, e.g. after pacman ran outside this
+    /// backend. Queries wait for the new index.
+    pub fn refresh_index(&self) {
+        self.index.refresh();
+    }
 
-        tokio::task::spawn_blocking(move || {
-            let handle = Alpm::new(config.root.clone(), config.dbpath.clone())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    /// Dependency tree of a package, cut off below `max_depth` levels.
+    pub async fn dependency_tree(
+        &self,
+        name: &str,
+        max_depth: usize,
+        optional: bool,
+    ) -> Result<DependencyNode> {
+        self.index
+            .snapshot()
+            .await?
+            .dependency_tree(name, max_depth, optional)
+    }
 
-            // Register sync databases.
-            let siglevel = SigLevel::PACKAGE_OPTIONAL | SigLevel::DATABASE_OPTIONAL;
-            for repo in ["core", "extra", "multilib", "xerolinux", "chaotic-aur"] {
-                handle.register_syncdb(repo, siglevel).ok();
-            }
+    /// Installed packages that depend on a package.
+    pub async fn reverse_dependencies(&self, name: &str) -> Result<ReverseDependencies> {
+        self.index.snapshot().await?.reverse_dependencies(name)
+    }
 
-            let mut results = Vec::new();
-            let query_lower = query.to_lowercase();
-
-            // Search in sync databases.
-            for db in handle.syncdbs() {
-                for pkg in db.pkgs() {
-                    let name = pkg.name();
-                    let desc = pkg.desc().unwrap_or_default();
-
-                    if name.to_lowercase().contains(&query_lower)
-                        || desc.to_lowercase().contains(&query_lower)
-                    {
-                        let installed = handle.localdb().pkg(name).is_ok();
-                        let installed_version = if installed {
-                            handle
-                                .localdb()
-                                .pkg(name)
-                                .ok()
-                                .map(|p| Version::new(p.version().as_str()))
-                        } else {
-                            None
-                        };
-
-                        results.push(SearchResult {
-                            name: name.to_string(),
-                            version: Version::new(pkg.version().as_str()),
-                            description: desc.to_string(),
-                            backend: PackageBackend::Pacman,
-                            repository: db.name().to_string(),
-                            installed,
-                            installed_version,
-                        });
-                    }
-                }
-            }
+    /// Chains from explicitly installed packages down to `name`.
+    pub async fn why_installed(&self, name: &str) -> Result<Vec<Vec<String>>> {
+        self.index.snapshot().await?.why_installed(name)
+    }
 
-            Ok(results)
+    /// Files of an installed package, with backup files marked.
+    pub async fn package_files(&self, name: &str) -> Result<Vec<PackageFile>> {
+        let config = self.config.clone();
+        let name = name.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let handle = open_handle(&config)?;
+            crate::files::package_files(&handle, &name)
         })
         .await
         .map_err(|e| Error::Other(e.to_string()))?
     }
 
-    async fn list_installed(&self) -> Result<Vec<Package>> {
+    /// Installed packages owning a file or directory.
+    pub async fn file_owners(&self, path: &str) -> Result<Vec<String>> {
+        let index = self.index.snapshot().await?;
+        crate::files::file_owners(&index, &self.config.root, path)
+    }
+
+    /// Searches the sync file databases for a path or file name.
+    pub async fn search_files(&self, query: &str) -> Result<Vec<FileMatch>> {
+        let config = self.config.clone();
+        let query = query.to_string();
+
+        tokio::task::spawn_blocking(move || crate::files::search_files(&config, &query))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?
+    }
+
+    /// Plans `operation` for review before it runs. Unlike
+    /// [`PackageSource::plan`], hooks are matched against the new file lists
+    /// from the `.files` databases when they are synced, which takes a while
+    /// to load.
+    pub async fn preview(&self, operation: &Operation) -> Result<TransactionPlan> {
         let config = self.config.clone();
+        let operation = self.resolve_orphans(operation.clone()).await?;
 
         tokio::task::spawn_blocking(move || {
-            let handle = Alpm::new(config.root.clone(), config.dbpath.clone())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            let mut handle = open_handle(&config)?;
+            let mut plan = crate::plan::plan_operation(&mut handle, &operation)?;
+            let installs = plan.packages.iter().any(|p| p.new_version.is_some());
+            let files = installs
+                .then(|| crate::files::open_files_handle(&config).ok())
+                .flatten();
+            plan.hooks = crate::hooks::planned_hooks(&handle, files.as_ref(), &config, &plan);
+            Ok(plan)
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?
+    }
 
-            let mut packages = Vec::new();
+    /// Reads a package archive and checks it against the installed system,
+    /// for review before `pacman -U`.
+    pub async fn inspect_archive(&self, path: &str) -> Result<LocalPackage> {
+        let config = self.config.clone();
+        let archive = path.to_string();
+
+        let (info, build, signature) = tokio::task::spawn_blocking(move || {
+            let archive = Path::new(&archive);
+            let (info, build) = crate::archive::read_archive(archive)?;
+            let signature = match open_handle(&config) {
+                Ok(handle) => crate::archive::check_signature(&handle, archive),
+                Err(e) => SignatureStatus::Unknown(e.to_string()),
+            };
+            Ok::<_, Error>((info, build, signature))
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()))??;
 
-            for pkg in handle.localdb().pkgs() {
-                let is_orphan = pkg.reason() == alpm::PackageReason::Depend
-                    && pkg.required_by().is_empty()
-                    && pkg.optional_for().is_empty();
+        let index = self.index.snapshot().await?;
+        Ok(LocalPackage::new(path, info, build, &index, signature))
+    }
 
-                let status = if is_orphan {
-                    PackageStatus::Orphan
-                } else {
-                    PackageStatus::Installed
-                };
+    /// Older versions of the installed package `name` in the package cache,
+    /// newest first, with their signatures checked.
+    pub async fn cached_versions(&self, name: &str) -> Result<Vec<CachedVersion>> {
+        let installed = self
+            .index
+            .snapshot()
+            .await?
+            .local(name)
+            .map(|pkg| pkg.version().clone())
+            .ok_or_else(|| Error::PackageNotFound(name.to_string()))?;
+        let older = self.cache_manager.older_versions(name, &installed).await?;
+        let config = self.config.clone();
 
-                packages.push(Package::new(
-                    pkg.name(),
-                    Version::new(pkg.version().as_str()),
-                    pkg.desc().unwrap_or_default(),
-                    PackageBackend::Pacman,
-                    status,
-                    "local",
-                ));
-            }
+        tokio::task::spawn_blocking(move || {
+            let handle = open_handle(&config);
+            older
+                .into_iter()
+                .map(|package| CachedVersion {
+                    signature: match &handle {
+                        Ok(handle) => crate::archive::check_signature(handle, &package.path),
+                        Err(e) => SignatureStatus::Unknown(e.to_string()),
+                    },
+                    package,
+                })
+                .collect()
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()))
+    }
 
-            Ok(packages)
+    /// `.pacnew` and `.pacsave` files beside the backup files of installed
+    /// packages.
+    pub async fn config_files(&self) -> Result<Vec<ConfigFile>> {
+        let config = self.config.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let handle = open_handle(&config)?;
+            let backups: Vec<(&str, &str)> = handle
+                .localdb()
+                .pkgs()
+                .iter()
+                .flat_map(|pkg| pkg.backup().iter().map(move |b| (pkg.name(), b.name())))
+                .collect();
+            Ok(crate::pacnew::find_config_files(&config.root, backups))
         })
         .await
         .map_err(|e| Error::Other(e.to_string()))?
     }
 
-    async fn list_updates(&self) -> Result<Vec<UpdateInfo>> {
-        let config = self.config.clone();
+    /// The original, current and new contents of a pending configuration
+    /// file, with a merge of the latter two. The original comes from the
+    /// newest cached package older than the installed one.
+    pub async fn config_file_view(&self, file: &ConfigFile) -> Result<ConfigFileView> {
+        let installed = self
+            .index
+            .snapshot()
+            .await?
+            .local(&file.package)
+            .map(|pkg| pkg.version().clone())
+            .ok_or_else(|| Error::PackageNotFound(file.package.clone()))?;
+        let previous = self
+            .cache_manager
+            .older_versions(&file.package, &installed)
+            .await?
+            .into_iter()
+            .next();
+        let relative = file
+            .path
+            .strip_prefix(self.config.root.trim_end_matches('/'))
+            .unwrap_or(&file.path)
+            .to_string();
+        let file = file.clone();
 
         tokio::task::spawn_blocking(move || {
-            // Use checkupdates approach: sync to temp db, then compare
-            // This doesn't require root privileges
+            let original = match previous {
+                Some(cached) => crate::archive::read_archive_file(&cached.path, &relative)?
+                    .map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+                None => None,
+            };
+            ConfigFileView::new(file, original)
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?
+    }
 
-            let temp_dir = std::env::temp_dir().join("xpm-checkupdates");
-            let temp_dbpath = temp_dir.join("db");
+    /// Keeps, replaces or merges a pending configuration file. Needs write
+    /// access to the file.
+    pub async fn resolve_config_file(
+        &self,
+        file: &ConfigFile,
+        action: ConfigFileAction,
+    ) -> Result<()> {
+        let file = file.clone();
+        tokio::task::spawn_blocking(move || crate::pacnew::resolve(&file, &action))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?
+    }
 
-            // Create temp directory structure
-            std::fs::create_dir_all(&temp_dbpath).ok();
+    /// Cleans the package cache like `paccache`. The installed version of
+    /// every package is always kept.
+    pub async fn clean_cache_with(&self, policy: &CleanPolicy) -> Result<CleanReport> {
+        let installed: HashMap<String, Version> = self
+            .index
+            .snapshot()
+            .await?
+            .installed()
+            .iter()
+            .map(|pkg| (pkg.name().to_string(), pkg.version().clone()))
+            .collect();
+
+        self.cache_manager.clean(policy, &installed).await
+    }
 
-            // Symlink local database to temp location (read-only)
-            let local_db_src = Path::new(&config.dbpath).join("local");
-            let local_db_dst = temp_dbpath.join("local");
-            if local_db_src.exists() && !local_db_dst.exists() {
-                std::os::unix::fs::symlink(&local_db_src, &local_db_dst).ok();
-            }
+    /// Desktop entries of installed packages, as package name and path.
+    pub async fn desktop_entries(&self) -> Result<Vec<(String, String)>> {
+        Ok(self
+            .index
+            .snapshot()
+            .await?
+            .desktop_entries()
+            .map(|(name, path)| (name.to_string(), path.to_string()))
+            .collect())
+    }
 
-            // Create handle with temp dbpath for syncing
-            let mut handle = Alpm::new(config.root.clone(), temp_dbpath.to_string_lossy().to_string())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
-
-            // Register databases with mirror servers
-            let siglevel = SigLevel::PACKAGE_OPTIONAL | SigLevel::DATABASE_OPTIONAL;
-            let arch = "x86_64";
-            for repo in ["core", "extra", "multilib", "xerolinux", "chaotic-aur"] {
-                if let Ok(db) = handle.register_syncdb_mut(repo, siglevel) {
-                    match repo {
-                        "core" | "extra" | "multilib" => {
-                            db.add_server(format!("https://geo.mirror.pkgbuild.com/{}/os/{}", repo, arch)).ok();
-                        }
-                        "chaotic-aur" => {
-                            db.add_server(format!("https://geo-mirror.chaotic.cx/{}/{}", repo, arch)).ok();
-                        }
-                        "xerolinux" => {
-                            db.add_server(format!("https://repos.xerolinux.xyz/{}/{}", repo, arch)).ok();
-                        }
-                        _ => {}
-                    }
-                }
-            }
+    /// Orphans grouped by the dependencies between them, with the space
+    /// each group frees.
+    pub async fn orphan_groups(&self) -> Result<Vec<OrphanGroup>> {
+        Ok(self.orphans.detect(&*self.index.snapshot().await?))
+    }
 
-            // Sync all databases at once
-            if let Err(e) = handle.syncdbs_mut().update(false) {
-                warn!("Failed to sync databases: {}", e);
-            }
+    /// Fills in the targets of an orphan removal without explicit packages.
+    async fn resolve_orphans(&self, mut operation: Operation) -> Result<Operation> {
+        if operation.kind == OperationKind::RemoveOrphans && operation.packages.is_empty() {
+            let index = self.index.snapshot().await?;
+            let mut names: Vec<String> = self
+                .orphans
+                .removable(&index)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            names.sort();
+            operation.packages = names;
+        }
+        Ok(operation)
+    }
+}
 
-            let mut updates = Vec::new();
-
-            for local_pkg in handle.localdb().pkgs() {
-                let name = local_pkg.name();
-
-                // Check sync dbs for newer version.
-                for db in handle.syncdbs() {
-                    if let Ok(sync_pkg) = db.pkg(name) {
-                        let local_ver = local_pkg.version();
-                        let sync_ver = sync_pkg.version();
-
-                        if alpm::vercmp(sync_ver.as_str(), local_ver.as_str())
-                            == std::cmp::Ordering::Greater
-                        {
-                            updates.push(UpdateInfo {
-                                name: name.to_string(),
-                                current_version: Version::new(local_ver.as_str()),
-                                new_version: Version::new(sync_ver.as_str()),
-                                backend: PackageBackend::Pacman,
-                                repository: db.name().to_string(),
-                                download_size: sync_pkg.download_size() as u64,
-                            });
-                            break;
-                        }
-                    }
+/// Registers the configured sync databases with their mirror servers.
+pub(crate) fn register_syncdbs(handle: &mut Alpm, config: &AlpmConfig) {
+    for repo in &config.repos {
+        match handle.register_syncdb_mut(repo.name.as_str(), repo.siglevel) {
+            Ok(db) => {
+                for server in &repo.servers {
+                    db.add_server(server.as_str()).ok();
                 }
             }
+            Err(e) => warn!("Failed to register repository {}: {}", repo.name, e),
+        }
+    }
+}
 
-            Ok(updates)
-        })
-        .await
-        .map_err(|e| Error::Other(e.to_string()))?
+/// Opens a handle set up for transactions: cache, hook and GPG directories,
+/// log file and sync databases.
+fn open_handle(config: &AlpmConfig) -> Result<Alpm> {
+    let mut handle = Alpm::new(config.root.clone(), config.dbpath.clone())
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+
+    for dir in &config.cache_dirs {
+        handle.add_cachedir(dir.as_str()).ok();
     }
+    for dir in &config.hook_dirs {
+        handle.add_hookdir(dir.as_str()).ok();
+    }
+    handle.set_gpgdir(config.gpgdir.as_str()).ok();
+    handle.set_logfile(config.logfile.as_str()).ok();
+    for arch in &config.architectures {
+        handle.add_architecture(arch.as_str()).ok();
+    }
+    for pkg in &config.ignore_pkgs {
+        handle.add_ignorepkg(pkg.as_str()).ok();
+    }
+    for group in &config.ignore_groups {
+        handle.add_ignoregroup(group.as_str()).ok();
+    }
+    handle.set_parallel_downloads(config.parallel_downloads);
+    handle.set_default_siglevel(config.siglevel).ok();
+    register_syncdbs(&mut handle, config);
 
-    async fn get_package_info(&self, name: &str) -> Result<PackageInfo> {
-        let config = self.config.clone();
-        let name = name.to_string();
+    Ok(handle)
+}
 
-        tokio::task::spawn_blocking(move || {
-            let handle = Alpm::new(config.root.clone(), config.dbpath.clone())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+#[async_trait]
+impl PackageSource for AlpmBackend {
+    fn source_id(&self) -> &str {
+        "pacman"
+    }
 
-            // Register sync databases.
-            let siglevel = SigLevel::PACKAGE_OPTIONAL | SigLevel::DATABASE_OPTIONAL;
-            for repo in ["core", "extra", "multilib", "xerolinux", "chaotic-aur"] {
-                handle.register_syncdb(repo, siglevel).ok();
-            }
+    fn display_name(&self) -> &str {
+        "Pacman"
+    }
 
-            // Try local db first.
-            if let Ok(pkg) = handle.localdb().pkg(name.as_bytes()) {
-                let is_orphan = pkg.reason() == alpm::PackageReason::Depend
-                    && pkg.required_by().is_empty()
-                    && pkg.optional_for().is_empty();
-
-                let status = if is_orphan {
-                    PackageStatus::Orphan
-                } else {
-                    PackageStatus::Installed
-                };
-
-                let reason = Some(match pkg.reason() {
-                    alpm::PackageReason::Explicit => InstallReason::Explicit,
-                    alpm::PackageReason::Depend => InstallReason::Dependency,
-                });
+    async fn is_available(&self) -> bool {
+        Path::new(&self.config.dbpath).exists()
+    }
 
-                return Ok(PackageInfo {
-                    package: Package::new(
-                        pkg.name(),
-                        Version::new(pkg.version().as_str()),
-                        pkg.desc().unwrap_or_default(),
-                        PackageBackend::Pacman,
-                        status,
-                        "local",
-                    ),
-                    url: pkg.url().map(|s| s.to_string()),
-                    licenses: pkg.licenses().iter().map(|s| s.to_string()).collect(),
-                    groups: pkg.groups().iter().map(|s| s.to_string()).collect(),
-                    depends: pkg.depends().iter().map(|d| d.to_string()).collect(),
-                    optdepends: pkg.optdepends().iter().map(|d| d.to_string()).collect(),
-                    provides: pkg.provides().iter().map(|d| d.to_string()).collect(),
-                    conflicts: pkg.conflicts().iter().map(|d| d.to_string()).collect(),
-                    replaces: pkg.replaces().iter().map(|d| d.to_string()).collect(),
-                    installed_size: pkg.isize() as u64,
-                    download_size: pkg.download_size() as u64,
-                    build_date: Some(
-                        chrono::DateTime::from_timestamp(pkg.build_date(), 0)
-                            .unwrap_or_default()
-                            .with_timezone(&chrono::Utc),
-                    ),
-                    install_date: pkg.install_date().map(|ts| {
-                        chrono::DateTime::from_timestamp(ts, 0)
-                            .unwrap_or_default()
-                            .with_timezone(&chrono::Utc)
-                    }),
-                    packager: pkg.packager().map(|s| s.to_string()),
-                    arch: pkg.arch().unwrap_or("any").to_string(),
-                    reason,
-                });
-            }
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let query = SearchQuery::parse(query)?;
+        Ok(self.index.snapshot().await?.search(&query))
+    }
 
-            // Try sync dbs.
-            for db in handle.syncdbs() {
-                if let Ok(pkg) = db.pkg(name.as_bytes()) {
-                    return Ok(PackageInfo {
-                        package: Package::new(
-                            pkg.name(),
-                            Version::new(pkg.version().as_str()),
-                            pkg.desc().unwrap_or_default(),
-                            PackageBackend::Pacman,
-                            PackageStatus::Available,
-                            db.name(),
-                        ),
-                        url: pkg.url().map(|s| s.to_string()),
-                        licenses: pkg.licenses().iter().map(|s| s.to_string()).collect(),
-                        groups: pkg.groups().iter().map(|s| s.to_string()).collect(),
-                        depends: pkg.depends().iter().map(|d| d.to_string()).collect(),
-                        optdepends: pkg.optdepends().iter().map(|d| d.to_string()).collect(),
-                        provides: pkg.provides().iter().map(|d| d.to_string()).collect(),
-                        conflicts: pkg.conflicts().iter().map(|d| d.to_string()).collect(),
-                        replaces: pkg.replaces().iter().map(|d| d.to_string()).collect(),
-                        installed_size: pkg.isize() as u64,
-                        download_size: pkg.download_size() as u64,
-                        build_date: Some(
-                            chrono::DateTime::from_timestamp(pkg.build_date(), 0)
-                                .unwrap_or_default()
-                                .with_timezone(&chrono::Utc),
-                        ),
-                        install_date: None,
-                        packager: pkg.packager().map(|s| s.to_string()),
-                        arch: pkg.arch().unwrap_or("any").to_string(),
-                        reason: None,
-                    });
-                }
-            }
+    async fn list_installed(&self) -> Result<Vec<Package>> {
+        let index = self.index.snapshot().await?;
+        Ok(index
+            .installed()
+            .iter()
+            .map(|pkg| pkg.info.package.clone())
+            .collect())
+    }
+
+    async fn list_updates(&self) -> Result<Vec<UpdateInfo>> {
+        Ok(self.check_updates(DEFAULT_MAX_AGE).await?.updates)
+    }
+
+    async fn get_package_info(&self, name: &str) -> Result<PackageInfo> {
+        self.index
+            .snapshot()
+            .await?
+            .info(name)
+            .ok_or_else(|| Error::PackageNotFound(name.to_string()))
+    }
 
-            Err(Error::PackageNotFound(name))
+    async fn plan(&self, operation: &Operation) -> Result<TransactionPlan> {
+        let config = self.config.clone();
+        let operation = self.resolve_orphans(operation.clone()).await?;
+
+        tokio::task::spawn_blocking(move || {
+            let mut handle = open_handle(&config)?;
+            let mut plan = crate::plan::plan_operation(&mut handle, &operation)?;
+            plan.hooks = crate::hooks::planned_hooks(&handle, None, &config, &plan);
+            Ok(plan)
         })
         .await
         .map_err(|e| Error::Other(e.to_string()))?
     }
 
     async fn execute(&self, operation: Operation) -> Result<OperationResult> {
-        self.execute_with_progress(operation, Box::new(|_| {})).await
+        self.execute_with_progress(
+            operation,
+            Box::new(|_| {}),
+            Arc::new(DefaultAnswerer),
+            CancellationToken::new(),
+        )
+        .await
     }
 
     async fn execute_with_progress(
         &self,
         operation: Operation,
-        _progress: ProgressCallback,
+        events: EventCallback,
+        questions: Arc<dyn QuestionAnswerer>,
+        cancel: CancellationToken,
     ) -> Result<OperationResult> {
         let start = std::time::Instant::now();
 
         info!("Executing operation: {:?}", operation.kind);
 
-        // For actual package operations, we would need to call pacman/libalpm
-        // with root privileges. For now, return a placeholder result.
-        let result = match operation.kind {
-            OperationKind::Install
-            | OperationKind::Remove
-            | OperationKind::RemoveWithDeps
-            | OperationKind::Update
-            | OperationKind::SystemUpgrade => {
-                // TODO: Implement actual package operations via polkit/pkexec.
-                warn!(
-                    "Package operations require root privileges - not implemented yet"
-                );
-                OperationResult::failure(
-                    operation,
-                    "Package operations require root privileges",
-                    start.elapsed().as_millis() as u64,
-                )
-            }
-            OperationKind::SyncDatabases => {
-                // Database sync also needs root for system-wide sync.
-                warn!("Database sync requires root privileges");
-                OperationResult::failure(
-                    operation,
-                    "Database sync requires root privileges",
-                    start.elapsed().as_millis() as u64,
-                )
-            }
-            OperationKind::CleanCache => {
-                let freed = self.cache_manager.clean(3).await?;
-                info!("Freed {} bytes from cache", freed);
-                OperationResult::success(operation, Vec::new(), start.elapsed().as_millis() as u64)
-            }
-            OperationKind::RemoveOrphans => {
-                let orphans = self.list_orphans().await?;
-                if orphans.is_empty() {
-                    OperationResult::success(
-                        operation,
-                        Vec::new(),
-                        start.elapsed().as_millis() as u64,
-                    )
-                } else {
-                    OperationResult::failure(
-                        operation,
-                        "Removing orphans requires root privileges",
-                        start.elapsed().as_millis() as u64,
-                    )
+        if cancel.is_cancelled() {
+            return Ok(OperationResult::cancelled(operation, Vec::new(), 0));
+        }
+
+        if operation.kind == OperationKind::CleanCache {
+            let freed = self.clean_cache_with(&CleanPolicy::new(3)).await?.freed;
+            info!("Freed {} bytes from cache", freed);
+            return Ok(OperationResult::success(
+                operation,
+                Vec::new(),
+                start.elapsed().as_millis() as u64,
+            ));
+        }
+
+        let operation = self.resolve_orphans(operation).await?;
+        let config = self.config.clone();
+        let runtime = tokio::runtime::Handle::current();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut config_files = Vec::new();
+            let mut old_versions = HashMap::new();
+            let outcome = open_handle(&config).and_then(|mut handle| {
+                let mut handler =
+                    TransactionHandler::new(&mut handle, events, questions, runtime, cancel);
+                let affected = handler.run(&operation);
+                config_files = handler.config_files();
+                old_versions = handler.old_versions();
+                affected
+            });
+            let duration_ms = start.elapsed().as_millis() as u64;
+
+            let mut result = match outcome {
+                Ok(affected) => OperationResult::success(operation, affected, duration_ms),
+                Err(e) => {
+                    warn!("Operation failed: {}", e);
+                    OperationResult::from_error(operation, &e, duration_ms)
+                }
+            };
+
+            // The downgrade itself succeeded, so failing to hold the
+            // packages back is only worth a warning.
+            if result.is_success()
+                && result.operation.kind == OperationKind::Downgrade
+                && result.operation.options.ignore_upgrades
+            {
+                // Only the package files, not dependencies pulled in from the
+                // repositories, belong to no database.
+                let names: Vec<String> = result
+                    .affected_packages
+                    .iter()
+                    .filter(|p| p.status == PackageStatus::Installed && p.repository.is_empty())
+                    .map(|p| p.name.clone())
+                    .collect();
+                if let Err(e) = config.add_ignore_pkgs(&names) {
+                    warn!("Failed to add {} to IgnorePkg: {}", names.join(", "), e);
+                    result
+                        .warnings
+                        .push(format!("Downgraded, but not added to IgnorePkg: {}", e));
                 }
             }
-        };
 
-        Ok(result)
+            result
+                .with_config_files(config_files)
+                .with_old_versions(old_versions)
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()));
+
+        self.index.refresh();
+        result
     }
 
     async fn sync_databases(&self) -> Result<()> {
-        // Database sync requires root privileges.
-        warn!("Database sync requires root privileges - skipping");
-        Ok(())
+        self.execute(Operation::sync_databases(PackageBackend::Pacman))
+            .await
+            .and_then(|result| match result.error_detail {
+                Some(e) => Err(e),
+                None => Ok(()),
+            })
     }
 
     async fn get_cache_size(&self) -> Result<u64> {
@@ -459,36 +556,98 @@ impl PackageSource for AlpmBackend {
     }
 
     async fn clean_cache(&self, keep_versions: usize) -> Result<u64> {
-        self.cache_manager.clean(keep_versions).await
+        Ok(self
+            .clean_cache_with(&CleanPolicy::new(keep_versions))
+            .await?
+            .freed)
     }
 
     async fn list_orphans(&self) -> Result<Vec<Package>> {
+        Ok(self
+            .orphan_groups()
+            .await?
+            .into_iter()
+            .flat_map(|group| group.packages)
+            .collect())
+    }
+
+    async fn verify_packages(
+        &self,
+        names: &[String],
+        events: EventCallback,
+        cancel: CancellationToken,
+    ) -> Result<Vec<PackageVerification>> {
         let config = self.config.clone();
+        let names = names.to_vec();
 
         tokio::task::spawn_blocking(move || {
-            let handle = Alpm::new(config.root.clone(), config.dbpath.clone())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
-
-            let mut orphans = Vec::new();
-
-            for pkg in handle.localdb().pkgs() {
-                let is_orphan = pkg.reason() == alpm::PackageReason::Depend
-                    && pkg.required_by().is_empty()
-                    && pkg.optional_for().is_empty();
-
-                if is_orphan {
-                    orphans.push(Package::new(
-                        pkg.name(),
-                        Version::new(pkg.version().as_str()),
-                        pkg.desc().unwrap_or_default(),
-                        PackageBackend::Pacman,
-                        PackageStatus::Orphan,
-                        "local",
-                    ));
-                }
-            }
+            let handle = open_handle(&config)?;
+            let localdb = handle.localdb();
+            let pkgs = if names.is_empty() {
+                localdb.pkgs().iter().collect()
+            } else {
+                names
+                    .iter()
+                    .map(|name| {
+                        localdb
+                            .pkg(name.as_str())
+                            .map_err(|_| Error::PackageNotFound(name.clone()))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            };
+
+            let total = pkgs.len();
+            let mut reports = Vec::with_capacity(total);
+            for (i, pkg) in pkgs.into_iter().enumerate() {
+                cancel.check()?;
+                events(TransactionEvent::PackageStarted {
+                    action: PackageAction::Verify,
+                    name: pkg.name().to_string(),
+                    index: i + 1,
+                    total,
+                });
 
-            Ok(orphans)
+                let backup: HashSet<&str> = pkg.backup().iter().map(|b| b.name()).collect();
+                let report = crate::verify::verify_package(
+                    &config.root,
+                    &config.dbpath,
+                    pkg.name(),
+                    pkg.version().as_str(),
+                    &backup,
+                )
+                .unwrap_or_else(|e| {
+                    // Without its mtree nothing of the package can be
+                    // checked, which is worth reporting on its own.
+                    PackageVerification {
+                        package: pkg.name().to_string(),
+                        backend: PackageBackend::Pacman,
+                        files_checked: 0,
+                        corrupted: vec![FileIssue {
+                            path: crate::verify::mtree_path(
+                                &config.dbpath,
+                                pkg.name(),
+                                pkg.version().as_str(),
+                            )
+                            .display()
+                            .to_string(),
+                            problem: FileProblem::Unreadable {
+                                reason: e.to_string(),
+                            },
+                        }],
+                        modified_config: Vec::new(),
+                        unverified: Vec::new(),
+                    }
+                });
+                reports.push(report);
+
+                events(TransactionEvent::PackageCompleted {
+                    action: PackageAction::Verify,
+                    name: pkg.name().to_string(),
+                    index: i + 1,
+                    total,
+                });
+            }
+            Ok(reports)
         })
         .await
         .map_err(|e| Error::Other(e.to_string()))?
@@ -1,10 +1,16 @@
 //! Cache management for pacman packages.
 
-use std::collections::HashMap;
+use crate::archive::SignatureStatus;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::{debug, info, warn};
-use xpm_core::error::Result;
+use xpm_core::{error::Result, package::Version};
+
+/// Compression suffixes makepkg may append to `.pkg.tar`.
+const COMPRESSIONS: &[&str] = &[
+    "", ".zst", ".xz", ".gz", ".bz2", ".lz4", ".lrz", ".lzo", ".Z", ".lz",
+];
 
 /// Manages the pacman package cache.
 pub struct CacheManager {
@@ -52,109 +58,80 @@ impl CacheManager {
         Ok(size)
     }
 
-    /// Cleans the cache, keeping only the specified number of versions per package.
-    pub async fn clean(&self, keep_versions: usize) -> Result<u64> {
-        let mut freed = 0u64;
-
-        for dir in &self.cache_dirs {
-            if !dir.exists() {
-                continue;
-            }
-
-            freed += self.clean_dir(dir, keep_versions)?;
-        }
-
-        info!("Cache cleaned, freed {} bytes", freed);
-        Ok(freed)
-    }
-
-    /// Cleans a single cache directory.
-    fn clean_dir(&self, dir: &Path, keep_versions: usize) -> Result<u64> {
-        let mut packages: HashMap<String, Vec<(PathBuf, std::time::SystemTime)>> = HashMap::new();
-        let mut freed = 0u64;
-
-        // Group package files by package name.
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            if !path.is_file() {
-                continue;
-            }
-
-            let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
-
-            // Parse package name from filename (e.g., "package-1.2.3-1-x86_64.pkg.tar.zst").
-            if let Some(pkg_name) = Self::parse_package_name(filename) {
-                let mtime = entry.metadata()?.modified().unwrap_or(std::time::UNIX_EPOCH);
-                packages.entry(pkg_name).or_default().push((path, mtime));
-            }
-        }
-
-        // Keep only the newest versions.
-        for (_name, mut versions) in packages {
-            if versions.len() <= keep_versions {
-                continue;
-            }
-
-            // Sort by modification time (newest first).
-            versions.sort_by(|a, b| b.1.cmp(&a.1));
-
-            // Remove older versions.
-            for (path, _) in versions.iter().skip(keep_versions) {
-                if let Ok(metadata) = fs::metadata(&path) {
-                    freed += metadata.len();
-                }
-
-                debug!("Removing old package: {:?}", path);
-                if let Err(e) = fs::remove_file(&path) {
-                    warn!("Failed to remove {:?}: {}", path, e);
+    /// Removes the cached packages `policy` selects, or only lists them on
+    /// a dry run. `installed` maps installed package names to their
+    /// versions.
+    pub async fn clean(
+        &self,
+        policy: &CleanPolicy,
+        installed: &HashMap<String, Version>,
+    ) -> Result<CleanReport> {
+        let mut report = CleanReport::default();
+
+        for pkg in policy.select(self.list().await?, installed) {
+            let sig_path = signature_path(&pkg.path);
+            let sig_size = fs::metadata(&sig_path).map(|m| m.len()).unwrap_or(0);
+
+            if !policy.dry_run {
+                debug!("Removing cached package: {:?}", pkg.path);
+                if let Err(e) = fs::remove_file(&pkg.path) {
+                    warn!("Failed to remove {:?}: {}", pkg.path, e);
+                    continue;
                 }
-
-                // Also remove signature file if present.
-                let sig_path = path.with_extension("sig");
-                if sig_path.exists() {
+                if sig_size > 0 {
                     fs::remove_file(&sig_path).ok();
                 }
             }
+
+            report.freed += pkg.size + sig_size;
+            report.removed.push(pkg);
         }
 
-        Ok(freed)
+        if policy.dry_run {
+            info!("Cache cleaning would free {} bytes", report.freed);
+        } else {
+            info!("Cache cleaned, freed {} bytes", report.freed);
+        }
+        Ok(report)
     }
 
-    /// Parses package name from a cache filename.
-    fn parse_package_name(filename: &str) -> Option<String> {
-        // Filename format: name-version-release-arch.pkg.tar.zst
-        // We need to extract the name part.
-
-        if !filename.contains(".pkg.tar") {
+    /// Splits a cache file name into package name, version and
+    /// architecture. Names may contain dashes and digits; the last three
+    /// dash-separated fields never do.
+    fn parse_filename(filename: &str) -> Option<(String, Version, String)> {
+        let (base, compression) = filename.split_once(".pkg.tar")?;
+        // Skips signatures and partial downloads.
+        if !COMPRESSIONS.contains(&compression) {
             return None;
         }
 
-        // Remove extension.
-        let base = filename.split(".pkg.tar").next()?;
-
-        // Split by '-' and find where version starts (first digit segment).
-        let parts: Vec<&str> = base.split('-').collect();
-
-        if parts.len() < 4 {
+        let mut parts = base.rsplitn(4, '-');
+        let arch = parts.next()?;
+        let pkgrel = parts.next()?;
+        let pkgver = parts.next()?;
+        let name = parts.next()?;
+        if [arch, pkgrel, pkgver, name]
+            .iter()
+            .any(|part| part.is_empty())
+        {
             return None;
         }
 
-        // Find the index where version starts (first part that starts with a digit).
-        let mut version_idx = parts.len();
-        for (i, part) in parts.iter().enumerate() {
-            if part.chars().next().is_some_and(|c| c.is_ascii_digit()) {
-                version_idx = i;
-                break;
-            }
-        }
-
-        if version_idx == 0 || version_idx >= parts.len() {
-            return None;
-        }
+        Some((
+            name.to_string(),
+            Version::new(&format!("{}-{}", pkgver, pkgrel)),
+            arch.to_string(),
+        ))
+    }
 
-        Some(parts[..version_idx].join("-"))
+    /// Cached versions of `name` older than `installed`, newest first. A
+    /// version cached in several directories is listed once.
+    pub async fn older_versions(
+        &self,
+        name: &str,
+        installed: &Version,
+    ) -> Result<Vec<CachedPackage>> {
+        Ok(older_than(self.list().await?, name, installed))
     }
 
     /// Lists all cached packages.
@@ -176,11 +153,14 @@ impl CacheManager {
 
                 let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("").to_string();
 
-                if filename.contains(".pkg.tar") {
+                if let Some((name, version, arch)) = Self::parse_filename(&filename) {
                     let metadata = entry.metadata()?;
                     cached.push(CachedPackage {
                         path,
                         filename,
+                        name,
+                        version,
+                        arch,
                         size: metadata.len(),
                     });
                 }
@@ -191,34 +171,232 @@ impl CacheManager {
     }
 }
 
+fn older_than(cached: Vec<CachedPackage>, name: &str, installed: &Version) -> Vec<CachedPackage> {
+    let mut older: Vec<CachedPackage> = cached
+        .into_iter()
+        .filter(|pkg| pkg.name == name && pkg.version.vercmp(installed).is_lt())
+        .collect();
+    older.sort_by(|a, b| b.version.vercmp(&a.version));
+    older.dedup_by(|a, b| a.version.vercmp(&b.version).is_eq());
+    older
+}
+
+/// The detached signature pacman downloads next to a package.
+pub(crate) fn signature_path(path: &Path) -> PathBuf {
+    let mut sig = path.as_os_str().to_owned();
+    sig.push(".sig");
+    PathBuf::from(sig)
+}
+
 /// Represents a cached package file.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CachedPackage {
     /// Full path to the cached package.
     pub path: PathBuf,
     /// Filename.
     pub filename: String,
+    /// Package name.
+    pub name: String,
+    /// Package version.
+    pub version: Version,
+    /// Package architecture.
+    pub arch: String,
     /// File size in bytes.
     pub size: u64,
 }
 
+/// Which cached packages to remove, like the options of `paccache`.
+#[derive(Debug, Clone)]
+pub struct CleanPolicy {
+    keep: usize,
+    uninstalled_only: bool,
+    per_arch: bool,
+    dry_run: bool,
+}
+
+impl CleanPolicy {
+    /// Keeps the `keep` newest versions of every package, plus the
+    /// installed one.
+    pub fn new(keep: usize) -> Self {
+        Self {
+            keep,
+            uninstalled_only: false,
+            per_arch: false,
+            dry_run: false,
+        }
+    }
+
+    /// Only cleans packages that are not installed (`paccache -u`).
+    pub fn uninstalled_only(mut self) -> Self {
+        self.uninstalled_only = true;
+        self
+    }
+
+    /// Counts the versions of each architecture separately.
+    pub fn per_arch(mut self) -> Self {
+        self.per_arch = true;
+        self
+    }
+
+    /// Reports what would be removed without removing it (`paccache -d`).
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Packages in `cached` this policy removes, newest first per package.
+    pub fn select(
+        &self,
+        cached: Vec<CachedPackage>,
+        installed: &HashMap<String, Version>,
+    ) -> Vec<CachedPackage> {
+        let mut groups: BTreeMap<(String, String), Vec<CachedPackage>> = BTreeMap::new();
+        for pkg in cached {
+            if self.uninstalled_only && installed.contains_key(&pkg.name) {
+                continue;
+            }
+            let arch = if self.per_arch {
+                pkg.arch.clone()
+            } else {
+                String::new()
+            };
+            groups
+                .entry((pkg.name.clone(), arch))
+                .or_default()
+                .push(pkg);
+        }
+
+        let mut removed = Vec::new();
+        for ((name, _), mut versions) in groups {
+            versions.sort_by(|a, b| b.version.vercmp(&a.version));
+
+            let mut kept: Vec<Version> = Vec::new();
+            for pkg in versions {
+                // Copies of a kept version in other cache directories stay too.
+                if kept.iter().any(|v| v.vercmp(&pkg.version).is_eq())
+                    || installed
+                        .get(&name)
+                        .is_some_and(|v| v.vercmp(&pkg.version).is_eq())
+                {
+                    continue;
+                }
+                if kept.len() < self.keep {
+                    kept.push(pkg.version.clone());
+                    continue;
+                }
+                removed.push(pkg);
+            }
+        }
+        removed
+    }
+}
+
+/// A cached package checked for use as a downgrade.
+#[derive(Debug, Clone)]
+pub struct CachedVersion {
+    /// The package file.
+    pub package: CachedPackage,
+    /// Result of checking its signature.
+    pub signature: SignatureStatus,
+}
+
+/// Result of cleaning the cache.
+#[derive(Debug, Clone, Default)]
+pub struct CleanReport {
+    /// Packages removed, or that would be removed on a dry run.
+    pub removed: Vec<CachedPackage>,
+    /// Bytes freed, including signatures.
+    pub freed: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn name(filename: &str) -> Option<String> {
+        CacheManager::parse_filename(filename).map(|(name, _, _)| name)
+    }
+
     #[test]
     fn test_parse_package_name() {
         assert_eq!(
-            CacheManager::parse_package_name("firefox-120.0-1-x86_64.pkg.tar.zst"),
+            name("firefox-120.0-1-x86_64.pkg.tar.zst"),
             Some("firefox".to_string())
         );
         assert_eq!(
-            CacheManager::parse_package_name("qt6-base-6.6.1-1-x86_64.pkg.tar.zst"),
+            name("qt6-base-6.6.1-1-x86_64.pkg.tar.zst"),
             Some("qt6-base".to_string())
         );
         assert_eq!(
-            CacheManager::parse_package_name("lib32-mesa-23.3.1-1-x86_64.pkg.tar.zst"),
+            name("lib32-mesa-23.3.1-1-x86_64.pkg.tar.zst"),
             Some("lib32-mesa".to_string())
         );
+        assert_eq!(
+            name("python-3to2-1.1.1-9-any.pkg.tar.xz"),
+            Some("python-3to2".to_string())
+        );
+        assert_eq!(name("firefox-120.0-1-x86_64.pkg.tar.zst.sig"), None);
+        assert_eq!(name("firefox-120.0-1-x86_64.pkg.tar.zst.part"), None);
+
+        let (_, version, arch) =
+            CacheManager::parse_filename("linux-1:6.6.1-1-x86_64.pkg.tar.zst").unwrap();
+        assert_eq!(version.epoch, Some(1));
+        assert_eq!(arch, "x86_64");
+    }
+
+    #[test]
+    fn test_select() {
+        let cached = |filename: &str| {
+            let (name, version, arch) = CacheManager::parse_filename(filename).unwrap();
+            CachedPackage {
+                path: PathBuf::from(filename),
+                filename: filename.to_string(),
+                name,
+                version,
+                arch,
+                size: 1,
+            }
+        };
+        let files = || {
+            [
+                "foo-1.9-1-x86_64.pkg.tar.zst",
+                "foo-1.10-1-x86_64.pkg.tar.zst",
+                "foo-1.8-1-x86_64.pkg.tar.zst",
+                "foo-1.9-1-i686.pkg.tar.zst",
+                "gone-2.0-1-any.pkg.tar.zst",
+            ]
+            .into_iter()
+            .map(cached)
+            .collect::<Vec<_>>()
+        };
+        let removed = |policy: CleanPolicy, installed: &HashMap<String, Version>| {
+            policy
+                .select(files(), installed)
+                .into_iter()
+                .map(|pkg| pkg.filename)
+                .collect::<Vec<_>>()
+        };
+        let installed = HashMap::from([("foo".to_string(), Version::new("1.8-1"))]);
+
+        // Ordered by version, not by name: 1.10 is newer than 1.9.
+        assert_eq!(
+            removed(CleanPolicy::new(1), &installed),
+            ["foo-1.9-1-x86_64.pkg.tar.zst", "foo-1.9-1-i686.pkg.tar.zst"]
+        );
+        assert_eq!(
+            removed(CleanPolicy::new(1).per_arch(), &installed),
+            ["foo-1.9-1-x86_64.pkg.tar.zst"]
+        );
+        assert_eq!(
+            removed(CleanPolicy::new(0).uninstalled_only(), &installed),
+            ["gone-2.0-1-any.pkg.tar.zst"]
+        );
+        assert_eq!(removed(CleanPolicy::new(0), &HashMap::new()).len(), 5);
+
+        let older: Vec<String> = older_than(files(), "foo", &Version::new("1.10-1"))
+            .into_iter()
+            .map(|pkg| pkg.version.to_string())
+            .collect();
+        assert_eq!(older, ["1.9-1", "1.8-1"]);
     }
 }
@@ -0,0 +1,568 @@
+//! Backend configuration, optionally read from `pacman.conf`.
+
+use alpm::SigLevel;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use xpm_core::error::{Error, Result};
+
+/// Default paths for Arch Linux.
+const DEFAULT_ROOT: &str = "/";
+const DEFAULT_DBPATH: &str = "/var/lib/pacman";
+
+/// Location of the system pacman configuration.
+pub const PACMAN_CONF: &str = "/etc/pacman.conf";
+
+/// Hook directory shipped by packages; always searched first so that
+/// hooks in user directories override it.
+const SYSTEM_HOOKDIR: &str = "/usr/share/libalpm/hooks";
+
+/// A sync repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoConfig {
+    /// Repository name, e.g. `core`.
+    pub name: String,
+    /// Mirror URLs with `$repo` and `$arch` already substituted.
+    pub servers: Vec<String>,
+    /// Signature checking level.
+    pub siglevel: SigLevel,
+}
+
+/// Configuration for the ALPM backend.
+#[derive(Debug, Clone)]
+pub struct AlpmConfig {
+    /// Root directory for package installation.
+    pub root: String,
+    /// Database path.
+    pub dbpath: String,
+    /// Cache directories.
+    pub cache_dirs: Vec<String>,
+    /// Hook directories.
+    pub hook_dirs: Vec<String>,
+    /// GPG directory.
+    pub gpgdir: String,
+    /// Log file path.
+    pub logfile: String,
+    /// Architectures packages may be installed for.
+    pub architectures: Vec<String>,
+    /// Default signature checking level.
+    pub siglevel: SigLevel,
+    /// Sync repositories, in priority order.
+    pub repos: Vec<RepoConfig>,
+    /// Packages never upgraded.
+    pub ignore_pkgs: Vec<String>,
+    /// Groups whose packages are never upgraded.
+    pub ignore_groups: Vec<String>,
+    /// Number of concurrent downloads.
+    pub parallel_downloads: u32,
+    /// The `pacman.conf` this was read from, if any.
+    pub conf_path: Option<PathBuf>,
+}
+
+impl Default for AlpmConfig {
+    fn default() -> Self {
+        Self {
+            root: DEFAULT_ROOT.to_string(),
+            dbpath: DEFAULT_DBPATH.to_string(),
+            cache_dirs: vec!["/var/cache/pacman/pkg".to_string()],
+            hook_dirs: vec![
+                SYSTEM_HOOKDIR.to_string(),
+                "/etc/pacman.d/hooks".to_string(),
+            ],
+            gpgdir: "/etc/pacman.d/gnupg".to_string(),
+            logfile: "/var/log/pacman.log".to_string(),
+            architectures: vec![std::env::consts::ARCH.to_string()],
+            siglevel: SigLevel::PACKAGE
+                | SigLevel::PACKAGE_OPTIONAL
+                | SigLevel::DATABASE
+                | SigLevel::DATABASE_OPTIONAL,
+            repos: Vec::new(),
+            ignore_pkgs: Vec::new(),
+            ignore_groups: Vec::new(),
+            parallel_downloads: 1,
+            conf_path: None,
+        }
+    }
+}
+
+/// Where the parser is in the file.
+enum Section {
+    Options,
+    Repo(usize),
+}
+
+/// A repo section with its servers and SigLevel still unresolved, since
+/// `Architecture` and the global `SigLevel` may come later in the file.
+struct PendingRepo {
+    name: String,
+    servers: Vec<String>,
+    siglevel: Vec<String>,
+}
+
+#[derive(Default)]
+struct Parser {
+    section: Option<Section>,
+    repos: Vec<PendingRepo>,
+    cache_dirs: Vec<String>,
+    hook_dirs: Vec<String>,
+    architectures: Vec<String>,
+    siglevel: Vec<String>,
+}
+
+impl AlpmConfig {
+    /// Reads a `pacman.conf`, following `Include` directives.
+    pub fn from_pacman_conf(path: impl AsRef<Path>) -> Result<Self> {
+        let mut config = Self {
+            conf_path: Some(path.as_ref().to_path_buf()),
+            ..Self::default()
+        };
+        let mut parser = Parser::default();
+        parser.read(&mut config, path.as_ref(), 0)?;
+
+        if !parser.cache_dirs.is_empty() {
+            config.cache_dirs = parser.cache_dirs;
+        }
+        if !parser.hook_dirs.is_empty() {
+            config.hook_dirs = std::iter::once(SYSTEM_HOOKDIR.to_string())
+                .chain(parser.hook_dirs)
+                .collect();
+        }
+        let architectures: Vec<String> = parser
+            .architectures
+            .into_iter()
+            .map(|arch| match arch.as_str() {
+                "auto" => std::env::consts::ARCH.to_string(),
+                _ => arch,
+            })
+            .collect();
+        if !architectures.is_empty() {
+            config.architectures = architectures;
+        }
+        config.siglevel = parse_siglevel(config.siglevel, &parser.siglevel)?;
+
+        let arch = config.arch().to_string();
+        config.repos = parser
+            .repos
+            .into_iter()
+            .map(|repo| {
+                Ok(RepoConfig {
+                    servers: repo
+                        .servers
+                        .iter()
+                        .map(|s| s.replace("$repo", &repo.name).replace("$arch", &arch))
+                        .collect(),
+                    siglevel: parse_siglevel(config.siglevel, &repo.siglevel)?,
+                    name: repo.name,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(config)
+    }
+
+    /// Reads the system `pacman.conf`. A missing or invalid file is an
+    /// error rather than a reason to fall back to the defaults.
+    pub fn system() -> Result<Self> {
+        Self::from_pacman_conf(PACMAN_CONF)
+    }
+
+    /// Adds `names` to `IgnorePkg` in the `pacman.conf` this was read from,
+    /// so upgrades skip them.
+    pub fn add_ignore_pkgs(&self, names: &[String]) -> Result<()> {
+        let path = self.conf_path.as_deref().ok_or_else(|| {
+            Error::ConfigError("the configuration was not read from a pacman.conf".to_string())
+        })?;
+        let contents = fs::read_to_string(path).map_err(|e| {
+            Error::ConfigError(format!("{} could not be read: {}", path.display(), e))
+        })?;
+        let updated = with_ignore_pkgs(&contents, names).ok_or_else(|| {
+            Error::ConfigError(format!("{}: no [options] section", path.display()))
+        })?;
+
+        if updated != contents {
+            replace_file(path, &updated).map_err(|e| {
+                Error::ConfigError(format!("{} could not be written: {}", path.display(), e))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The first configured architecture, used for `$arch` in mirror URLs.
+    pub fn arch(&self) -> &str {
+        self.architectures
+            .first()
+            .map(|a| a.as_str())
+            .unwrap_or(std::env::consts::ARCH)
+    }
+}
+
+impl Parser {
+    fn read(&mut self, config: &mut AlpmConfig, path: &Path, depth: usize) -> Result<()> {
+        // pacman caps nesting the same way to stop include loops.
+        if depth > 10 {
+            return Err(Error::ConfigError(format!(
+                "{}: too many levels of Include",
+                path.display()
+            )));
+        }
+
+        let contents = fs::read_to_string(path).map_err(|e| {
+            Error::ConfigError(format!("{} could not be read: {}", path.display(), e))
+        })?;
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let at = || format!("{}:{}", path.display(), number + 1);
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                self.section = Some(match name {
+                    "" => return Err(Error::ConfigError(format!("{}: empty section", at()))),
+                    "options" => Section::Options,
+                    _ => {
+                        self.repos.push(PendingRepo {
+                            name: name.to_string(),
+                            servers: Vec::new(),
+                            siglevel: Vec::new(),
+                        });
+                        Section::Repo(self.repos.len() - 1)
+                    }
+                });
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (line, ""),
+            };
+            let words = || value.split_whitespace().map(|w| w.to_string());
+
+            if key == "Include" {
+                for file in expand_include(value) {
+                    self.read(config, &file, depth + 1)?;
+                }
+                continue;
+            }
+
+            match self.section {
+                None => {
+                    return Err(Error::ConfigError(format!(
+                        "{}: '{}' is outside any section",
+                        at(),
+                        key
+                    )))
+                }
+                Some(Section::Options) => match key {
+                    "RootDir" => config.root = value.to_string(),
+                    "DBPath" => config.dbpath = value.to_string(),
+                    "GPGDir" => config.gpgdir = value.to_string(),
+                    "LogFile" => config.logfile = value.to_string(),
+                    "CacheDir" => self.cache_dirs.extend(words()),
+                    "HookDir" => self.hook_dirs.extend(words()),
+                    "Architecture" => self.architectures.extend(words()),
+                    "SigLevel" => self.siglevel.extend(words()),
+                    "IgnorePkg" => config.ignore_pkgs.extend(words()),
+                    "IgnoreGroup" => config.ignore_groups.extend(words()),
+                    "ParallelDownloads" => {
+                        config.parallel_downloads =
+                            value.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                                Error::ConfigError(format!(
+                                    "{}: invalid ParallelDownloads '{}'",
+                                    at(),
+                                    value
+                                ))
+                            })?;
+                    }
+                    // Options that only affect pacman's own behaviour.
+                    _ => {}
+                },
+                Some(Section::Repo(index)) => {
+                    let repo = &mut self.repos[index];
+                    match key {
+                        "Server" => repo.servers.push(value.to_string()),
+                        "SigLevel" => repo.siglevel.extend(words()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds an `IgnorePkg` line for the `names` not ignored yet right after the
+/// `[options]` header of `contents`, or returns `None` if there is none.
+/// Replaces the contents of `path` so that a crash or a full disk leaves
+/// either the old or the new file: writes a synced temporary file beside it
+/// with the same mode and owner, then renames it over the original.
+fn replace_file(path: &Path, contents: &str) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let metadata = fs::metadata(&path)?;
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{}.xpm-{}", name, std::process::id()));
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        std::os::unix::fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid()))?;
+        file.set_permissions(metadata.permissions())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)?;
+        File::open(dir)?.sync_all()
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn with_ignore_pkgs(contents: &str, names: &[String]) -> Option<String> {
+    let mut header = None;
+    let mut in_options = false;
+    let mut ignored: Vec<&str> = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_options = name == "options";
+            if in_options && header.is_none() {
+                header = Some(number);
+            }
+        } else if in_options {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "IgnorePkg" {
+                    ignored.extend(value.split_whitespace());
+                }
+            }
+        }
+    }
+
+    let header = header?;
+    let mut added: Vec<&str> = Vec::new();
+    for name in names {
+        if !ignored.contains(&name.as_str()) && !added.contains(&name.as_str()) {
+            added.push(name);
+        }
+    }
+    if added.is_empty() {
+        return Some(contents.to_string());
+    }
+
+    let line = format!("IgnorePkg = {}", added.join(" "));
+    let mut lines: Vec<&str> = contents.lines().collect();
+    lines.insert(header + 1, &line);
+    let mut updated = lines.join("\n");
+    if contents.ends_with('\n') {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+/// Resolves an `Include` value, which may be a glob on the file name.
+fn expand_include(pattern: &str) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return vec![path.to_path_buf()];
+    };
+    if !name.contains(['*', '?']) {
+        return vec![path.to_path_buf()];
+    }
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut matches: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|file| wildcard_match(name.as_bytes(), file.as_bytes()))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    matches.sort();
+    matches
+}
+
+/// Matches `*` and `?` wildcards.
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], text)
+                || (!text.is_empty() && wildcard_match(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => wildcard_match(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+/// Applies `SigLevel` values on top of `base`, the same way pacman does.
+fn parse_siglevel(base: SigLevel, values: &[String]) -> Result<SigLevel> {
+    let mut level = base;
+
+    for value in values {
+        let (package, database, option) = if let Some(option) = value.strip_prefix("Package") {
+            (true, false, option)
+        } else if let Some(option) = value.strip_prefix("Database") {
+            (false, true, option)
+        } else {
+            (true, true, value.as_str())
+        };
+
+        let mut apply = |pkg_flags: SigLevel, db_flags: SigLevel, set: bool| {
+            for (enabled, flags) in [(package, pkg_flags), (database, db_flags)] {
+                if enabled {
+                    level.set(flags, set);
+                }
+            }
+        };
+
+        match option {
+            "Never" => apply(
+                SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL,
+                SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL,
+                false,
+            ),
+            "Optional" => apply(
+                SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL,
+                SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL,
+                true,
+            ),
+            "Required" => {
+                apply(SigLevel::PACKAGE, SigLevel::DATABASE, true);
+                apply(
+                    SigLevel::PACKAGE_OPTIONAL,
+                    SigLevel::DATABASE_OPTIONAL,
+                    false,
+                );
+            }
+            "TrustedOnly" => apply(
+                SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK,
+                SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK,
+                false,
+            ),
+            "TrustAll" => apply(
+                SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK,
+                SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK,
+                true,
+            ),
+            _ => {
+                return Err(Error::ConfigError(format!(
+                    "invalid SigLevel value '{}'",
+                    value
+                )))
+            }
+        }
+    }
+
+    Ok(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pacman_conf() {
+        let dir = std::env::temp_dir().join(format!("xpm-pacman-conf-{}", std::process::id()));
+        fs::create_dir_all(dir.join("pacman.d")).unwrap();
+        fs::write(
+            dir.join("pacman.d/mirrorlist"),
+            "# comment\nServer = https://mirror.example/$repo/os/$arch\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("pacman.conf"),
+            format!(
+                "[options]\n\
+                 Architecture = aarch64\n\
+                 CacheDir = /tmp/cache\n\
+                 SigLevel = Required DatabaseOptional\n\
+                 IgnorePkg = linux linux-headers\n\
+                 IgnoreGroup = gnome\n\
+                 ParallelDownloads = 5\n\
+                 \n\
+                 [core]\n\
+                 Include = {dir}/pacman.d/mirror*\n\
+                 \n\
+                 [custom]\n\
+                 SigLevel = Optional TrustAll\n\
+                 Server = file:///srv/$repo\n",
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+
+        let config = AlpmConfig::from_pacman_conf(dir.join("pacman.conf")).unwrap();
+        assert_eq!(config.architectures, ["aarch64"]);
+        assert_eq!(config.cache_dirs, ["/tmp/cache"]);
+        assert_eq!(config.ignore_pkgs, ["linux", "linux-headers"]);
+        assert_eq!(config.ignore_groups, ["gnome"]);
+        assert_eq!(config.parallel_downloads, 5);
+        assert_eq!(
+            config.siglevel,
+            SigLevel::PACKAGE | SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL
+        );
+
+        assert_eq!(config.repos.len(), 2);
+        assert_eq!(config.repos[0].name, "core");
+        assert_eq!(
+            config.repos[0].servers,
+            ["https://mirror.example/core/os/aarch64"]
+        );
+        assert_eq!(config.repos[0].siglevel, config.siglevel);
+        assert_eq!(config.repos[1].servers, ["file:///srv/custom"]);
+        assert!(config.repos[1]
+            .siglevel
+            .contains(SigLevel::PACKAGE_OPTIONAL | SigLevel::PACKAGE_UNKNOWN_OK));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_with_ignore_pkgs() {
+        let conf = "# pacman.conf\n[options]\nIgnorePkg = linux\n\n[core]\nInclude = x\n";
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            with_ignore_pkgs(conf, &names(&["firefox", "linux", "firefox"])).unwrap(),
+            "# pacman.conf\n[options]\nIgnorePkg = firefox\nIgnorePkg = linux\n\n[core]\nInclude = x\n"
+        );
+        assert_eq!(with_ignore_pkgs(conf, &names(&["linux"])).unwrap(), conf);
+        assert_eq!(with_ignore_pkgs("[core]\n", &names(&["linux"])), None);
+    }
+
+    #[test]
+    fn test_add_ignore_pkgs_replaces_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("xpm-ignore-pkgs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pacman.conf");
+        fs::write(&path, "[options]\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let config = AlpmConfig::from_pacman_conf(&path).unwrap();
+        config.add_ignore_pkgs(&["linux".to_string()]).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[options]\nIgnorePkg = linux\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o640);
+        // Only the configuration is left, no temporary file.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! Dependency trees, reverse dependencies and install reasons.
+
+use crate::index::{IndexedPackage, PackageIndex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use xpm_core::{
+    dependency::Dependency,
+    error::{Error, Result},
+    package::{InstallReason, PackageStatus},
+};
+
+/// A dependency and the package satisfying it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyNode {
+    /// The dependency as declared; for the root, the package itself.
+    pub dependency: Dependency,
+    /// Package satisfying the dependency, or `None` if nothing does.
+    pub provider: Option<String>,
+    /// Whether the provider is installed.
+    pub installed: bool,
+    /// Whether this is an optional dependency.
+    pub optional: bool,
+    /// The provider's dependencies are shown elsewhere in the tree, either
+    /// above it (a cycle) or earlier.
+    pub repeated: bool,
+    /// The provider has dependencies below the depth limit.
+    pub truncated: bool,
+    /// Dependencies of the provider.
+    pub children: Vec<DependencyNode>,
+}
+
+/// Installed packages that depend on a package.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReverseDependencies {
+    /// Packages that require it.
+    pub required_by: Vec<String>,
+    /// Packages that optionally depend on it.
+    pub optional_for: Vec<String>,
+}
+
+/// Walks dependencies, expanding each provider only once.
+struct TreeBuilder<'a> {
+    index: &'a PackageIndex,
+    max_depth: usize,
+    optional: bool,
+    expanded: HashSet<String>,
+}
+
+impl TreeBuilder<'_> {
+    fn node(
+        &mut self,
+        dependency: Dependency,
+        provider: Option<&IndexedPackage>,
+        optional: bool,
+        depth: usize,
+    ) -> DependencyNode {
+        let mut node = DependencyNode {
+            dependency,
+            provider: provider.map(|pkg| pkg.name().to_string()),
+            installed: provider
+                .is_some_and(|pkg| pkg.info.package.status != PackageStatus::Available),
+            optional,
+            repeated: false,
+            truncated: false,
+            children: Vec::new(),
+        };
+        let Some(pkg) = provider else {
+            return node;
+        };
+
+        let deps: Vec<(&Dependency, bool)> = pkg
+            .info
+            .depends
+            .iter()
+            .map(|dep| (dep, false))
+            .chain(
+                pkg.info
+                    .optdepends
+                    .iter()
+                    .filter(|_| self.optional)
+                    .map(|dep| (dep, true)),
+            )
+            .collect();
+        if deps.is_empty() {
+            return node;
+        }
+        if depth >= self.max_depth {
+            node.truncated = true;
+            return node;
+        }
+        if !self.expanded.insert(pkg.name().to_string()) {
+            node.repeated = true;
+            return node;
+        }
+
+        node.children = deps
+            .into_iter()
+            .map(|(dep, optional)| {
+                let provider = self.index.resolve(dep);
+                self.node(dep.clone(), provider, optional, depth + 1)
+            })
+            .collect();
+        node
+    }
+}
+
+impl PackageIndex {
+    /// Builds the dependency tree of `name`, preferring the installed
+    /// package. Levels below `max_depth` are cut off, and optional
+    /// dependencies are only included when `optional` is set.
+    pub fn dependency_tree(
+        &self,
+        name: &str,
+        max_depth: usize,
+        optional: bool,
+    ) -> Result<DependencyNode> {
+        let root = self
+            .local(name)
+            .or_else(|| self.sync(name))
+            .ok_or_else(|| Error::PackageNotFound(name.to_string()))?;
+
+        let mut builder = TreeBuilder {
+            index: self,
+            max_depth,
+            optional,
+            expanded: HashSet::new(),
+        };
+        Ok(builder.node(Dependency::new(name), Some(root), false, 0))
+    }
+
+    /// Installed packages depending on `name`. Empty for packages that are
+    /// not installed.
+    pub fn reverse_dependencies(&self, name: &str) -> Result<ReverseDependencies> {
+        if let Some(pkg) = self.local(name) {
+            return Ok(ReverseDependencies {
+                required_by: pkg.required_by.clone(),
+                optional_for: pkg.optional_for.clone(),
+            });
+        }
+        match self.sync(name) {
+            Some(_) => Ok(ReverseDependencies::default()),
+            None => Err(Error::PackageNotFound(name.to_string())),
+        }
+    }
+
+    /// Explains why an installed package is present.
+    ///
+    /// Each chain starts at an explicitly installed package and follows
+    /// hard dependencies down to `name`, shortest chains first. An explicit
+    /// package yields just itself; an orphan yields no chains.
+    pub fn why_installed(&self, name: &str) -> Result<Vec<Vec<String>>> {
+        let target = self
+            .local(name)
+            .ok_or_else(|| Error::PackageNotFound(name.to_string()))?;
+        if target.info.reason == Some(InstallReason::Explicit) {
+            return Ok(vec![vec![name.to_string()]]);
+        }
+
+        // Breadth-first up the required-by edges, remembering for each
+        // package the dependency it was reached from.
+        let mut towards_target: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([target]);
+        let mut chains = Vec::new();
+
+        while let Some(pkg) = queue.pop_front() {
+            for dependent in &pkg.required_by {
+                if dependent == name || towards_target.contains_key(dependent.as_str()) {
+                    continue;
+                }
+                let Some(parent) = self.local(dependent) else {
+                    continue;
+                };
+                towards_target.insert(parent.name(), pkg.name());
+
+                if parent.info.reason == Some(InstallReason::Explicit) {
+                    let mut chain = vec![parent.name().to_string()];
+                    let mut current = parent.name();
+                    while let Some(next) = towards_target.get(current) {
+                        chain.push(next.to_string());
+                        current = next;
+                    }
+                    chains.push(chain);
+                } else {
+                    queue.push_back(parent);
+                }
+            }
+        }
+
+        Ok(chains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orphan::OrphanDetector;
+    use xpm_core::package::PackageInfo;
+
+    fn info(name: &str, depends: &[&str], provides: &[&str], status: PackageStatus) -> PackageInfo {
+        let mut info = PackageInfo::test(name, "1.0-1");
+        info.package.status = status;
+        info.package.repository = "core".into();
+        info.depends = depends.iter().map(|d| Dependency::parse(d)).collect();
+        info.provides = provides.iter().map(|p| Dependency::parse(p)).collect();
+        info
+    }
+
+    fn local(
+        name: &str,
+        depends: &[&str],
+        reason: InstallReason,
+        required_by: &[&str],
+    ) -> IndexedPackage {
+        let mut info = info(name, depends, &[], PackageStatus::Installed);
+        info.reason = Some(reason);
+        IndexedPackage::new(
+            info,
+            required_by.iter().map(|s| s.to_string()).collect(),
+            Vec::new(),
+        )
+    }
+
+    fn index() -> PackageIndex {
+        use InstallReason::{Dependency as Dep, Explicit};
+
+        PackageIndex::from_packages(
+            vec![
+                local("bash", &["readline", "glibc"], Explicit, &[]),
+                local("readline", &["ncurses", "glibc"], Dep, &["bash", "python"]),
+                local("ncurses", &["glibc"], Dep, &["readline"]),
+                local(
+                    "glibc",
+                    &["filesystem"],
+                    Dep,
+                    &["bash", "readline", "ncurses"],
+                ),
+                local("python", &["readline"], Explicit, &[]),
+            ],
+            vec![
+                IndexedPackage::new(
+                    info("app", &["sh", "libmissing"], &[], PackageStatus::Available),
+                    Vec::new(),
+                    Vec::new(),
+                ),
+                IndexedPackage::new(
+                    info("dash", &[], &["sh"], PackageStatus::Available),
+                    Vec::new(),
+                    Vec::new(),
+                ),
+            ],
+            &OrphanDetector::new(),
+        )
+    }
+
+    #[test]
+    fn test_dependency_tree() {
+        let index = index();
+
+        let tree = index.dependency_tree("bash", 10, false).unwrap();
+        assert_eq!(tree.children.len(), 2);
+        let readline = &tree.children[0];
+        assert_eq!(readline.provider.as_deref(), Some("readline"));
+        assert!(readline.installed);
+        // glibc is expanded under readline > ncurses first, then repeated.
+        assert!(tree.children[1].repeated);
+        assert_eq!(readline.children[1].provider.as_deref(), Some("glibc"));
+        assert!(readline.children[1].repeated);
+        // Nothing provides filesystem.
+        let glibc = &readline.children[0].children[0];
+        assert_eq!(glibc.children[0].provider, None);
+
+        let tree = index.dependency_tree("bash", 1, false).unwrap();
+        assert!(tree.children[0].truncated);
+        assert!(tree.children[0].children.is_empty());
+
+        let tree = index.dependency_tree("app", 10, false).unwrap();
+        assert_eq!(tree.children[0].provider.as_deref(), Some("dash"));
+        assert!(!tree.children[0].installed);
+        assert_eq!(tree.children[1].provider, None);
+    }
+
+    #[test]
+    fn test_why_installed() {
+        let index = index();
+
+        assert_eq!(index.why_installed("bash").unwrap(), [["bash"]]);
+        assert_eq!(
+            index.why_installed("ncurses").unwrap(),
+            [
+                vec!["bash", "readline", "ncurses"],
+                vec!["python", "readline", "ncurses"],
+            ]
+        );
+        assert_eq!(index.why_installed("glibc").unwrap()[0], ["bash", "glibc"]);
+        assert!(index.why_installed("app").is_err());
+        assert_eq!(
+            index.reverse_dependencies("readline").unwrap().required_by,
+            ["bash", "python"]
+        );
+    }
+}
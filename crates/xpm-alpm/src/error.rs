@@ -0,0 +1,201 @@
+//! Mapping libalpm errors onto [`xpm_core::Error`].
+
+use alpm::{
+    Alpm, CommitData, CommitError, FileConflictType, PrepareData, PrepareError, SigLevel, SigList,
+    SigStatus,
+};
+use std::path::Path;
+use xpm_core::error::{Error, FileConflict, PackageConflict, UnsatisfiedDependency};
+
+/// Converts a bare libalpm error code. Use [`from_handle`] for errors that
+/// can name the lock file or the keys of failed signatures.
+pub fn from_alpm(err: alpm::Error) -> Error {
+    use alpm::Error as E;
+
+    match err {
+        E::DiskSpace => Error::DiskFull { required: None },
+        E::BadPerms => Error::PermissionDenied(err.to_string()),
+        E::PkgNotFound | E::DbNotFound => {
+            Error::PackageNotFound(err.to_string())
+        }
+        E::TransAbort => Error::Cancelled,
+        E::PkgInvalidSig | E::PkgMissingSig | E::DbInvalidSig | E::SigMissing | E::SigInvalid => {
+            Error::SignatureError {
+                package: None,
+                key_id: None,
+                reason: err.to_string(),
+            }
+        }
+        E::Retrieve | E::Libcurl | E::ExternalDownload | E::ServerNone | E::ServerBadUrl => {
+            Error::NetworkError(err.to_string())
+        }
+        E::DbOpen | E::DbCreate | E::DbInvalid | E::DbVersion | E::DbWrite | E::DbRemove => {
+            Error::DatabaseError(err.to_string())
+        }
+        _ => Error::TransactionError(err.to_string()),
+    }
+}
+
+/// Converts an error from a call on `handle`, filling in the lock path and
+/// the keys of sync databases whose signatures do not verify.
+pub fn from_handle(handle: &Alpm, err: alpm::Error) -> Error {
+    use alpm::Error as E;
+
+    match err {
+        E::HandleLock => Error::DatabaseLocked {
+            path: handle.lockfile().to_string(),
+        },
+        E::DbInvalidSig | E::SigInvalid => {
+            let mut packages = Vec::new();
+            let mut keys = Vec::new();
+            for db in handle.syncdbs() {
+                let mut siglist = SigList::new();
+                if db.check_signature(&mut siglist).is_err() || !failing_keys(&siglist, &mut keys) {
+                    continue;
+                }
+                packages.push(db.name().to_string());
+            }
+            Error::SignatureError {
+                package: (!packages.is_empty()).then(|| packages.join(", ")),
+                key_id: (!keys.is_empty()).then(|| keys.join(", ")),
+                reason: err.to_string(),
+            }
+        }
+        _ => from_alpm(err),
+    }
+}
+
+/// Converts a failed `trans_prepare`, keeping the unresolved dependencies
+/// or conflicts.
+pub fn from_prepare(err: PrepareError<'_>) -> Error {
+    let error = err.error();
+
+    match err.data() {
+        Some(PrepareData::UnsatisfiedDeps(missing)) => Error::UnsatisfiedDependencies(
+            missing
+                .iter()
+                .map(|m| UnsatisfiedDependency {
+                    package: m.target().to_string(),
+                    dependency: m.depend().to_string(),
+                    causing: m.causing_pkg().map(|s| s.to_string()),
+                })
+                .collect(),
+        ),
+        Some(PrepareData::ConflictingDeps(conflicts)) => Error::PackageConflicts(
+            conflicts
+                .iter()
+                .map(|c| PackageConflict {
+                    package: c.package1().name().to_string(),
+                    conflicts_with: c.package2().name().to_string(),
+                    reason: Some(c.reason().to_string()),
+                })
+                .collect(),
+        ),
+        Some(PrepareData::PkgInvalidArch(pkgs)) => Error::TransactionError(format!(
+            "invalid architecture: {}",
+            pkgs.iter()
+                .map(|p| p.name().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        _ => from_alpm(error),
+    }
+}
+
+/// Converts a failed `trans_commit` on `handle`, keeping conflicting files
+/// and invalid packages.
+pub fn from_commit(handle: &Alpm, err: CommitError) -> Error {
+    let error = err.error();
+
+    match err.data() {
+        Some(CommitData::FileConflict(conflicts)) => Error::FileConflicts(
+            conflicts
+                .iter()
+                .map(|c| {
+                    // The list holds file conflicts despite its item type.
+                    let c = unsafe { &*(c as *const alpm::Conflict as *const alpm::FileConflict) };
+                    FileConflict {
+                        package: c.target().to_string(),
+                        path: c.file().to_string(),
+                        // libalpm does not expose the other package of a
+                        // conflict between two targets.
+                        owner: match c.conflict_type() {
+                            FileConflictType::Target => None,
+                            FileConflictType::Filesystem => installed_owner(handle, c.file()),
+                        },
+                    }
+                })
+                .collect(),
+        ),
+        Some(CommitData::PkgInvalid(files)) => {
+            let files: Vec<&str> = files.iter().collect();
+            match error {
+                alpm::Error::PkgInvalidSig | alpm::Error::PkgMissingSig => {
+                    let mut keys = Vec::new();
+                    for file in &files {
+                        package_keys(handle, file, &mut keys);
+                    }
+                    Error::SignatureError {
+                        package: Some(files.join(", ")),
+                        key_id: (!keys.is_empty()).then(|| keys.join(", ")),
+                        reason: error.to_string(),
+                    }
+                }
+                _ => Error::TransactionError(format!("{}: {}", error, files.join(", "))),
+            }
+        }
+        None => from_handle(handle, error),
+    }
+}
+
+/// The installed package owning the absolute path `file`, if any.
+fn installed_owner(handle: &Alpm, file: &str) -> Option<String> {
+    let root = handle.root().trim_end_matches('/');
+    let entry = file.strip_prefix(root)?.trim_start_matches('/');
+    handle
+        .localdb()
+        .pkgs()
+        .iter()
+        .find(|pkg| pkg.files().contains(entry).is_some())
+        .map(|pkg| pkg.name().to_string())
+}
+
+/// Adds the keys of the signatures of the package file `file` that do not
+/// verify to `keys`. Bare file names are looked up in the cache
+/// directories.
+fn package_keys(handle: &Alpm, file: &str, keys: &mut Vec<String>) {
+    let path = if Path::new(file).is_absolute() {
+        Some(file.to_string())
+    } else {
+        handle
+            .cachedirs()
+            .iter()
+            .map(|dir| Path::new(dir).join(file))
+            .find(|path| path.exists())
+            .map(|path| path.to_string_lossy().into_owned())
+    };
+    let Some(pkg) = path.and_then(|path| handle.pkg_load(path, false, SigLevel::empty()).ok()) else {
+        return;
+    };
+    let mut siglist = SigList::new();
+    if pkg.check_signature(&mut siglist).is_ok() {
+        failing_keys(&siglist, keys);
+    }
+}
+
+/// Adds the fingerprints of the signatures in `siglist` that do not verify
+/// to `keys`; returns whether there were any.
+fn failing_keys(siglist: &SigList, keys: &mut Vec<String>) -> bool {
+    let mut found = false;
+    for result in siglist.results() {
+        if result.status() == SigStatus::Valid {
+            continue;
+        }
+        let fingerprint = result.key().fingerprint().to_string();
+        if !fingerprint.is_empty() && !keys.contains(&fingerprint) {
+            keys.push(fingerprint);
+        }
+        found = true;
+    }
+    found
+}
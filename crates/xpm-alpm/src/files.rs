@@ -0,0 +1,205 @@
+//! Package file lists, file ownership and file database search.
+
+use crate::backend::register_syncdbs;
+use crate::config::AlpmConfig;
+use crate::index::PackageIndex;
+use alpm::Alpm;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
+use xpm_core::{
+    error::{Error, Result},
+    package::Version,
+};
+
+/// Directory holding the desktop entries of installed applications.
+pub(crate) const APPLICATIONS_DIR: &str = "usr/share/applications/";
+
+/// A file or directory installed by a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageFile {
+    /// Absolute path; directories end with `/`.
+    pub path: String,
+    /// Whether pacman keeps local changes to the file (a `backup` entry).
+    pub backup: bool,
+}
+
+/// A file found in the sync file databases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatch {
+    /// Package shipping the file.
+    pub package: String,
+    /// Repository of the package.
+    pub repository: String,
+    /// Version of the package.
+    pub version: Version,
+    /// Absolute path of the file.
+    pub path: String,
+}
+
+/// Files of the installed package `name`, like `pacman -Ql`.
+pub fn package_files(handle: &Alpm, name: &str) -> Result<Vec<PackageFile>> {
+    let pkg = handle
+        .localdb()
+        .pkg(name)
+        .map_err(|_| Error::PackageNotFound(name.to_string()))?;
+    let backup: HashSet<&str> = pkg.backup().iter().map(|b| b.name()).collect();
+
+    Ok(pkg
+        .files()
+        .files()
+        .iter()
+        .map(|file| {
+            let name = entry_name(file);
+            PackageFile {
+                path: absolute_path(handle.root(), &name),
+                backup: backup.contains(name.as_ref()),
+            }
+        })
+        .collect())
+}
+
+/// Installed packages owning `path` under `root`, like `pacman -Qo`.
+/// Directories can be owned by several packages.
+pub fn file_owners(index: &PackageIndex, root: &str, path: &str) -> Result<Vec<String>> {
+    let relative = relative_path(root, path)
+        .ok_or_else(|| Error::Other(format!("{} is not under {}", path, root)))?;
+    Ok(index.owners(&relative).map(str::to_string).collect())
+}
+
+/// Sync packages shipping a file matching `query`, like `pacman -F`.
+///
+/// Needs the `.files` databases from `pacman -Fy`; repositories without
+/// one are skipped.
+pub fn search_files(config: &AlpmConfig, query: &str) -> Result<Vec<FileMatch>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let handle = open_files_handle(config)?;
+    let mut matches = Vec::new();
+    for db in handle.syncdbs() {
+        for pkg in db.pkgs() {
+            for file in pkg.files().files() {
+                let name = entry_name(file);
+                if file_matches(query, &name) {
+                    matches.push(FileMatch {
+                        package: pkg.name().to_string(),
+                        repository: db.name().to_string(),
+                        version: Version::new(pkg.version().as_str()),
+                        path: absolute_path(handle.root(), &name),
+                    });
+                }
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Opens the sync `.files` databases, which list the files of every sync
+/// package. Repositories without one are skipped; none at all is an error.
+pub(crate) fn open_files_handle(config: &AlpmConfig) -> Result<Alpm> {
+    let mut files_config = config.clone();
+    files_config.repos.retain(|repo| {
+        Path::new(&config.dbpath)
+            .join("sync")
+            .join(format!("{}.files", repo.name))
+            .exists()
+    });
+    if files_config.repos.is_empty() {
+        return Err(Error::DatabaseError(
+            "No file databases found; sync them with pacman -Fy".to_string(),
+        ));
+    }
+
+    let mut handle = Alpm::new(config.root.clone(), config.dbpath.clone())
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    handle.set_dbext(".files");
+    register_syncdbs(&mut handle, &files_config);
+    Ok(handle)
+}
+
+/// Whether the file list entry `entry` matches `query`: queries containing
+/// a `/` match the whole path, anything else the name of a file.
+fn file_matches(query: &str, entry: &str) -> bool {
+    if query.contains('/') {
+        entry == query.trim_start_matches('/')
+    } else {
+        !entry.ends_with('/') && entry.rsplit('/').next() == Some(query)
+    }
+}
+
+/// `path` relative to `root`, without leading or trailing slashes.
+fn relative_path(root: &str, path: &str) -> Option<String> {
+    let relative = path.strip_prefix(root.trim_end_matches('/'))?;
+    if !relative.starts_with('/') {
+        return None;
+    }
+    let relative = relative.trim_matches('/');
+    (!relative.is_empty()).then(|| relative.to_string())
+}
+
+/// The path of a file list entry. libalpm keeps raw bytes; paths that are
+/// not UTF-8 are converted lossily.
+pub(crate) fn entry_name<'a>(file: &alpm::File<'a>) -> Cow<'a, str> {
+    String::from_utf8_lossy(file.name())
+}
+
+/// Joins a file list entry onto `root`.
+pub(crate) fn absolute_path(root: &str, entry: &str) -> String {
+    format!("{}/{}", root.trim_end_matches('/'), entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orphan::OrphanDetector;
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(
+            relative_path("/", "/usr/bin/bash").as_deref(),
+            Some("usr/bin/bash")
+        );
+        assert_eq!(relative_path("/mnt/", "/mnt/etc/").as_deref(), Some("etc"));
+        assert_eq!(relative_path("/mnt", "/mntx/etc"), None);
+        assert_eq!(relative_path("/", "/"), None);
+        assert_eq!(absolute_path("/", "usr/bin/"), "/usr/bin/");
+
+        assert!(file_matches("bash", "usr/bin/bash"));
+        assert!(!file_matches("bin", "usr/bin/"));
+        assert!(!file_matches("bash", "usr/share/bash-completion/"));
+        assert!(file_matches("/usr/bin/bash", "usr/bin/bash"));
+        assert!(!file_matches("/bin/bash", "usr/bin/bash"));
+    }
+
+    #[test]
+    fn test_file_owners() {
+        use crate::index::IndexedPackage;
+        use xpm_core::package::PackageInfo;
+
+        let package = |name: &str, files: &[&str]| {
+            let info = PackageInfo::test(name, "1.0-1");
+            let mut pkg = IndexedPackage::new(info, Vec::new(), Vec::new());
+            pkg.files = files.iter().map(|f| f.to_string()).collect();
+            pkg
+        };
+        let index = PackageIndex::from_packages(
+            vec![
+                package("bash", &["usr/", "usr/bin/", "usr/bin/bash"]),
+                package("zsh", &["usr/", "usr/bin/", "usr/bin/zsh"]),
+            ],
+            Vec::new(),
+            &OrphanDetector::new(),
+        );
+
+        assert_eq!(file_owners(&index, "/", "/usr/bin/bash").unwrap(), ["bash"]);
+        assert_eq!(
+            file_owners(&index, "/", "/usr/bin/").unwrap(),
+            ["bash", "zsh"]
+        );
+        assert!(file_owners(&index, "/", "/etc/passwd").unwrap().is_empty());
+        assert!(file_owners(&index, "/mnt", "/usr/bin/bash").is_err());
+    }
+}
@@ -0,0 +1,509 @@
+//! ALPM hooks (`alpm-hooks(5)`): parsing `.hook` files and predicting
+//! which ones a transaction will run.
+
+use crate::config::AlpmConfig;
+use crate::files::entry_name;
+use alpm::Alpm;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+use xpm_core::{
+    error::{Error, Result},
+    event::PackageAction,
+    plan::{PlannedHook, TransactionPlan},
+};
+
+/// Whether a trigger matches package names or file paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerKind {
+    Package,
+    Path,
+}
+
+/// What happens to a package or file for a trigger to fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerOperation {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+/// A `[Trigger]` section; the hook runs if any of its triggers match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookTrigger {
+    pub kind: TriggerKind,
+    pub operations: Vec<TriggerOperation>,
+    /// Globs; a leading `!` excludes, and the last matching one decides.
+    pub targets: Vec<String>,
+}
+
+/// A parsed `.hook` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    /// File name without `.hook`.
+    pub name: String,
+    pub triggers: Vec<HookTrigger>,
+    pub description: Option<String>,
+    /// Runs before the packages are changed rather than after.
+    pub pre_transaction: bool,
+    pub exec: String,
+    pub depends: Vec<String>,
+    /// Only honoured for pre-transaction hooks.
+    pub abort_on_fail: bool,
+    /// The matched targets are passed to `exec` on stdin.
+    pub needs_targets: bool,
+}
+
+impl Hook {
+    /// Whether any trigger matches what `touches` describes.
+    pub fn triggered_by(&self, touches: &TransactionTouches) -> bool {
+        self.triggers.iter().any(|trigger| {
+            let candidates = match trigger.kind {
+                TriggerKind::Package => &touches.packages,
+                TriggerKind::Path => &touches.paths,
+            };
+            candidates.iter().any(|(name, operation)| {
+                trigger.operations.contains(operation) && matches_targets(&trigger.targets, name)
+            })
+        })
+    }
+
+    /// The hook as listed in a transaction plan.
+    pub fn to_planned(&self) -> PlannedHook {
+        PlannedHook {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            pre_transaction: self.pre_transaction,
+            abort_on_fail: self.abort_on_fail && self.pre_transaction,
+        }
+    }
+}
+
+/// Packages and files a transaction changes, as hooks see them.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionTouches {
+    pub packages: Vec<(String, TriggerOperation)>,
+    /// Paths relative to the root, as in package file lists.
+    pub paths: Vec<(String, TriggerOperation)>,
+}
+
+impl TransactionTouches {
+    /// Records a package and its files. On upgrades, files only the new
+    /// version has count as installed and files only the old one has as
+    /// removed. Without the new file list, the old files stand in for it.
+    pub fn add_package(
+        &mut self,
+        name: &str,
+        operation: TriggerOperation,
+        old_files: &[String],
+        new_files: Option<&[String]>,
+    ) {
+        self.packages.push((name.to_string(), operation));
+        let paths = &mut self.paths;
+        match (operation, new_files) {
+            (TriggerOperation::Install, new) => paths.extend(
+                new.unwrap_or_default()
+                    .iter()
+                    .map(|file| (file.clone(), TriggerOperation::Install)),
+            ),
+            (TriggerOperation::Remove, _) => paths.extend(
+                old_files
+                    .iter()
+                    .map(|file| (file.clone(), TriggerOperation::Remove)),
+            ),
+            (TriggerOperation::Upgrade, None) => paths.extend(
+                old_files
+                    .iter()
+                    .map(|file| (file.clone(), TriggerOperation::Upgrade)),
+            ),
+            (TriggerOperation::Upgrade, Some(new)) => {
+                let old: HashSet<&String> = old_files.iter().collect();
+                let kept: HashSet<&String> = new.iter().collect();
+                paths.extend(new.iter().map(|file| {
+                    let operation = if old.contains(file) {
+                        TriggerOperation::Upgrade
+                    } else {
+                        TriggerOperation::Install
+                    };
+                    (file.clone(), operation)
+                }));
+                paths.extend(
+                    old_files
+                        .iter()
+                        .filter(|file| !kept.contains(file))
+                        .map(|file| (file.clone(), TriggerOperation::Remove)),
+                );
+            }
+        }
+    }
+}
+
+/// Parses the contents of a `.hook` file.
+pub fn parse_hook(name: &str, text: &str) -> Result<Hook> {
+    let invalid = |reason: String| Error::ConfigError(format!("hook {}: {}", name, reason));
+    let mut hook = Hook {
+        name: name.to_string(),
+        triggers: Vec::new(),
+        description: None,
+        pre_transaction: false,
+        exec: String::new(),
+        depends: Vec::new(),
+        abort_on_fail: false,
+        needs_targets: false,
+    };
+    let mut kinds: Vec<Option<TriggerKind>> = Vec::new();
+    let mut when = None;
+    let mut in_action = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_action = match section {
+                "Trigger" => {
+                    hook.triggers.push(HookTrigger {
+                        kind: TriggerKind::Package,
+                        operations: Vec::new(),
+                        targets: Vec::new(),
+                    });
+                    kinds.push(None);
+                    false
+                }
+                "Action" => true,
+                other => return Err(invalid(format!("unknown section [{}]", other))),
+            };
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, ""),
+        };
+        let unknown = || invalid(format!("line {}: invalid value {}", number + 1, value));
+        if in_action {
+            match key {
+                "Description" => hook.description = Some(value.to_string()),
+                "When" => {
+                    when = Some(match value {
+                        "PreTransaction" => true,
+                        "PostTransaction" => false,
+                        _ => return Err(unknown()),
+                    })
+                }
+                "Exec" => hook.exec = value.to_string(),
+                "Depends" => hook.depends.push(value.to_string()),
+                "AbortOnFail" => hook.abort_on_fail = true,
+                "NeedsTargets" => hook.needs_targets = true,
+                _ => warn!("hook {}: unknown option {}", name, key),
+            }
+            continue;
+        }
+
+        let (Some(trigger), Some(kind)) = (hook.triggers.last_mut(), kinds.last_mut()) else {
+            return Err(invalid(format!(
+                "line {}: option outside a section",
+                number + 1
+            )));
+        };
+        match key {
+            // `File` is the deprecated spelling of `Path`.
+            "Type" => {
+                *kind = Some(match value {
+                    "Package" => TriggerKind::Package,
+                    "Path" | "File" => TriggerKind::Path,
+                    _ => return Err(unknown()),
+                })
+            }
+            "Operation" => trigger.operations.push(match value {
+                "Install" => TriggerOperation::Install,
+                "Upgrade" => TriggerOperation::Upgrade,
+                "Remove" => TriggerOperation::Remove,
+                _ => return Err(unknown()),
+            }),
+            "Target" => trigger.targets.push(value.to_string()),
+            _ => warn!("hook {}: unknown option {}", name, key),
+        }
+    }
+
+    if hook.triggers.is_empty() {
+        return Err(invalid("no [Trigger] section".to_string()));
+    }
+    for (trigger, kind) in hook.triggers.iter_mut().zip(kinds) {
+        trigger.kind = kind.ok_or_else(|| invalid("trigger without Type".to_string()))?;
+        if trigger.operations.is_empty() || trigger.targets.is_empty() {
+            return Err(invalid("trigger without Operation or Target".to_string()));
+        }
+    }
+    hook.pre_transaction = when.ok_or_else(|| invalid("missing When".to_string()))?;
+    if hook.exec.is_empty() {
+        return Err(invalid("missing Exec".to_string()));
+    }
+    Ok(hook)
+}
+
+/// Loads the hooks in `dirs`, sorted by name. As with pacman, a hook in a
+/// later directory overrides one of the same name in an earlier one, and a
+/// symlink to `/dev/null` disables it. Hooks that fail to parse are
+/// skipped.
+pub fn load_hooks(dirs: &[String]) -> Vec<Hook> {
+    let mut seen = HashSet::new();
+    let mut hooks = Vec::new();
+    for dir in dirs.iter().rev() {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(name) = file_name.strip_suffix(".hook") else {
+                continue;
+            };
+            if !seen.insert(name.to_string()) {
+                continue;
+            }
+            let path = entry.path();
+            if fs::canonicalize(&path).is_ok_and(|target| target == Path::new("/dev/null")) {
+                continue;
+            }
+            match fs::read_to_string(&path).map_err(|e| Error::IoError(e.to_string())) {
+                Ok(text) => match parse_hook(name, &text) {
+                    Ok(hook) => hooks.push(hook),
+                    Err(e) => warn!("Skipping {}: {}", path.display(), e),
+                },
+                Err(e) => warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+    }
+    hooks.sort_by(|a, b| a.name.cmp(&b.name));
+    hooks
+}
+
+/// Whether `name` matches `targets`: the last glob matching it decides, and
+/// `!` globs exclude.
+fn matches_targets(targets: &[String], name: &str) -> bool {
+    targets
+        .iter()
+        .rev()
+        .find_map(|target| {
+            let (negated, glob) = match target.strip_prefix('!') {
+                Some(glob) => (true, glob),
+                None => (false, target.as_str()),
+            };
+            fnmatch(glob.as_bytes(), name.as_bytes()).then_some(!negated)
+        })
+        .unwrap_or(false)
+}
+
+/// `fnmatch(3)` without flags: `*` and `?` also match `/`.
+fn fnmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| fnmatch(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && fnmatch(rest, &text[1..]),
+        Some((b'[', rest)) => match (text.split_first(), bracket(rest, text.first().copied())) {
+            (Some((_, tail)), Some((matched, after))) => matched && fnmatch(after, tail),
+            // An unclosed bracket is a literal `[`.
+            (Some((b'[', tail)), None) => fnmatch(rest, tail),
+            _ => false,
+        },
+        Some((b'\\', [escaped, rest @ ..])) => {
+            text.first() == Some(escaped) && fnmatch(rest, &text[1..])
+        }
+        Some((p, rest)) => text.first() == Some(p) && fnmatch(rest, &text[1..]),
+    }
+}
+
+/// Matches `c` against the bracket expression starting `pattern` (just
+/// after the `[`). Returns whether it matched and the pattern after the
+/// closing `]`, or `None` if the bracket is not closed.
+fn bracket(pattern: &[u8], c: Option<u8>) -> Option<(bool, &[u8])> {
+    let c = c?;
+    let (negated, mut i) = match pattern.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+    let start = i;
+    let mut matched = false;
+    loop {
+        let p = *pattern.get(i)?;
+        // A `]` right after the opening is a literal.
+        if p == b']' && i > start {
+            return Some((matched != negated, &pattern[i + 1..]));
+        }
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some(b'-'), Some(&end)) if end != b']' => {
+                matched |= (p..=end).contains(&c);
+                i += 3;
+            }
+            _ => {
+                matched |= p == c;
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Hooks from `config.hook_dirs` that `plan` would run, in order. New file
+/// lists come from `files`, a handle on the `.files` databases; without it, upgrades are matched against the
+/// installed files and path triggers cannot see newly installed packages.
+pub fn planned_hooks(
+    handle: &Alpm,
+    files: Option<&Alpm>,
+    config: &AlpmConfig,
+    plan: &TransactionPlan,
+) -> Vec<PlannedHook> {
+    let hooks = load_hooks(&config.hook_dirs);
+    if hooks.is_empty() || plan.is_empty() {
+        return Vec::new();
+    }
+
+    let file_names = |pkg: &alpm::Package| -> Vec<String> {
+        pkg.files()
+            .files()
+            .iter()
+            .map(|file| entry_name(file).into_owned())
+            .collect()
+    };
+
+    let mut touches = TransactionTouches::default();
+    for planned in &plan.packages {
+        let old = handle
+            .localdb()
+            .pkg(planned.name.as_str())
+            .map(file_names)
+            .unwrap_or_default();
+        let new = files.and_then(|files| {
+            let db = files
+                .syncdbs()
+                .iter()
+                .find(|db| db.name() == planned.repository)?;
+            db.pkg(planned.name.as_str()).ok().map(file_names)
+        });
+        let operation = match planned.action {
+            PackageAction::Install => TriggerOperation::Install,
+            PackageAction::Remove => TriggerOperation::Remove,
+            _ => TriggerOperation::Upgrade,
+        };
+        touches.add_package(&planned.name, operation, &old, new.as_deref());
+    }
+
+    hooks
+        .iter()
+        .filter(|hook| hook.triggered_by(&touches))
+        .map(Hook::to_planned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MKINITCPIO: &str = "[Trigger]
+Type = Path
+Operation = Install
+Operation = Upgrade
+Target = usr/lib/modules/*/vmlinux
+Target = usr/lib/initcpio/*
+Target = !usr/lib/initcpio/ignored
+
+[Trigger]
+Type = Package
+Operation = Install
+Operation = Upgrade
+Target = mkinitcpio
+
+[Action]
+Description = Building initramfs images...
+When = PostTransaction
+Exec = /usr/share/libalpm/scripts/mkinitcpio install
+NeedsTargets
+";
+
+    #[test]
+    fn test_parse_and_match() {
+        let hook = parse_hook("90-mkinitcpio-install", MKINITCPIO).unwrap();
+        assert_eq!(hook.triggers.len(), 2);
+        assert_eq!(hook.triggers[0].kind, TriggerKind::Path);
+        assert!(hook.needs_targets);
+        assert!(!hook.pre_transaction);
+        assert!(parse_hook(
+            "broken",
+            "[Action]\nWhen = PostTransaction\nExec = /bin/true\n"
+        )
+        .is_err());
+
+        let files = |list: &[&str]| list.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        let mut kernel = TransactionTouches::default();
+        kernel.add_package(
+            "linux",
+            TriggerOperation::Upgrade,
+            &files(&["usr/lib/modules/6.1/vmlinux"]),
+            None,
+        );
+        assert!(hook.triggered_by(&kernel));
+
+        let mut ignored = TransactionTouches::default();
+        ignored.add_package(
+            "other",
+            TriggerOperation::Upgrade,
+            &files(&["usr/lib/initcpio/ignored"]),
+            Some(&files(&["usr/lib/initcpio/ignored"])),
+        );
+        assert!(!hook.triggered_by(&ignored));
+
+        let mut removal = TransactionTouches::default();
+        removal.add_package("mkinitcpio", TriggerOperation::Remove, &[], None);
+        assert!(!hook.triggered_by(&removal));
+    }
+
+    #[test]
+    fn test_fnmatch() {
+        assert!(fnmatch(
+            b"usr/lib/modules/*/vmlinux",
+            b"usr/lib/modules/6.1/extra/vmlinux"
+        ));
+        assert!(fnmatch(b"boot/vmlinuz-?", b"boot/vmlinuz-x"));
+        assert!(fnmatch(b"lib[0-9]*.so", b"lib3foo.so"));
+        assert!(!fnmatch(b"lib[!0-9]*.so", b"lib3foo.so"));
+        assert!(fnmatch(b"a[]]b", b"a]b"));
+        assert!(fnmatch(b"a[b", b"a[b"));
+        assert!(!fnmatch(b"*.hook", b"x.hooks"));
+    }
+
+    #[test]
+    fn test_load_hooks() {
+        let dir = std::env::temp_dir().join(format!("xpm-hooks-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (system, user) = (dir.join("system"), dir.join("user"));
+        fs::create_dir_all(&system).unwrap();
+        fs::create_dir_all(&user).unwrap();
+
+        let hook = |description: &str| {
+            format!(
+                "[Trigger]\nType = Package\nOperation = Install\nTarget = *\n\n\
+                 [Action]\nDescription = {}\nWhen = PreTransaction\nExec = /bin/true\nAbortOnFail\n",
+                description
+            )
+        };
+        fs::write(system.join("b.hook"), hook("system b")).unwrap();
+        fs::write(system.join("a.hook"), hook("system a")).unwrap();
+        fs::write(system.join("masked.hook"), hook("masked")).unwrap();
+        fs::write(system.join("broken.hook"), "[Trigger]\n").unwrap();
+        fs::write(system.join("notes.txt"), "").unwrap();
+        fs::write(user.join("b.hook"), hook("user b")).unwrap();
+        std::os::unix::fs::symlink("/dev/null", user.join("masked.hook")).unwrap();
+
+        let dirs = [system, user].map(|d| d.to_string_lossy().into_owned());
+        let hooks = load_hooks(&dirs);
+        let described: Vec<_> = hooks
+            .iter()
+            .map(|h| h.description.as_deref().unwrap())
+            .collect();
+        assert_eq!(described, ["system a", "user b"]);
+        assert!(hooks[0].to_planned().abort_on_fail);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,572 @@
+//! In-memory index of the local and sync databases.
+//!
+//! Opening a handle and registering the sync databases costs far more than
+//! a query does, so a worker thread keeps a snapshot of every package and
+//! rebuilds it when the files under the database path change. Queries run
+//! against the snapshot without touching libalpm.
+
+use crate::backend::register_syncdbs;
+use crate::config::AlpmConfig;
+use crate::files::{absolute_path, entry_name, APPLICATIONS_DIR};
+use crate::orphan::OrphanDetector;
+use alpm::{Alpm, PackageReason};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+use xpm_core::{
+    dependency::{Dependency, DependencyOp},
+    error::{Error, Result},
+    package::{
+        InstallReason, Package, PackageBackend, PackageInfo, PackageStatus, SearchResult, Version,
+    },
+    query::{SearchQuery, Searchable},
+};
+
+/// How often the worker checks the databases for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A package copied out of libalpm.
+#[derive(Debug, Clone)]
+pub struct IndexedPackage {
+    /// Full package details; the status of local packages is `Installed` or
+    /// `Orphan`.
+    pub info: PackageInfo,
+    /// Installed packages that depend on this one. Empty for sync packages.
+    pub required_by: Vec<String>,
+    /// Installed packages that optionally depend on this one. Empty for sync
+    /// packages.
+    pub optional_for: Vec<String>,
+    /// Absolute paths of the desktop entries the package installs. Empty for
+    /// sync packages.
+    pub desktop_entries: Vec<String>,
+    /// File list entries relative to the root, sorted as libalpm keeps
+    /// them. Empty for sync packages.
+    pub files: Vec<String>,
+}
+
+impl IndexedPackage {
+    pub(crate) fn new(
+        info: PackageInfo,
+        required_by: Vec<String>,
+        optional_for: Vec<String>,
+    ) -> Self {
+        Self {
+            info,
+            required_by,
+            optional_for,
+            desktop_entries: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// The package name.
+    pub fn name(&self) -> &str {
+        &self.info.package.name
+    }
+
+    /// The package version.
+    pub fn version(&self) -> &Version {
+        &self.info.package.version
+    }
+
+    /// Whether the package installs the file list entry `entry`.
+    pub fn owns(&self, entry: &str) -> bool {
+        self.files
+            .binary_search_by(|file| file.as_str().cmp(entry))
+            .is_ok()
+    }
+
+    /// Whether this installed package is a dependency nothing needs anymore.
+    pub fn is_orphan(&self) -> bool {
+        self.info.package.status == PackageStatus::Orphan
+    }
+}
+
+/// A snapshot of the local and sync databases.
+#[derive(Debug, Default)]
+pub struct PackageIndex {
+    local: Vec<IndexedPackage>,
+    local_by_name: HashMap<String, usize>,
+    /// Installed packages by the names they provide.
+    local_providers: HashMap<String, Vec<usize>>,
+    /// Sync packages in repository priority order.
+    sync: Vec<IndexedPackage>,
+    /// First (highest priority) sync package of each name.
+    sync_by_name: HashMap<String, usize>,
+    /// Sync packages by the names they provide.
+    sync_providers: HashMap<String, Vec<usize>>,
+}
+
+impl PackageIndex {
+    /// Copies every package out of `handle`, which must have its sync
+    /// databases registered. Local packages `orphans` finds removable are
+    /// marked `Orphan`.
+    pub fn build(handle: &Alpm, orphans: &OrphanDetector) -> Self {
+        let mut local = Vec::new();
+        for pkg in handle.localdb().pkgs() {
+            let required_by: Vec<String> =
+                pkg.required_by().iter().map(|s| s.to_string()).collect();
+            let optional_for: Vec<String> =
+                pkg.optional_for().iter().map(|s| s.to_string()).collect();
+            let mut info = package_info(pkg, PackageStatus::Installed, "local");
+            info.reason = Some(match pkg.reason() {
+                PackageReason::Explicit => InstallReason::Explicit,
+                PackageReason::Depend => InstallReason::Dependency,
+            });
+            info.install_date = pkg.install_date().map(timestamp);
+
+            let mut indexed = IndexedPackage::new(info, required_by, optional_for);
+            indexed.files = pkg
+                .files()
+                .files()
+                .iter()
+                .map(|file| entry_name(file).into_owned())
+                .collect();
+            indexed.desktop_entries = indexed
+                .files
+                .iter()
+                .filter(|name| name.starts_with(APPLICATIONS_DIR) && name.ends_with(".desktop"))
+                .map(|name| absolute_path(handle.root(), name))
+                .collect();
+            local.push(indexed);
+        }
+
+        let mut sync = Vec::new();
+        for db in handle.syncdbs() {
+            for pkg in db.pkgs() {
+                let info = package_info(pkg, PackageStatus::Available, db.name());
+                sync.push(IndexedPackage::new(info, Vec::new(), Vec::new()));
+            }
+        }
+
+        Self::from_packages(local, sync, orphans)
+    }
+
+    /// Indexes already converted packages; `sync` is in repository priority
+    /// order. Local packages are marked `Orphan` by `orphans`.
+    pub(crate) fn from_packages(
+        local: Vec<IndexedPackage>,
+        sync: Vec<IndexedPackage>,
+        orphans: &OrphanDetector,
+    ) -> Self {
+        let mut index = Self::default();
+
+        for (i, pkg) in local.iter().enumerate() {
+            index.local_by_name.insert(pkg.name().to_string(), i);
+            for provision in &pkg.info.provides {
+                index
+                    .local_providers
+                    .entry(provision.name.clone())
+                    .or_default()
+                    .push(i);
+            }
+        }
+        for (i, pkg) in sync.iter().enumerate() {
+            index
+                .sync_by_name
+                .entry(pkg.name().to_string())
+                .or_insert(i);
+            for provision in &pkg.info.provides {
+                index
+                    .sync_providers
+                    .entry(provision.name.clone())
+                    .or_default()
+                    .push(i);
+            }
+        }
+
+        index.local = local;
+        index.sync = sync;
+
+        let orphans: HashSet<String> = orphans
+            .removable(&index)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        for pkg in &mut index.local {
+            pkg.info.package.status = if orphans.contains(&pkg.info.package.name) {
+                PackageStatus::Orphan
+            } else {
+                PackageStatus::Installed
+            };
+        }
+        index
+    }
+
+    /// Installed packages.
+    pub fn installed(&self) -> &[IndexedPackage] {
+        &self.local
+    }
+
+    /// Packages from all sync databases, in repository priority order.
+    pub fn available(&self) -> &[IndexedPackage] {
+        &self.sync
+    }
+
+    /// Looks up an installed package.
+    pub fn local(&self, name: &str) -> Option<&IndexedPackage> {
+        self.local_by_name.get(name).map(|&i| &self.local[i])
+    }
+
+    /// Looks up a package in the first sync database that has it.
+    pub fn sync(&self, name: &str) -> Option<&IndexedPackage> {
+        self.sync_by_name.get(name).map(|&i| &self.sync[i])
+    }
+
+    /// Finds the package satisfying `dep` by name or provision, preferring
+    /// installed packages and then repository order.
+    pub fn resolve(&self, dep: &Dependency) -> Option<&IndexedPackage> {
+        let satisfies = |pkg: &&IndexedPackage| dep.satisfied_by_info(&pkg.info);
+        let local_providers = self.local_providers.get(&dep.name).into_iter().flatten();
+        let sync_providers = self.sync_providers.get(&dep.name).into_iter().flatten();
+
+        self.local(&dep.name)
+            .filter(satisfies)
+            .or_else(|| local_providers.map(|&i| &self.local[i]).find(satisfies))
+            .or_else(|| self.sync(&dep.name).filter(satisfies))
+            .or_else(|| sync_providers.map(|&i| &self.sync[i]).find(satisfies))
+    }
+
+    /// Desktop entries of installed packages, as package name and path.
+    pub fn desktop_entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.local.iter().flat_map(|pkg| {
+            pkg.desktop_entries
+                .iter()
+                .map(move |path| (pkg.name(), path.as_str()))
+        })
+    }
+
+    /// Installed packages owning the file list entry `entry` or the
+    /// directory of that name.
+    pub fn owners<'a>(&'a self, entry: &'a str) -> impl Iterator<Item = &'a str> {
+        let directory = format!("{}/", entry);
+        self.local
+            .iter()
+            .filter(move |pkg| pkg.owns(entry) || pkg.owns(&directory))
+            .map(|pkg| pkg.name())
+    }
+
+    /// Details of a package, preferring the installed copy.
+    pub fn info(&self, name: &str) -> Option<PackageInfo> {
+        self.local(name)
+            .or_else(|| self.sync(name))
+            .map(|pkg| pkg.info.clone())
+    }
+
+    /// Sync packages matching `query`, best matches first.
+    pub fn search(&self, query: &SearchQuery) -> Vec<SearchResult> {
+        let mut results: Vec<SearchResult> = self
+            .sync
+            .iter()
+            .filter(|pkg| {
+                query.matches(&Candidate {
+                    pkg,
+                    installed: self.local_by_name.contains_key(pkg.name()),
+                })
+            })
+            .map(|pkg| self.search_result(pkg))
+            .collect();
+        query.sort(&mut results);
+        results
+    }
+
+    fn search_result(&self, pkg: &IndexedPackage) -> SearchResult {
+        let installed = self.local(pkg.name());
+        SearchResult {
+            name: pkg.name().to_string(),
+            version: pkg.version().clone(),
+            description: pkg.info.package.description.clone(),
+            backend: PackageBackend::Pacman,
+            repository: pkg.info.package.repository.clone(),
+            installed: installed.is_some(),
+            installed_version: installed.map(|p| p.version().clone()),
+        }
+    }
+}
+
+/// A sync package together with whether it is installed, for matching
+/// `installed:` terms.
+struct Candidate<'a> {
+    pkg: &'a IndexedPackage,
+    installed: bool,
+}
+
+impl Searchable for Candidate<'_> {
+    fn name(&self) -> &str {
+        self.pkg.name()
+    }
+
+    fn description(&self) -> &str {
+        &self.pkg.info.package.description
+    }
+
+    fn repository(&self) -> &str {
+        &self.pkg.info.package.repository
+    }
+
+    fn installed(&self) -> bool {
+        self.installed
+    }
+
+    fn provides(&self) -> impl Iterator<Item = &str> {
+        self.pkg.info.provides()
+    }
+
+    fn groups(&self) -> impl Iterator<Item = &str> {
+        self.pkg.info.groups()
+    }
+
+    fn licenses(&self) -> impl Iterator<Item = &str> {
+        self.pkg.info.licenses()
+    }
+}
+
+/// Converts a libalpm dependency into the core representation.
+pub(crate) fn dependency_from_alpm(dep: &alpm::Dep) -> Dependency {
+    let op = match dep.depmod() {
+        alpm::DepMod::Any => DependencyOp::Any,
+        alpm::DepMod::Eq => DependencyOp::Eq,
+        alpm::DepMod::Ge => DependencyOp::Ge,
+        alpm::DepMod::Le => DependencyOp::Le,
+        alpm::DepMod::Gt => DependencyOp::Gt,
+        alpm::DepMod::Lt => DependencyOp::Lt,
+    };
+
+    Dependency {
+        name: dep.name().to_string(),
+        op,
+        version: dep.version().map(|v| Version::new(v.as_str())),
+        description: dep.desc().map(|s| s.to_string()),
+    }
+}
+
+fn timestamp(ts: i64) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::from_timestamp(ts, 0).unwrap_or_default()
+}
+
+/// Copies the details libalpm has for `pkg`.
+fn package_info(pkg: &alpm::Package, status: PackageStatus, repository: &str) -> PackageInfo {
+    PackageInfo {
+        package: Package::new(
+            pkg.name(),
+            Version::new(pkg.version().as_str()),
+            pkg.desc().unwrap_or_default(),
+            PackageBackend::Pacman,
+            status,
+            repository,
+        ),
+        url: pkg.url().map(|s| s.to_string()),
+        licenses: pkg.licenses().iter().map(|s| s.to_string()).collect(),
+        groups: pkg.groups().iter().map(|s| s.to_string()).collect(),
+        depends: pkg.depends().iter().map(dependency_from_alpm).collect(),
+        optdepends: pkg.optdepends().iter().map(dependency_from_alpm).collect(),
+        provides: pkg.provides().iter().map(dependency_from_alpm).collect(),
+        conflicts: pkg.conflicts().iter().map(dependency_from_alpm).collect(),
+        replaces: pkg.replaces().iter().map(dependency_from_alpm).collect(),
+        installed_size: pkg.isize() as u64,
+        download_size: pkg.download_size() as u64,
+        build_date: Some(timestamp(pkg.build_date())),
+        install_date: None,
+        packager: pkg.packager().map(|s| s.to_string()),
+        arch: pkg.arch().unwrap_or("any").to_string(),
+        reason: None,
+    }
+}
+
+/// Modification times and sizes of the database files, used to notice when
+/// pacman or another tool has changed them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint(Vec<(PathBuf, Option<SystemTime>, u64)>);
+
+impl Fingerprint {
+    fn read(dbpath: &Path) -> Self {
+        let stat = |path: PathBuf| {
+            let meta = fs::metadata(&path).ok();
+            let modified = meta.as_ref().and_then(|m| m.modified().ok());
+            let len = meta.map(|m| m.len()).unwrap_or(0);
+            (path, modified, len)
+        };
+
+        // The directory changes when packages come and go; the desc files
+        // change when only the install reason does.
+        let local = dbpath.join("local");
+        let mut entries = vec![stat(local.clone())];
+        let newest_desc = fs::read_dir(&local)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| fs::metadata(entry.path().join("desc")).ok())
+            .filter_map(|meta| meta.modified().ok())
+            .max();
+        entries.push((local.join("*/desc"), newest_desc, 0));
+
+        let mut sync: Vec<PathBuf> = fs::read_dir(dbpath.join("sync"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "db"))
+            .collect();
+        sync.sort();
+        entries.extend(sync.into_iter().map(stat));
+
+        Self(entries)
+    }
+}
+
+/// Latest build result; `stale` is set while a requested rebuild is pending.
+#[derive(Default)]
+struct State {
+    index: Option<Result<Arc<PackageIndex>>>,
+    stale: bool,
+    /// Marks orphans in the next build.
+    orphans: OrphanDetector,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+impl Shared {
+    fn publish(&self, result: Result<Arc<PackageIndex>>) {
+        let mut state = self.state.lock().unwrap();
+        state.index = Some(result);
+        state.stale = false;
+        self.ready.notify_all();
+    }
+
+    /// Blocks until a current snapshot is available.
+    fn wait(&self) -> Result<Arc<PackageIndex>> {
+        let mut state = self.state.lock().unwrap();
+        while state.stale || state.index.is_none() {
+            state = self.ready.wait(state).unwrap();
+        }
+        state.index.clone().unwrap()
+    }
+}
+
+/// Owns the thread that builds and refreshes the [`PackageIndex`].
+///
+/// The thread exits once the worker is dropped.
+pub struct IndexWorker {
+    shared: Arc<Shared>,
+    wake: Sender<()>,
+}
+
+impl IndexWorker {
+    /// Starts the worker; the first snapshot is built right away.
+    pub fn spawn(config: AlpmConfig) -> Self {
+        let shared = Arc::new(Shared::default());
+        let (wake, requests) = mpsc::channel();
+
+        let worker = shared.clone();
+        thread::Builder::new()
+            .name("alpm-index".into())
+            .spawn(move || {
+                let dbpath = PathBuf::from(&config.dbpath);
+                let lock = dbpath.join("db.lck");
+                let mut seen = None;
+
+                loop {
+                    let (requested, first, orphans) = {
+                        let state = worker.state.lock().unwrap();
+                        (state.stale, state.index.is_none(), state.orphans.clone())
+                    };
+                    let current = Fingerprint::read(&dbpath);
+                    // The first snapshot is always built, so queries never
+                    // wait on a lock. Later rebuilds wait for a running
+                    // transaction to finish unless one was asked for.
+                    let changed = seen.as_ref() != Some(&current) && !lock.exists();
+
+                    if first || requested || changed {
+                        let start = Instant::now();
+                        let result = build(&config, &orphans);
+                        match &result {
+                            Ok(_) => {
+                                debug!("Indexed packages in {:?}", start.elapsed());
+                                seen = Some(current);
+                            }
+                            Err(e) => {
+                                warn!("Failed to index packages: {}", e);
+                                seen = None;
+                            }
+                        }
+                        worker.publish(result.map(Arc::new));
+                    }
+
+                    match requests.recv_timeout(POLL_INTERVAL) {
+                        Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })
+            .expect("failed to spawn index thread");
+
+        Self { shared, wake }
+    }
+
+    /// Returns the current snapshot, waiting for the first build or a
+    /// requested rebuild to finish.
+    pub async fn snapshot(&self) -> Result<Arc<PackageIndex>> {
+        {
+            let state = self.shared.state.lock().unwrap();
+            if let (false, Some(Ok(index))) = (state.stale, &state.index) {
+                return Ok(index.clone());
+            }
+        }
+
+        let shared = self.shared.clone();
+        tokio::task::spawn_blocking(move || shared.wait())
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?
+    }
+
+    /// Rebuilds the snapshot now, e.g. after a transaction. Queries wait for
+    /// the new snapshot.
+    pub fn refresh(&self) {
+        self.shared.state.lock().unwrap().stale = true;
+        self.wake.send(()).ok();
+    }
+
+    /// Marks orphans with `detector` from now on, rebuilding the snapshot.
+    pub fn set_orphan_detector(&self, detector: OrphanDetector) {
+        self.shared.state.lock().unwrap().orphans = detector;
+        self.refresh();
+    }
+}
+
+fn build(config: &AlpmConfig, orphans: &OrphanDetector) -> Result<PackageIndex> {
+    let mut handle = Alpm::new(config.root.clone(), config.dbpath.clone())
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    register_syncdbs(&mut handle, config);
+    Ok(PackageIndex::build(&handle, orphans))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_tracks_database_files() {
+        let dbpath = std::env::temp_dir().join(format!("xpm-index-{}", std::process::id()));
+        fs::create_dir_all(dbpath.join("local/bash-5.2-1")).unwrap();
+        fs::create_dir_all(dbpath.join("sync")).unwrap();
+        fs::write(dbpath.join("local/bash-5.2-1/desc"), "%NAME%\nbash\n").unwrap();
+        fs::write(dbpath.join("sync/core.db"), "db").unwrap();
+
+        let before = Fingerprint::read(&dbpath);
+        assert_eq!(before, Fingerprint::read(&dbpath));
+
+        fs::write(dbpath.join("sync/core.db"), "newer db").unwrap();
+        assert_ne!(before, Fingerprint::read(&dbpath));
+
+        fs::remove_dir_all(&dbpath).unwrap();
+    }
+}
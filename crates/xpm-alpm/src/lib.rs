@@ -1,8 +1,21 @@
 //! Pacman/libalpm backend for xPackageManager.
 
+pub mod archive;
 pub mod backend;
 pub mod cache;
+pub mod config;
+pub mod deps;
+pub mod error;
+pub mod files;
+pub mod hooks;
+pub mod index;
 pub mod orphan;
+pub mod pacnew;
+pub mod plan;
+pub mod question;
 pub mod transaction;
+pub mod updates;
+pub mod verify;
 
 pub use backend::AlpmBackend;
+pub use config::AlpmConfig;
@@ -1,34 +1,235 @@
 //! Orphan package detection.
+//!
+//! Unlike `pacman -Qdt`, detection is recursive: a dependency is an orphan
+//! when nothing but other orphans needs it, so chains that only orphans keep
+//! alive and dependency cycles are found in one pass.
 
-use alpm::{Package, PackageReason};
+use crate::index::{IndexedPackage, PackageIndex};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use xpm_core::package::{InstallReason, Package, PackageStatus};
+
+/// Orphans that depend on each other, so they are removed together.
+#[derive(Debug, Clone)]
+pub struct OrphanGroup {
+    /// The orphans, sorted by name.
+    pub packages: Vec<Package>,
+    /// Installed size freed by removing the whole group, in bytes.
+    pub reclaimable_size: u64,
+}
 
 /// Detects orphan packages (installed as dependencies but no longer needed).
-pub struct OrphanDetector;
+#[derive(Debug, Clone, Default)]
+pub struct OrphanDetector {
+    include_optional: bool,
+    keep: HashSet<String>,
+}
 
 impl OrphanDetector {
-    /// Creates a new orphan detector.
+    /// Creates a detector that honours optional dependencies and keeps
+    /// nothing back.
     pub fn new() -> Self {
-        Self
-    }
-
-    /// Checks if a package is an orphan.
-    ///
-    /// A package is considered orphan if:
-    /// - It was installed as a dependency (not explicitly)
-    /// - No other installed package depends on it
-    pub fn is_orphan(&self, pkg: &Package) -> bool {
-        // Must be installed as a dependency.
-        if pkg.reason() != PackageReason::Depend {
-            return false;
+        Self::default()
+    }
+
+    /// Also treats packages that are only optional dependencies of others
+    /// as orphans.
+    pub fn with_optional(mut self, include_optional: bool) -> Self {
+        self.include_optional = include_optional;
+        self
+    }
+
+    /// Never offers these packages for removal. Their dependencies are kept
+    /// as well.
+    pub fn with_keep<I, S>(mut self, keep: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.keep = keep.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Names of every installed package that can be removed without
+    /// breaking a package outside the set.
+    pub fn removable<'a>(&self, index: &'a PackageIndex) -> HashSet<&'a str> {
+        let mut removable: HashSet<&str> = index
+            .installed()
+            .iter()
+            .filter(|pkg| {
+                pkg.info.reason == Some(InstallReason::Dependency)
+                    && !self.keep.contains(pkg.name())
+            })
+            .map(IndexedPackage::name)
+            .collect();
+
+        // Drop candidates needed from outside the set until none are left;
+        // whatever remains only keeps itself alive.
+        loop {
+            let needed: Vec<&str> = removable
+                .iter()
+                .copied()
+                .filter(|name| {
+                    index.local(name).is_some_and(|pkg| {
+                        self.dependents(pkg)
+                            .any(|dependent| !removable.contains(dependent))
+                    })
+                })
+                .collect();
+            if needed.is_empty() {
+                return removable;
+            }
+            for name in needed {
+                removable.remove(name);
+            }
+        }
+    }
+
+    /// Orphans grouped by the dependencies between them, largest
+    /// reclaimable size first.
+    pub fn detect(&self, index: &PackageIndex) -> Vec<OrphanGroup> {
+        let mut names: Vec<&str> = self.removable(index).into_iter().collect();
+        names.sort_unstable();
+        let position: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (name, i))
+            .collect();
+
+        let mut parent: Vec<usize> = (0..names.len()).collect();
+        for (i, name) in names.iter().enumerate() {
+            let Some(pkg) = index.local(name) else {
+                continue;
+            };
+            for dependent in pkg.required_by.iter().chain(&pkg.optional_for) {
+                if let Some(&j) = position.get(dependent.as_str()) {
+                    let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                    parent[a] = b;
+                }
+            }
         }
 
-        // Check if any other package requires this one.
-        pkg.required_by().is_empty() && pkg.optional_for().is_empty()
+        let mut groups: BTreeMap<usize, OrphanGroup> = BTreeMap::new();
+        for (i, name) in names.iter().enumerate() {
+            let Some(pkg) = index.local(name) else {
+                continue;
+            };
+            let group = groups
+                .entry(find(&mut parent, i))
+                .or_insert_with(|| OrphanGroup {
+                    packages: Vec::new(),
+                    reclaimable_size: 0,
+                });
+            let mut package = pkg.info.package.clone();
+            package.status = PackageStatus::Orphan;
+            group.packages.push(package);
+            group.reclaimable_size += pkg.info.installed_size;
+        }
+
+        let mut groups: Vec<OrphanGroup> = groups.into_values().collect();
+        groups.sort_by(|a, b| {
+            b.reclaimable_size
+                .cmp(&a.reclaimable_size)
+                .then_with(|| a.packages[0].name.cmp(&b.packages[0].name))
+        });
+        groups
+    }
+
+    /// Installed packages that need `pkg`.
+    fn dependents<'p>(&self, pkg: &'p IndexedPackage) -> impl Iterator<Item = &'p str> + 'p {
+        let optional: &[String] = if self.include_optional {
+            &[]
+        } else {
+            &pkg.optional_for
+        };
+        pkg.required_by.iter().chain(optional).map(String::as_str)
     }
 }
 
-impl Default for OrphanDetector {
-    fn default() -> Self {
-        Self::new()
+/// Root of `i` in the union-find forest, compressing the path on the way.
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xpm_core::package::PackageInfo;
+
+    fn local(
+        name: &str,
+        reason: InstallReason,
+        required_by: &[&str],
+        optional_for: &[&str],
+        size: u64,
+    ) -> IndexedPackage {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect();
+        let mut info = PackageInfo::test(name, "1.0-1");
+        info.installed_size = size;
+        info.reason = Some(reason);
+        IndexedPackage::new(info, names(required_by), names(optional_for))
+    }
+
+    fn index(orphans: &OrphanDetector) -> PackageIndex {
+        use InstallReason::{Dependency as Dep, Explicit};
+
+        PackageIndex::from_packages(
+            vec![
+                local("editor", Explicit, &[], &[], 10),
+                local("libedit", Dep, &["editor"], &[], 20),
+                // A chain only kept alive by the orphan at its top.
+                local("tool", Dep, &[], &[], 100),
+                local("libtool", Dep, &["tool"], &[], 50),
+                // A cycle nothing else needs.
+                local("cycle-a", Dep, &["cycle-b"], &[], 5),
+                local("cycle-b", Dep, &["cycle-a"], &[], 5),
+                // Only an optional dependency of an explicit package.
+                local("spell", Dep, &[], &["editor"], 1),
+            ],
+            Vec::new(),
+            orphans,
+        )
+    }
+
+    fn names(groups: &[OrphanGroup]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|g| g.packages.iter().map(|p| p.name.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        let index = index(&OrphanDetector::new());
+
+        let groups = OrphanDetector::new().detect(&index);
+        assert_eq!(
+            names(&groups),
+            [vec!["libtool", "tool"], vec!["cycle-a", "cycle-b"]]
+        );
+        assert_eq!(groups[0].reclaimable_size, 150);
+        assert!(index.local("tool").unwrap().is_orphan());
+
+        let groups = OrphanDetector::new().with_optional(true).detect(&index);
+        assert_eq!(names(&groups).last().unwrap(), &["spell"]);
+
+        let groups = OrphanDetector::new().with_keep(["tool"]).detect(&index);
+        assert_eq!(names(&groups), [vec!["cycle-a", "cycle-b"]]);
+    }
+
+    #[test]
+    fn test_index_marks_orphans_with_detector() {
+        let detector = OrphanDetector::new()
+            .with_keep(["tool"])
+            .with_optional(true);
+        let index = index(&detector);
+
+        assert!(!index.local("tool").unwrap().is_orphan());
+        assert!(!index.local("libtool").unwrap().is_orphan());
+        assert!(index.local("cycle-a").unwrap().is_orphan());
+        assert!(index.local("spell").unwrap().is_orphan());
     }
 }
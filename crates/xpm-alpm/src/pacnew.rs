@@ -0,0 +1,256 @@
+//! `.pacnew` and `.pacsave` files beside the backup files of installed
+//! packages, and resolving them by keeping, replacing or merging.
+
+use crate::files::absolute_path;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use xpm_core::{
+    config_file::{ConfigFile, ConfigFileAction, ConfigFileKind},
+    error::{Error, Result},
+};
+
+/// Three versions of a configuration file to merge a pending file with.
+#[derive(Debug, Clone)]
+pub struct ConfigFileView {
+    /// The pending file.
+    pub file: ConfigFile,
+    /// The file as an older cached version of the package shipped it, the
+    /// common ancestor of the other two.
+    pub original: Option<String>,
+    /// The file in use.
+    pub current: Option<String>,
+    /// The `.pacnew` or `.pacsave` file.
+    pub new: Option<String>,
+    /// `current` and `new` merged line by line, with conflict markers where
+    /// both changed the same lines.
+    pub merged: String,
+    /// Number of conflicting regions in `merged`.
+    pub conflicts: usize,
+}
+
+impl ConfigFileView {
+    /// Reads the file in use and the pending one, and merges them against
+    /// `original`.
+    pub fn new(file: ConfigFile, original: Option<String>) -> Result<Self> {
+        let current = read_text(Path::new(&file.path))?;
+        let new = read_text(Path::new(&file.pending_path()))?;
+        let (merged, conflicts) = merge(
+            original.as_deref(),
+            current.as_deref().unwrap_or_default(),
+            new.as_deref().unwrap_or_default(),
+        );
+        Ok(Self {
+            file,
+            original,
+            current,
+            new,
+            merged,
+            conflicts,
+        })
+    }
+}
+
+/// Pending files beside the backup files below `root`, given as package
+/// name and path relative to the root.
+pub fn find_config_files<'a>(
+    root: &str,
+    backups: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Vec<ConfigFile> {
+    let mut found = Vec::new();
+    for (package, path) in backups {
+        for kind in [ConfigFileKind::Pacnew, ConfigFileKind::Pacsave] {
+            let file = ConfigFile {
+                package: package.to_string(),
+                path: absolute_path(root, path),
+                kind,
+            };
+            if Path::new(&file.pending_path()).exists() {
+                found.push(file);
+            }
+        }
+    }
+    found
+}
+
+/// Applies `action` to a pending file. Replacing moves the pending file,
+/// so the result has its permissions; merging keeps those of the file in
+/// use.
+pub fn resolve(file: &ConfigFile, action: &ConfigFileAction) -> Result<()> {
+    let pending = file.pending_path();
+    match action {
+        ConfigFileAction::Keep => fs::remove_file(&pending).map_err(|e| io_error(&pending, e)),
+        ConfigFileAction::Replace => {
+            fs::rename(&pending, &file.path).map_err(|e| io_error(&pending, e))
+        }
+        ConfigFileAction::Merge(contents) => {
+            fs::write(&file.path, contents).map_err(|e| io_error(&file.path, e))?;
+            fs::remove_file(&pending).map_err(|e| io_error(&pending, e))
+        }
+    }
+}
+
+/// Contents of a text file, or `None` if it does not exist.
+fn read_text(path: &Path) -> Result<Option<String>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error(&path.display().to_string(), e)),
+    }
+}
+
+fn io_error(path: &str, err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => Error::PermissionDenied(path.to_string()),
+        _ => Error::IoError(format!("{}: {}", path, err)),
+    }
+}
+
+/// Three-way merge of `current` and `new` against `original`, like
+/// `diff3 -m`. Without an original, the lines both files share are taken as
+/// the ancestor. Returns the merged text and the number of conflicts.
+pub fn merge(original: Option<&str>, current: &str, new: &str) -> (String, usize) {
+    let current: Vec<&str> = current.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let base: Vec<&str> = match original {
+        Some(original) => original.lines().collect(),
+        None => common_lines(&current, &new)
+            .into_iter()
+            .map(|(i, _)| current[i])
+            .collect(),
+    };
+    let in_current: HashMap<usize, usize> = common_lines(&base, &current).into_iter().collect();
+    let in_new: HashMap<usize, usize> = common_lines(&base, &new).into_iter().collect();
+
+    let mut merged = Vec::new();
+    let mut conflicts = 0;
+    let (mut b, mut c, mut n) = (0, 0, 0);
+    loop {
+        // The next ancestor line both sides kept ends the changed region.
+        let stable =
+            (b..base.len()).find_map(|i| Some((i, *in_current.get(&i)?, *in_new.get(&i)?)));
+        let (bi, ci, ni) = stable.unwrap_or((base.len(), current.len(), new.len()));
+        let (ours, theirs) = (&current[c..ci], &new[n..ni]);
+        if ours == &base[b..bi] {
+            merged.extend_from_slice(theirs);
+        } else if theirs == &base[b..bi] || ours == theirs {
+            merged.extend_from_slice(ours);
+        } else {
+            conflicts += 1;
+            merged.push("<<<<<<< current");
+            merged.extend_from_slice(ours);
+            merged.push("=======");
+            merged.extend_from_slice(theirs);
+            merged.push(">>>>>>> new");
+        }
+
+        let Some((bi, ci, ni)) = stable else {
+            break;
+        };
+        merged.push(base[bi]);
+        (b, c, n) = (bi + 1, ci + 1, ni + 1);
+    }
+
+    let mut text = merged.join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    (text, conflicts)
+}
+
+/// Index pairs of a longest common subsequence of lines.
+fn common_lines(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let original = "a\nb\nc\nd\n";
+        let (merged, conflicts) = merge(Some(original), "A\nb\nc\nd\n", "a\nb\nc\nD\ne\n");
+        assert_eq!((merged.as_str(), conflicts), ("A\nb\nc\nD\ne\n", 0));
+
+        let (merged, conflicts) = merge(Some(original), "a\nB\nc\nd\n", "a\nb2\nc\nd\n");
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< current\nB\n=======\nb2\n>>>>>>> new\nc\nd\n"
+        );
+
+        let (merged, conflicts) = merge(None, "a\nx\nc\n", "a\ny\nc\n");
+        assert_eq!(conflicts, 1);
+        assert!(merged.starts_with("a\n<<<<<<< current\nx\n"));
+        assert_eq!(merge(None, "same\n", "same\n"), ("same\n".to_string(), 0));
+    }
+
+    #[test]
+    fn test_find_and_resolve() {
+        let root = std::env::temp_dir().join(format!("xpm-pacnew-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc/a.conf"), "mine\n").unwrap();
+        fs::write(root.join("etc/a.conf.pacnew"), "theirs\n").unwrap();
+        fs::write(root.join("etc/b.conf"), "b\n").unwrap();
+        fs::write(root.join("etc/b.conf.pacsave"), "old b\n").unwrap();
+
+        let root_str = root.to_str().unwrap();
+        let found = find_config_files(
+            root_str,
+            [
+                ("a", "etc/a.conf"),
+                ("b", "etc/b.conf"),
+                ("c", "etc/c.conf"),
+            ],
+        );
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].kind, ConfigFileKind::Pacnew);
+        assert_eq!(found[1].kind, ConfigFileKind::Pacsave);
+
+        let view = ConfigFileView::new(found[0].clone(), None).unwrap();
+        assert_eq!(view.current.as_deref(), Some("mine\n"));
+        assert_eq!(view.new.as_deref(), Some("theirs\n"));
+        assert_eq!(view.conflicts, 1);
+
+        resolve(&found[0], &ConfigFileAction::Merge("merged\n".into())).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("etc/a.conf")).unwrap(),
+            "merged\n"
+        );
+        resolve(&found[1], &ConfigFileAction::Replace).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("etc/b.conf")).unwrap(),
+            "old b\n"
+        );
+        assert!(find_config_files(root_str, [("a", "etc/a.conf"), ("b", "etc/b.conf")]).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! Dry-run transaction planning.
+//!
+//! Runs libalpm's dependency resolution with `NO_LOCK`, the same way
+//! `pacman -Sp` does, reads back the resolved package lists and releases the
+//! transaction before anything is downloaded or committed.
+
+use crate::error::{from_handle, from_prepare};
+use alpm::{Alpm, Package as AlpmPackage, Question, TransFlag};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use xpm_core::{
+    error::{Error, Result},
+    event::PackageAction,
+    operation::{Operation, OperationKind},
+    package::Version,
+    plan::{install_action, PlannedConflict, PlannedPackage, PlannedReplacement, TransactionPlan},
+};
+
+/// Notes collected from libalpm's question callback while preparing.
+#[derive(Default)]
+struct Recorded {
+    conflicts: Vec<PlannedConflict>,
+    replacements: Vec<PlannedReplacement>,
+    warnings: Vec<String>,
+}
+
+/// Plans `operation` against `handle`, which must have its sync databases
+/// registered. The handle is left without a transaction.
+pub fn plan_operation(handle: &mut Alpm, operation: &Operation) -> Result<TransactionPlan> {
+    let mut plan = TransactionPlan::new(operation.clone());
+
+    // Nothing to resolve for operations that never touch installed packages.
+    let Some(flags) = trans_flags(operation) else {
+        return Ok(plan);
+    };
+
+    let recorded = Arc::new(Mutex::new(Recorded::default()));
+    record_questions(handle, recorded.clone());
+
+    handle
+        .trans_init(flags | TransFlag::NO_LOCK)
+        .map_err(|e| from_handle(handle, e))?;
+    let result = prepare(handle, operation, &mut plan);
+    handle.trans_release().ok();
+    result?;
+
+    let recorded = std::mem::take(&mut *recorded.lock().unwrap());
+    plan.conflicts = recorded.conflicts;
+    plan.replacements = recorded.replacements;
+    plan.warnings.extend(recorded.warnings);
+
+    Ok(plan)
+}
+
+/// Transaction flags for `operation`, or `None` if it does not run a
+/// libalpm transaction.
+pub(crate) fn trans_flags(operation: &Operation) -> Option<TransFlag> {
+    let options = &operation.options;
+    let mut flags = TransFlag::empty();
+    if options.no_deps {
+        flags |= TransFlag::NO_DEP_VERSION;
+    }
+    if options.force {
+        flags |= TransFlag::NO_DEPS;
+    }
+
+    match operation.kind {
+        OperationKind::Install | OperationKind::Update | OperationKind::SystemUpgrade => {
+            if options.download_only {
+                flags |= TransFlag::DOWNLOAD_ONLY;
+            }
+            if options.as_deps && operation.kind == OperationKind::Install {
+                flags |= TransFlag::ALL_DEPS;
+            }
+        }
+        OperationKind::Remove => {
+            if options.recursive {
+                flags |= TransFlag::RECURSE;
+            }
+            if options.no_save {
+                flags |= TransFlag::NO_SAVE;
+            }
+        }
+        OperationKind::RemoveWithDeps => {
+            flags |= TransFlag::RECURSE;
+            if options.no_save {
+                flags |= TransFlag::NO_SAVE;
+            }
+        }
+        OperationKind::Downgrade => {}
+        // The targets are already the full set of orphans; recursing would
+        // also take packages the detector keeps back.
+        OperationKind::RemoveOrphans => {
+            if options.no_save {
+                flags |= TransFlag::NO_SAVE;
+            }
+        }
+        OperationKind::SyncDatabases
+        | OperationKind::CleanCache
+        | OperationKind::MarkExplicit
+        | OperationKind::MarkDependency => return None,
+    }
+
+    Some(flags)
+}
+
+/// Adds the targets of `operation` to the initialised transaction.
+///
+/// Returns the names of the packages that were explicitly targeted.
+pub(crate) fn add_targets(handle: &mut Alpm, operation: &Operation) -> Result<HashSet<String>> {
+    let mut targets: HashSet<String> = operation.packages.iter().cloned().collect();
+
+    match operation.kind {
+        OperationKind::Install | OperationKind::Update => {
+            for name in &operation.packages {
+                let pkg = handle
+                    .syncdbs()
+                    .find_satisfier(name.as_str())
+                    .ok_or_else(|| Error::PackageNotFound(name.clone()))?;
+                targets.insert(pkg.name().to_string());
+                handle
+                    .trans_add_pkg(pkg)
+                    .map_err(|e| Error::TransactionError(format!("{}: {}", name, e)))?;
+            }
+        }
+        OperationKind::SystemUpgrade => {
+            handle
+                .sync_sysupgrade(false)
+                .map_err(|e| from_handle(handle, e))?;
+        }
+        OperationKind::Downgrade => {
+            // Targets are package files; report them by package name.
+            targets.clear();
+            for path in &operation.packages {
+                let pkg = handle
+                    .pkg_load(path.as_str(), true, handle.local_file_siglevel())
+                    .map_err(|e| Error::TransactionError(format!("{}: {}", path, e)))?;
+                targets.insert(pkg.name().to_string());
+                handle
+                    .trans_add_pkg(pkg)
+                    .map_err(|e| Error::TransactionError(format!("{}: {}", path, e)))?;
+            }
+        }
+        OperationKind::Remove | OperationKind::RemoveWithDeps | OperationKind::RemoveOrphans => {
+            for name in &operation.packages {
+                let pkg = handle
+                    .localdb()
+                    .pkg(name.as_str())
+                    .map_err(|_| Error::PackageNotFound(name.clone()))?;
+                handle
+                    .trans_remove_pkg(pkg)
+                    .map_err(|e| Error::TransactionError(format!("{}: {}", name, e)))?;
+            }
+        }
+        OperationKind::SyncDatabases
+        | OperationKind::CleanCache
+        | OperationKind::MarkExplicit
+        | OperationKind::MarkDependency => {}
+    }
+
+    Ok(targets)
+}
+
+/// Adds the targets, prepares the transaction and fills in `plan`.
+fn prepare(handle: &mut Alpm, operation: &Operation, plan: &mut TransactionPlan) -> Result<()> {
+    let targets = add_targets(handle, operation)?;
+    handle.trans_prepare().map_err(from_prepare)?;
+
+    let localdb = handle.localdb();
+    for pkg in handle.trans_add() {
+        let old = localdb.pkg(pkg.name()).ok();
+        let new_version = Version::new(pkg.version().as_str());
+        let old_version = old.map(|old| Version::new(old.version().as_str()));
+        let action = install_action(old_version.as_ref(), &new_version);
+        let explicit = targets.contains(pkg.name())
+            || (operation.kind == OperationKind::SystemUpgrade && old.is_some());
+
+        plan.packages.push(PlannedPackage {
+            name: pkg.name().to_string(),
+            action,
+            old_version,
+            new_version: Some(new_version),
+            repository: pkg.db().map(|db| db.name().to_string()).unwrap_or_default(),
+            download_size: pkg.download_size().max(0) as u64,
+            installed_size_delta: pkg.isize() - old.map(|o| o.isize()).unwrap_or(0),
+            explicit,
+        });
+    }
+
+    for pkg in handle.trans_remove() {
+        // Includes packages removed because of conflicts or replacements.
+        plan.packages
+            .push(removal(pkg, targets.contains(pkg.name())));
+    }
+
+    Ok(())
+}
+
+/// Builds the planned entry for a package being removed.
+fn removal(pkg: &AlpmPackage, explicit: bool) -> PlannedPackage {
+    PlannedPackage {
+        name: pkg.name().to_string(),
+        action: PackageAction::Remove,
+        old_version: Some(Version::new(pkg.version().as_str())),
+        new_version: None,
+        repository: "local".to_string(),
+        download_size: 0,
+        installed_size_delta: -pkg.isize(),
+        explicit,
+    }
+}
+
+/// Answers libalpm's questions so resolution can finish, noting what would
+/// have been asked.
+///
+/// Conflicts and replacements are accepted so the affected packages appear
+/// in the plan; the real transaction still asks the user.
+fn record_questions(handle: &mut Alpm, recorded: Arc<Mutex<Recorded>>) {
+    handle.set_question_cb((), move |question, _| {
+        let mut recorded = recorded.lock().unwrap();
+        match question.question() {
+            Question::Conflict(mut q) => {
+                let conflict = q.conflict();
+                recorded.conflicts.push(PlannedConflict {
+                    package: conflict.package1().name().to_string(),
+                    conflicts_with: conflict.package2().name().to_string(),
+                    reason: Some(conflict.reason().to_string()),
+                });
+                q.set_remove(true);
+            }
+            Question::Replace(q) => {
+                recorded.replacements.push(PlannedReplacement {
+                    old: q.oldpkg().name().to_string(),
+                    new: q.newpkg().name().to_string(),
+                });
+                q.set_replace(true);
+            }
+            Question::InstallIgnorepkg(mut q) => {
+                recorded.warnings.push(format!(
+                    "{} is ignored and would be skipped",
+                    q.pkg().name()
+                ));
+                q.set_install(false);
+            }
+            Question::RemovePkgs(mut q) => {
+                let names: Vec<String> =
+                    q.packages().iter().map(|p| p.name().to_string()).collect();
+                recorded.warnings.push(format!(
+                    "Unresolvable dependencies, would skip: {}",
+                    names.join(", ")
+                ));
+                q.set_skip(true);
+            }
+            Question::ImportKey(mut q) => {
+                recorded.warnings.push(format!(
+                    "PGP key {} ({}) would need importing",
+                    q.fingerprint(),
+                    q.uid()
+                ));
+                q.set_import(false);
+            }
+            Question::Corrupted(mut q) => {
+                recorded
+                    .warnings
+                    .push(format!("{} is corrupted: {}", q.filepath(), q.reason()));
+                q.set_remove(false);
+            }
+            Question::SelectProvider(mut q) => {
+                q.set_index(0);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xpm_core::operation::OperationOptions;
+    use xpm_core::package::PackageBackend;
+
+    #[test]
+    fn test_remove_keeps_pacsave_by_default() {
+        let remove = Operation::remove(vec!["foo".into()], PackageBackend::Pacman);
+        let flags = trans_flags(&remove).unwrap();
+        assert!(!flags.contains(TransFlag::NO_SAVE));
+
+        let remove = remove.with_options(OperationOptions {
+            no_save: true,
+            ..Default::default()
+        });
+        assert!(trans_flags(&remove).unwrap().contains(TransFlag::NO_SAVE));
+    }
+}
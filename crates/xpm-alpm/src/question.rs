@@ -0,0 +1,85 @@
+//! Bridges libalpm's question callback to a [`QuestionAnswerer`].
+
+use alpm::{Alpm, Question};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tracing::info;
+use xpm_core::question::{QuestionAnswer, QuestionAnswerer, TransactionQuestion};
+
+/// Routes libalpm questions to `answerer`, blocking the transaction until
+/// it replies.
+///
+/// Must be installed from a blocking thread; `runtime` is used to drive the
+/// async answerer from inside libalpm's synchronous callback.
+pub fn set_question_handler(
+    handle: &mut Alpm,
+    answerer: Arc<dyn QuestionAnswerer>,
+    runtime: Handle,
+) {
+    handle.set_question_cb((), move |question, _| {
+        let ask = |question: TransactionQuestion| -> QuestionAnswer {
+            let answer = runtime.block_on(answerer.answer(&question));
+            info!("{} -> {:?}", question, answer);
+            answer
+        };
+
+        match question.question() {
+            Question::InstallIgnorepkg(mut q) => {
+                let answer = ask(TransactionQuestion::InstallIgnored {
+                    package: q.pkg().name().to_string(),
+                });
+                q.set_install(answer.is_yes());
+            }
+            Question::Replace(q) => {
+                let answer = ask(TransactionQuestion::ReplacePackage {
+                    old: q.oldpkg().name().to_string(),
+                    new: q.newpkg().name().to_string(),
+                    repository: q.newdb().name().to_string(),
+                });
+                q.set_replace(answer.is_yes());
+            }
+            Question::Conflict(mut q) => {
+                let conflict = q.conflict();
+                let answer = ask(TransactionQuestion::RemoveConflict {
+                    package: conflict.package1().name().to_string(),
+                    conflict: conflict.package2().name().to_string(),
+                    reason: Some(conflict.reason().to_string()),
+                });
+                q.set_remove(answer.is_yes());
+            }
+            Question::Corrupted(mut q) => {
+                let answer = ask(TransactionQuestion::RemoveCorrupted {
+                    filepath: q.filepath().to_string(),
+                    reason: q.reason().to_string(),
+                });
+                q.set_remove(answer.is_yes());
+            }
+            Question::RemovePkgs(mut q) => {
+                let answer = ask(TransactionQuestion::SkipUnresolvable {
+                    packages: q.packages().iter().map(|p| p.name().to_string()).collect(),
+                });
+                q.set_skip(answer.is_yes());
+            }
+            Question::SelectProvider(mut q) => {
+                let providers: Vec<String> =
+                    q.providers().iter().map(|p| p.name().to_string()).collect();
+                let answer = ask(TransactionQuestion::SelectProvider {
+                    dependency: q.depend().to_string(),
+                    providers: providers.clone(),
+                });
+                let index = match answer {
+                    QuestionAnswer::Provider(i) if i < providers.len() => i,
+                    _ => 0,
+                };
+                q.set_index(index as i32);
+            }
+            Question::ImportKey(mut q) => {
+                let answer = ask(TransactionQuestion::ImportKey {
+                    fingerprint: q.fingerprint().to_string(),
+                    uid: q.uid().to_string(),
+                });
+                q.set_import(answer.is_yes());
+            }
+        }
+    });
+}
@@ -1,86 +1,475 @@
 //! Transaction handling for ALPM operations.
 //!
-//! Note: Full transaction support requires root privileges and careful
-//! handling of libalpm's mutable borrow constraints. This is a placeholder
-//! implementation that would need to be expanded for actual package operations.
+//! Runs full libalpm transaction lifecycles (init, add/remove targets,
+//! prepare, commit, release) and forwards libalpm's download, event,
+//! progress, log and question callbacks to the caller. Committing requires
+//! root privileges and the database lock.
 
+use crate::error::{from_commit, from_handle, from_prepare};
+use crate::plan::{add_targets, trans_flags};
+use crate::question::set_question_handler;
+use alpm::{
+    Alpm, DownloadEvent, DownloadResult, Event, LogLevel, PackageReason, Progress, TransFlag,
+};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+use tracing::info;
 use xpm_core::{
+    cancel::CancellationToken,
+    config_file::{ConfigFile, ConfigFileKind},
     error::{Error, Result},
-    operation::OperationOptions,
-    package::Package,
-    source::ProgressCallback,
+    event::{PackageAction, TransactionEvent},
+    operation::{Operation, OperationKind, OperationOptions, OperationStatus},
+    package::{Package, PackageBackend, PackageStatus, Version},
+    question::QuestionAnswerer,
+    source::EventCallback,
 };
 
-/// Placeholder for transaction handling.
-/// Actual implementation would need:
-/// 1. Root privileges (via polkit/pkexec)
-/// 2. Proper transaction lifecycle management
-/// 3. Progress callback integration
-pub struct TransactionHandler;
-
-impl TransactionHandler {
-    /// Creates a new transaction handler.
-    pub fn new() -> Self {
-        Self
+/// Drives libalpm transactions on a handle with its sync databases
+/// registered.
+///
+/// Cancellation is honoured before each phase and while the commit
+/// downloads and checks packages: libalpm lets the downloads in flight
+/// finish, then stops before changing any package. Once packages are being
+/// installed or removed the commit runs to completion, as with pacman.
+pub struct TransactionHandler<'a> {
+    handle: &'a mut Alpm,
+    events: Arc<EventCallback>,
+    cancel: CancellationToken,
+    commit: Rc<CommitState>,
+    config_files: Arc<Mutex<Vec<ConfigFile>>>,
+    old_versions: HashMap<String, Version>,
+}
+
+/// Lets libalpm's callbacks interrupt the commit in progress.
+#[derive(Default)]
+struct CommitState {
+    /// The handle being committed, until packages start changing.
+    handle: Cell<Option<*mut Alpm>>,
+    /// Whether the commit was interrupted.
+    interrupted: Cell<bool>,
+}
+
+impl CommitState {
+    /// Interrupts the commit once `cancel` fires, if it can still be
+    /// stopped cleanly.
+    fn interrupt_if_cancelled(&self, cancel: &CancellationToken) {
+        if !cancel.is_cancelled() {
+            return;
+        }
+        if let Some(handle) = self.handle.take() {
+            // SAFETY: the pointer is only set while `trans_commit` runs,
+            // and libalpm calls back on the thread running it.
+            if unsafe { (*handle).trans_interrupt() }.is_ok() {
+                info!("Interrupting the transaction");
+                self.interrupted.set(true);
+            }
+        }
+    }
+}
+
+impl<'a> TransactionHandler<'a> {
+    /// Creates a handler and installs its callbacks on `handle`.
+    ///
+    /// Must be called from a blocking thread; `runtime` drives the async
+    /// `questions` answerer from inside libalpm's callbacks.
+    pub fn new(
+        handle: &'a mut Alpm,
+        events: EventCallback,
+        questions: Arc<dyn QuestionAnswerer>,
+        runtime: Handle,
+        cancel: CancellationToken,
+    ) -> Self {
+        let events = Arc::new(events);
+        let config_files = Arc::default();
+        let commit = Rc::new(CommitState::default());
+        set_callbacks(
+            handle,
+            events.clone(),
+            Arc::clone(&config_files),
+            commit.clone(),
+            cancel.clone(),
+        );
+        set_question_handler(handle, questions, runtime);
+
+        Self {
+            handle,
+            events,
+            cancel,
+            commit,
+            config_files,
+            old_versions: HashMap::new(),
+        }
+    }
+
+    /// The `.pacnew` and `.pacsave` files created so far.
+    pub fn config_files(&self) -> Vec<ConfigFile> {
+        self.config_files.lock().unwrap().clone()
+    }
+
+    /// The versions installed before the last transaction, keyed by package
+    /// name, for the packages it upgraded, downgraded, reinstalled or
+    /// removed.
+    pub fn old_versions(&self) -> HashMap<String, Version> {
+        self.old_versions.clone()
     }
 
-    /// Placeholder for install operation.
+    /// Installs packages from the sync databases.
     pub fn install(
-        &self,
-        _packages: &[String],
-        _options: &OperationOptions,
-        _progress: ProgressCallback,
+        &mut self,
+        packages: &[String],
+        options: &OperationOptions,
     ) -> Result<Vec<Package>> {
-        Err(Error::PermissionDenied(
-            "Package installation requires root privileges".into(),
-        ))
+        self.run(&operation(OperationKind::Install, packages, options))
     }
 
-    /// Placeholder for remove operation.
+    /// Removes installed packages.
     pub fn remove(
-        &self,
-        _packages: &[String],
-        _options: &OperationOptions,
-        _progress: ProgressCallback,
+        &mut self,
+        packages: &[String],
+        options: &OperationOptions,
     ) -> Result<Vec<Package>> {
-        Err(Error::PermissionDenied(
-            "Package removal requires root privileges".into(),
-        ))
+        self.run(&operation(OperationKind::Remove, packages, options))
     }
 
-    /// Placeholder for upgrade operation.
+    /// Upgrades specific installed packages.
     pub fn upgrade(
-        &self,
-        _packages: &[String],
-        _options: &OperationOptions,
-        _progress: ProgressCallback,
+        &mut self,
+        packages: &[String],
+        options: &OperationOptions,
     ) -> Result<Vec<Package>> {
-        Err(Error::PermissionDenied(
-            "Package upgrade requires root privileges".into(),
-        ))
+        self.run(&operation(OperationKind::Update, packages, options))
+    }
+
+    /// Upgrades every installed package, like `pacman -Syu` unless
+    /// `no_refresh` is set.
+    pub fn sysupgrade(&mut self, options: &OperationOptions) -> Result<Vec<Package>> {
+        self.run(&operation(OperationKind::SystemUpgrade, &[], options))
     }
 
-    /// Placeholder for system upgrade.
-    pub fn sysupgrade(
-        &self,
-        _options: &OperationOptions,
-        _progress: ProgressCallback,
+    /// Refreshes the sync databases, like `pacman -Sy`.
+    pub fn sync_dbs(&mut self, force: bool) -> Result<()> {
+        self.cancel.check()?;
+        (self.events)(TransactionEvent::Status(OperationStatus::Downloading));
+
+        let result = self.handle.syncdbs_mut().update(force);
+        let updated = result.map_err(|e| from_handle(self.handle, e))?;
+        info!(
+            "Sync databases {}",
+            if updated { "updated" } else { "up to date" }
+        );
+        Ok(())
+    }
+
+    /// Runs `operation` as a single transaction and returns the packages it
+    /// installed, upgraded or removed.
+    pub fn run(&mut self, operation: &Operation) -> Result<Vec<Package>> {
+        self.cancel.check()?;
+
+        let Some(flags) = trans_flags(operation) else {
+            match operation.kind {
+                OperationKind::SyncDatabases => self.sync_dbs(operation.options.force)?,
+                OperationKind::MarkExplicit => {
+                    return self.set_reason(&operation.packages, PackageReason::Explicit)
+                }
+                OperationKind::MarkDependency => {
+                    return self.set_reason(&operation.packages, PackageReason::Depend)
+                }
+                _ => {}
+            }
+            return Ok(Vec::new());
+        };
+
+        if operation.kind == OperationKind::SystemUpgrade && !operation.options.no_refresh {
+            self.sync_dbs(false)?;
+        }
+
+        (self.events)(TransactionEvent::Status(OperationStatus::ResolvingDeps));
+        self.handle
+            .trans_init(flags)
+            .map_err(|e| from_handle(self.handle, e))?;
+        let result = self.prepare_and_commit(operation);
+        self.handle.trans_release().ok();
+        result
+    }
+
+    /// Changes the install reason of installed packages, like `pacman -D`,
+    /// and returns the packages that changed.
+    pub fn set_reason(
+        &mut self,
+        packages: &[String],
+        reason: PackageReason,
     ) -> Result<Vec<Package>> {
-        Err(Error::PermissionDenied(
-            "System upgrade requires root privileges".into(),
-        ))
+        self.cancel.check()?;
+        // An empty transaction takes the database lock, as pacman does.
+        self.handle
+            .trans_init(TransFlag::empty())
+            .map_err(|e| from_handle(self.handle, e))?;
+        let result = self.apply_reason(packages, reason);
+        self.handle.trans_release().ok();
+        result
+    }
+
+    fn apply_reason(&mut self, packages: &[String], reason: PackageReason) -> Result<Vec<Package>> {
+        let localdb = self.handle.localdb();
+        let mut changed = Vec::new();
+        for name in packages {
+            let pkg = localdb
+                .pkg(name.as_str())
+                .map_err(|_| Error::PackageNotFound(name.clone()))?;
+            if pkg.reason() == reason {
+                continue;
+            }
+            pkg.set_reason(reason)
+                .map_err(|e| from_handle(self.handle, e))?;
+            changed.push(Package::new(
+                pkg.name(),
+                Version::new(pkg.version().as_str()),
+                pkg.desc().unwrap_or_default(),
+                PackageBackend::Pacman,
+                PackageStatus::Installed,
+                "local",
+            ));
+        }
+        Ok(changed)
     }
 
-    /// Placeholder for database sync.
-    pub fn sync_dbs(&self, _progress: ProgressCallback) -> Result<Vec<Package>> {
-        Err(Error::PermissionDenied(
-            "Database sync requires root privileges".into(),
-        ))
+    fn prepare_and_commit(&mut self, operation: &Operation) -> Result<Vec<Package>> {
+        add_targets(self.handle, operation)?;
+        self.handle.trans_prepare().map_err(from_prepare)?;
+
+        let mut affected: Vec<Package> = self
+            .handle
+            .trans_add()
+            .iter()
+            .map(|pkg| {
+                Package::new(
+                    pkg.name(),
+                    Version::new(pkg.version().as_str()),
+                    pkg.desc().unwrap_or_default(),
+                    PackageBackend::Pacman,
+                    PackageStatus::Installed,
+                    pkg.db().map(|db| db.name()).unwrap_or_default(),
+                )
+            })
+            .collect();
+        affected.extend(self.handle.trans_remove().iter().map(|pkg| {
+            Package::new(
+                pkg.name(),
+                Version::new(pkg.version().as_str()),
+                pkg.desc().unwrap_or_default(),
+                PackageBackend::Pacman,
+                PackageStatus::Available,
+                "local",
+            )
+        }));
+
+        if affected.is_empty() {
+            info!("Nothing to do");
+            return Ok(affected);
+        }
+
+        let localdb = self.handle.localdb();
+        self.old_versions = affected
+            .iter()
+            .filter_map(|p| {
+                let installed = localdb.pkg(p.name.as_str()).ok()?;
+                Some((p.name.clone(), Version::new(installed.version().as_str())))
+            })
+            .collect();
+
+        self.cancel.check()?;
+        self.commit.interrupted.set(false);
+        self.commit.handle.set(Some(&mut *self.handle as *mut Alpm));
+        let committed = self.handle.trans_commit();
+        self.commit.handle.set(None);
+        if self.commit.interrupted.get() {
+            return Err(Error::Cancelled);
+        }
+        committed.map_err(|e| from_commit(self.handle, e))?;
+
+        if operation.options.download_only {
+            return Ok(Vec::new());
+        }
+        Ok(affected)
     }
 }
 
-impl Default for TransactionHandler {
-    fn default() -> Self {
-        Self::new()
+fn operation(kind: OperationKind, packages: &[String], options: &OperationOptions) -> Operation {
+    Operation {
+        kind,
+        packages: packages.to_vec(),
+        backend: PackageBackend::Pacman,
+        options: options.clone(),
     }
 }
+
+/// Progress of the package libalpm is currently working on.
+#[derive(Default)]
+struct PackageState {
+    name: String,
+    done: bool,
+}
+
+/// Forwards libalpm's download, event, progress and log callbacks as
+/// [`TransactionEvent`]s, recording created `.pacnew` and `.pacsave` files
+/// in `config_files` and interrupting the commit once `cancel` fires.
+fn set_callbacks(
+    handle: &mut Alpm,
+    events: Arc<EventCallback>,
+    config_files: Arc<Mutex<Vec<ConfigFile>>>,
+    commit: Rc<CommitState>,
+    cancel: CancellationToken,
+) {
+    let emit = events.clone();
+    let dl_commit = commit.clone();
+    let dl_cancel = cancel.clone();
+    handle.set_dl_cb((), move |file, event, _| {
+        dl_commit.interrupt_if_cancelled(&dl_cancel);
+        let file = file.to_string();
+        match event.event() {
+            DownloadEvent::Init(_) => emit(TransactionEvent::DownloadStarted { file }),
+            DownloadEvent::Progress(progress) => emit(TransactionEvent::DownloadProgress {
+                file,
+                downloaded: progress.downloaded.max(0) as u64,
+                total: progress.total.max(0) as u64,
+            }),
+            DownloadEvent::Completed(completed) => emit(TransactionEvent::DownloadCompleted {
+                file,
+                success: completed.result != DownloadResult::Failed,
+            }),
+            DownloadEvent::Retry(_) => {}
+        }
+    });
+
+    let emit = events.clone();
+    handle.set_event_cb((), move |event, _| {
+        commit.interrupt_if_cancelled(&cancel);
+        match event.event() {
+            Event::ResolveDepsStart => {
+                emit(TransactionEvent::Status(OperationStatus::ResolvingDeps))
+            }
+            Event::IntegrityStart | Event::KeyringStart | Event::LoadStart => {
+                emit(TransactionEvent::Status(OperationStatus::Verifying))
+            }
+            Event::PkgRetrieveStart(retrieve) => {
+                emit(TransactionEvent::Status(OperationStatus::Downloading));
+                emit(TransactionEvent::DownloadTotals {
+                    files: retrieve.num(),
+                    total_bytes: retrieve.total_size().max(0) as u64,
+                });
+            }
+            Event::TransactionStart => {
+                // Packages start changing: too late to stop cleanly.
+                commit.handle.set(None);
+                emit(TransactionEvent::Status(OperationStatus::Processing))
+            }
+            Event::ScriptletInfo(info) => emit(TransactionEvent::ScriptletOutput(
+                info.line().trim_end().to_string(),
+            )),
+            Event::HookStart(_) => emit(TransactionEvent::Status(OperationStatus::RunningHooks)),
+            Event::HookRunStart(hook) => emit(TransactionEvent::HookStarted {
+                name: hook.name().to_string(),
+                description: hook.desc().map(|d| d.to_string()),
+                index: hook.position(),
+                total: hook.total(),
+            }),
+            Event::HookRunDone(hook) => emit(TransactionEvent::HookCompleted {
+                name: hook.name().to_string(),
+                index: hook.position(),
+                total: hook.total(),
+            }),
+            Event::PacnewCreated(pacnew) => {
+                config_files.lock().unwrap().push(ConfigFile {
+                    package: pacnew
+                        .newpkg()
+                        .or(pacnew.oldpkg())
+                        .map(|pkg| pkg.name().to_string())
+                        .unwrap_or_default(),
+                    path: pacnew.file().to_string(),
+                    kind: ConfigFileKind::Pacnew,
+                });
+                emit(TransactionEvent::Warning(format!(
+                    "{} installed as {}.pacnew",
+                    pacnew.file(),
+                    pacnew.file()
+                )))
+            }
+            Event::PacsaveCreated(pacsave) => {
+                config_files.lock().unwrap().push(ConfigFile {
+                    package: pacsave
+                        .oldpkg()
+                        .map(|pkg| pkg.name().to_string())
+                        .unwrap_or_default(),
+                    path: pacsave.file().to_string(),
+                    kind: ConfigFileKind::Pacsave,
+                });
+                emit(TransactionEvent::Warning(format!(
+                    "{} saved as {}.pacsave",
+                    pacsave.file(),
+                    pacsave.file()
+                )))
+            }
+            Event::DatabaseMissing(missing) => emit(TransactionEvent::Warning(format!(
+                "database file for '{}' does not exist",
+                missing.dbname()
+            ))),
+            _ => {}
+        }
+    });
+
+    let emit = events.clone();
+    handle.set_progress_cb(
+        PackageState::default(),
+        move |progress, name, percent, total, current, state| {
+            let action = match progress {
+                Progress::AddStart => PackageAction::Install,
+                Progress::UpgradeStart => PackageAction::Upgrade,
+                Progress::DowngradeStart => PackageAction::Downgrade,
+                Progress::ReinstallStart => PackageAction::Reinstall,
+                Progress::RemoveStart => PackageAction::Remove,
+                _ => return,
+            };
+
+            if state.name != name {
+                state.name = name.to_string();
+                state.done = false;
+                emit(TransactionEvent::PackageStarted {
+                    action,
+                    name: name.to_string(),
+                    index: current,
+                    total,
+                });
+            }
+            if state.done {
+                return;
+            }
+
+            emit(TransactionEvent::PackageProgress {
+                action,
+                name: name.to_string(),
+                percent: percent.clamp(0, 100) as u8,
+                index: current,
+                total,
+            });
+            if percent >= 100 {
+                state.done = true;
+                emit(TransactionEvent::PackageCompleted {
+                    action,
+                    name: name.to_string(),
+                    index: current,
+                    total,
+                });
+            }
+        },
+    );
+
+    handle.set_log_cb((), move |level, message, _| {
+        if level.contains(LogLevel::WARNING) {
+            events(TransactionEvent::Warning(message.trim_end().to_string()));
+        }
+    });
+}
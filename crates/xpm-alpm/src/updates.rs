@@ -0,0 +1,267 @@
+//! Update checks without root, like `checkupdates`: the sync databases are
+//! downloaded into a private per-user copy next to a link to the system's
+//! local database, so the system databases are never touched.
+
+use crate::backend::register_syncdbs;
+use crate::config::AlpmConfig;
+use crate::error::from_handle;
+use alpm::Alpm;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{symlink, DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::info;
+use xpm_core::{
+    error::{Error, Result},
+    package::{PackageBackend, UpdateInfo, Version},
+};
+
+/// How long a sync is reused before the databases are downloaded again.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Pending updates and how current the databases they come from are.
+#[derive(Debug, Clone)]
+pub struct UpdateCheck {
+    /// Installed packages with a newer version in the repositories.
+    pub updates: Vec<UpdateInfo>,
+    /// When the databases were last synced.
+    pub synced_at: SystemTime,
+    /// Whether an earlier sync was recent enough to be reused.
+    pub reused: bool,
+}
+
+/// Holds the update-check directory until dropped.
+#[derive(Debug)]
+pub struct UpdateCheckLock {
+    _file: File,
+}
+
+/// A private directory holding the sync databases used to check for
+/// updates. Checks lock it, so concurrent callers wait for each other and
+/// then reuse the fresh sync.
+#[derive(Debug, Clone)]
+pub struct UpdateCheckDb {
+    dir: PathBuf,
+}
+
+impl UpdateCheckDb {
+    /// Keeps the databases in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CACHE_HOME/xpm/checkdb`, falling back to `~/.cache` and then to
+    /// a per-user directory in the temporary directory.
+    pub fn for_current_user() -> Self {
+        let cache = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .filter(|home| !home.is_empty())
+                    .map(|home| Path::new(&home).join(".cache"))
+            });
+        match cache {
+            Some(cache) => Self::new(cache.join("xpm").join("checkdb")),
+            None => {
+                let uid = fs::metadata("/proc/self")
+                    .map(|m| m.uid())
+                    .unwrap_or_default();
+                Self::new(std::env::temp_dir().join(format!("xpm-checkdb-{}", uid)))
+            }
+        }
+    }
+
+    /// Database path handed to libalpm.
+    pub fn dbpath(&self) -> PathBuf {
+        self.dir.join("db")
+    }
+
+    fn stamp_path(&self) -> PathBuf {
+        self.dir.join("last-sync")
+    }
+
+    /// When the databases were last synced successfully.
+    pub fn last_sync(&self) -> Option<SystemTime> {
+        fs::metadata(self.stamp_path())
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Whether the last successful sync is older than `max_age`, or there
+    /// was none.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.last_sync()
+            .and_then(|synced| synced.elapsed().ok())
+            .is_none_or(|age| age > max_age)
+    }
+
+    /// Creates the directory, readable only by the current user, and locks
+    /// it until the returned guard is dropped.
+    pub fn lock(&self) -> Result<UpdateCheckLock> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .map_err(|e| io_error(&self.dir, e))?;
+        fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))
+            .map_err(|e| io_error(&self.dir, e))?;
+
+        let path = self.dir.join("lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| io_error(&path, e))?;
+        file.lock().map_err(|e| io_error(&path, e))?;
+        Ok(UpdateCheckLock { _file: file })
+    }
+
+    /// Points `local` in the private database path at the local database
+    /// under `system_dbpath`, replacing a link to anywhere else.
+    fn link_local(&self, system_dbpath: &str) -> Result<()> {
+        let dbpath = self.dbpath();
+        fs::create_dir_all(&dbpath).map_err(|e| io_error(&dbpath, e))?;
+
+        let target = Path::new(system_dbpath).join("local");
+        let link = dbpath.join("local");
+        match fs::read_link(&link) {
+            Ok(current) if current == target => return Ok(()),
+            Ok(_) => fs::remove_file(&link).map_err(|e| io_error(&link, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(_) => {
+                return Err(Error::DatabaseError(format!(
+                    "{} exists and is not a symlink",
+                    link.display()
+                )))
+            }
+        }
+        symlink(&target, &link).map_err(|e| io_error(&link, e))
+    }
+
+    /// Removes the libalpm lock a crashed check left behind. Only valid
+    /// while holding [`UpdateCheckDb::lock`].
+    fn clear_stale_alpm_lock(&self) -> Result<()> {
+        let path = self.dbpath().join("db.lck");
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(&path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn mark_synced(&self) -> Result<()> {
+        let path = self.stamp_path();
+        File::create(&path).map_err(|e| io_error(&path, e))?;
+        Ok(())
+    }
+
+    /// Syncs the private databases unless that happened within `max_age`,
+    /// then lists the pending updates. A failed sync is an error and keeps
+    /// the previous databases and timestamp.
+    pub fn check(&self, config: &AlpmConfig, max_age: Duration) -> Result<UpdateCheck> {
+        let _lock = self.lock()?;
+        self.link_local(&config.dbpath)?;
+
+        let mut handle = self.open_handle(config)?;
+        let reused = !self.is_stale(max_age);
+        if !reused {
+            self.clear_stale_alpm_lock()?;
+            let result = handle.syncdbs_mut().update(false);
+            let updated = result.map_err(|e| from_handle(&handle, e))?;
+            self.mark_synced()?;
+            info!(
+                "Update-check databases {}",
+                if updated { "updated" } else { "up to date" }
+            );
+        }
+
+        Ok(UpdateCheck {
+            updates: pending_updates(&handle),
+            synced_at: self.last_sync().unwrap_or_else(SystemTime::now),
+            reused,
+        })
+    }
+
+    fn open_handle(&self, config: &AlpmConfig) -> Result<Alpm> {
+        let dbpath = self.dbpath();
+        let mut handle = Alpm::new(config.root.clone(), dbpath.to_string_lossy().into_owned())
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        handle.set_gpgdir(config.gpgdir.as_str()).ok();
+        for arch in &config.architectures {
+            handle.add_architecture(arch.as_str()).ok();
+        }
+        handle.set_parallel_downloads(config.parallel_downloads);
+        handle.set_default_siglevel(config.siglevel).ok();
+        register_syncdbs(&mut handle, config);
+        Ok(handle)
+    }
+}
+
+/// Installed packages whose first repository, in `pacman.conf` order, has a
+/// newer version.
+fn pending_updates(handle: &Alpm) -> Vec<UpdateInfo> {
+    let mut updates = Vec::new();
+    for local_pkg in handle.localdb().pkgs() {
+        let name = local_pkg.name();
+        let Some((db, sync_pkg)) = handle
+            .syncdbs()
+            .iter()
+            .find_map(|db| db.pkg(name).ok().map(|pkg| (db, pkg)))
+        else {
+            continue;
+        };
+        if alpm::vercmp(sync_pkg.version().as_str(), local_pkg.version().as_str())
+            == std::cmp::Ordering::Greater
+        {
+            updates.push(UpdateInfo {
+                name: name.to_string(),
+                current_version: Version::new(local_pkg.version().as_str()),
+                new_version: Version::new(sync_pkg.version().as_str()),
+                backend: PackageBackend::Pacman,
+                repository: db.name().to_string(),
+                download_size: sync_pkg.download_size() as u64,
+            });
+        }
+    }
+    updates
+}
+
+fn io_error(path: &Path, err: io::Error) -> Error {
+    Error::IoError(format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_link_and_staleness() {
+        let dir = std::env::temp_dir().join(format!("xpm-checkdb-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let db = UpdateCheckDb::new(dir.join("checkdb"));
+        assert!(db.is_stale(DEFAULT_MAX_AGE));
+
+        let lock = db.lock().unwrap();
+        let mode = fs::metadata(dir.join("checkdb"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        db.link_local("/var/lib/pacman").unwrap();
+        db.link_local("/srv/pacman").unwrap();
+        assert_eq!(
+            fs::read_link(db.dbpath().join("local")).unwrap(),
+            Path::new("/srv/pacman/local")
+        );
+
+        db.mark_synced().unwrap();
+        assert!(!db.is_stale(DEFAULT_MAX_AGE));
+        assert!(db.is_stale(Duration::ZERO));
+        drop(lock);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
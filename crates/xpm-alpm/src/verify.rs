@@ -0,0 +1,381 @@
+//! Verification of installed files against the mtree pacman keeps for each
+//! package, like `pacman -Qkk`.
+
+use crate::files::absolute_path;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use xpm_core::{
+    error::{Error, Result},
+    package::PackageBackend,
+    verify::{FileIssue, FileProblem, PackageVerification},
+};
+
+/// Kind of file recorded in an mtree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Link,
+}
+
+impl EntryKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "file" => Some(EntryKind::File),
+            "dir" => Some(EntryKind::Dir),
+            "link" => Some(EntryKind::Link),
+            _ => None,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            EntryKind::File => "file",
+            EntryKind::Dir => "directory",
+            EntryKind::Link => "symlink",
+        }
+    }
+}
+
+/// A file as its package installed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MtreeEntry {
+    /// Path relative to the root, without the leading `./`.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Permission bits.
+    pub mode: Option<u32>,
+    pub size: Option<u64>,
+    /// Lowercase hex SHA-256 of the contents.
+    pub sha256: Option<String>,
+    /// Symlink target.
+    pub link: Option<String>,
+}
+
+/// Verifies the installed package `name` against the mtree in the local
+/// database under `dbpath`. Problems with files listed in `backup` are
+/// reported as modified configuration instead of corruption, and files that
+/// cannot be read as unverified.
+pub fn verify_package(
+    root: &str,
+    dbpath: &str,
+    name: &str,
+    version: &str,
+    backup: &HashSet<&str>,
+) -> Result<PackageVerification> {
+    let mtree = mtree_path(dbpath, name, version);
+    let file = File::open(&mtree)
+        .map_err(|e| Error::DatabaseError(format!("{}: {}", mtree.display(), e)))?;
+    let entries = parse_mtree(BufReader::new(GzDecoder::new(file)))?;
+
+    let mut report = PackageVerification {
+        package: name.to_string(),
+        backend: PackageBackend::Pacman,
+        files_checked: entries.len(),
+        corrupted: Vec::new(),
+        modified_config: Vec::new(),
+        unverified: Vec::new(),
+    };
+    for entry in &entries {
+        let Some(problem) = check_entry(root, entry) else {
+            continue;
+        };
+        let issue = FileIssue {
+            path: absolute_path(root, &entry.path),
+            problem,
+        };
+        if matches!(issue.problem, FileProblem::Unreadable { .. }) {
+            report.unverified.push(issue);
+        } else if backup.contains(entry.path.as_str()) {
+            report.modified_config.push(issue);
+        } else {
+            report.corrupted.push(issue);
+        }
+    }
+    Ok(report)
+}
+
+/// Where the local database keeps the mtree of a package.
+pub fn mtree_path(dbpath: &str, name: &str, version: &str) -> PathBuf {
+    Path::new(dbpath)
+        .join("local")
+        .join(format!("{}-{}", name, version))
+        .join("mtree")
+}
+
+/// Parses a decompressed mtree, applying `/set` and `/unset` defaults.
+/// Package metadata such as `.PKGINFO` is left out, as it is not installed.
+pub fn parse_mtree(reader: impl BufRead) -> Result<Vec<MtreeEntry>> {
+    let mut defaults: HashMap<String, String> = HashMap::new();
+    let mut entries = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.map_err(|e| Error::IoError(e.to_string()))?;
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        match first {
+            _ if first.starts_with('#') => {}
+            "/set" => defaults.extend(keywords(words)),
+            "/unset" => {
+                for word in words {
+                    if word == "all" {
+                        defaults.clear();
+                    } else {
+                        defaults.remove(word);
+                    }
+                }
+            }
+            path => {
+                let mut values = defaults.clone();
+                values.extend(keywords(words));
+                entries.extend(entry(path, &values));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// `key=value` pairs of an mtree line.
+fn keywords<'a>(
+    words: impl Iterator<Item = &'a str> + 'a,
+) -> impl Iterator<Item = (String, String)> + 'a {
+    words.filter_map(|word| {
+        let (key, value) = word.split_once('=')?;
+        Some((key.to_string(), value.to_string()))
+    })
+}
+
+fn entry(path: &str, values: &HashMap<String, String>) -> Option<MtreeEntry> {
+    let path = unescape(path);
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    if path.is_empty() || path.starts_with('.') {
+        return None;
+    }
+
+    Some(MtreeEntry {
+        path: path.to_string(),
+        kind: EntryKind::parse(values.get("type")?)?,
+        mode: values
+            .get("mode")
+            .and_then(|m| u32::from_str_radix(m, 8).ok()),
+        size: values.get("size").and_then(|s| s.parse().ok()),
+        sha256: values.get("sha256digest").cloned(),
+        link: values.get("link").map(|l| unescape(l)),
+    })
+}
+
+/// Decodes the `\ooo` octal escapes mtree uses for whitespace and other
+/// special characters.
+fn unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+            if let Some(byte) = octal {
+                out.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Compares `entry` with what is on disk below `root`, returning the first
+/// problem found.
+pub fn check_entry(root: &str, entry: &MtreeEntry) -> Option<FileProblem> {
+    let path = absolute_path(root, &entry.path);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Some(FileProblem::Missing),
+        Err(e) => {
+            return Some(FileProblem::Unreadable {
+                reason: e.to_string(),
+            })
+        }
+    };
+
+    let file_type = metadata.file_type();
+    let actual = if file_type.is_symlink() {
+        Some(EntryKind::Link)
+    } else if file_type.is_dir() {
+        Some(EntryKind::Dir)
+    } else if file_type.is_file() {
+        Some(EntryKind::File)
+    } else {
+        None
+    };
+    if actual != Some(entry.kind) {
+        return Some(FileProblem::WrongType {
+            expected: entry.kind.describe().to_string(),
+            actual: actual
+                .map_or("special file", EntryKind::describe)
+                .to_string(),
+        });
+    }
+
+    match entry.kind {
+        EntryKind::File => {
+            if let Some(expected) = entry.size {
+                if metadata.len() != expected {
+                    return Some(FileProblem::Size {
+                        expected,
+                        actual: metadata.len(),
+                    });
+                }
+            }
+            if let Some(expected) = &entry.sha256 {
+                match sha256_file(&path) {
+                    Ok(actual) if actual != *expected => return Some(FileProblem::Checksum),
+                    Err(e) => {
+                        return Some(FileProblem::Unreadable {
+                            reason: e.to_string(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        EntryKind::Link => {
+            if let Some(expected) = &entry.link {
+                match fs::read_link(&path) {
+                    Ok(target) if target.to_string_lossy() != expected.as_str() => {
+                        return Some(FileProblem::SymlinkTarget {
+                            expected: expected.clone(),
+                            actual: target.to_string_lossy().into_owned(),
+                        })
+                    }
+                    Err(e) => {
+                        return Some(FileProblem::Unreadable {
+                            reason: e.to_string(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        EntryKind::Dir => {}
+    }
+
+    // Symlink permissions are not used on Linux.
+    if entry.kind != EntryKind::Link {
+        if let Some(expected) = entry.mode {
+            let actual = metadata.permissions().mode() & 0o7777;
+            if actual != expected {
+                return Some(FileProblem::Mode { expected, actual });
+            }
+        }
+    }
+    None
+}
+
+fn sha256_file(path: &str) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    const MTREE: &str = "#mtree
+/set type=file uid=0 gid=0 mode=644
+./.PKGINFO time=1700000000.0 size=512 md5digest=00 sha256digest=00
+./etc time=1700000000.0 mode=755 type=dir
+./etc/demo.conf time=1700000000.0 size=6 sha256digest=5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03
+./usr time=1700000000.0 mode=755 type=dir
+./usr/bin time=1700000000.0 mode=755 type=dir
+./usr/bin/demo time=1700000000.0 mode=755 size=6 sha256digest=5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03
+./usr/bin/old\\040name time=1700000000.0 mode=777 type=link link=demo
+/unset mode
+./usr/bin/gone time=1700000000.0 size=1
+";
+
+    #[test]
+    fn test_parse_mtree() {
+        let entries = parse_mtree(MTREE.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 7);
+        assert_eq!(entries[0].path, "etc");
+        assert_eq!(entries[0].kind, EntryKind::Dir);
+        assert_eq!(entries[2].mode, Some(0o755));
+        let link = &entries[5];
+        assert_eq!(link.path, "usr/bin/old name");
+        assert_eq!(link.link.as_deref(), Some("demo"));
+        assert_eq!(entries[6].mode, None);
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let root = std::env::temp_dir().join(format!("xpm-verify-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::write(root.join("etc/demo.conf"), "hellO\n").unwrap();
+        fs::write(root.join("usr/bin/demo"), "HELLO\n").unwrap();
+        symlink("other", root.join("usr/bin/old name")).unwrap();
+        for dir in ["etc", "usr", "usr/bin"] {
+            fs::set_permissions(root.join(dir), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::set_permissions(root.join("usr/bin/demo"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(
+            root.join("etc/demo.conf"),
+            fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+
+        let root = root.to_str().unwrap();
+        let problems: Vec<_> = parse_mtree(MTREE.as_bytes())
+            .unwrap()
+            .iter()
+            .map(|entry| check_entry(root, entry))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                None,
+                Some(FileProblem::Checksum),
+                None,
+                None,
+                Some(FileProblem::Checksum),
+                Some(FileProblem::SymlinkTarget {
+                    expected: "demo".into(),
+                    actual: "other".into()
+                }),
+                Some(FileProblem::Missing),
+            ]
+        );
+
+        fs::write(format!("{}/usr/bin/demo", root), "hello\n").unwrap();
+        fs::set_permissions(
+            format!("{}/usr/bin/demo", root),
+            fs::Permissions::from_mode(0o700),
+        )
+        .unwrap();
+        let entries = parse_mtree(MTREE.as_bytes()).unwrap();
+        assert_eq!(
+            check_entry(root, &entries[4]),
+            Some(FileProblem::Mode {
+                expected: 0o755,
+                actual: 0o700
+            })
+        );
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,61 @@
+//! Configuration files a transaction left beside the ones in use.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a configuration file was left beside the one in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigFileKind {
+    /// The package shipped a new version of a locally modified file.
+    Pacnew,
+    /// A locally modified file was saved when its package stopped
+    /// installing it.
+    Pacsave,
+}
+
+impl ConfigFileKind {
+    /// Suffix appended to the configuration file's path.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            ConfigFileKind::Pacnew => ".pacnew",
+            ConfigFileKind::Pacsave => ".pacsave",
+        }
+    }
+}
+
+impl fmt::Display for ConfigFileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.suffix()[1..])
+    }
+}
+
+/// A `.pacnew` or `.pacsave` file waiting to be dealt with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConfigFile {
+    /// Package owning the configuration file.
+    pub package: String,
+    /// Absolute path of the configuration file in use.
+    pub path: String,
+    /// Whether the file beside it is a pacnew or a pacsave.
+    pub kind: ConfigFileKind,
+}
+
+impl ConfigFile {
+    /// Path of the `.pacnew` or `.pacsave` file.
+    pub fn pending_path(&self) -> String {
+        format!("{}{}", self.path, self.kind.suffix())
+    }
+}
+
+/// How to resolve a pending configuration file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "contents", rename_all = "snake_case")]
+pub enum ConfigFileAction {
+    /// Keep the file in use and delete the pending one.
+    Keep,
+    /// Move the pending file over the one in use.
+    Replace,
+    /// Write these contents to the file in use and delete the pending one.
+    Merge(String),
+}
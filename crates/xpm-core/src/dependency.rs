@@ -0,0 +1,248 @@
+//! Dependency expressions (depends, optdepends, provides, conflicts, replaces).
+
+use crate::package::{Package, PackageInfo, Version};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Version comparison operator of a dependency expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DependencyOp {
+    /// Any version (`foo`).
+    #[default]
+    Any,
+    /// Exactly this version (`foo=1.2`).
+    Eq,
+    /// This version or newer (`foo>=1.2`).
+    Ge,
+    /// This version or older (`foo<=1.2`).
+    Le,
+    /// Newer than this version (`foo>1.2`).
+    Gt,
+    /// Older than this version (`foo<1.2`).
+    Lt,
+}
+
+impl DependencyOp {
+    /// Returns the operator as written in a dependency string.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependencyOp::Any => "",
+            DependencyOp::Eq => "=",
+            DependencyOp::Ge => ">=",
+            DependencyOp::Le => "<=",
+            DependencyOp::Gt => ">",
+            DependencyOp::Lt => "<",
+        }
+    }
+
+    /// Checks whether `version` satisfies this operator against `required`.
+    pub fn matches(&self, version: &Version, required: &Version) -> bool {
+        let ord = version.vercmp(required);
+        match self {
+            DependencyOp::Any => true,
+            DependencyOp::Eq => ord == Ordering::Equal,
+            DependencyOp::Ge => ord != Ordering::Less,
+            DependencyOp::Le => ord != Ordering::Greater,
+            DependencyOp::Gt => ord == Ordering::Greater,
+            DependencyOp::Lt => ord == Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for DependencyOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A parsed dependency expression such as `foo>=1.2`, `libfoo.so=3-64` or
+/// `python-bar: for X support`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Dependency {
+    /// Package (or provision) name.
+    pub name: String,
+    /// Version comparison operator.
+    pub op: DependencyOp,
+    /// Required version, `None` when `op` is `Any`.
+    pub version: Option<Version>,
+    /// Optional description (optdepends only).
+    pub description: Option<String>,
+}
+
+impl Dependency {
+    /// Creates an unversioned dependency on `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            op: DependencyOp::Any,
+            version: None,
+            description: None,
+        }
+    }
+
+    /// Parses a dependency string the same way libalpm's
+    /// `alpm_dep_from_string` does.
+    pub fn parse(depstring: &str) -> Self {
+        let (expr, description) = match depstring.split_once(": ") {
+            Some((expr, desc)) => (expr, Some(desc.to_string())),
+            None => (depstring, None),
+        };
+
+        // Two-character operators must be checked before their prefixes.
+        let found = [
+            ("<=", DependencyOp::Le),
+            (">=", DependencyOp::Ge),
+            ("=", DependencyOp::Eq),
+            ("<", DependencyOp::Lt),
+            (">", DependencyOp::Gt),
+        ]
+        .into_iter()
+        .find_map(|(token, op)| expr.find(token).map(|idx| (idx, token.len(), op)));
+
+        match found {
+            Some((idx, len, op)) => Self {
+                name: expr[..idx].to_string(),
+                op,
+                version: Some(Version::new(&expr[idx + len..])),
+                description,
+            },
+            None => Self {
+                name: expr.to_string(),
+                op: DependencyOp::Any,
+                version: None,
+                description,
+            },
+        }
+    }
+
+    /// Checks whether `version` satisfies this dependency's version constraint.
+    pub fn version_matches(&self, version: &Version) -> bool {
+        match &self.version {
+            Some(required) => self.op.matches(version, required),
+            None => true,
+        }
+    }
+
+    /// Checks whether a package satisfies this dependency by name and version.
+    pub fn satisfied_by(&self, pkg: &Package) -> bool {
+        pkg.name == self.name && self.version_matches(&pkg.version)
+    }
+
+    /// Checks whether a package satisfies this dependency, either directly or
+    /// through one of its `provides` entries.
+    ///
+    /// Like pacman, an unversioned provision never satisfies a versioned
+    /// dependency.
+    pub fn satisfied_by_info(&self, info: &PackageInfo) -> bool {
+        self.satisfied_by(&info.package)
+            || info.provides.iter().any(|provision| {
+                provision.name == self.name
+                    && match (&self.version, &provision.version) {
+                        (None, _) => true,
+                        (Some(_), None) => false,
+                        (Some(_), Some(provided)) => self.version_matches(provided),
+                    }
+            })
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, "{}{}", self.op, version)?;
+        }
+        if let Some(desc) = &self.description {
+            write!(f, ": {}", desc)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for Dependency {
+    fn from(depstring: &str) -> Self {
+        Self::parse(depstring)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str) -> Package {
+        PackageInfo::test(name, version).package
+    }
+
+    fn info(name: &str, version: &str, provides: &[&str]) -> PackageInfo {
+        let mut info = PackageInfo::test(name, version);
+        info.provides = provides.iter().map(|p| Dependency::parse(p)).collect();
+        info
+    }
+
+    #[test]
+    fn test_parse() {
+        let dep = Dependency::parse("foo>=1.2");
+        assert_eq!(dep.name, "foo");
+        assert_eq!(dep.op, DependencyOp::Ge);
+        assert_eq!(dep.version, Some(Version::new("1.2")));
+        assert_eq!(dep.description, None);
+
+        let dep = Dependency::parse("libfoo.so=3-64");
+        assert_eq!(dep.name, "libfoo.so");
+        assert_eq!(dep.op, DependencyOp::Eq);
+        assert_eq!(dep.version.as_ref().map(|v| v.full.as_str()), Some("3-64"));
+
+        let dep = Dependency::parse("python-bar: for X support");
+        assert_eq!(dep.name, "python-bar");
+        assert_eq!(dep.op, DependencyOp::Any);
+        assert_eq!(dep.version, None);
+        assert_eq!(dep.description.as_deref(), Some("for X support"));
+
+        let dep = Dependency::parse("glibc<2.40: needs old ABI");
+        assert_eq!(dep.name, "glibc");
+        assert_eq!(dep.op, DependencyOp::Lt);
+        assert_eq!(dep.description.as_deref(), Some("needs old ABI"));
+
+        assert_eq!(Dependency::parse("bar<=1:2.0").op, DependencyOp::Le);
+        assert_eq!(Dependency::parse("bar>2").op, DependencyOp::Gt);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in [
+            "foo",
+            "foo>=1.2",
+            "libfoo.so=3-64",
+            "bar<1:2.0-1",
+            "python-bar: for X support",
+        ] {
+            assert_eq!(Dependency::parse(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_satisfied_by() {
+        let pkg = package("foo", "1.2.3-1");
+
+        assert!(Dependency::parse("foo").satisfied_by(&pkg));
+        assert!(Dependency::parse("foo>=1.2").satisfied_by(&pkg));
+        assert!(Dependency::parse("foo=1.2.3").satisfied_by(&pkg));
+        assert!(Dependency::parse("foo<2").satisfied_by(&pkg));
+        assert!(!Dependency::parse("foo>1.2.3").satisfied_by(&pkg));
+        assert!(!Dependency::parse("foo=1.2.3-2").satisfied_by(&pkg));
+        assert!(!Dependency::parse("bar").satisfied_by(&pkg));
+    }
+
+    #[test]
+    fn test_satisfied_by_provides() {
+        let pkg = info("libfoo", "3.1-1", &["libfoo.so=3-64", "foo-impl"]);
+
+        assert!(Dependency::parse("libfoo.so").satisfied_by_info(&pkg));
+        assert!(Dependency::parse("libfoo.so=3-64").satisfied_by_info(&pkg));
+        assert!(!Dependency::parse("libfoo.so=4-64").satisfied_by_info(&pkg));
+        assert!(Dependency::parse("foo-impl").satisfied_by_info(&pkg));
+        // An unversioned provision does not satisfy a versioned dependency.
+        assert!(!Dependency::parse("foo-impl>=1").satisfied_by_info(&pkg));
+    }
+}
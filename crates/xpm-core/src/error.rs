@@ -1,9 +1,47 @@
 //! Error types for xPackageManager.
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// A file that more than one package wants to own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileConflict {
+    /// Package being installed.
+    pub package: String,
+    /// Conflicting path.
+    pub path: String,
+    /// Installed package owning the path, or `None` if it is untracked.
+    pub owner: Option<String>,
+}
+
+/// A dependency that cannot be satisfied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnsatisfiedDependency {
+    /// Package requiring the dependency.
+    pub package: String,
+    /// The dependency expression, e.g. `glibc>=2.40`.
+    pub dependency: String,
+    /// Package whose removal breaks the dependency, if any.
+    pub causing: Option<String>,
+}
+
+/// Two packages that cannot be installed together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageConflict {
+    /// First package.
+    pub package: String,
+    /// Package it conflicts with.
+    pub conflicts_with: String,
+    /// The conflicting dependency expression.
+    pub reason: Option<String>,
+}
+
 /// The main error type for xPackageManager operations.
-#[derive(Error, Debug)]
+///
+/// Errors serialize with a `kind` tag so frontends on the other side of an
+/// IPC boundary can still match on them.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
 pub enum Error {
     #[error("Package not found: {0}")]
     PackageNotFound(String),
@@ -14,6 +52,40 @@ pub enum Error {
     #[error("Dependency resolution failed: {0}")]
     DependencyError(String),
 
+    #[error("Unsatisfied dependencies: {}", join(.0, |d| format!("{} requires {}", d.package, d.dependency)))]
+    UnsatisfiedDependencies(Vec<UnsatisfiedDependency>),
+
+    #[error("Conflicting packages: {}", join(.0, |c| format!("{} and {}", c.package, c.conflicts_with)))]
+    PackageConflicts(Vec<PackageConflict>),
+
+    #[error("Conflicting files: {}", join(.0, |c| match &c.owner {
+        Some(owner) => format!("{}: {} exists in {}", c.package, c.path, owner),
+        None => format!("{}: {} exists in filesystem", c.package, c.path),
+    }))]
+    FileConflicts(Vec<FileConflict>),
+
+    #[error("Signature check failed{}: {reason}", package.as_ref().map(|p| format!(" for {}", p)).unwrap_or_default())]
+    SignatureError {
+        /// Package or database that failed verification.
+        package: Option<String>,
+        /// Key ID or fingerprint involved, if known.
+        key_id: Option<String>,
+        /// What went wrong.
+        reason: String,
+    },
+
+    #[error("Database is locked: {path}")]
+    DatabaseLocked {
+        /// Path of the lock file.
+        path: String,
+    },
+
+    #[error("Not enough disk space{}", .required.map(|r| format!(" ({} bytes needed)", r)).unwrap_or_default())]
+    DiskFull {
+        /// Bytes needed, if known.
+        required: Option<u64>,
+    },
+
     #[error("Transaction failed: {0}")]
     TransactionError(String),
 
@@ -35,12 +107,58 @@ pub enum Error {
     #[error("Invalid configuration: {0}")]
     ConfigError(String),
 
+    #[error("Invalid search query: {0}")]
+    InvalidQuery(String),
+
     #[error("I/O error: {0}")]
-    IoError(#[from] std::io::Error),
+    IoError(String),
 
     #[error("{0}")]
     Other(String),
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::StorageFull => Error::DiskFull { required: None },
+            std::io::ErrorKind::PermissionDenied => Error::PermissionDenied(e.to_string()),
+            _ => Error::IoError(e.to_string()),
+        }
+    }
+}
+
+/// Joins items for an error message.
+fn join<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+    items.iter().map(f).collect::<Vec<_>>().join(", ")
+}
+
 /// A type alias for Results using our Error type.
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_round_trip() {
+        let error = Error::FileConflicts(vec![FileConflict {
+            package: "foo".into(),
+            path: "/usr/bin/foo".into(),
+            owner: Some("foo-git".into()),
+        }]);
+        assert_eq!(
+            error.to_string(),
+            "Conflicting files: foo: /usr/bin/foo exists in foo-git"
+        );
+
+        let json = serde_json::to_string(&error).unwrap();
+        assert!(json.contains("\"kind\":\"file_conflicts\""));
+        assert_eq!(serde_json::from_str::<Error>(&json).unwrap(), error);
+
+        let locked = Error::DatabaseLocked {
+            path: "/var/lib/pacman/db.lck".into(),
+        };
+        let json = serde_json::to_string(&locked).unwrap();
+        assert_eq!(serde_json::from_str::<Error>(&json).unwrap(), locked);
+    }
+}
@@ -0,0 +1,240 @@
+//! Typed events emitted while a transaction runs.
+
+use crate::operation::OperationStatus;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What is being done to a package during a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PackageAction {
+    /// Installing a new package.
+    Install,
+    /// Upgrading to a newer version.
+    Upgrade,
+    /// Downgrading to an older version.
+    Downgrade,
+    /// Reinstalling the same version.
+    Reinstall,
+    /// Removing a package.
+    Remove,
+    /// Checking the installed files of a package.
+    Verify,
+}
+
+impl fmt::Display for PackageAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageAction::Install => write!(f, "Installing"),
+            PackageAction::Upgrade => write!(f, "Upgrading"),
+            PackageAction::Downgrade => write!(f, "Downgrading"),
+            PackageAction::Reinstall => write!(f, "Reinstalling"),
+            PackageAction::Remove => write!(f, "Removing"),
+            PackageAction::Verify => write!(f, "Verifying"),
+        }
+    }
+}
+
+/// An event reported by a backend while executing an operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TransactionEvent {
+    /// The operation moved to a new phase.
+    Status(OperationStatus),
+    /// Totals for the download phase became known.
+    DownloadTotals {
+        /// Number of files to download.
+        files: usize,
+        /// Total bytes to download.
+        total_bytes: u64,
+    },
+    /// A file download started.
+    DownloadStarted {
+        /// File (or ref) being downloaded.
+        file: String,
+    },
+    /// Progress of a single file download.
+    DownloadProgress {
+        /// File (or ref) being downloaded.
+        file: String,
+        /// Bytes downloaded so far for this file.
+        downloaded: u64,
+        /// Total size of this file, 0 if unknown.
+        total: u64,
+    },
+    /// A file download finished.
+    DownloadCompleted {
+        /// File (or ref) that was downloaded.
+        file: String,
+        /// Whether the download succeeded.
+        success: bool,
+    },
+    /// A package started being installed, upgraded or removed.
+    PackageStarted {
+        /// What is being done.
+        action: PackageAction,
+        /// Package name.
+        name: String,
+        /// 1-based position in the transaction.
+        index: usize,
+        /// Number of packages in the transaction.
+        total: usize,
+    },
+    /// Progress of the package currently being processed.
+    PackageProgress {
+        /// What is being done.
+        action: PackageAction,
+        /// Package name.
+        name: String,
+        /// Percentage done for this package (0-100).
+        percent: u8,
+        /// 1-based position in the transaction.
+        index: usize,
+        /// Number of packages in the transaction.
+        total: usize,
+    },
+    /// A package finished processing.
+    PackageCompleted {
+        /// What was done.
+        action: PackageAction,
+        /// Package name.
+        name: String,
+        /// 1-based position in the transaction.
+        index: usize,
+        /// Number of packages in the transaction.
+        total: usize,
+    },
+    /// A line of output from an install scriptlet.
+    ScriptletOutput(String),
+    /// A hook started running.
+    HookStarted {
+        /// Hook name.
+        name: String,
+        /// Hook description, if any.
+        description: Option<String>,
+        /// 1-based position among the hooks being run.
+        index: usize,
+        /// Number of hooks being run.
+        total: usize,
+    },
+    /// A hook finished running.
+    HookCompleted {
+        /// Hook name.
+        name: String,
+        /// 1-based position among the hooks being run.
+        index: usize,
+        /// Number of hooks being run.
+        total: usize,
+    },
+    /// A non-fatal warning.
+    Warning(String),
+}
+
+impl TransactionEvent {
+    /// Returns a short human-readable description of the event.
+    pub fn message(&self) -> String {
+        match self {
+            TransactionEvent::Status(status) => status.to_string(),
+            TransactionEvent::DownloadTotals { files, .. } => {
+                format!("Downloading {} files", files)
+            }
+            TransactionEvent::DownloadStarted { file } => format!("Downloading {}", file),
+            TransactionEvent::DownloadProgress { file, .. } => format!("Downloading {}", file),
+            TransactionEvent::DownloadCompleted {
+                file,
+                success: true,
+            } => {
+                format!("Downloaded {}", file)
+            }
+            TransactionEvent::DownloadCompleted {
+                file,
+                success: false,
+            } => format!("Failed to download {}", file),
+            TransactionEvent::PackageStarted {
+                action,
+                name,
+                index,
+                total,
+            }
+            | TransactionEvent::PackageProgress {
+                action,
+                name,
+                index,
+                total,
+                ..
+            }
+            | TransactionEvent::PackageCompleted {
+                action,
+                name,
+                index,
+                total,
+            } => format!("({}/{}) {} {}", index, total, action, name),
+            TransactionEvent::ScriptletOutput(line) => line.clone(),
+            TransactionEvent::HookStarted {
+                name,
+                description,
+                index,
+                total,
+            } => format!(
+                "({}/{}) {}",
+                index,
+                total,
+                description.as_deref().unwrap_or(name)
+            ),
+            TransactionEvent::HookCompleted { name, .. } => format!("Finished {}", name),
+            TransactionEvent::Warning(warning) => format!("warning: {}", warning),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::OperationProgress;
+
+    #[test]
+    fn test_progress_from_events() {
+        let mut progress = OperationProgress::new(0, 0);
+
+        progress.apply(&TransactionEvent::DownloadTotals {
+            files: 2,
+            total_bytes: 200,
+        });
+        progress.apply(&TransactionEvent::DownloadProgress {
+            file: "a".into(),
+            downloaded: 100,
+            total: 100,
+        });
+        progress.apply(&TransactionEvent::DownloadProgress {
+            file: "b".into(),
+            downloaded: 50,
+            total: 100,
+        });
+        assert_eq!(progress.status, OperationStatus::Downloading);
+        assert_eq!(progress.downloaded_bytes, 150);
+        assert_eq!(progress.overall_percent(), 30);
+
+        progress.apply(&TransactionEvent::PackageProgress {
+            action: PackageAction::Upgrade,
+            name: "b".into(),
+            percent: 50,
+            index: 2,
+            total: 2,
+        });
+        assert_eq!(progress.status, OperationStatus::Processing);
+        assert_eq!(progress.current_package.as_deref(), Some("b"));
+        assert_eq!(progress.package_percent(), 75);
+        assert_eq!(progress.message, "(2/2) Upgrading b");
+
+        // Output lines leave the phase message alone.
+        progress.apply(&TransactionEvent::ScriptletOutput("hello".into()));
+        assert_eq!(progress.message, "(2/2) Upgrading b");
+
+        progress.apply(&TransactionEvent::HookStarted {
+            name: "90-mkinitcpio-install".into(),
+            description: Some("Updating linux initcpios...".into()),
+            index: 2,
+            total: 2,
+        });
+        assert_eq!(progress.status, OperationStatus::RunningHooks);
+        assert_eq!(progress.overall_percent(), 95);
+    }
+}
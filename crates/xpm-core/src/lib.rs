@@ -3,12 +3,28 @@
 //! This crate provides the foundational abstractions used by all backends
 //! and the service layer.
 
+pub mod cancel;
+pub mod config_file;
+pub mod dependency;
 pub mod error;
+pub mod event;
 pub mod operation;
 pub mod package;
+pub mod plan;
+pub mod query;
+pub mod question;
 pub mod source;
+pub mod verify;
 
+pub use cancel::CancellationToken;
+pub use config_file::{ConfigFile, ConfigFileAction, ConfigFileKind};
+pub use dependency::{Dependency, DependencyOp};
 pub use error::{Error, Result};
+pub use event::{PackageAction, TransactionEvent};
 pub use operation::{Operation, OperationKind, OperationResult, OperationStatus};
 pub use package::{Package, PackageInfo, PackageStatus, SearchResult, UpdateInfo, Version};
+pub use plan::TransactionPlan;
+pub use query::{MatchRank, SearchQuery, Searchable};
+pub use question::{QuestionAnswer, QuestionAnswerer, TransactionQuestion};
 pub use source::PackageSource;
+pub use verify::{FileIssue, FileProblem, PackageVerification};
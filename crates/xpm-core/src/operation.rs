@@ -1,7 +1,11 @@
 //! Package operations (install, remove, update, etc.).
 
-use crate::package::{Package, PackageBackend};
+use crate::config_file::ConfigFile;
+use crate::error::Error;
+use crate::event::TransactionEvent;
+use crate::package::{InstallReason, Package, PackageBackend, Version};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 
 /// The kind of operation to perform.
@@ -23,6 +27,12 @@ pub enum OperationKind {
     CleanCache,
     /// Remove orphan packages.
     RemoveOrphans,
+    /// Mark installed packages as explicitly installed.
+    MarkExplicit,
+    /// Mark installed packages as installed as dependencies.
+    MarkDependency,
+    /// Install older versions of installed packages from package files.
+    Downgrade,
 }
 
 impl fmt::Display for OperationKind {
@@ -36,6 +46,9 @@ impl fmt::Display for OperationKind {
             OperationKind::SyncDatabases => write!(f, "Sync databases"),
             OperationKind::CleanCache => write!(f, "Clean cache"),
             OperationKind::RemoveOrphans => write!(f, "Remove orphans"),
+            OperationKind::MarkExplicit => write!(f, "Mark as explicitly installed"),
+            OperationKind::MarkDependency => write!(f, "Mark as dependency"),
+            OperationKind::Downgrade => write!(f, "Downgrade"),
         }
     }
 }
@@ -104,6 +117,35 @@ impl Operation {
         }
     }
 
+    /// Creates an operation changing the install reason of installed
+    /// packages.
+    pub fn set_install_reason(
+        packages: Vec<String>,
+        reason: InstallReason,
+        backend: PackageBackend,
+    ) -> Self {
+        Self {
+            kind: match reason {
+                InstallReason::Explicit => OperationKind::MarkExplicit,
+                InstallReason::Dependency => OperationKind::MarkDependency,
+            },
+            packages,
+            backend,
+            options: OperationOptions::default(),
+        }
+    }
+
+    /// Creates an operation installing older versions from package files,
+    /// such as those in the package cache. `packages` are file paths.
+    pub fn downgrade(packages: Vec<String>, backend: PackageBackend) -> Self {
+        Self {
+            kind: OperationKind::Downgrade,
+            packages,
+            backend,
+            options: OperationOptions::default(),
+        }
+    }
+
     /// Sets the options for this operation.
     pub fn with_options(mut self, options: OperationOptions) -> Self {
         self.options = options;
@@ -126,6 +168,22 @@ pub struct OperationOptions {
     pub keep_config: bool,
     /// Ignore dependency version requirements.
     pub no_deps: bool,
+    /// Install packages as dependencies rather than explicitly (for Install).
+    #[serde(default)]
+    pub as_deps: bool,
+    /// Add the packages to `IgnorePkg` so upgrades skip them (for
+    /// Downgrade).
+    #[serde(default)]
+    pub ignore_upgrades: bool,
+    /// Delete modified configuration files instead of saving them as
+    /// `.pacsave`, like `pacman -Rn` (for Remove).
+    #[serde(default)]
+    pub no_save: bool,
+    /// Upgrade against the sync databases as they are instead of
+    /// refreshing them first, like `pacman -Su` rather than `-Syu` (for
+    /// SystemUpgrade).
+    #[serde(default)]
+    pub no_refresh: bool,
 }
 
 /// Status of an ongoing or completed operation.
@@ -151,6 +209,22 @@ pub enum OperationStatus {
     Cancelled,
 }
 
+impl fmt::Display for OperationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationStatus::Pending => write!(f, "Pending"),
+            OperationStatus::ResolvingDeps => write!(f, "Resolving dependencies"),
+            OperationStatus::Downloading => write!(f, "Downloading"),
+            OperationStatus::Verifying => write!(f, "Verifying"),
+            OperationStatus::Processing => write!(f, "Processing"),
+            OperationStatus::RunningHooks => write!(f, "Running hooks"),
+            OperationStatus::Completed => write!(f, "Completed"),
+            OperationStatus::Failed => write!(f, "Failed"),
+            OperationStatus::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
 /// Result of a completed operation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OperationResult {
@@ -162,8 +236,18 @@ pub struct OperationResult {
     pub affected_packages: Vec<Package>,
     /// Any warnings generated.
     pub warnings: Vec<String>,
+    /// `.pacnew` and `.pacsave` files the operation created.
+    #[serde(default)]
+    pub config_files: Vec<ConfigFile>,
+    /// Versions the affected packages had before the operation, for those
+    /// that were installed.
+    #[serde(default)]
+    pub old_versions: HashMap<String, Version>,
     /// Error message if failed.
     pub error: Option<String>,
+    /// The structured error, when the failure came from one.
+    #[serde(default)]
+    pub error_detail: Option<Error>,
     /// Duration in milliseconds.
     pub duration_ms: u64,
 }
@@ -176,7 +260,10 @@ impl OperationResult {
             status: OperationStatus::Completed,
             affected_packages: affected,
             warnings: Vec::new(),
+            config_files: Vec::new(),
+            old_versions: HashMap::new(),
             error: None,
+            error_detail: None,
             duration_ms,
         }
     }
@@ -188,21 +275,67 @@ impl OperationResult {
             status: OperationStatus::Failed,
             affected_packages: Vec::new(),
             warnings: Vec::new(),
+            config_files: Vec::new(),
+            old_versions: HashMap::new(),
             error: Some(error.into()),
+            error_detail: None,
             duration_ms,
         }
     }
 
+    /// Creates a cancelled result, keeping any packages already processed.
+    pub fn cancelled(operation: Operation, affected: Vec<Package>, duration_ms: u64) -> Self {
+        Self {
+            operation,
+            status: OperationStatus::Cancelled,
+            affected_packages: affected,
+            warnings: Vec::new(),
+            config_files: Vec::new(),
+            old_versions: HashMap::new(),
+            error: Some(Error::Cancelled.to_string()),
+            error_detail: Some(Error::Cancelled),
+            duration_ms,
+        }
+    }
+
+    /// Creates a failed or cancelled result from an error.
+    pub fn from_error(operation: Operation, error: &Error, duration_ms: u64) -> Self {
+        match error {
+            Error::Cancelled => Self::cancelled(operation, Vec::new(), duration_ms),
+            e => Self {
+                error_detail: Some(e.clone()),
+                ..Self::failure(operation, e.to_string(), duration_ms)
+            },
+        }
+    }
+
     /// Returns true if the operation was successful.
     pub fn is_success(&self) -> bool {
         self.status == OperationStatus::Completed
     }
 
+    /// Returns true if the operation was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.status == OperationStatus::Cancelled
+    }
+
     /// Adds a warning to the result.
     pub fn with_warning(mut self, warning: impl Into<String>) -> Self {
         self.warnings.push(warning.into());
         self
     }
+
+    /// Records the `.pacnew` and `.pacsave` files the operation created.
+    pub fn with_config_files(mut self, files: Vec<ConfigFile>) -> Self {
+        self.config_files = files;
+        self
+    }
+
+    /// Records the versions the affected packages had beforehand.
+    pub fn with_old_versions(mut self, versions: HashMap<String, Version>) -> Self {
+        self.old_versions = versions;
+        self
+    }
 }
 
 /// Progress information for an operation.
@@ -222,6 +355,15 @@ pub struct OperationProgress {
     pub downloaded_bytes: u64,
     /// Current status message.
     pub message: String,
+    /// Percentage done for the current package (0-100).
+    pub current_percent: u8,
+    /// Total hooks to run.
+    pub total_hooks: usize,
+    /// Hooks completed so far.
+    pub completed_hooks: usize,
+    /// Bytes downloaded per file, summed into `downloaded_bytes`.
+    #[serde(skip)]
+    file_bytes: HashMap<String, u64>,
 }
 
 impl OperationProgress {
@@ -235,7 +377,72 @@ impl OperationProgress {
             total_bytes,
             downloaded_bytes: 0,
             message: String::new(),
+            current_percent: 0,
+            total_hooks: 0,
+            completed_hooks: 0,
+            file_bytes: HashMap::new(),
+        }
+    }
+
+    /// Folds a transaction event into this snapshot.
+    pub fn apply(&mut self, event: &TransactionEvent) {
+        match event {
+            TransactionEvent::Status(status) => self.status = status.clone(),
+            TransactionEvent::DownloadTotals { total_bytes, .. } => {
+                self.status = OperationStatus::Downloading;
+                self.total_bytes = *total_bytes;
+            }
+            TransactionEvent::DownloadStarted { .. } => {
+                self.status = OperationStatus::Downloading;
+            }
+            TransactionEvent::DownloadProgress {
+                file, downloaded, ..
+            } => {
+                self.status = OperationStatus::Downloading;
+                self.file_bytes.insert(file.clone(), *downloaded);
+                self.downloaded_bytes = self.file_bytes.values().sum();
+            }
+            TransactionEvent::DownloadCompleted { .. } => {}
+            TransactionEvent::PackageStarted {
+                name, index, total, ..
+            } => {
+                self.status = OperationStatus::Processing;
+                self.current_package = Some(name.clone());
+                self.total_packages = *total;
+                self.completed_packages = index.saturating_sub(1);
+                self.current_percent = 0;
+            }
+            TransactionEvent::PackageProgress {
+                name,
+                percent,
+                index,
+                total,
+                ..
+            } => {
+                self.status = OperationStatus::Processing;
+                self.current_package = Some(name.clone());
+                self.total_packages = *total;
+                self.completed_packages = index.saturating_sub(1);
+                self.current_percent = (*percent).min(100);
+            }
+            TransactionEvent::PackageCompleted { index, total, .. } => {
+                self.total_packages = *total;
+                self.completed_packages = *index;
+                self.current_percent = 0;
+            }
+            TransactionEvent::HookStarted { index, total, .. } => {
+                self.status = OperationStatus::RunningHooks;
+                self.total_hooks = *total;
+                self.completed_hooks = index.saturating_sub(1);
+            }
+            TransactionEvent::HookCompleted { index, total, .. } => {
+                self.total_hooks = *total;
+                self.completed_hooks = *index;
+            }
+            // Output lines and warnings don't change the phase.
+            TransactionEvent::ScriptletOutput(_) | TransactionEvent::Warning(_) => return,
         }
+        self.message = event.message();
     }
 
     /// Returns the download progress as a percentage (0-100).
@@ -251,6 +458,33 @@ impl OperationProgress {
         if self.total_packages == 0 {
             return 100;
         }
-        ((self.completed_packages as f64 / self.total_packages as f64) * 100.0) as u8
+        let done = self.completed_packages as f64 + self.current_percent as f64 / 100.0;
+        ((done / self.total_packages as f64) * 100.0).min(100.0) as u8
+    }
+
+    /// Returns the hook progress as a percentage (0-100).
+    pub fn hook_percent(&self) -> u8 {
+        if self.total_hooks == 0 {
+            return 100;
+        }
+        ((self.completed_hooks as f64 / self.total_hooks as f64) * 100.0) as u8
+    }
+
+    /// Returns the progress of the whole operation as a percentage (0-100).
+    ///
+    /// Downloads cover 0-40%, package processing 45-90% and hooks 90-100%.
+    pub fn overall_percent(&self) -> u8 {
+        let scale = |start: u32, end: u32, percent: u8| {
+            (start + (end - start) * percent as u32 / 100) as u8
+        };
+        match self.status {
+            OperationStatus::Pending | OperationStatus::ResolvingDeps => 0,
+            OperationStatus::Downloading => scale(0, 40, self.download_percent()),
+            OperationStatus::Verifying => 40,
+            OperationStatus::Processing => scale(45, 90, self.package_percent()),
+            OperationStatus::RunningHooks => scale(90, 100, self.hook_percent()),
+            OperationStatus::Completed => 100,
+            OperationStatus::Failed | OperationStatus::Cancelled => 0,
+        }
     }
 }
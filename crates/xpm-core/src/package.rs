@@ -1,5 +1,6 @@
 //! Package and version types.
 
+use crate::dependency::Dependency;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -20,27 +21,30 @@ pub struct Version {
 
 impl Version {
     /// Creates a new Version from a version string.
+    ///
+    /// The string is split the same way libalpm does it: an epoch is only
+    /// recognised when the string starts with digits followed by `:`, and the
+    /// pkgrel is whatever follows the last `-`.
     pub fn new(version_str: &str) -> Self {
-        let (epoch, rest) = if let Some(idx) = version_str.find(':') {
-            let epoch = version_str[..idx].parse().ok();
-            (epoch, &version_str[idx + 1..])
-        } else {
-            (None, version_str)
-        };
-
-        let (pkgver, pkgrel) = if let Some(idx) = rest.rfind('-') {
-            (rest[..idx].to_string(), rest[idx + 1..].to_string())
-        } else {
-            (rest.to_string(), String::new())
-        };
+        let (epoch, version, release) = parse_evr(version_str);
 
         Self {
             full: version_str.to_string(),
-            epoch,
-            pkgver,
-            pkgrel,
+            epoch: epoch.map(|e| e.parse().unwrap_or(0)),
+            pkgver: version.to_string(),
+            pkgrel: release.unwrap_or_default().to_string(),
         }
     }
+
+    /// Orders two versions exactly like `alpm_pkg_vercmp`.
+    ///
+    /// `Version` deliberately does not implement `Ord`: this ordering is not
+    /// consistent with `Eq`, which compares the raw strings. pacman treats
+    /// `1.0` and `1.0-1` as equal because a missing pkgrel is not compared,
+    /// and likewise `1.0` and `1.00`.
+    pub fn vercmp(&self, other: &Self) -> Ordering {
+        vercmp(&self.full, &other.full)
+    }
 }
 
 impl fmt::Display for Version {
@@ -49,139 +53,209 @@ impl fmt::Display for Version {
     }
 }
 
-impl PartialOrd for Version {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+/// Compares two full version strings (`[epoch:]pkgver[-pkgrel]`) the same way
+/// pacman's `vercmp` does.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
     }
+
+    let (epoch_a, ver_a, rel_a) = parse_evr(a);
+    let (epoch_b, ver_b, rel_b) = parse_evr(b);
+
+    rpmvercmp(epoch_a.unwrap_or("0"), epoch_b.unwrap_or("0"))
+        .then_with(|| rpmvercmp(ver_a, ver_b))
+        .then_with(|| match (rel_a, rel_b) {
+            // The pkgrel is only compared when both sides have one.
+            (Some(rel_a), Some(rel_b)) => rpmvercmp(rel_a, rel_b),
+            _ => Ordering::Equal,
+        })
 }
 
-impl Ord for Version {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Compare epochs first
-        match (self.epoch, other.epoch) {
-            (Some(a), Some(b)) => match a.cmp(&b) {
+/// Splits a version string into epoch, pkgver and pkgrel like libalpm's
+/// `parseEVR`.
+///
+/// The epoch is `None` when absent and `Some("0")` when given but empty
+/// (`:1.0`). The pkgrel is `None` when there is no `-`, which is different
+/// from an empty pkgrel (`1.0-`).
+fn parse_evr(evr: &str) -> (Option<&str>, &str, Option<&str>) {
+    let digits = evr.bytes().take_while(u8::is_ascii_digit).count();
+
+    let (epoch, rest) = if evr.as_bytes().get(digits) == Some(&b':') {
+        let epoch = &evr[..digits];
+        (
+            Some(if epoch.is_empty() { "0" } else { epoch }),
+            &evr[digits + 1..],
+        )
+    } else {
+        (None, evr)
+    };
+
+    match rest.rfind('-') {
+        Some(idx) => (epoch, &rest[..idx], Some(&rest[idx + 1..])),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Port of libalpm's `rpmvercmp`, comparing a single version component.
+///
+/// Unlike RPM, pacman gives `~` no special meaning: it is an ordinary
+/// separator like `.` or `_`.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let one = a.as_bytes();
+    let two = b.as_bytes();
+    // Start of the current segment (`ptr` in libalpm) and scan position.
+    let (mut seg1, mut seg2) = (0, 0);
+    let (mut i, mut j) = (0, 0);
+
+    while i < one.len() && j < two.len() {
+        while i < one.len() && !one[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < two.len() && !two[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+
+        // If we ran to the end of either, we are finished with the loop.
+        if i >= one.len() || j >= two.len() {
+            break;
+        }
+
+        // If the separator lengths were different, we are also finished.
+        if i - seg1 != j - seg2 {
+            return (i - seg1).cmp(&(j - seg2));
+        }
+
+        seg1 = i;
+        seg2 = j;
+
+        // Grab the first completely alpha or completely numeric segment.
+        let is_num = one[seg1].is_ascii_digit();
+        let in_segment = |c: &u8| {
+            if is_num {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let end1 = seg1 + one[seg1..].iter().take_while(|c| in_segment(c)).count();
+        let end2 = seg2 + two[seg2..].iter().take_while(|c| in_segment(c)).count();
+
+        // The segments are of different types: numeric segments are always
+        // newer than alpha segments.
+        if end2 == seg2 {
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let mut s1 = &one[seg1..end1];
+        let mut s2 = &two[seg2..end2];
+
+        if is_num {
+            // Throw away leading zeros; whichever number has more digits wins.
+            while s1.first() == Some(&b'0') {
+                s1 = &s1[1..];
+            }
+            while s2.first() == Some(&b'0') {
+                s2 = &s2[1..];
+            }
+            match s1.len().cmp(&s2.len()) {
                 Ordering::Equal => {}
                 ord => return ord,
-            },
-            (Some(_), None) => return Ordering::Greater,
-            (None, Some(_)) => return Ordering::Less,
-            (None, None) => {}
+            }
         }
 
-        // Then compare pkgver using version comparison
-        match vercmp(&self.pkgver, &other.pkgver) {
+        match s1.cmp(s2) {
             Ordering::Equal => {}
             ord => return ord,
         }
 
-        // Finally compare pkgrel
-        vercmp(&self.pkgrel, &other.pkgrel)
+        seg1 = end1;
+        seg2 = end2;
+        i = end1;
+        j = end2;
     }
-}
 
-/// Simple version comparison (alphanumeric segments).
-fn vercmp(a: &str, b: &str) -> Ordering {
-    let mut a_chars = a.chars().peekable();
-    let mut b_chars = b.chars().peekable();
+    let rest1 = &one[i..];
+    let rest2 = &two[j..];
 
-    loop {
-        // Skip non-alphanumeric
-        while a_chars.peek().is_some_and(|c| !c.is_alphanumeric()) {
-            a_chars.next();
-        }
-        while b_chars.peek().is_some_and(|c| !c.is_alphanumeric()) {
-            b_chars.next();
-        }
+    // All segments compared identically but the separators were different.
+    if rest1.is_empty() && rest2.is_empty() {
+        return Ordering::Equal;
+    }
 
-        match (a_chars.peek().copied(), b_chars.peek().copied()) {
-            (None, None) => return Ordering::Equal,
-            (None, Some(_)) => return Ordering::Less,
-            (Some(_), None) => return Ordering::Greater,
-            (Some(ac), Some(bc)) => {
-                let a_is_digit = ac.is_ascii_digit();
-                let b_is_digit = bc.is_ascii_digit();
-
-                match (a_is_digit, b_is_digit) {
-                    (true, true) => {
-                        // Compare numeric segments
-                        let mut a_num = String::new();
-                        while let Some(&c) = a_chars.peek() {
-                            if c.is_ascii_digit() {
-                                a_num.push(c);
-                                a_chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-                        let mut b_num = String::new();
-                        while let Some(&c) = b_chars.peek() {
-                            if c.is_ascii_digit() {
-                                b_num.push(c);
-                                b_chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-
-                        // Compare as numbers (longer numeric string is greater, or lexicographic)
-                        match a_num.len().cmp(&b_num.len()) {
-                            Ordering::Equal => match a_num.cmp(&b_num) {
-                                Ordering::Equal => continue,
-                                ord => return ord,
-                            },
-                            ord => return ord,
-                        }
-                    }
-                    (false, false) => {
-                        // Compare alphabetic segments
-                        let mut a_alpha = String::new();
-                        while let Some(&c) = a_chars.peek() {
-                            if c.is_alphabetic() {
-                                a_alpha.push(c);
-                                a_chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-                        let mut b_alpha = String::new();
-                        while let Some(&c) = b_chars.peek() {
-                            if c.is_alphabetic() {
-                                b_alpha.push(c);
-                                b_chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-
-                        match a_alpha.cmp(&b_alpha) {
-                            Ordering::Equal => continue,
-                            ord => return ord,
-                        }
-                    }
-                    (true, false) => return Ordering::Greater, // Numbers > letters
-                    (false, true) => return Ordering::Less,
-                }
-            }
-        }
+    // The final showdown: a remaining alpha string never beats an empty one.
+    // - if one is empty and two is not an alpha, two is newer.
+    // - if one is an alpha, two is newer.
+    // - otherwise one is newer.
+    let one_alpha = rest1.first().is_some_and(u8::is_ascii_alphabetic);
+    let two_alpha = rest2.first().is_some_and(u8::is_ascii_alphabetic);
+    if (rest1.is_empty() && !two_alpha) || one_alpha {
+        Ordering::Less
+    } else {
+        Ordering::Greater
     }
 }
 
 /// The source/backend a package comes from.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+///
+/// Identified by the `source_id()` of the [`PackageSource`](crate::source::PackageSource)
+/// that produced it. The built-in backends have named variants; any other
+/// registered source is carried as [`PackageBackend::Other`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum PackageBackend {
     /// Pacman/libalpm (Arch repos + AUR).
     Pacman,
     /// Flatpak.
     Flatpak,
+    /// Any other registered source, by source id.
+    Other(String),
 }
 
-impl fmt::Display for PackageBackend {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl PackageBackend {
+    /// Returns the source id of this backend.
+    pub fn id(&self) -> &str {
         match self {
-            PackageBackend::Pacman => write!(f, "pacman"),
-            PackageBackend::Flatpak => write!(f, "flatpak"),
+            PackageBackend::Pacman => "pacman",
+            PackageBackend::Flatpak => "flatpak",
+            PackageBackend::Other(id) => id,
         }
     }
+
+    /// Creates a backend from a source id.
+    pub fn from_id(id: &str) -> Self {
+        match id {
+            "pacman" => PackageBackend::Pacman,
+            "flatpak" => PackageBackend::Flatpak,
+            other => PackageBackend::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for PackageBackend {
+    fn from(id: String) -> Self {
+        Self::from_id(&id)
+    }
+}
+
+impl From<PackageBackend> for String {
+    fn from(backend: PackageBackend) -> Self {
+        backend.id().to_string()
+    }
+}
+
+impl fmt::Display for PackageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
 }
 
 /// Installation status of a package.
@@ -247,15 +321,15 @@ pub struct PackageInfo {
     /// Package groups.
     pub groups: Vec<String>,
     /// Direct dependencies.
-    pub depends: Vec<String>,
+    pub depends: Vec<Dependency>,
     /// Optional dependencies.
-    pub optdepends: Vec<String>,
+    pub optdepends: Vec<Dependency>,
     /// Packages this provides.
-    pub provides: Vec<String>,
+    pub provides: Vec<Dependency>,
     /// Packages this conflicts with.
-    pub conflicts: Vec<String>,
+    pub conflicts: Vec<Dependency>,
     /// Packages this replaces.
-    pub replaces: Vec<String>,
+    pub replaces: Vec<Dependency>,
     /// Installed size in bytes.
     pub installed_size: u64,
     /// Download size in bytes.
@@ -272,6 +346,39 @@ pub struct PackageInfo {
     pub reason: Option<InstallReason>,
 }
 
+impl PackageInfo {
+    /// A bare installed pacman package from the `local` repository, as a
+    /// starting point for test fixtures.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn test(name: &str, version: &str) -> Self {
+        Self {
+            package: Package::new(
+                name,
+                Version::new(version),
+                "",
+                PackageBackend::Pacman,
+                PackageStatus::Installed,
+                "local",
+            ),
+            url: None,
+            licenses: Vec::new(),
+            groups: Vec::new(),
+            depends: Vec::new(),
+            optdepends: Vec::new(),
+            provides: Vec::new(),
+            conflicts: Vec::new(),
+            replaces: Vec::new(),
+            installed_size: 0,
+            download_size: 0,
+            build_date: None,
+            install_date: None,
+            packager: None,
+            arch: "x86_64".to_string(),
+            reason: None,
+        }
+    }
+}
+
 /// Why a package was installed.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum InstallReason {
@@ -321,6 +428,109 @@ pub struct UpdateInfo {
 mod tests {
     use super::*;
 
+    /// Expected results taken from pacman's `test/util/vercmptest.sh`, plus
+    /// a few extra edge cases checked against `vercmp` from pacman 6.1.
+    const VERCMP_CASES: &[(&str, &str, i8)] = &[
+        // All similar length, no pkgrel.
+        ("1.5.0", "1.5.0", 0),
+        ("1.5.1", "1.5.0", 1),
+        // Mixed length.
+        ("1.5.1", "1.5", 1),
+        // With pkgrel, simple.
+        ("1.5.0-1", "1.5.0-1", 0),
+        ("1.5.0-1", "1.5.0-2", -1),
+        ("1.5.0-1", "1.5.1-1", -1),
+        ("1.5.0-2", "1.5.1-1", -1),
+        // With pkgrel, mixed lengths.
+        ("1.5-1", "1.5.1-1", -1),
+        ("1.5-2", "1.5.1-1", -1),
+        ("1.5-2", "1.5.1-2", -1),
+        // Mixed pkgrel inclusion.
+        ("1.5", "1.5-1", 0),
+        ("1.5-1", "1.5", 0),
+        ("1.1-1", "1.1", 0),
+        ("1.0-1", "1.1", -1),
+        ("1.1-1", "1.0", 1),
+        // Alphanumeric versions.
+        ("1.5b-1", "1.5-1", -1),
+        ("1.5b", "1.5", -1),
+        ("1.5b-1", "1.5", -1),
+        ("1.5b", "1.5.1", -1),
+        // From the manpage.
+        ("1.0a", "1.0alpha", -1),
+        ("1.0alpha", "1.0b", -1),
+        ("1.0b", "1.0beta", -1),
+        ("1.0beta", "1.0rc", -1),
+        ("1.0rc", "1.0", -1),
+        // Alpha-dotted versions.
+        ("1.5.a", "1.5", 1),
+        ("1.5.b", "1.5.a", 1),
+        ("1.5.1", "1.5.b", 1),
+        // Alpha dots and dashes.
+        ("1.5.b-1", "1.5.b", 0),
+        ("1.5-1", "1.5.b", -1),
+        // Same/similar content, differing separators.
+        ("2.0", "2_0", 0),
+        ("2.0_a", "2_0.a", 0),
+        ("2.0a", "2.0.a", -1),
+        ("2___a", "2_a", 1),
+        // Epoch included version comparisons.
+        ("0:1.0", "0:1.0", 0),
+        ("0:1.0", "0:1.1", -1),
+        ("1:1.0", "0:1.0", 1),
+        ("1:1.0", "0:1.1", 1),
+        ("1:1.0", "2:1.1", -1),
+        // Epoch + sometimes present pkgrel.
+        ("1:1.0", "0:1.0-1", 1),
+        ("1:1.0-1", "0:1.1-1", 1),
+        // Epoch included on one version.
+        ("0:1.0", "1.0", 0),
+        ("0:1.0", "1.1", -1),
+        ("0:1.1", "1.0", 1),
+        ("1:1.0", "1.0", 1),
+        ("1:1.0", "1.1", 1),
+        ("1:1.1", "1.1", 1),
+        // Empty and zero-padded epochs are epoch 0.
+        (":1.0", "1.0", 0),
+        ("00:1.0", "0:1.0", 0),
+        ("10:1.0", "9:1.0", 1),
+        // Only leading digits followed by ':' form an epoch.
+        ("1a:2.0", "1a:1.0", 1),
+        // Empty pkgrel is compared when both sides have one.
+        ("1.0-", "1.0-1", -1),
+        ("1.0-", "1.0", 0),
+        ("1.0-", "1.0-", 0),
+        // Leading zeros and long numeric segments.
+        ("1.01", "1.1", 0),
+        ("1.001", "1.1", 0),
+        ("1.0010", "1.9", 1),
+        ("20240101", "9", 1),
+        ("1.99999999999999999999", "1.100000000000000000000", -1),
+        // Numeric segments beat alpha segments.
+        ("1.0.1", "1.0.a", 1),
+        ("1.a", "1.1", -1),
+        ("1a", "1.1", -1),
+        // `~` is just a separator for pacman, unlike RPM.
+        ("1.0~rc1", "1.0", 1),
+        ("1.0~rc1", "1.0.rc1", 0),
+        ("1.0~~rc1", "1.0.rc1", 1),
+        // Trailing separators.
+        ("1.0.", "1.0", 1),
+        ("1.0_", "1.0", 1),
+        // Real-world package versions.
+        ("6.6.1.arch1-1", "6.6.10.arch1-1", -1),
+        ("1:2.42.0-1", "2.43.0-1", 1),
+        ("r1234.abcdef-1", "r1235.abcdef-1", -1),
+        ("2.38+r3+g1d2c3e4-1", "2.38-1", 1),
+        ("1.2.3.r10.g1234567-1", "1.2.3-1", 1),
+        ("23.3.1-1", "23.3.1-1.1", -1),
+        ("23.3.1-2", "23.3.1-1.1", 1),
+    ];
+
+    fn expected(ord: i8) -> Ordering {
+        ord.cmp(&0)
+    }
+
     #[test]
     fn test_version_comparison() {
         let v1 = Version::new("1.0.0-1");
@@ -328,10 +538,15 @@ mod tests {
         let v3 = Version::new("1:0.5.0-1");
         let v4 = Version::new("2.0.0-1");
 
-        assert!(v1 < v2);
-        assert!(v2 < v4);
-        assert!(v3 > v4); // epoch wins
-        assert!(v1 == Version::new("1.0.0-1"));
+        assert_eq!(v1.vercmp(&v2), Ordering::Less);
+        assert_eq!(v2.vercmp(&v4), Ordering::Less);
+        assert_eq!(v3.vercmp(&v4), Ordering::Greater); // epoch wins
+        assert_eq!(v1, Version::new("1.0.0-1"));
+
+        // Equal for vercmp, but not the same version string.
+        let bare = Version::new("1.0.0");
+        assert_eq!(bare.vercmp(&v1), Ordering::Equal);
+        assert_ne!(bare, v1);
     }
 
     #[test]
@@ -340,5 +555,156 @@ mod tests {
         assert_eq!(v.epoch, Some(1));
         assert_eq!(v.pkgver, "2.3.4");
         assert_eq!(v.pkgrel, "5");
+
+        let v = Version::new("1a:2.3-4");
+        assert_eq!(v.epoch, None);
+        assert_eq!(v.pkgver, "1a:2.3");
+        assert_eq!(v.pkgrel, "4");
+
+        let v = Version::new(":2.3");
+        assert_eq!(v.epoch, Some(0));
+        assert_eq!(v.pkgver, "2.3");
+        assert_eq!(v.pkgrel, "");
+    }
+
+    #[test]
+    fn test_vercmp_table() {
+        for &(a, b, ord) in VERCMP_CASES {
+            assert_eq!(vercmp(a, b), expected(ord), "vercmp({a:?}, {b:?})");
+            assert_eq!(
+                vercmp(b, a),
+                expected(ord).reverse(),
+                "vercmp({b:?}, {a:?})"
+            );
+            assert_eq!(Version::new(a).vercmp(&Version::new(b)), expected(ord));
+        }
+    }
+
+    /// Small deterministic PRNG so the property tests are reproducible.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[(self.next() % items.len() as u64) as usize]
+        }
+    }
+
+    /// Generates a random `[epoch:]pkgver-pkgrel` string.
+    fn random_version(rng: &mut XorShift) -> String {
+        const SEGMENTS: &[&str] = &[
+            "0", "1", "2", "9", "10", "010", "a", "b", "rc", "beta", "r12",
+        ];
+        const SEPARATORS: &[&str] = &[".", ".", ".", "_", "+", "~", ""];
+
+        let mut version = String::new();
+        if rng.next().is_multiple_of(4) {
+            version.push_str(rng.pick(&["0", "1", "2"]));
+            version.push(':');
+        }
+        for i in 0..=(rng.next() % 4) {
+            if i > 0 {
+                version.push_str(rng.pick(SEPARATORS));
+            }
+            version.push_str(rng.pick(SEGMENTS));
+        }
+        version.push('-');
+        version.push_str(rng.pick(&["1", "2", "1.1", "10"]));
+        version
+    }
+
+    #[test]
+    fn test_vercmp_properties() {
+        let mut rng = XorShift(0x5eed_1234_abcd_ef01);
+        let versions: Vec<String> = (0..120).map(|_| random_version(&mut rng)).collect();
+
+        for a in &versions {
+            assert_eq!(vercmp(a, a), Ordering::Equal, "reflexive: {a}");
+
+            for b in &versions {
+                let ab = vercmp(a, b);
+                assert_eq!(ab, vercmp(b, a).reverse(), "antisymmetric: {a} vs {b}");
+
+                for c in &versions {
+                    // Every generated version has a pkgrel, so the ordering is
+                    // a total order and must be transitive.
+                    if ab != Ordering::Greater && vercmp(b, c) != Ordering::Greater {
+                        assert_ne!(
+                            vercmp(a, c),
+                            Ordering::Greater,
+                            "transitive: {a} <= {b} <= {c}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_vercmp_ordering_properties() {
+        let mut rng = XorShift(0x0123_4567_89ab_cdef);
+
+        for _ in 0..500 {
+            let v = random_version(&mut rng);
+
+            // Bumping the epoch always wins.
+            let (epoch, rest) = match v.split_once(':') {
+                Some((e, rest)) => (e.parse::<u32>().unwrap(), rest),
+                None => (0, v.as_str()),
+            };
+            let bumped = format!("{}:{}", epoch + 1, rest);
+            assert_eq!(vercmp(&bumped, &v), Ordering::Greater, "{bumped} > {v}");
+
+            // Bumping the pkgrel wins when the pkgver is unchanged.
+            let (ver, rel) = v.rsplit_once('-').unwrap();
+            let bumped = format!("{}-{}", ver, rel.replace('.', "") + "0");
+            assert_eq!(vercmp(&bumped, &v), Ordering::Greater, "{bumped} > {v}");
+
+            // A missing pkgrel never changes the result.
+            assert_eq!(vercmp(ver, &v), Ordering::Equal, "{ver} == {v}");
+
+            // Appending a numeric segment makes the version newer, appending
+            // an alpha segment directly after a number makes it older.
+            assert_eq!(vercmp(&format!("{ver}.1-{rel}"), &v), Ordering::Greater);
+            if ver.ends_with(|c: char| c.is_ascii_digit()) {
+                assert_eq!(vercmp(&format!("{ver}a-{rel}"), &v), Ordering::Less);
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_sorting() {
+        let mut versions: Vec<Version> = [
+            "1:0.1-1", "1.0-1", "1.0rc1-1", "1.0-2", "0.9-1", "1.0.1-1", "1.0a-1",
+        ]
+        .into_iter()
+        .map(Version::new)
+        .collect();
+        versions.sort_by(Version::vercmp);
+
+        let sorted: Vec<&str> = versions.iter().map(|v| v.full.as_str()).collect();
+        assert_eq!(
+            sorted,
+            ["0.9-1", "1.0a-1", "1.0rc1-1", "1.0-1", "1.0-2", "1.0.1-1", "1:0.1-1"]
+        );
+    }
+
+    #[test]
+    fn test_backend_ids() {
+        for backend in [
+            PackageBackend::Pacman,
+            PackageBackend::Flatpak,
+            PackageBackend::Other("snap".to_string()),
+        ] {
+            assert_eq!(PackageBackend::from_id(backend.id()), backend);
+            assert_eq!(backend.to_string(), backend.id());
+        }
+        assert_eq!(PackageBackend::from_id("pacman"), PackageBackend::Pacman);
     }
 }
@@ -0,0 +1,177 @@
+//! Dry-run previews of what an operation would change.
+
+use crate::event::PackageAction;
+use crate::operation::Operation;
+use crate::package::Version;
+use serde::{Deserialize, Serialize};
+
+/// A package the transaction would touch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedPackage {
+    /// Package name.
+    pub name: String,
+    /// What would be done to it.
+    pub action: PackageAction,
+    /// Currently installed version, if any.
+    pub old_version: Option<Version>,
+    /// Version that would be installed, `None` for removals.
+    pub new_version: Option<Version>,
+    /// Repository (or remote) the new version comes from.
+    pub repository: String,
+    /// Bytes that still need downloading (0 if cached).
+    pub download_size: u64,
+    /// Change in installed size in bytes.
+    pub installed_size_delta: i64,
+    /// True if the package was requested, false if pulled in as a
+    /// dependency (or removed as an unneeded one).
+    pub explicit: bool,
+}
+
+/// A conflict found while planning.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedConflict {
+    /// Package being installed.
+    pub package: String,
+    /// Installed package it conflicts with.
+    pub conflicts_with: String,
+    /// The conflicting dependency expression, if known.
+    pub reason: Option<String>,
+}
+
+/// A package that would be replaced by another.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedReplacement {
+    /// Installed package being replaced.
+    pub old: String,
+    /// Package replacing it.
+    pub new: String,
+}
+
+/// A hook the transaction would trigger, such as rebuilding the initramfs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedHook {
+    /// Hook name, the file name without `.hook`.
+    pub name: String,
+    /// What the hook does, from its `Description`.
+    pub description: Option<String>,
+    /// True if it runs before the packages are changed, false if after.
+    pub pre_transaction: bool,
+    /// True if its failure would abort the transaction.
+    pub abort_on_fail: bool,
+}
+
+/// What an operation would do, computed without changing the system.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionPlan {
+    /// The planned operation.
+    pub operation: Operation,
+    /// Packages to install, upgrade, downgrade, reinstall or remove.
+    pub packages: Vec<PlannedPackage>,
+    /// Conflicts that would remove installed packages.
+    pub conflicts: Vec<PlannedConflict>,
+    /// Packages that would be replaced.
+    pub replacements: Vec<PlannedReplacement>,
+    /// Anything else worth telling the user.
+    pub warnings: Vec<String>,
+    /// Hooks that would run, in order.
+    #[serde(default)]
+    pub hooks: Vec<PlannedHook>,
+}
+
+impl TransactionPlan {
+    /// Creates an empty plan for an operation.
+    pub fn new(operation: Operation) -> Self {
+        Self {
+            operation,
+            packages: Vec::new(),
+            conflicts: Vec::new(),
+            replacements: Vec::new(),
+            warnings: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Returns true if the plan changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Packages with the given action.
+    pub fn with_action(&self, action: PackageAction) -> impl Iterator<Item = &PlannedPackage> {
+        self.packages.iter().filter(move |p| p.action == action)
+    }
+
+    /// Packages pulled in (or removed) as dependencies.
+    pub fn dependencies(&self) -> impl Iterator<Item = &PlannedPackage> {
+        self.packages.iter().filter(|p| !p.explicit)
+    }
+
+    /// Total bytes to download.
+    pub fn download_size(&self) -> u64 {
+        self.packages.iter().map(|p| p.download_size).sum()
+    }
+
+    /// Net change in installed size in bytes.
+    pub fn installed_size_delta(&self) -> i64 {
+        self.packages.iter().map(|p| p.installed_size_delta).sum()
+    }
+}
+
+/// Picks the action for installing `new` over an optionally installed `old`.
+pub fn install_action(old: Option<&Version>, new: &Version) -> PackageAction {
+    match old.map(|old| new.vercmp(old)) {
+        None => PackageAction::Install,
+        Some(std::cmp::Ordering::Greater) => PackageAction::Upgrade,
+        Some(std::cmp::Ordering::Less) => PackageAction::Downgrade,
+        Some(std::cmp::Ordering::Equal) => PackageAction::Reinstall,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::PackageBackend;
+
+    fn planned(name: &str, action: PackageAction, download: u64, delta: i64) -> PlannedPackage {
+        PlannedPackage {
+            name: name.into(),
+            action,
+            old_version: None,
+            new_version: None,
+            repository: "extra".into(),
+            download_size: download,
+            installed_size_delta: delta,
+            explicit: name == "foo",
+        }
+    }
+
+    #[test]
+    fn test_plan_totals() {
+        let mut plan = TransactionPlan::new(Operation::install(
+            vec!["foo".into()],
+            PackageBackend::Pacman,
+        ));
+        assert!(plan.is_empty());
+
+        plan.packages = vec![
+            planned("foo", PackageAction::Install, 100, 400),
+            planned("libfoo", PackageAction::Upgrade, 50, 20),
+            planned("oldfoo", PackageAction::Remove, 0, -300),
+        ];
+
+        assert_eq!(plan.download_size(), 150);
+        assert_eq!(plan.installed_size_delta(), 120);
+        assert_eq!(plan.dependencies().count(), 2);
+        assert_eq!(plan.with_action(PackageAction::Remove).count(), 1);
+    }
+
+    #[test]
+    fn test_install_action() {
+        let v1 = Version::new("1.0-1");
+        let v2 = Version::new("1.1-1");
+        assert_eq!(install_action(None, &v1), PackageAction::Install);
+        assert_eq!(install_action(Some(&v1), &v2), PackageAction::Upgrade);
+        assert_eq!(install_action(Some(&v2), &v1), PackageAction::Downgrade);
+        assert_eq!(install_action(Some(&v1), &v1), PackageAction::Reinstall);
+    }
+}
@@ -0,0 +1,425 @@
+//! Search query language shared by all backends.
+//!
+//! A query is a list of whitespace-separated terms that must all match:
+//!
+//! - `firefox` matches the name or description, ignoring case.
+//! - `name:`, `desc:` and `license:` match a substring of that field;
+//!   `provides:`, `group:` and `repo:` match a whole value.
+//! - `installed:yes` or `installed:no` filters on install state.
+//! - `"firefox"` matches the exact name; `desc:"web browser"` the exact
+//!   field value. Quotes may contain spaces.
+//! - `/^lib.*32$/` is a case-insensitive regular expression, and can be
+//!   scoped like any other value: `provides:/^sh$/`.
+//!
+//! Unknown `field:` prefixes are searched for literally.
+
+use crate::error::{Error, Result};
+use crate::package::{PackageInfo, PackageStatus, SearchResult};
+use regex::Regex;
+
+/// A package field a term can be restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryField {
+    /// Name or description.
+    Any,
+    /// Package name.
+    Name,
+    /// Description.
+    Description,
+    /// Names of provided packages.
+    Provides,
+    /// Package groups.
+    Group,
+    /// Repository or remote.
+    Repository,
+    /// Licenses.
+    License,
+}
+
+impl QueryField {
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        Some(match prefix.to_lowercase().as_str() {
+            "name" => Self::Name,
+            "desc" | "description" => Self::Description,
+            "provides" => Self::Provides,
+            "group" => Self::Group,
+            "repo" | "repository" => Self::Repository,
+            "license" => Self::License,
+            _ => return None,
+        })
+    }
+
+    /// Whether a bare value must equal a whole field value rather than a
+    /// substring of it.
+    fn whole_value(self) -> bool {
+        matches!(self, Self::Provides | Self::Group | Self::Repository)
+    }
+}
+
+/// How a term's value is compared.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// Case-insensitive substring; stored lowercased.
+    Contains(String),
+    /// Case-insensitive equality; stored lowercased.
+    Exact(String),
+    /// Case-insensitive regular expression.
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Self::Contains(needle) => value.to_lowercase().contains(needle.as_str()),
+            Self::Exact(expected) => value.to_lowercase() == *expected,
+            Self::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// One condition of a query.
+#[derive(Debug, Clone)]
+pub enum QueryTerm {
+    /// A field must match.
+    Field(QueryField, Matcher),
+    /// The package must (or must not) be installed.
+    Installed(bool),
+}
+
+/// How well a package matched, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MatchRank {
+    /// The name equals the search text.
+    ExactName,
+    /// The name starts with the search text.
+    NamePrefix,
+    /// The name contains the search text.
+    NameSubstring,
+    /// Only the description matched.
+    Description,
+    /// Matched on other fields only.
+    Other,
+}
+
+/// Fields a query can be matched against.
+pub trait Searchable {
+    /// Package name.
+    fn name(&self) -> &str;
+    /// Description.
+    fn description(&self) -> &str;
+    /// Repository or remote.
+    fn repository(&self) -> &str;
+    /// Whether the package is installed.
+    fn installed(&self) -> bool;
+    /// Names of provided packages.
+    fn provides(&self) -> impl Iterator<Item = &str> {
+        std::iter::empty()
+    }
+    /// Package groups.
+    fn groups(&self) -> impl Iterator<Item = &str> {
+        std::iter::empty()
+    }
+    /// Licenses.
+    fn licenses(&self) -> impl Iterator<Item = &str> {
+        std::iter::empty()
+    }
+}
+
+/// A parsed search query.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    terms: Vec<QueryTerm>,
+}
+
+impl SearchQuery {
+    /// Parses a query string.
+    pub fn parse(input: &str) -> Result<Self> {
+        let terms = tokenize(input)?
+            .into_iter()
+            .map(|token| parse_term(&token))
+            .collect::<Result<_>>()?;
+        Ok(Self { terms })
+    }
+
+    /// The parsed terms.
+    pub fn terms(&self) -> &[QueryTerm] {
+        &self.terms
+    }
+
+    /// Whether the query has no terms and so matches everything.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Whether every term matches `item`.
+    pub fn matches(&self, item: &impl Searchable) -> bool {
+        self.terms.iter().all(|term| match term {
+            QueryTerm::Installed(installed) => item.installed() == *installed,
+            QueryTerm::Field(field, matcher) => match field {
+                QueryField::Any => {
+                    matcher.is_match(item.name()) || matcher.is_match(item.description())
+                }
+                QueryField::Name => matcher.is_match(item.name()),
+                QueryField::Description => matcher.is_match(item.description()),
+                QueryField::Repository => matcher.is_match(item.repository()),
+                QueryField::Provides => item.provides().any(|v| matcher.is_match(v)),
+                QueryField::Group => item.groups().any(|v| matcher.is_match(v)),
+                QueryField::License => item.licenses().any(|v| matcher.is_match(v)),
+            },
+        })
+    }
+
+    /// Ranks a matching package by its best name or free-text term.
+    pub fn rank(&self, name: &str, description: &str) -> MatchRank {
+        let name_lower = name.to_lowercase();
+
+        self.terms
+            .iter()
+            .filter_map(|term| match term {
+                QueryTerm::Field(field @ (QueryField::Any | QueryField::Name), matcher) => {
+                    Some(rank_term(*field, matcher, name, &name_lower, description))
+                }
+                _ => None,
+            })
+            .min()
+            .unwrap_or(MatchRank::Other)
+    }
+
+    /// Orders search results by rank, then by name.
+    pub fn sort(&self, results: &mut [SearchResult]) {
+        results.sort_by_cached_key(|r| (self.rank(&r.name, &r.description), r.name.clone()));
+    }
+}
+
+fn rank_term(
+    field: QueryField,
+    matcher: &Matcher,
+    name: &str,
+    name_lower: &str,
+    description: &str,
+) -> MatchRank {
+    let in_name = match matcher {
+        Matcher::Exact(text) if name_lower == text => return MatchRank::ExactName,
+        Matcher::Contains(text) if name_lower == text => return MatchRank::ExactName,
+        Matcher::Contains(text) if name_lower.starts_with(text.as_str()) => {
+            return MatchRank::NamePrefix
+        }
+        Matcher::Regex(regex) => {
+            regex
+                .find(name)
+                .map(|m| match (m.start(), m.end() == name.len()) {
+                    (0, true) => MatchRank::ExactName,
+                    (0, false) => MatchRank::NamePrefix,
+                    _ => MatchRank::NameSubstring,
+                })
+        }
+        _ => matcher.is_match(name).then_some(MatchRank::NameSubstring),
+    };
+
+    match in_name {
+        Some(rank) => rank,
+        None if field == QueryField::Any && matcher.is_match(description) => MatchRank::Description,
+        None => MatchRank::Other,
+    }
+}
+
+/// Splits on whitespace, keeping quoted text together.
+fn tokenize(input: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(Error::InvalidQuery(format!(
+            "unterminated quote in '{}'",
+            input
+        )));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(token: &str) -> Result<QueryTerm> {
+    let (field, value) = match token.split_once(':') {
+        Some((prefix, value)) if prefix.eq_ignore_ascii_case("installed") => {
+            return match value.to_lowercase().as_str() {
+                "yes" | "true" => Ok(QueryTerm::Installed(true)),
+                "no" | "false" => Ok(QueryTerm::Installed(false)),
+                _ => Err(Error::InvalidQuery(format!(
+                    "installed: expects yes or no, got '{}'",
+                    value
+                ))),
+            };
+        }
+        Some((prefix, value)) => match QueryField::from_prefix(prefix) {
+            Some(field) => (field, value),
+            None => (QueryField::Any, token),
+        },
+        None => (QueryField::Any, token),
+    };
+
+    if value.is_empty() {
+        return Err(Error::InvalidQuery(format!("'{}' has no value", token)));
+    }
+
+    if let Some(text) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        // Unscoped quotes mean an exact package name.
+        let field = match field {
+            QueryField::Any => QueryField::Name,
+            field => field,
+        };
+        return Ok(QueryTerm::Field(field, Matcher::Exact(text.to_lowercase())));
+    }
+
+    let matcher = if let Some(pattern) = value
+        .strip_prefix('/')
+        .and_then(|v| v.strip_suffix('/'))
+        .filter(|p| !p.is_empty())
+    {
+        let regex = Regex::new(&format!("(?i){}", pattern))
+            .map_err(|e| Error::InvalidQuery(format!("invalid regex /{}/: {}", pattern, e)))?;
+        Matcher::Regex(regex)
+    } else if field.whole_value() {
+        Matcher::Exact(value.to_lowercase())
+    } else {
+        Matcher::Contains(value.to_lowercase())
+    };
+
+    Ok(QueryTerm::Field(field, matcher))
+}
+
+impl Searchable for SearchResult {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    fn installed(&self) -> bool {
+        self.installed
+    }
+}
+
+impl Searchable for PackageInfo {
+    fn name(&self) -> &str {
+        &self.package.name
+    }
+
+    fn description(&self) -> &str {
+        &self.package.description
+    }
+
+    fn repository(&self) -> &str {
+        &self.package.repository
+    }
+
+    fn installed(&self) -> bool {
+        self.package.status != PackageStatus::Available
+    }
+
+    fn provides(&self) -> impl Iterator<Item = &str> {
+        self.provides.iter().map(|d| d.name.as_str())
+    }
+
+    fn groups(&self) -> impl Iterator<Item = &str> {
+        self.groups.iter().map(|g| g.as_str())
+    }
+
+    fn licenses(&self) -> impl Iterator<Item = &str> {
+        self.licenses.iter().map(|l| l.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{PackageBackend, Version};
+
+    fn info(name: &str, description: &str, provides: &[&str]) -> PackageInfo {
+        let mut info = PackageInfo::test(name, "1.0-1");
+        info.package.description = description.into();
+        info.package.status = PackageStatus::Available;
+        info.package.repository = "extra".into();
+        info.licenses = vec!["MPL-2.0".into()];
+        info.provides = provides
+            .iter()
+            .map(|p| crate::dependency::Dependency::parse(p))
+            .collect();
+        info
+    }
+
+    #[test]
+    fn test_field_terms() {
+        let bash = info("bash", "The GNU Bourne Again shell", &["sh"]);
+        let firefox = info("firefox", "Fast, Private & Safe Web Browser", &[]);
+
+        let query = SearchQuery::parse("provides:sh repo:extra installed:no").unwrap();
+        assert!(query.matches(&bash));
+        assert!(!query.matches(&firefox));
+
+        let query = SearchQuery::parse(r#"desc:"fast, private & safe web browser""#).unwrap();
+        assert!(query.matches(&firefox));
+        assert!(SearchQuery::parse("license:mpl /^fire.*x$/")
+            .unwrap()
+            .matches(&firefox));
+        assert!(!SearchQuery::parse(r#""fire""#).unwrap().matches(&firefox));
+
+        assert!(SearchQuery::parse("installed:maybe").is_err());
+        assert!(SearchQuery::parse("/(/").is_err());
+        assert!(SearchQuery::parse("desc:\"open").is_err());
+    }
+
+    #[test]
+    fn test_ranking() {
+        let query = SearchQuery::parse("fire").unwrap();
+        let mut results: Vec<_> = [
+            ("campfire", "Chat client"),
+            ("wildfire", "Browser plugin"),
+            ("vlc", "Plays fire videos"),
+            ("firefox", "Web browser"),
+            ("fire", "Fire starter"),
+        ]
+        .iter()
+        .map(|(name, description)| SearchResult {
+            name: name.to_string(),
+            version: Version::new("1"),
+            description: description.to_string(),
+            backend: PackageBackend::Pacman,
+            repository: "extra".into(),
+            installed: false,
+            installed_version: None,
+        })
+        .collect();
+
+        query.sort(&mut results);
+        let names: Vec<_> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["fire", "firefox", "campfire", "wildfire", "vlc"]);
+        assert_eq!(
+            query.rank("vlc", "Plays fire videos"),
+            MatchRank::Description
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Questions a backend may ask while a transaction runs.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A decision a backend needs from the user before it can continue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionQuestion {
+    /// Replace an installed package with one that supersedes it.
+    ReplacePackage {
+        /// Installed package being replaced.
+        old: String,
+        /// Package replacing it.
+        new: String,
+        /// Repository the new package comes from.
+        repository: String,
+    },
+    /// Import a PGP key needed to verify a package or database.
+    ImportKey {
+        /// Key fingerprint.
+        fingerprint: String,
+        /// Key owner.
+        uid: String,
+    },
+    /// Remove an installed package that conflicts with one being installed.
+    RemoveConflict {
+        /// Package being installed.
+        package: String,
+        /// Installed package it conflicts with.
+        conflict: String,
+        /// The conflicting dependency expression, if any.
+        reason: Option<String>,
+    },
+    /// Pick one of several packages providing a dependency.
+    SelectProvider {
+        /// The dependency being resolved.
+        dependency: String,
+        /// Candidate packages, in repository order.
+        providers: Vec<String>,
+    },
+    /// Delete a corrupted package file from the cache.
+    RemoveCorrupted {
+        /// Path of the corrupted file.
+        filepath: String,
+        /// Why the file is considered corrupted.
+        reason: String,
+    },
+    /// Skip packages whose dependencies cannot be resolved.
+    SkipUnresolvable {
+        /// Packages that would be skipped.
+        packages: Vec<String>,
+    },
+    /// Install a package listed in IgnorePkg/IgnoreGroup anyway.
+    InstallIgnored {
+        /// The ignored package.
+        package: String,
+    },
+}
+
+/// The answer to a [`TransactionQuestion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuestionAnswer {
+    /// Accept.
+    Yes,
+    /// Decline.
+    No,
+    /// Use the provider at this index (for `SelectProvider`).
+    Provider(usize),
+}
+
+impl QuestionAnswer {
+    /// Returns true for `Yes`.
+    pub fn is_yes(&self) -> bool {
+        matches!(self, QuestionAnswer::Yes)
+    }
+}
+
+impl TransactionQuestion {
+    /// Returns the answer pacman uses with `--noconfirm`.
+    pub fn default_answer(&self) -> QuestionAnswer {
+        match self {
+            TransactionQuestion::ReplacePackage { .. }
+            | TransactionQuestion::ImportKey { .. }
+            | TransactionQuestion::RemoveCorrupted { .. }
+            | TransactionQuestion::InstallIgnored { .. } => QuestionAnswer::Yes,
+            TransactionQuestion::RemoveConflict { .. }
+            | TransactionQuestion::SkipUnresolvable { .. } => QuestionAnswer::No,
+            TransactionQuestion::SelectProvider { .. } => QuestionAnswer::Provider(0),
+        }
+    }
+}
+
+impl fmt::Display for TransactionQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionQuestion::ReplacePackage {
+                old,
+                new,
+                repository,
+            } => write!(f, "Replace {} with {}/{}?", old, repository, new),
+            TransactionQuestion::ImportKey { fingerprint, uid } => {
+                write!(f, "Import PGP key {}, \"{}\"?", fingerprint, uid)
+            }
+            TransactionQuestion::RemoveConflict {
+                package, conflict, ..
+            } => write!(
+                f,
+                "{} and {} are in conflict. Remove {}?",
+                package, conflict, conflict
+            ),
+            TransactionQuestion::SelectProvider {
+                dependency,
+                providers,
+            } => write!(
+                f,
+                "There are {} providers available for {}: {}",
+                providers.len(),
+                dependency,
+                providers.join(", ")
+            ),
+            TransactionQuestion::RemoveCorrupted { filepath, reason } => write!(
+                f,
+                "File {} is corrupted ({}). Do you want to delete it?",
+                filepath, reason
+            ),
+            TransactionQuestion::SkipUnresolvable { packages } => write!(
+                f,
+                "The following packages cannot be upgraded due to unresolvable dependencies: {}. \
+                 Skip them for this upgrade?",
+                packages.join(", ")
+            ),
+            TransactionQuestion::InstallIgnored { package } => {
+                write!(
+                    f,
+                    "{} is in IgnorePkg/IgnoreGroup. Install anyway?",
+                    package
+                )
+            }
+        }
+    }
+}
+
+/// Answers questions raised by a backend during a transaction.
+#[async_trait]
+pub trait QuestionAnswerer: Send + Sync {
+    /// Answers a question. Backends wait for the answer before continuing.
+    async fn answer(&self, question: &TransactionQuestion) -> QuestionAnswer;
+}
+
+/// Answers every question with its default, like `pacman --noconfirm`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultAnswerer;
+
+#[async_trait]
+impl QuestionAnswerer for DefaultAnswerer {
+    async fn answer(&self, question: &TransactionQuestion) -> QuestionAnswer {
+        question.default_answer()
+    }
+}
+
+/// Declines every question and picks the first provider.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeclineAnswerer;
+
+#[async_trait]
+impl QuestionAnswerer for DeclineAnswerer {
+    async fn answer(&self, question: &TransactionQuestion) -> QuestionAnswer {
+        match question {
+            TransactionQuestion::SelectProvider { .. } => QuestionAnswer::Provider(0),
+            _ => QuestionAnswer::No,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_answers() {
+        let replace = TransactionQuestion::ReplacePackage {
+            old: "foo".into(),
+            new: "foo-ng".into(),
+            repository: "extra".into(),
+        };
+        assert_eq!(replace.default_answer(), QuestionAnswer::Yes);
+        assert_eq!(replace.to_string(), "Replace foo with extra/foo-ng?");
+
+        let conflict = TransactionQuestion::RemoveConflict {
+            package: "foo".into(),
+            conflict: "bar".into(),
+            reason: None,
+        };
+        assert_eq!(conflict.default_answer(), QuestionAnswer::No);
+
+        let provider = TransactionQuestion::SelectProvider {
+            dependency: "sh".into(),
+            providers: vec!["bash".into(), "dash".into()],
+        };
+        assert_eq!(provider.default_answer(), QuestionAnswer::Provider(0));
+    }
+}
@@ -1,12 +1,18 @@
 //! Package source trait defining the backend interface.
 
+use crate::cancel::CancellationToken;
 use crate::error::Result;
-use crate::operation::{Operation, OperationProgress, OperationResult};
-use crate::package::{Package, PackageInfo, SearchResult, UpdateInfo};
+use crate::event::TransactionEvent;
+use crate::operation::{Operation, OperationResult};
+use crate::package::{Package, PackageBackend, PackageInfo, SearchResult, UpdateInfo};
+use crate::plan::TransactionPlan;
+use crate::question::QuestionAnswerer;
+use crate::verify::PackageVerification;
 use async_trait::async_trait;
+use std::sync::Arc;
 
-/// Callback type for progress updates.
-pub type ProgressCallback = Box<dyn Fn(OperationProgress) + Send + Sync>;
+/// Callback type for transaction events.
+pub type EventCallback = Box<dyn Fn(TransactionEvent) + Send + Sync>;
 
 /// Trait implemented by all package backends (pacman, flatpak, etc.).
 #[async_trait]
@@ -17,6 +23,11 @@ pub trait PackageSource: Send + Sync {
     /// Returns a human-readable name for this source.
     fn display_name(&self) -> &str;
 
+    /// Returns the backend tag carried by packages from this source.
+    fn backend(&self) -> PackageBackend {
+        PackageBackend::from_id(self.source_id())
+    }
+
     /// Checks if this backend is available on the system.
     async fn is_available(&self) -> bool;
 
@@ -32,14 +43,23 @@ pub trait PackageSource: Send + Sync {
     /// Gets detailed information about a specific package.
     async fn get_package_info(&self, name: &str) -> Result<PackageInfo>;
 
+    /// Works out what `operation` would do without changing the system.
+    async fn plan(&self, operation: &Operation) -> Result<TransactionPlan>;
+
     /// Executes a package operation.
     async fn execute(&self, operation: Operation) -> Result<OperationResult>;
 
-    /// Executes a package operation with progress reporting.
+    /// Executes a package operation, reporting progress through `events`.
+    ///
+    /// Questions raised mid-transaction are put to `questions`. Backends
+    /// check `cancel` at safe points and return a result with status
+    /// `Cancelled` when it fires.
     async fn execute_with_progress(
         &self,
         operation: Operation,
-        progress: ProgressCallback,
+        events: EventCallback,
+        questions: Arc<dyn QuestionAnswerer>,
+        cancel: CancellationToken,
     ) -> Result<OperationResult>;
 
     /// Synchronizes package databases (if applicable).
@@ -53,6 +73,20 @@ pub trait PackageSource: Send + Sync {
 
     /// Lists orphan packages (installed as deps but no longer needed).
     async fn list_orphans(&self) -> Result<Vec<Package>>;
+
+    /// Checks installed files against what their packages installed, for
+    /// the named packages or every installed package if `names` is empty.
+    /// Reports each package through `events` and stops with
+    /// [`crate::error::Error::Cancelled`] once `cancel` fires. Sources that
+    /// do not track installed files report nothing.
+    async fn verify_packages(
+        &self,
+        _names: &[String],
+        _events: EventCallback,
+        _cancel: CancellationToken,
+    ) -> Result<Vec<PackageVerification>> {
+        Ok(Vec::new())
+    }
 }
 
 /// Extension trait for common operations across sources.
@@ -0,0 +1,110 @@
+//! Reports from checking installed files against their packages.
+
+use crate::package::PackageBackend;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How an installed file differs from what its package installed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileProblem {
+    /// The file no longer exists.
+    Missing,
+    /// A different kind of file (regular file, directory, symlink) is there.
+    WrongType {
+        /// Kind the package installed.
+        expected: String,
+        /// Kind found on disk.
+        actual: String,
+    },
+    /// The size differs.
+    Size {
+        /// Size the package installed.
+        expected: u64,
+        /// Size on disk.
+        actual: u64,
+    },
+    /// The permission bits differ.
+    Mode {
+        /// Mode the package installed.
+        expected: u32,
+        /// Mode on disk.
+        actual: u32,
+    },
+    /// The contents differ although the size matches.
+    Checksum,
+    /// A symlink points somewhere else.
+    SymlinkTarget {
+        /// Target the package installed.
+        expected: String,
+        /// Target on disk.
+        actual: String,
+    },
+    /// The file could not be inspected.
+    Unreadable {
+        /// Why it could not be read.
+        reason: String,
+    },
+}
+
+impl fmt::Display for FileProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileProblem::Missing => write!(f, "missing"),
+            FileProblem::WrongType { expected, actual } => {
+                write!(f, "is a {} instead of a {}", actual, expected)
+            }
+            FileProblem::Size { expected, actual } => {
+                write!(f, "size changed ({} to {} bytes)", expected, actual)
+            }
+            FileProblem::Mode { expected, actual } => {
+                write!(f, "permissions changed ({:o} to {:o})", expected, actual)
+            }
+            FileProblem::Checksum => write!(f, "contents changed"),
+            FileProblem::SymlinkTarget { expected, actual } => {
+                write!(f, "points to {} instead of {}", actual, expected)
+            }
+            FileProblem::Unreadable { reason } => write!(f, "unreadable: {}", reason),
+        }
+    }
+}
+
+/// A file that failed verification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIssue {
+    /// Absolute path of the file.
+    pub path: String,
+    /// What is wrong with it.
+    pub problem: FileProblem,
+}
+
+/// Verification report for one installed package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageVerification {
+    /// Package name.
+    pub package: String,
+    /// Backend the package belongs to.
+    pub backend: PackageBackend,
+    /// Number of files and directories checked.
+    pub files_checked: usize,
+    /// Problems with files that should be exactly as installed.
+    pub corrupted: Vec<FileIssue>,
+    /// Changes to configuration (backup) files, which are usually
+    /// intentional.
+    pub modified_config: Vec<FileIssue>,
+    /// Files that could not be read, so whether they changed is unknown.
+    #[serde(default)]
+    pub unverified: Vec<FileIssue>,
+}
+
+impl PackageVerification {
+    /// Whether every file that should be unchanged is.
+    pub fn is_intact(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+
+    /// Whether every file could be checked.
+    pub fn is_complete(&self) -> bool {
+        self.unverified.is_empty()
+    }
+}
@@ -0,0 +1,753 @@
+//! The in-memory package source.
+
+use crate::fixture::{Fixture, FixtureFailure, FixturePackage};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::info;
+use xpm_core::{
+    cancel::CancellationToken,
+    dependency::Dependency,
+    error::{Error, PackageConflict, Result, UnsatisfiedDependency},
+    event::{PackageAction, TransactionEvent},
+    operation::{Operation, OperationKind, OperationResult, OperationStatus},
+    package::{
+        InstallReason, Package, PackageBackend, PackageInfo, PackageStatus, SearchResult,
+        UpdateInfo, Version,
+    },
+    plan::{install_action, PlannedConflict, PlannedPackage, TransactionPlan},
+    query::SearchQuery,
+    question::{DefaultAnswerer, QuestionAnswerer, TransactionQuestion},
+    source::{EventCallback, PackageSource},
+};
+
+/// Mutable package state shared by all calls.
+struct State {
+    packages: BTreeMap<String, FixturePackage>,
+    cache_size: u64,
+}
+
+impl State {
+    fn get(&self, name: &str) -> Result<&FixturePackage> {
+        self.packages
+            .get(name)
+            .ok_or_else(|| Error::PackageNotFound(name.to_string()))
+    }
+
+    fn installed(&self) -> impl Iterator<Item = &FixturePackage> {
+        self.packages.values().filter(|p| p.installed.is_some())
+    }
+
+    /// Returns true if an installed package outside `except` depends on `name`.
+    fn is_required(&self, name: &str, except: &HashSet<String>) -> bool {
+        self.installed()
+            .filter(|p| !except.contains(&p.name))
+            .any(|p| p.depends.iter().any(|d| Dependency::parse(d).name == name))
+    }
+}
+
+/// A package source backed by an in-memory fixture.
+///
+/// Operations change only the in-memory state and emit a scripted sequence
+/// of [`TransactionEvent`]s, pausing `step_delay_ms` between steps so the UI
+/// has something to show.
+pub struct FakeBackend {
+    source_id: String,
+    display_name: String,
+    step_delay: Duration,
+    failures: Vec<FixtureFailure>,
+    state: Mutex<State>,
+}
+
+impl FakeBackend {
+    /// Creates a fake source from a fixture.
+    pub fn from_fixture(fixture: Fixture) -> Self {
+        let display_name = format!("{} (demo)", PackageBackend::from_id(&fixture.source_id));
+
+        Self {
+            source_id: fixture.source_id,
+            display_name,
+            step_delay: Duration::from_millis(fixture.step_delay_ms),
+            failures: fixture.failures,
+            state: Mutex::new(State {
+                packages: fixture
+                    .packages
+                    .into_iter()
+                    .map(|p| (p.name.clone(), p))
+                    .collect(),
+                cache_size: fixture.cache_size,
+            }),
+        }
+    }
+
+    /// Creates a fake source with the built-in demo fixture.
+    pub fn demo() -> Self {
+        Self::from_fixture(Fixture::demo())
+    }
+
+    /// Creates a fake source from a fixture file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_fixture(Fixture::load(path)?))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn package(&self, pkg: &FixturePackage) -> Package {
+        let (version, status) = match &pkg.installed {
+            Some(installed) if is_upgradable(pkg) => {
+                (Version::new(installed), PackageStatus::Upgradable)
+            }
+            Some(installed) => (Version::new(installed), PackageStatus::Installed),
+            None => (Version::new(&pkg.version), PackageStatus::Available),
+        };
+
+        Package::new(
+            &pkg.name,
+            version,
+            &pkg.description,
+            self.backend(),
+            status,
+            &pkg.repository,
+        )
+    }
+
+    /// Returns the scripted failure for `plan`, if any.
+    fn failure_for(&self, plan: &TransactionPlan) -> Option<&FixtureFailure> {
+        self.failures.iter().find(|f| {
+            f.operation
+                .as_ref()
+                .is_none_or(|kind| *kind == plan.operation.kind)
+                && (plan.operation.packages.contains(&f.package)
+                    || plan.packages.iter().any(|p| p.name == f.package))
+        })
+    }
+
+    async fn step(&self) {
+        if !self.step_delay.is_zero() {
+            tokio::time::sleep(self.step_delay).await;
+        }
+    }
+
+    /// Changes the install reason of installed packages and returns the
+    /// packages that changed.
+    fn set_reason(&self, names: &[String], dependency: bool) -> Result<Vec<Package>> {
+        let mut state = self.state();
+        for name in names {
+            if state.get(name)?.installed.is_none() {
+                return Err(Error::PackageNotFound(format!("{} is not installed", name)));
+            }
+        }
+
+        let mut changed = Vec::new();
+        for name in names {
+            let pkg = state.packages.get_mut(name).expect("checked above");
+            if pkg.dependency != dependency {
+                pkg.dependency = dependency;
+                let pkg = pkg.clone();
+                changed.push(self.package(&pkg));
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Applies one planned change to the state; new packages are marked as
+    /// dependencies if they were pulled in or `as_deps` is set.
+    fn apply(&self, planned: &PlannedPackage, as_deps: bool) -> Package {
+        let mut state = self.state();
+        let pkg = state
+            .packages
+            .get_mut(&planned.name)
+            .expect("planned packages exist");
+
+        match &planned.new_version {
+            Some(version) => {
+                if pkg.installed.is_none() {
+                    pkg.dependency = as_deps || !planned.explicit;
+                }
+                pkg.installed = Some(version.to_string());
+                let pkg = pkg.clone();
+                state.cache_size += planned.download_size;
+                self.package(&pkg)
+            }
+            None => {
+                pkg.installed = None;
+                pkg.dependency = false;
+                let pkg = pkg.clone();
+                self.package(&pkg)
+            }
+        }
+    }
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        Self::demo()
+    }
+}
+
+/// Works out what `operation` would do against `state`.
+fn plan_operation(state: &State, operation: &Operation) -> Result<TransactionPlan> {
+    let mut plan = TransactionPlan::new(operation.clone());
+
+    match operation.kind {
+        OperationKind::Install => plan_install(state, &mut plan, &operation.packages)?,
+        OperationKind::Update => {
+            let targets: Vec<String> = operation
+                .packages
+                .iter()
+                .map(|name| {
+                    let pkg = state.get(name)?;
+                    if pkg.installed.is_none() {
+                        return Err(Error::PackageNotFound(format!("{} is not installed", name)));
+                    }
+                    Ok(pkg)
+                })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|p| is_upgradable(p))
+                .map(|p| p.name.clone())
+                .collect();
+            plan_install(state, &mut plan, &targets)?;
+        }
+        OperationKind::SystemUpgrade => {
+            let targets: Vec<String> = state
+                .installed()
+                .filter(|p| is_upgradable(p))
+                .map(|p| p.name.clone())
+                .collect();
+            plan_install(state, &mut plan, &targets)?;
+        }
+        OperationKind::Remove | OperationKind::RemoveWithDeps => {
+            let recursive =
+                operation.kind == OperationKind::RemoveWithDeps || operation.options.recursive;
+            plan_remove(
+                state,
+                &mut plan,
+                &operation.packages,
+                recursive,
+                operation.options.force,
+            )?;
+        }
+        OperationKind::RemoveOrphans => {
+            let orphans: Vec<String> = orphans(state).map(|p| p.name.clone()).collect();
+            plan_remove(state, &mut plan, &orphans, true, false)?;
+        }
+        OperationKind::Downgrade => {
+            return Err(Error::Other(
+                "The demo backend has no package files to downgrade to".to_string(),
+            ))
+        }
+        OperationKind::SyncDatabases
+        | OperationKind::CleanCache
+        | OperationKind::MarkExplicit
+        | OperationKind::MarkDependency => {}
+    }
+
+    Ok(plan)
+}
+
+fn is_upgradable(pkg: &FixturePackage) -> bool {
+    pkg.installed
+        .as_deref()
+        .is_some_and(|v| Version::new(&pkg.version).vercmp(&Version::new(v)).is_gt())
+}
+
+fn orphans(state: &State) -> impl Iterator<Item = &FixturePackage> {
+    state
+        .installed()
+        .filter(|p| p.dependency && !state.is_required(&p.name, &HashSet::new()))
+}
+
+fn plan_install(state: &State, plan: &mut TransactionPlan, targets: &[String]) -> Result<()> {
+    let mut queue: VecDeque<(String, bool)> = targets.iter().map(|t| (t.clone(), true)).collect();
+    let mut seen = HashSet::new();
+    let mut missing = Vec::new();
+
+    while let Some((name, explicit)) = queue.pop_front() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let pkg = state.get(&name)?;
+        let old = pkg.installed.as_deref().map(Version::new);
+        let new = Version::new(&pkg.version);
+
+        plan.packages.push(PlannedPackage {
+            name: pkg.name.clone(),
+            action: install_action(old.as_ref(), &new),
+            old_version: old.clone(),
+            new_version: Some(new),
+            repository: pkg.repository.clone(),
+            download_size: pkg.download_size,
+            installed_size_delta: match old {
+                // The fixture does not track old sizes, so upgrades count as
+                // size-neutral.
+                Some(_) => 0,
+                None => pkg.installed_size as i64,
+            },
+            explicit,
+        });
+
+        for depend in &pkg.depends {
+            let dep = Dependency::parse(depend);
+            let Some(provider) = state.packages.get(&dep.name) else {
+                missing.push(UnsatisfiedDependency {
+                    package: pkg.name.clone(),
+                    dependency: depend.clone(),
+                    causing: None,
+                });
+                continue;
+            };
+
+            let satisfied = provider
+                .installed
+                .as_deref()
+                .is_some_and(|v| dep.version_matches(&Version::new(v)));
+            if satisfied {
+                continue;
+            }
+            if !dep.version_matches(&Version::new(&provider.version)) {
+                missing.push(UnsatisfiedDependency {
+                    package: pkg.name.clone(),
+                    dependency: depend.clone(),
+                    causing: None,
+                });
+                continue;
+            }
+            queue.push_back((provider.name.clone(), false));
+        }
+
+        for other in state.installed() {
+            let conflicts =
+                pkg.conflicts.contains(&other.name) || other.conflicts.contains(&pkg.name);
+            if conflicts && other.name != pkg.name {
+                plan.conflicts.push(PlannedConflict {
+                    package: pkg.name.clone(),
+                    conflicts_with: other.name.clone(),
+                    reason: None,
+                });
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(Error::UnsatisfiedDependencies(missing));
+    }
+
+    // Conflicting packages are removed as part of the transaction.
+    for conflict in plan.conflicts.clone() {
+        let pkg = state.get(&conflict.conflicts_with)?;
+        plan.packages.push(removal(pkg, false));
+    }
+
+    Ok(())
+}
+
+fn plan_remove(
+    state: &State,
+    plan: &mut TransactionPlan,
+    targets: &[String],
+    recursive: bool,
+    force: bool,
+) -> Result<()> {
+    let mut removing = HashSet::new();
+    for name in targets {
+        let pkg = state.get(name)?;
+        if pkg.installed.is_none() {
+            return Err(Error::PackageNotFound(format!("{} is not installed", name)));
+        }
+        removing.insert(name.clone());
+        plan.packages.push(removal(pkg, true));
+    }
+
+    if recursive {
+        // Keep removing dependencies nothing else needs until none are left.
+        loop {
+            let unneeded: Vec<&FixturePackage> = state
+                .installed()
+                .filter(|p| p.dependency && !removing.contains(&p.name))
+                .filter(|p| {
+                    removing.iter().any(|r| {
+                        state.packages[r]
+                            .depends
+                            .iter()
+                            .any(|d| Dependency::parse(d).name == p.name)
+                    })
+                })
+                .filter(|p| !state.is_required(&p.name, &removing))
+                .collect();
+            if unneeded.is_empty() {
+                break;
+            }
+            for pkg in unneeded {
+                removing.insert(pkg.name.clone());
+                plan.packages.push(removal(pkg, false));
+            }
+        }
+    }
+
+    if !force {
+        let broken: Vec<UnsatisfiedDependency> = state
+            .installed()
+            .filter(|p| !removing.contains(&p.name))
+            .flat_map(|p| {
+                p.depends
+                    .iter()
+                    .filter(|d| removing.contains(&Dependency::parse(d).name))
+                    .map(|d| UnsatisfiedDependency {
+                        package: p.name.clone(),
+                        dependency: d.clone(),
+                        causing: Some(Dependency::parse(d).name),
+                    })
+            })
+            .collect();
+        if !broken.is_empty() {
+            return Err(Error::UnsatisfiedDependencies(broken));
+        }
+    }
+
+    Ok(())
+}
+
+fn removal(pkg: &FixturePackage, explicit: bool) -> PlannedPackage {
+    PlannedPackage {
+        name: pkg.name.clone(),
+        action: PackageAction::Remove,
+        old_version: pkg.installed.as_deref().map(Version::new),
+        new_version: None,
+        repository: pkg.repository.clone(),
+        download_size: 0,
+        installed_size_delta: -(pkg.installed_size as i64),
+        explicit,
+    }
+}
+
+#[async_trait]
+impl PackageSource for FakeBackend {
+    fn source_id(&self) -> &str {
+        &self.source_id
+    }
+
+    fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    async fn is_available(&self) -> bool {
+        true
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let query = SearchQuery::parse(query)?;
+        let state = self.state();
+
+        let mut results: Vec<_> = state
+            .packages
+            .values()
+            .filter(|p| query.matches(*p))
+            .map(|p| SearchResult {
+                name: p.name.clone(),
+                version: Version::new(&p.version),
+                description: p.description.clone(),
+                backend: self.backend(),
+                repository: p.repository.clone(),
+                installed: p.installed.is_some(),
+                installed_version: p
+                    .installed
+                    .as_deref()
+                    .filter(|v| *v != p.version)
+                    .map(Version::new),
+            })
+            .collect();
+        query.sort(&mut results);
+
+        Ok(results)
+    }
+
+    async fn list_installed(&self) -> Result<Vec<Package>> {
+        let state = self.state();
+        Ok(state.installed().map(|p| self.package(p)).collect())
+    }
+
+    async fn list_updates(&self) -> Result<Vec<UpdateInfo>> {
+        let state = self.state();
+
+        Ok(state
+            .installed()
+            .filter(|p| is_upgradable(p))
+            .map(|p| UpdateInfo {
+                name: p.name.clone(),
+                current_version: Version::new(p.installed.as_deref().unwrap_or_default()),
+                new_version: Version::new(&p.version),
+                backend: self.backend(),
+                repository: p.repository.clone(),
+                download_size: p.download_size,
+            })
+            .collect())
+    }
+
+    async fn get_package_info(&self, name: &str) -> Result<PackageInfo> {
+        let state = self.state();
+        let pkg = state.get(name)?;
+
+        Ok(PackageInfo {
+            package: self.package(pkg),
+            url: pkg.url.clone(),
+            licenses: pkg.licenses.clone(),
+            groups: Vec::new(),
+            depends: pkg.depends.iter().map(|d| Dependency::parse(d)).collect(),
+            optdepends: Vec::new(),
+            provides: Vec::new(),
+            conflicts: pkg.conflicts.iter().map(|d| Dependency::parse(d)).collect(),
+            replaces: Vec::new(),
+            installed_size: pkg.installed_size,
+            download_size: pkg.download_size,
+            build_date: None,
+            install_date: None,
+            packager: None,
+            arch: "x86_64".to_string(),
+            reason: pkg.installed.as_ref().map(|_| {
+                if pkg.dependency {
+                    InstallReason::Dependency
+                } else {
+                    InstallReason::Explicit
+                }
+            }),
+        })
+    }
+
+    async fn plan(&self, operation: &Operation) -> Result<TransactionPlan> {
+        plan_operation(&self.state(), operation)
+    }
+
+    async fn execute(&self, operation: Operation) -> Result<OperationResult> {
+        self.execute_with_progress(
+            operation,
+            Box::new(|_| {}),
+            Arc::new(DefaultAnswerer),
+            CancellationToken::new(),
+        )
+        .await
+    }
+
+    async fn execute_with_progress(
+        &self,
+        operation: Operation,
+        events: EventCallback,
+        questions: Arc<dyn QuestionAnswerer>,
+        cancel: CancellationToken,
+    ) -> Result<OperationResult> {
+        let start = Instant::now();
+        let elapsed = || start.elapsed().as_millis() as u64;
+
+        info!("Executing fake operation: {:?}", operation.kind);
+
+        if cancel.is_cancelled() {
+            return Ok(OperationResult::cancelled(operation, Vec::new(), 0));
+        }
+
+        match operation.kind {
+            OperationKind::SyncDatabases => {
+                events(TransactionEvent::Status(OperationStatus::Downloading));
+                self.step().await;
+                events(TransactionEvent::Status(OperationStatus::Completed));
+                return Ok(OperationResult::success(operation, Vec::new(), elapsed()));
+            }
+            OperationKind::CleanCache => {
+                self.clean_cache(0).await?;
+                events(TransactionEvent::Status(OperationStatus::Completed));
+                return Ok(OperationResult::success(operation, Vec::new(), elapsed()));
+            }
+            OperationKind::MarkExplicit | OperationKind::MarkDependency => {
+                let dependency = operation.kind == OperationKind::MarkDependency;
+                return Ok(match self.set_reason(&operation.packages, dependency) {
+                    Ok(changed) => OperationResult::success(operation, changed, elapsed()),
+                    Err(e) => OperationResult::from_error(operation, &e, elapsed()),
+                });
+            }
+            _ => {}
+        }
+
+        events(TransactionEvent::Status(OperationStatus::ResolvingDeps));
+        let plan = match self.plan(&operation).await {
+            Ok(plan) => plan,
+            Err(e) => return Ok(OperationResult::from_error(operation, &e, elapsed())),
+        };
+        self.step().await;
+
+        let mut declined = Vec::new();
+        for conflict in &plan.conflicts {
+            let answer = questions
+                .answer(&TransactionQuestion::RemoveConflict {
+                    package: conflict.package.clone(),
+                    conflict: conflict.conflicts_with.clone(),
+                    reason: conflict.reason.clone(),
+                })
+                .await;
+            if !answer.is_yes() {
+                declined.push(PackageConflict {
+                    package: conflict.package.clone(),
+                    conflicts_with: conflict.conflicts_with.clone(),
+                    reason: conflict.reason.clone(),
+                });
+            }
+        }
+        if !declined.is_empty() {
+            let error = Error::PackageConflicts(declined);
+            return Ok(OperationResult::from_error(operation, &error, elapsed()));
+        }
+
+        let downloads: Vec<&PlannedPackage> = plan
+            .packages
+            .iter()
+            .filter(|p| p.download_size > 0)
+            .collect();
+        if !downloads.is_empty() {
+            events(TransactionEvent::Status(OperationStatus::Downloading));
+            events(TransactionEvent::DownloadTotals {
+                files: downloads.len(),
+                total_bytes: plan.download_size(),
+            });
+            for pkg in downloads {
+                if cancel.is_cancelled() {
+                    return Ok(OperationResult::cancelled(operation, Vec::new(), elapsed()));
+                }
+                let file = format!(
+                    "{}-{}",
+                    pkg.name,
+                    pkg.new_version
+                        .as_ref()
+                        .map(|v| v.to_string())
+                        .unwrap_or_default()
+                );
+                events(TransactionEvent::DownloadStarted { file: file.clone() });
+                for downloaded in [pkg.download_size / 2, pkg.download_size] {
+                    self.step().await;
+                    events(TransactionEvent::DownloadProgress {
+                        file: file.clone(),
+                        downloaded,
+                        total: pkg.download_size,
+                    });
+                }
+                events(TransactionEvent::DownloadCompleted {
+                    file,
+                    success: true,
+                });
+            }
+        }
+
+        // Scripted failures hit before anything is changed, like a file
+        // conflict found by libalpm ahead of extraction.
+        if let Some(failure) = self.failure_for(&plan) {
+            return Ok(OperationResult::from_error(
+                operation,
+                &failure.error,
+                elapsed(),
+            ));
+        }
+
+        let old_versions: HashMap<String, Version> = plan
+            .packages
+            .iter()
+            .filter_map(|p| Some((p.name.clone(), p.old_version.clone()?)))
+            .collect();
+
+        events(TransactionEvent::Status(OperationStatus::Processing));
+        let total = plan.packages.len();
+        let mut affected = Vec::new();
+        for (i, planned) in plan.packages.iter().enumerate() {
+            if cancel.is_cancelled() {
+                return Ok(OperationResult::cancelled(operation, affected, elapsed())
+                    .with_old_versions(old_versions));
+            }
+            let index = i + 1;
+            events(TransactionEvent::PackageStarted {
+                action: planned.action,
+                name: planned.name.clone(),
+                index,
+                total,
+            });
+            for percent in [50, 100] {
+                self.step().await;
+                events(TransactionEvent::PackageProgress {
+                    action: planned.action,
+                    name: planned.name.clone(),
+                    percent,
+                    index,
+                    total,
+                });
+            }
+            affected.push(self.apply(planned, operation.options.as_deps));
+            events(TransactionEvent::PackageCompleted {
+                action: planned.action,
+                name: planned.name.clone(),
+                index,
+                total,
+            });
+        }
+
+        if !affected.is_empty() {
+            events(TransactionEvent::Status(OperationStatus::RunningHooks));
+            self.step().await;
+        }
+        events(TransactionEvent::Status(OperationStatus::Completed));
+
+        let mut result = OperationResult::success(operation, affected, elapsed())
+            .with_old_versions(old_versions);
+        result.warnings = plan.warnings;
+        Ok(result)
+    }
+
+    async fn sync_databases(&self) -> Result<()> {
+        self.step().await;
+        Ok(())
+    }
+
+    async fn get_cache_size(&self) -> Result<u64> {
+        Ok(self.state().cache_size)
+    }
+
+    async fn clean_cache(&self, _keep_versions: usize) -> Result<u64> {
+        Ok(std::mem::take(&mut self.state().cache_size))
+    }
+
+    async fn list_orphans(&self) -> Result<Vec<Package>> {
+        let state = self.state();
+        Ok(orphans(&state)
+            .map(|p| {
+                let mut pkg = self.package(p);
+                pkg.status = PackageStatus::Orphan;
+                pkg
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_demo_fixture() {
+        let fake = FakeBackend::demo();
+        let updates = fake.list_updates().await.unwrap();
+        assert!(updates.iter().any(|u| u.name == "firefox"));
+
+        let orphans = fake.list_orphans().await.unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].name, "python-six");
+
+        let plan = fake
+            .plan(&Operation::install(
+                vec!["vlc".into()],
+                PackageBackend::Pacman,
+            ))
+            .await
+            .unwrap();
+        let names: Vec<&str> = plan.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["vlc", "libvlc"]);
+        assert!(!plan.packages[1].explicit);
+    }
+}
@@ -0,0 +1,139 @@
+//! Fixture files describing a fake package universe.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use xpm_core::error::{Error, Result};
+use xpm_core::operation::OperationKind;
+use xpm_core::query::Searchable;
+
+/// A package known to the fake source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixturePackage {
+    /// Package name.
+    pub name: String,
+    /// Version available from the repository.
+    pub version: String,
+    /// Installed version, if installed.
+    #[serde(default)]
+    pub installed: Option<String>,
+    /// True if installed as a dependency rather than explicitly.
+    #[serde(default)]
+    pub dependency: bool,
+    /// Package description.
+    #[serde(default)]
+    pub description: String,
+    /// Repository name.
+    #[serde(default = "default_repository")]
+    pub repository: String,
+    /// Dependency expressions, e.g. `glibc>=2.40`.
+    #[serde(default)]
+    pub depends: Vec<String>,
+    /// Packages this one cannot be installed alongside.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// License(s).
+    #[serde(default)]
+    pub licenses: Vec<String>,
+    /// Homepage.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Installed size in bytes.
+    #[serde(default)]
+    pub installed_size: u64,
+    /// Download size in bytes.
+    #[serde(default)]
+    pub download_size: u64,
+}
+
+fn default_repository() -> String {
+    "demo".to_string()
+}
+
+impl Searchable for FixturePackage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    fn installed(&self) -> bool {
+        self.installed.is_some()
+    }
+
+    fn licenses(&self) -> impl Iterator<Item = &str> {
+        self.licenses.iter().map(|l| l.as_str())
+    }
+}
+
+/// A scripted failure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureFailure {
+    /// Operations touching this package fail.
+    pub package: String,
+    /// Only fail this kind of operation; any kind if unset.
+    #[serde(default)]
+    pub operation: Option<OperationKind>,
+    /// The error to report.
+    pub error: Error,
+}
+
+/// The contents of a fixture file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    /// Source id the fake registers as.
+    #[serde(default = "default_source_id")]
+    pub source_id: String,
+    /// Pause between scripted progress steps, in milliseconds.
+    #[serde(default)]
+    pub step_delay_ms: u64,
+    /// Reported package cache size in bytes.
+    #[serde(default)]
+    pub cache_size: u64,
+    /// All known packages, installed or not.
+    #[serde(default)]
+    pub packages: Vec<FixturePackage>,
+    /// Scripted failures.
+    #[serde(default)]
+    pub failures: Vec<FixtureFailure>,
+}
+
+fn default_source_id() -> String {
+    "pacman".to_string()
+}
+
+impl Fixture {
+    /// Parses a JSON fixture.
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text)
+            .map_err(|e| Error::ConfigError(format!("Invalid fixture: {}", e)))
+    }
+
+    /// Parses a TOML fixture.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| Error::ConfigError(format!("Invalid fixture: {}", e)))
+    }
+
+    /// Loads a fixture file, choosing the format by extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&text),
+            _ => Self::from_json(&text),
+        }
+    }
+
+    /// The built-in demo fixture.
+    pub fn demo() -> Self {
+        Self::from_json(include_str!("../fixtures/demo.json")).expect("demo fixture is valid")
+    }
+}
@@ -0,0 +1,9 @@
+//! In-memory package source for xPackageManager.
+//!
+//! Used by the UI's `--demo` mode and by the service integration tests.
+
+pub mod backend;
+pub mod fixture;
+
+pub use backend::FakeBackend;
+pub use fixture::Fixture;
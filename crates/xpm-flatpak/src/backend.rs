@@ -1,14 +1,20 @@
 //! Flatpak backend implementation.
 
 use crate::remote::RemoteManager;
+use crate::transaction;
 use async_trait::async_trait;
 use libflatpak::{gio, prelude::*, Installation, RefKind};
+use std::sync::Arc;
 use tracing::{info, warn};
 use xpm_core::{
+    cancel::CancellationToken,
     error::{Error, Result},
     operation::{Operation, OperationKind, OperationResult},
     package::{Package, PackageBackend, PackageInfo, PackageStatus, SearchResult, UpdateInfo, Version},
-    source::{PackageSource, ProgressCallback},
+    plan::TransactionPlan,
+    query::SearchQuery,
+    question::{DefaultAnswerer, QuestionAnswerer},
+    source::{EventCallback, PackageSource},
 };
 
 /// The Flatpak backend.
@@ -159,11 +165,10 @@ impl PackageSource for FlatpakBackend {
     }
 
     async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
-        let query = query.to_string();
+        let query = SearchQuery::parse(query)?;
 
         tokio::task::spawn_blocking(move || {
             let mut results = Vec::new();
-            let query_lower = query.to_lowercase();
 
             // Try both user and system installations.
             let installations: Vec<Installation> = [
@@ -180,6 +185,21 @@ impl PackageSource for FlatpakBackend {
                     Err(_) => continue,
                 };
 
+                // Installed apps, looked up once rather than per remote ref.
+                let installed_refs: std::collections::HashSet<_> = installation
+                    .list_installed_refs(gio::Cancellable::NONE)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|iref| iref.kind() == RefKind::App)
+                    .map(|iref| {
+                        (
+                            iref.name().map(|s| s.to_string()).unwrap_or_default(),
+                            iref.arch().map(|s| s.to_string()).unwrap_or_default(),
+                            iref.branch().map(|s| s.to_string()).unwrap_or_default(),
+                        )
+                    })
+                    .collect();
+
                 for remote in remotes {
                     let remote_name = match remote.name() {
                         Some(n) => n.to_string(),
@@ -205,19 +225,12 @@ impl PackageSource for FlatpakBackend {
                             None => continue,
                         };
 
-                        if !name.to_lowercase().contains(&query_lower) {
-                            continue;
-                        }
-
                         let arch = rref.arch().map(|s| s.to_string()).unwrap_or_default();
                         let branch = rref.branch().map(|s| s.to_string()).unwrap_or_default();
+                        let installed =
+                            installed_refs.contains(&(name.clone(), arch, branch.clone()));
 
-                        // Check if installed.
-                        let installed = installation
-                            .installed_ref(RefKind::App, &name, Some(&arch), Some(&branch), gio::Cancellable::NONE)
-                            .is_ok();
-
-                        results.push(SearchResult {
+                        let result = SearchResult {
                             name: name.clone(),
                             version: Version::new(&branch),
                             description: name.clone(), // Remote refs don't have descriptions.
@@ -225,7 +238,10 @@ impl PackageSource for FlatpakBackend {
                             repository: remote_name.clone(),
                             installed,
                             installed_version: None,
-                        });
+                        };
+                        if query.matches(&result) {
+                            results.push(result);
+                        }
                     }
                 }
             }
@@ -233,6 +249,7 @@ impl PackageSource for FlatpakBackend {
             // Deduplicate results.
             results.sort_by(|a, b| a.name.cmp(&b.name));
             results.dedup_by(|a, b| a.name == b.name);
+            query.sort(&mut results);
 
             Ok(results)
         })
@@ -412,33 +429,89 @@ impl PackageSource for FlatpakBackend {
         .map_err(|e| Error::Other(e.to_string()))?
     }
 
+    async fn plan(&self, operation: &Operation) -> Result<TransactionPlan> {
+        let operation = operation.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let installations: Vec<Installation> = [
+                Self::get_system_installation().ok(),
+                Self::get_user_installation().ok(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+
+            transaction::plan(installations, &operation)
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?
+    }
+
     async fn execute(&self, operation: Operation) -> Result<OperationResult> {
-        self.execute_with_progress(operation, Box::new(|_| {})).await
+        self.execute_with_progress(
+            operation,
+            Box::new(|_| {}),
+            Arc::new(DefaultAnswerer),
+            CancellationToken::new(),
+        )
+        .await
     }
 
     async fn execute_with_progress(
         &self,
         operation: Operation,
-        _progress: ProgressCallback,
+        events: EventCallback,
+        _questions: Arc<dyn QuestionAnswerer>,
+        cancel: CancellationToken,
     ) -> Result<OperationResult> {
         let start = std::time::Instant::now();
 
         info!("Flatpak operation: {:?}", operation.kind);
 
-        // Flatpak operations would need proper implementation with transactions.
-        // For now, return placeholder results.
+        if cancel.is_cancelled() {
+            return Ok(OperationResult::cancelled(operation, Vec::new(), 0));
+        }
+
         let result = match operation.kind {
             OperationKind::Install
             | OperationKind::Remove
             | OperationKind::RemoveWithDeps
             | OperationKind::Update
             | OperationKind::SystemUpgrade => {
-                warn!("Flatpak operations not fully implemented yet");
-                OperationResult::failure(
-                    operation,
-                    "Flatpak operations require further implementation",
-                    start.elapsed().as_millis() as u64,
-                )
+                tokio::task::spawn_blocking(move || {
+                    let installations: Vec<Installation> = [
+                        Self::get_system_installation().ok(),
+                        Self::get_user_installation().ok(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+
+                    let mut affected = Vec::new();
+                    let outcome = transaction::run(
+                        installations,
+                        &operation,
+                        Arc::new(events),
+                        &cancel,
+                        &mut affected,
+                    );
+                    let duration_ms = start.elapsed().as_millis() as u64;
+
+                    match outcome {
+                        Ok(()) => OperationResult::success(operation, affected, duration_ms),
+                        Err(Error::Cancelled) => {
+                            warn!("Flatpak operation cancelled");
+                            OperationResult::cancelled(operation, affected, duration_ms)
+                        }
+                        Err(e) => {
+                            let mut result = OperationResult::failure(operation, e.to_string(), duration_ms);
+                            result.affected_packages = affected;
+                            result
+                        }
+                    }
+                })
+                .await
+                .map_err(|e| Error::Other(e.to_string()))?
             }
             OperationKind::SyncDatabases => {
                 // Flatpak doesn't have separate db sync.
@@ -465,6 +538,14 @@ impl PackageSource for FlatpakBackend {
                     start.elapsed().as_millis() as u64,
                 )
             }
+            OperationKind::MarkExplicit
+            | OperationKind::MarkDependency
+            | OperationKind::Downgrade => {
+                // Flatpak has no install reasons to change and no package
+                // files to downgrade to.
+                let error = format!("{} is not supported for Flatpak", operation.kind);
+                OperationResult::failure(operation, error, start.elapsed().as_millis() as u64)
+            }
         };
 
         Ok(result)
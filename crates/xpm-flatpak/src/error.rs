@@ -0,0 +1,112 @@
+//! Mapping libflatpak and GIO errors onto [`xpm_core::Error`].
+
+use libflatpak::{gio, glib};
+use xpm_core::error::{Error, UnsatisfiedDependency};
+
+/// Converts an error returned by libflatpak.
+pub fn from_glib(err: &glib::Error) -> Error {
+    if let Some(kind) = err.kind::<libflatpak::Error>() {
+        use libflatpak::Error as E;
+
+        return match kind {
+            E::AlreadyInstalled => Error::AlreadyInstalled(err.message().to_string()),
+            E::NotInstalled | E::RefNotFound | E::RemoteNotFound => {
+                Error::PackageNotFound(err.message().to_string())
+            }
+            E::RuntimeNotFound => {
+                // "The application <ref> requires the runtime <ref> which ..."
+                let refs = refs_in(err.message());
+                Error::UnsatisfiedDependencies(vec![UnsatisfiedDependency {
+                    package: refs.first().cloned().unwrap_or_default(),
+                    dependency: refs
+                        .get(1)
+                        .cloned()
+                        .unwrap_or_else(|| err.message().to_string()),
+                    causing: None,
+                }])
+            }
+            E::Aborted => Error::Cancelled,
+            E::Untrusted => Error::SignatureError {
+                package: None,
+                key_id: key_id_in(err.message()),
+                reason: err.message().to_string(),
+            },
+            E::OutOfSpace => Error::DiskFull { required: None },
+            E::PermissionDenied | E::NotAuthorized | E::WrongUser => {
+                Error::PermissionDenied(err.message().to_string())
+            }
+            E::AuthenticationFailed => Error::NetworkError(err.message().to_string()),
+            _ => Error::TransactionError(err.message().to_string()),
+        };
+    }
+
+    if let Some(kind) = err.kind::<gio::IOErrorEnum>() {
+        return match kind {
+            gio::IOErrorEnum::Cancelled => Error::Cancelled,
+            gio::IOErrorEnum::NoSpace => Error::DiskFull { required: None },
+            gio::IOErrorEnum::PermissionDenied => {
+                Error::PermissionDenied(err.message().to_string())
+            }
+            gio::IOErrorEnum::NotFound => Error::PackageNotFound(err.message().to_string()),
+            gio::IOErrorEnum::HostNotFound
+            | gio::IOErrorEnum::HostUnreachable
+            | gio::IOErrorEnum::NetworkUnreachable
+            | gio::IOErrorEnum::ConnectionRefused
+            | gio::IOErrorEnum::TimedOut => Error::NetworkError(err.message().to_string()),
+            _ => Error::TransactionError(err.message().to_string()),
+        };
+    }
+
+    Error::TransactionError(err.message().to_string())
+}
+
+/// The flatpak refs (`name/arch/branch`, optionally prefixed with `app/` or
+/// `runtime/`) mentioned in an error message, in order.
+fn refs_in(message: &str) -> Vec<String> {
+    message
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| matches!(c, '\'' | '"' | ',' | '.' | ':')))
+        .filter(|word| matches!(word.matches('/').count(), 2 | 3))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The key ID or fingerprint following "key" or "key ID" in a GPG
+/// verification message.
+fn key_id_in(message: &str) -> Option<String> {
+    let words: Vec<&str> = message
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_ascii_alphanumeric()))
+        .collect();
+    words.windows(2).find_map(|pair| {
+        let candidate = pair[1];
+        let after_key = pair[0].eq_ignore_ascii_case("key") || pair[0] == "ID";
+        let hex = candidate.len() >= 8 && candidate.chars().all(|c| c.is_ascii_hexdigit());
+        (after_key && hex).then(|| candidate.to_uppercase())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_parsing() {
+        assert_eq!(
+            refs_in(
+                "The application org.gnome.Maps/x86_64/stable requires the runtime \
+                 org.gnome.Platform/x86_64/46 which was not found"
+            ),
+            ["org.gnome.Maps/x86_64/stable", "org.gnome.Platform/x86_64/46"]
+        );
+        assert_eq!(
+            key_id_in("Signature made using RSA key ID 4D7B1A2F8C2C9D3E: public key not found")
+                .as_deref(),
+            Some("4D7B1A2F8C2C9D3E")
+        );
+        assert_eq!(
+            key_id_in("GPG signatures found, but none are in trusted keyring"),
+            None
+        );
+    }
+}
@@ -1,6 +1,8 @@
 //! Flatpak backend for xPackageManager.
 
 pub mod backend;
+pub mod error;
 pub mod remote;
+mod transaction;
 
 pub use backend::FlatpakBackend;
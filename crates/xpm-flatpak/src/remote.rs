@@ -1,5 +1,6 @@
 //! Flatpak remote management.
 
+use crate::error::from_glib;
 use libflatpak::{gio, prelude::*, Installation, Remote};
 use tracing::{debug, info};
 use xpm_core::error::{Error, Result};
@@ -67,7 +68,7 @@ impl RemoteManager {
 
         installation
             .add_remote(&remote, true, gio::Cancellable::NONE)
-            .map_err(|e| Error::TransactionError(e.to_string()))?;
+            .map_err(|e| from_glib(&e))?;
 
         info!("Added flatpak remote: {} ({})", name, url);
         Ok(())
@@ -77,7 +78,7 @@ impl RemoteManager {
     pub fn remove_remote(&self, installation: &Installation, name: &str) -> Result<()> {
         installation
             .remove_remote(name, gio::Cancellable::NONE)
-            .map_err(|e| Error::TransactionError(e.to_string()))?;
+            .map_err(|e| from_glib(&e))?;
 
         info!("Removed flatpak remote: {}", name);
         Ok(())
@@ -101,7 +102,7 @@ impl RemoteManager {
 
                 installation
                     .modify_remote(&remote, gio::Cancellable::NONE)
-                    .map_err(|e| Error::TransactionError(e.to_string()))?;
+                    .map_err(|e| from_glib(&e))?;
 
                 info!(
                     "Flatpak remote {} {}",
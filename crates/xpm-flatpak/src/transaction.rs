@@ -0,0 +1,536 @@
+//! Flatpak transaction handling.
+
+use crate::error::from_glib;
+use libflatpak::{
+    gio, glib, prelude::*, InstalledRef, Installation, QueryFlags, Ref, RefKind, RemoteRef,
+    Transaction, TransactionOperationType,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::info;
+use xpm_core::{
+    cancel::CancellationToken,
+    error::{Error, Result},
+    event::{PackageAction, TransactionEvent},
+    operation::{Operation, OperationKind, OperationStatus},
+    package::{Package, PackageBackend, PackageStatus, Version},
+    plan::{PlannedPackage, TransactionPlan},
+    source::EventCallback,
+};
+
+/// A single ref queued in a transaction.
+struct Target {
+    name: String,
+    full_ref: String,
+    branch: String,
+    origin: String,
+}
+
+/// The refs to process for one installation.
+struct Batch {
+    installation: Installation,
+    installs: Vec<Target>,
+    uninstalls: Vec<Target>,
+    updates: Vec<Target>,
+}
+
+impl Batch {
+    fn new(installation: Installation) -> Self {
+        Self {
+            installation,
+            installs: Vec::new(),
+            uninstalls: Vec::new(),
+            updates: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.installs.is_empty() && self.uninstalls.is_empty() && self.updates.is_empty()
+    }
+
+    /// Creates a transaction with this batch's refs queued.
+    fn transaction(&self, cancellable: &gio::Cancellable) -> Result<Transaction> {
+        let transaction = Transaction::for_installation(&self.installation, Some(cancellable))
+            .map_err(|e| from_glib(&e))?;
+        transaction.add_default_dependency_sources();
+
+        for target in &self.installs {
+            transaction
+                .add_install(&target.origin, &target.full_ref, &[])
+                .map_err(|e| from_glib(&e))?;
+        }
+        for target in &self.uninstalls {
+            transaction
+                .add_uninstall(&target.full_ref)
+                .map_err(|e| from_glib(&e))?;
+        }
+        for target in &self.updates {
+            transaction
+                .add_update(&target.full_ref, &[], None)
+                .map_err(|e| from_glib(&e))?;
+        }
+
+        Ok(transaction)
+    }
+}
+
+/// Runs an install, remove or update operation as libflatpak transactions,
+/// one per installation.
+///
+/// Packages processed by completed transactions are appended to `affected`,
+/// so callers can report partial progress when a later batch fails or is
+/// cancelled. Cancellation aborts in-flight downloads and vetoes the commit
+/// of any transaction that has not started deploying yet.
+pub(crate) fn run(
+    installations: Vec<Installation>,
+    operation: &Operation,
+    events: Arc<EventCallback>,
+    cancel: &CancellationToken,
+    affected: &mut Vec<Package>,
+) -> Result<()> {
+    let cancellable = gio::Cancellable::new();
+    {
+        let cancellable = cancellable.clone();
+        cancel.on_cancel(move || cancellable.cancel());
+    }
+
+    events(TransactionEvent::Status(OperationStatus::ResolvingDeps));
+    let batches = resolve(installations, operation, QueryFlags::NONE, &cancellable)?;
+
+    for batch in batches {
+        cancel.check()?;
+
+        let transaction = batch.transaction(&cancellable)?;
+
+        // Last chance to back out before anything is deployed.
+        let total = Arc::new(AtomicUsize::new(0));
+        {
+            let token = cancel.clone();
+            let total = total.clone();
+            let events = events.clone();
+            transaction.connect_ready(move |transaction| {
+                total.store(transaction.operations().len(), Ordering::SeqCst);
+                events(TransactionEvent::Status(OperationStatus::Processing));
+                !token.is_cancelled()
+            });
+        }
+        connect_events(&transaction, &events, &total);
+
+        transaction.run(Some(&cancellable)).map_err(|e| {
+            if cancel.is_cancelled() {
+                Error::Cancelled
+            } else {
+                from_glib(&e)
+            }
+        })?;
+
+        info!(
+            "Flatpak transaction finished: {} installed, {} removed, {} updated",
+            batch.installs.len(),
+            batch.uninstalls.len(),
+            batch.updates.len()
+        );
+
+        let installed = batch.installs.into_iter().chain(batch.updates);
+        affected.extend(installed.map(|t| t.into_package(PackageStatus::Installed)));
+        affected.extend(
+            batch
+                .uninstalls
+                .into_iter()
+                .map(|t| t.into_package(PackageStatus::Available)),
+        );
+    }
+
+    Ok(())
+}
+
+/// Resolves an operation, including the runtimes it pulls in, without
+/// downloading or deploying anything.
+///
+/// Only the cached remote metadata is read, so planning never touches the
+/// network. Versions are short commit IDs, as `flatpak` shows them; related
+/// refs such as extensions are not listed.
+pub(crate) fn plan(
+    installations: Vec<Installation>,
+    operation: &Operation,
+) -> Result<TransactionPlan> {
+    let mut plan = TransactionPlan::new(operation.clone());
+    if !matches!(
+        operation.kind,
+        OperationKind::Install
+            | OperationKind::Remove
+            | OperationKind::RemoveWithDeps
+            | OperationKind::Update
+            | OperationKind::SystemUpgrade
+    ) {
+        return Ok(plan);
+    }
+
+    let cancellable = gio::Cancellable::new();
+    let batches = resolve(
+        installations,
+        operation,
+        QueryFlags::ONLY_CACHED,
+        &cancellable,
+    )?;
+
+    for batch in batches {
+        let installed: HashMap<String, InstalledRef> = batch
+            .installation
+            .list_installed_refs(Some(&cancellable))
+            .map(|refs| {
+                refs.into_iter()
+                    .filter_map(|iref| Some((iref.format_ref()?.to_string(), iref)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let cached_remote_ref = |origin: &str, full_ref: &str| -> Option<RemoteRef> {
+            let parsed = Ref::parse(full_ref).ok()?;
+            batch
+                .installation
+                .fetch_remote_ref_sync_full(
+                    origin,
+                    parsed.kind(),
+                    &parsed.name()?,
+                    parsed.arch().as_deref(),
+                    parsed.branch().as_deref(),
+                    QueryFlags::ONLY_CACHED,
+                    Some(&cancellable),
+                )
+                .ok()
+        };
+
+        for target in &batch.installs {
+            let remote = cached_remote_ref(&target.origin, &target.full_ref);
+            plan.packages.push(planned_install(target, remote.as_ref(), true));
+
+            // The runtime comes along unless it is already installed.
+            let Some(runtime) = remote.as_ref().and_then(runtime_ref) else {
+                continue;
+            };
+            let planned = plan.packages.iter().any(|p| p.name == ref_name(&runtime));
+            if installed.contains_key(&runtime) || planned {
+                continue;
+            }
+            let target = Target {
+                name: ref_name(&runtime),
+                branch: branch(&runtime),
+                full_ref: runtime,
+                origin: target.origin.clone(),
+            };
+            let remote = cached_remote_ref(&target.origin, &target.full_ref);
+            plan.packages.push(planned_install(&target, remote.as_ref(), false));
+        }
+
+        for target in &batch.uninstalls {
+            let old = installed.get(&target.full_ref);
+            plan.packages.push(PlannedPackage {
+                name: target.name.clone(),
+                action: PackageAction::Remove,
+                explicit: true,
+                old_version: old.and_then(|iref| iref.commit()).map(|c| short_commit(&c)),
+                new_version: None,
+                repository: target.origin.clone(),
+                download_size: 0,
+                installed_size_delta: -(old.map_or(0, |iref| iref.installed_size()) as i64),
+            });
+        }
+
+        for target in &batch.updates {
+            let Some(old) = installed.get(&target.full_ref) else {
+                continue;
+            };
+            let (current, latest) = (old.commit(), old.latest_commit());
+            if latest.is_none() || latest == current {
+                continue;
+            }
+            let remote = cached_remote_ref(&target.origin, &target.full_ref);
+            plan.packages.push(PlannedPackage {
+                name: target.name.clone(),
+                action: PackageAction::Upgrade,
+                explicit: operation.kind == OperationKind::Update,
+                old_version: current.map(|c| short_commit(&c)),
+                new_version: latest.map(|c| short_commit(&c)),
+                repository: target.origin.clone(),
+                download_size: remote.as_ref().map_or(0, |r| r.download_size()),
+                installed_size_delta: remote
+                    .as_ref()
+                    .map_or(0, |r| r.installed_size() as i64 - old.installed_size() as i64),
+            });
+        }
+    }
+
+    Ok(plan)
+}
+
+/// A planned install of `target`, sized from its cached remote ref.
+fn planned_install(target: &Target, remote: Option<&RemoteRef>, explicit: bool) -> PlannedPackage {
+    PlannedPackage {
+        name: target.name.clone(),
+        action: PackageAction::Install,
+        explicit,
+        old_version: None,
+        new_version: remote.and_then(|r| r.commit()).map(|c| short_commit(&c)),
+        repository: target.origin.clone(),
+        download_size: remote.map_or(0, |r| r.download_size()),
+        installed_size_delta: remote.map_or(0, |r| r.installed_size() as i64),
+    }
+}
+
+/// The full ref of the runtime an app's metadata asks for.
+fn runtime_ref(remote: &RemoteRef) -> Option<String> {
+    let keyfile = glib::KeyFile::new();
+    keyfile
+        .load_from_bytes(&remote.metadata()?, glib::KeyFileFlags::NONE)
+        .ok()?;
+    let runtime = keyfile.string("Application", "runtime").ok()?;
+    Some(format!("runtime/{}", runtime))
+}
+
+/// The abbreviated commit ID `flatpak` shows.
+fn short_commit(commit: &str) -> Version {
+    Version::new(&commit[..commit.len().min(12)])
+}
+
+/// Relays per-operation progress from a transaction as events.
+fn connect_events(
+    transaction: &Transaction,
+    events: &Arc<EventCallback>,
+    total: &Arc<AtomicUsize>,
+) {
+    let index = Arc::new(AtomicUsize::new(0));
+
+    {
+        let events = events.clone();
+        let total = total.clone();
+        let index = index.clone();
+        transaction.connect_new_operation(move |_, operation, progress| {
+            let action = operation_action(operation.operation_type());
+            let name = ref_name(&operation.get_ref().unwrap_or_default());
+            let index = index.fetch_add(1, Ordering::SeqCst) + 1;
+            let total = total.load(Ordering::SeqCst).max(index);
+
+            events(TransactionEvent::PackageStarted {
+                action,
+                name: name.clone(),
+                index,
+                total,
+            });
+
+            let events = events.clone();
+            progress.set_update_frequency(200);
+            progress.connect_changed(move |progress| {
+                events(TransactionEvent::PackageProgress {
+                    action,
+                    name: name.clone(),
+                    percent: progress.progress().clamp(0, 100) as u8,
+                    index,
+                    total,
+                });
+            });
+        });
+    }
+
+    {
+        let events = events.clone();
+        let total = total.clone();
+        let index = index.clone();
+        transaction.connect_operation_done(move |_, operation, _, _| {
+            let index = index.load(Ordering::SeqCst);
+            events(TransactionEvent::PackageCompleted {
+                action: operation_action(operation.operation_type()),
+                name: ref_name(&operation.get_ref().unwrap_or_default()),
+                index,
+                total: total.load(Ordering::SeqCst).max(index),
+            });
+        });
+    }
+}
+
+/// Maps a flatpak operation type to the package action it performs.
+fn operation_action(kind: TransactionOperationType) -> PackageAction {
+    match kind {
+        TransactionOperationType::Update => PackageAction::Upgrade,
+        TransactionOperationType::Uninstall => PackageAction::Remove,
+        _ => PackageAction::Install,
+    }
+}
+
+/// Extracts the branch from a full ref.
+fn branch(full_ref: &str) -> String {
+    full_ref.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+/// Extracts the app id from a full ref such as `app/org.foo.Bar/x86_64/stable`.
+fn ref_name(full_ref: &str) -> String {
+    full_ref.split('/').nth(1).unwrap_or(full_ref).to_string()
+}
+
+impl Target {
+    fn into_package(self, status: PackageStatus) -> Package {
+        Package::new(
+            self.name,
+            Version::new(&self.branch),
+            "",
+            PackageBackend::Flatpak,
+            status,
+            self.origin,
+        )
+    }
+}
+
+/// Groups the operation's targets by the installation they belong to.
+/// With [`QueryFlags::ONLY_CACHED`], remotes are not contacted and updates
+/// are the installed refs whose cached latest commit differs.
+fn resolve(
+    installations: Vec<Installation>,
+    operation: &Operation,
+    flags: QueryFlags,
+    cancellable: &gio::Cancellable,
+) -> Result<Vec<Batch>> {
+    let mut pending: Vec<&str> = operation.packages.iter().map(String::as_str).collect();
+    let mut batches = Vec::new();
+
+    for installation in installations {
+        let mut batch = Batch::new(installation);
+
+        match operation.kind {
+            OperationKind::Install => {
+                pending.retain(|name| {
+                    match find_remote_app(&batch.installation, name, flags, cancellable) {
+                        Some(target) => {
+                            batch.installs.push(target);
+                            false
+                        }
+                        None => true,
+                    }
+                });
+            }
+            OperationKind::Remove | OperationKind::RemoveWithDeps => {
+                pending.retain(|name| {
+                    match find_installed_app(&batch.installation, name, cancellable) {
+                        Some(target) => {
+                            batch.uninstalls.push(target);
+                            false
+                        }
+                        None => true,
+                    }
+                });
+            }
+            OperationKind::Update => {
+                pending.retain(|name| {
+                    match find_installed_app(&batch.installation, name, cancellable) {
+                        Some(target) => {
+                            batch.updates.push(target);
+                            false
+                        }
+                        None => true,
+                    }
+                });
+            }
+            OperationKind::SystemUpgrade => {
+                let refs = if flags.contains(QueryFlags::ONLY_CACHED) {
+                    batch
+                        .installation
+                        .list_installed_refs(Some(cancellable))
+                        .map_err(|e| from_glib(&e))?
+                        .into_iter()
+                        .filter(|iref| {
+                            iref.latest_commit().is_some_and(|latest| Some(latest) != iref.commit())
+                        })
+                        .collect()
+                } else {
+                    batch
+                        .installation
+                        .list_installed_refs_for_update(Some(cancellable))
+                        .map_err(|e| from_glib(&e))?
+                };
+                batch.updates.extend(refs.iter().filter_map(|iref| {
+                    Some(Target {
+                        name: iref.name()?.to_string(),
+                        full_ref: iref.format_ref()?.to_string(),
+                        branch: iref.branch().map(|s| s.to_string()).unwrap_or_default(),
+                        origin: iref.origin().map(|s| s.to_string()).unwrap_or_default(),
+                    })
+                }));
+            }
+            _ => {
+                return Err(Error::Other(format!(
+                    "Not a flatpak transaction: {}",
+                    operation.kind
+                )))
+            }
+        }
+
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+    }
+
+    if let Some(name) = pending.first() {
+        return Err(Error::PackageNotFound(name.to_string()));
+    }
+
+    Ok(batches)
+}
+
+/// Finds an installed app by id.
+fn find_installed_app(
+    installation: &Installation,
+    name: &str,
+    cancellable: &gio::Cancellable,
+) -> Option<Target> {
+    let refs = installation
+        .list_installed_refs_by_kind(RefKind::App, Some(cancellable))
+        .ok()?;
+    refs.iter()
+        .find(|iref| iref.name().is_some_and(|n| n == name))
+        .and_then(|iref| {
+            Some(Target {
+                name: name.to_string(),
+                full_ref: iref.format_ref()?.to_string(),
+                branch: iref.branch().map(|s| s.to_string()).unwrap_or_default(),
+                origin: iref.origin().map(|s| s.to_string()).unwrap_or_default(),
+            })
+        })
+}
+
+/// Finds an app by id in this installation's remotes, preferring the
+/// `stable` branch.
+fn find_remote_app(
+    installation: &Installation,
+    name: &str,
+    flags: QueryFlags,
+    cancellable: &gio::Cancellable,
+) -> Option<Target> {
+    let remotes = installation.list_remotes(Some(cancellable)).ok()?;
+
+    for remote in remotes {
+        let Some(remote_name) = remote.name() else {
+            continue;
+        };
+        let Ok(refs) =
+            installation.list_remote_refs_sync_full(&remote_name, flags, Some(cancellable))
+        else {
+            continue;
+        };
+
+        let mut matches: Vec<_> = refs
+            .into_iter()
+            .filter(|rref| rref.kind() == RefKind::App && rref.name().is_some_and(|n| n == name))
+            .collect();
+        matches.sort_by_key(|rref| rref.branch().is_none_or(|b| b != "stable"));
+
+        if let Some(rref) = matches.first() {
+            return Some(Target {
+                name: name.to_string(),
+                full_ref: rref.format_ref()?.to_string(),
+                branch: rref.branch().map(|s| s.to_string()).unwrap_or_default(),
+                origin: remote_name.to_string(),
+            });
+        }
+    }
+
+    None
+}
@@ -0,0 +1,340 @@
+//! Persistent journal of executed operations.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use xpm_core::{
+    error::{Error, Result},
+    event::PackageAction,
+    operation::{Operation, OperationResult, OperationStatus},
+    package::{PackageBackend, PackageStatus, Version},
+    plan::install_action,
+};
+
+/// Default location of the system-wide journal.
+const SYSTEM_JOURNAL: &str = "/var/lib/xpm/history.jsonl";
+
+/// One package changed by an operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageChange {
+    /// Package name.
+    pub name: String,
+    /// Backend the package belongs to.
+    pub backend: PackageBackend,
+    /// What was done to it.
+    pub action: PackageAction,
+    /// Version before the operation, if it was installed.
+    pub old_version: Option<Version>,
+    /// Version after the operation, `None` if removed.
+    pub new_version: Option<Version>,
+}
+
+/// A journal record of one executed operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the operation started.
+    pub started_at: DateTime<Utc>,
+    /// How long it ran, in milliseconds.
+    pub duration_ms: u64,
+    /// The requested operation.
+    pub operation: Operation,
+    /// Final status.
+    pub status: OperationStatus,
+    /// Packages that were changed.
+    pub changes: Vec<PackageChange>,
+    /// Warnings reported by the backend.
+    pub warnings: Vec<String>,
+    /// Error message if the operation failed.
+    pub error: Option<String>,
+    /// The structured error, if any.
+    #[serde(default)]
+    pub error_detail: Option<Error>,
+}
+
+impl HistoryEntry {
+    /// Builds an entry from a result.
+    ///
+    /// `previous` maps package names to the versions installed before the
+    /// operation ran, so upgrades and removals can record what they replaced.
+    pub fn new(
+        started_at: DateTime<Utc>,
+        result: &OperationResult,
+        previous: &HashMap<String, Version>,
+    ) -> Self {
+        let changes = result
+            .affected_packages
+            .iter()
+            .map(|pkg| {
+                let old_version = previous.get(&pkg.name).cloned();
+                if pkg.status == PackageStatus::Installed {
+                    PackageChange {
+                        name: pkg.name.clone(),
+                        backend: pkg.backend.clone(),
+                        action: install_action(old_version.as_ref(), &pkg.version),
+                        old_version,
+                        new_version: Some(pkg.version.clone()),
+                    }
+                } else {
+                    PackageChange {
+                        name: pkg.name.clone(),
+                        backend: pkg.backend.clone(),
+                        action: PackageAction::Remove,
+                        old_version: old_version.or_else(|| Some(pkg.version.clone())),
+                        new_version: None,
+                    }
+                }
+            })
+            .collect();
+
+        Self {
+            started_at,
+            duration_ms: result.duration_ms,
+            operation: result.operation.clone(),
+            status: result.status.clone(),
+            changes,
+            warnings: result.warnings.clone(),
+            error: result.error.clone(),
+            error_detail: result.error_detail.clone(),
+        }
+    }
+
+    /// Returns true if the operation did not complete.
+    pub fn is_failed(&self) -> bool {
+        matches!(
+            self.status,
+            OperationStatus::Failed | OperationStatus::Cancelled
+        )
+    }
+
+    /// Returns true if the entry concerns `package`, either as a target or
+    /// as a changed package.
+    pub fn involves(&self, package: &str) -> bool {
+        self.operation.packages.iter().any(|p| p == package)
+            || self.changes.iter().any(|c| c.name == package)
+    }
+}
+
+/// Filters for [`HistoryJournal::query`].
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// Only entries involving this package.
+    pub package: Option<String>,
+    /// Only entries started at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries started before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only failed or cancelled operations.
+    pub failed_only: bool,
+    /// Maximum number of entries to return.
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Creates a query matching every entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the query to one package.
+    pub fn package(mut self, name: impl Into<String>) -> Self {
+        self.package = Some(name.into());
+        self
+    }
+
+    /// Restricts the query to a date range.
+    pub fn between(mut self, since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self.until = Some(until);
+        self
+    }
+
+    /// Restricts the query to failed or cancelled operations.
+    pub fn failed_only(mut self) -> Self {
+        self.failed_only = true;
+        self
+    }
+
+    /// Limits the number of entries returned.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns true if `entry` passes every filter.
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.package.as_deref().is_none_or(|p| entry.involves(p))
+            && self.since.is_none_or(|since| entry.started_at >= since)
+            && self.until.is_none_or(|until| entry.started_at < until)
+            && (!self.failed_only || entry.is_failed())
+    }
+}
+
+/// An append-only journal stored as one JSON object per line.
+#[derive(Debug, Clone)]
+pub struct HistoryJournal {
+    path: PathBuf,
+}
+
+impl HistoryJournal {
+    /// Opens the journal at `path`. The file is created on first append.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Opens the system-wide journal.
+    pub fn system() -> Self {
+        Self::new(SYSTEM_JOURNAL)
+    }
+
+    /// Opens the journal this process can write to: the system-wide one for
+    /// root, otherwise `$XDG_DATA_HOME/xpm/history.jsonl`, falling back to
+    /// `~/.local/share` and then to a per-user file in the temporary
+    /// directory.
+    pub fn for_current_user() -> Self {
+        let uid = fs::metadata("/proc/self")
+            .map(|m| m.uid())
+            .unwrap_or_default();
+        if uid == 0 {
+            return Self::system();
+        }
+
+        let data = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .filter(|home| !home.is_empty())
+                    .map(|home| Path::new(&home).join(".local/share"))
+            });
+        match data {
+            Some(data) => Self::new(data.join("xpm").join("history.jsonl")),
+            None => Self::new(std::env::temp_dir().join(format!("xpm-history-{}.jsonl", uid))),
+        }
+    }
+
+    /// Gets the journal file path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends an entry.
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(entry)
+            .map_err(|e| Error::Other(format!("Failed to encode history entry: {}", e)))?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Reads every entry, oldest first. Unreadable lines are skipped.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    warn!("Skipping history line {}: {}", i + 1, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Returns matching entries, newest first.
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>> {
+        let entries = self.entries()?;
+        Ok(entries
+            .into_iter()
+            .rev()
+            .filter(|e| query.matches(e))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use xpm_core::package::Package;
+
+    #[test]
+    fn test_journal_query() {
+        let path = std::env::temp_dir().join(format!("xpm-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let journal = HistoryJournal::new(&path);
+        assert!(journal.entries().unwrap().is_empty());
+
+        let start = Utc::now();
+        let upgraded = Package::new(
+            "foo",
+            Version::new("1.1-1"),
+            "",
+            PackageBackend::Pacman,
+            PackageStatus::Installed,
+            "extra",
+        );
+        let ok = OperationResult::success(
+            Operation::update(vec!["foo".into()], PackageBackend::Pacman),
+            vec![upgraded],
+            10,
+        );
+        let previous = HashMap::from([("foo".to_string(), Version::new("1.0-1"))]);
+        journal
+            .append(&HistoryEntry::new(start, &ok, &previous))
+            .unwrap();
+
+        let failed = OperationResult::failure(
+            Operation::install(vec!["bar".into()], PackageBackend::Pacman),
+            "boom",
+            5,
+        );
+        journal
+            .append(&HistoryEntry::new(
+                start + Duration::seconds(1),
+                &failed,
+                &HashMap::new(),
+            ))
+            .unwrap();
+
+        let all = journal.query(&HistoryQuery::new()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(all[0].involves("bar"));
+
+        let foo = journal.query(&HistoryQuery::new().package("foo")).unwrap();
+        assert_eq!(foo.len(), 1);
+        assert_eq!(foo[0].changes[0].action, PackageAction::Upgrade);
+        assert_eq!(foo[0].changes[0].old_version, Some(Version::new("1.0-1")));
+
+        let failed = journal.query(&HistoryQuery::new().failed_only()).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].error.as_deref(), Some("boom"));
+
+        let range = HistoryQuery::new().between(start, start + Duration::milliseconds(500));
+        assert_eq!(journal.query(&range).unwrap().len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,9 +1,15 @@
 //! Business logic and orchestration for xPackageManager.
 
+pub mod history;
 pub mod manager;
 pub mod progress;
+pub mod questions;
+pub mod registry;
 pub mod state;
 
+pub use history::{HistoryEntry, HistoryJournal, HistoryQuery, PackageChange};
 pub use manager::PackageManager;
 pub use progress::ProgressTracker;
+pub use questions::{ChannelAnswerer, PendingQuestion};
+pub use registry::BackendRegistry;
 pub use state::{AppState, ViewState};
@@ -1,74 +1,133 @@
 //! Package manager orchestrator.
 
+use crate::history::{HistoryEntry, HistoryJournal, HistoryQuery};
 use crate::progress::ProgressTracker;
+use crate::registry::BackendRegistry;
 use crate::state::AppState;
-use std::sync::Arc;
-use tokio::sync::{broadcast, Mutex, RwLock};
-use tracing::{error, info};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{error, info, warn};
 use xpm_alpm::AlpmBackend;
 use xpm_core::{
+    cancel::CancellationToken,
     error::{Error, Result},
+    event::TransactionEvent,
     operation::{Operation, OperationProgress, OperationResult},
     package::{Package, PackageBackend, PackageInfo, SearchResult, UpdateInfo},
+    plan::TransactionPlan,
+    query::SearchQuery,
+    question::{DefaultAnswerer, QuestionAnswerer},
     source::PackageSource,
+    verify::PackageVerification,
 };
 use xpm_flatpak::FlatpakBackend;
 
 /// Message types for progress updates.
 #[derive(Debug, Clone)]
 pub enum ProgressMessage {
-    /// Operation progress update.
-    Progress(OperationProgress),
+    /// Transaction event from the running operation.
+    Event(TransactionEvent),
     /// Operation completed.
-    Completed(OperationResult),
-    /// Error occurred.
-    Error(String),
+    Completed(Box<OperationResult>),
+    /// The operation failed before producing a result.
+    Error(Error),
 }
 
 /// The main package manager that orchestrates all backends.
 pub struct PackageManager {
-    alpm: Option<Arc<AlpmBackend>>,
-    flatpak: Option<Arc<FlatpakBackend>>,
+    registry: BackendRegistry,
     state: Arc<RwLock<AppState>>,
-    _progress_tracker: Arc<Mutex<ProgressTracker>>,
+    progress_trackers: Arc<Mutex<BTreeMap<u64, ProgressTracker>>>,
     progress_tx: broadcast::Sender<ProgressMessage>,
+    active_cancel: Mutex<BTreeMap<u64, CancellationToken>>,
+    next_operation_id: AtomicU64,
+    answerer: Arc<dyn QuestionAnswerer>,
+    history: Option<HistoryJournal>,
 }
 
 impl PackageManager {
-    /// Creates a new package manager.
+    /// Creates a new package manager with the built-in backends.
     pub fn new() -> Result<Self> {
-        let (progress_tx, _) = broadcast::channel(100);
+        let mut registry = BackendRegistry::new();
 
         // Initialize backends.
-        let alpm = match AlpmBackend::new() {
+        match AlpmBackend::new() {
             Ok(backend) => {
                 info!("ALPM backend initialized");
-                Some(Arc::new(backend))
-            }
-            Err(e) => {
-                error!("Failed to initialize ALPM: {}", e);
-                None
+                registry.register(Arc::new(backend));
             }
-        };
+            Err(e) => error!("Failed to initialize ALPM: {}", e),
+        }
 
-        let flatpak = match FlatpakBackend::new() {
+        match FlatpakBackend::new() {
             Ok(backend) => {
                 info!("Flatpak backend initialized");
-                Some(Arc::new(backend))
-            }
-            Err(e) => {
-                error!("Failed to initialize Flatpak: {}", e);
-                None
+                registry.register(Arc::new(backend));
             }
-        };
+            Err(e) => error!("Failed to initialize Flatpak: {}", e),
+        }
+
+        let mut manager = Self::with_registry(registry);
+        manager.set_history(Some(HistoryJournal::for_current_user()));
+        Ok(manager)
+    }
+
+    /// Creates a package manager over an explicit set of backends.
+    pub fn with_registry(registry: BackendRegistry) -> Self {
+        let (progress_tx, _) = broadcast::channel(100);
 
-        Ok(Self {
-            alpm,
-            flatpak,
+        Self {
+            registry,
             state: Arc::new(RwLock::new(AppState::new())),
-            _progress_tracker: Arc::new(Mutex::new(ProgressTracker::new())),
+            progress_trackers: Arc::new(Mutex::new(BTreeMap::new())),
             progress_tx,
-        })
+            active_cancel: Mutex::new(BTreeMap::new()),
+            next_operation_id: AtomicU64::new(1),
+            answerer: Arc::new(DefaultAnswerer),
+            history: None,
+        }
+    }
+
+    /// Sets the journal executed operations are recorded in.
+    ///
+    /// [`PackageManager::new`] uses [`HistoryJournal::for_current_user`];
+    /// managers built with [`PackageManager::with_registry`] record nothing
+    /// until one is set.
+    pub fn set_history(&mut self, journal: Option<HistoryJournal>) {
+        self.history = journal;
+    }
+
+    /// Queries the operation history, newest first.
+    pub async fn history(&self, query: HistoryQuery) -> Result<Vec<HistoryEntry>> {
+        let Some(journal) = self.history.clone() else {
+            return Ok(Vec::new());
+        };
+
+        tokio::task::spawn_blocking(move || journal.query(&query))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?
+    }
+
+    /// Sets who answers questions raised during transactions.
+    ///
+    /// Defaults to [`DefaultAnswerer`]. Operations with `no_confirm` set
+    /// always use the defaults.
+    pub fn set_answerer(&mut self, answerer: Arc<dyn QuestionAnswerer>) {
+        self.answerer = answerer;
+    }
+
+    /// Registers an additional backend, replacing any with the same source id.
+    pub fn register_backend(&mut self, source: Arc<dyn PackageSource>) {
+        info!("Registered backend: {}", source.source_id());
+        self.registry.register(source);
+    }
+
+    /// Gets the backend registry.
+    pub fn registry(&self) -> &BackendRegistry {
+        &self.registry
     }
 
     /// Gets a receiver for progress updates.
@@ -76,40 +135,38 @@ impl PackageManager {
         self.progress_tx.subscribe()
     }
 
+    /// Gets a snapshot of the oldest running operation's progress, if any.
+    pub fn current_progress(&self) -> Option<OperationProgress> {
+        let trackers = self.progress_trackers.lock().unwrap();
+        trackers.values().next()?.current().cloned()
+    }
+
+    /// Gets a snapshot of the progress of the running operation `id`.
+    pub fn operation_progress(&self, id: u64) -> Option<OperationProgress> {
+        let trackers = self.progress_trackers.lock().unwrap();
+        trackers.get(&id)?.current().cloned()
+    }
+
     /// Gets the current app state.
     pub async fn state(&self) -> AppState {
         self.state.read().await.clone()
     }
 
     /// Gets the backend for a specific package source.
-    fn get_backend(&self, backend: PackageBackend) -> Result<&dyn PackageSource> {
-        match backend {
-            PackageBackend::Pacman => self
-                .alpm
-                .as_ref()
-                .map(|b| b.as_ref() as &dyn PackageSource)
-                .ok_or_else(|| Error::BackendUnavailable("Pacman".into())),
-            PackageBackend::Flatpak => self
-                .flatpak
-                .as_ref()
-                .map(|b| b.as_ref() as &dyn PackageSource)
-                .ok_or_else(|| Error::BackendUnavailable("Flatpak".into())),
-        }
+    fn get_backend(&self, backend: &PackageBackend) -> Result<&dyn PackageSource> {
+        self.registry
+            .get_backend(backend)
+            .map(|b| b.as_ref())
+            .ok_or_else(|| Error::BackendUnavailable(backend.to_string()))
     }
 
     /// Checks which backends are available.
     pub async fn available_backends(&self) -> Vec<PackageBackend> {
         let mut backends = Vec::new();
 
-        if let Some(ref alpm) = self.alpm {
-            if alpm.is_available().await {
-                backends.push(PackageBackend::Pacman);
-            }
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            if flatpak.is_available().await {
-                backends.push(PackageBackend::Flatpak);
+        for source in self.registry.iter() {
+            if source.is_available().await {
+                backends.push(source.backend());
             }
         }
 
@@ -117,25 +174,21 @@ impl PackageManager {
     }
 
     /// Searches for packages across all backends.
+    ///
+    /// `query` uses the [`SearchQuery`] syntax; results are ranked by how
+    /// well their names match.
     pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let parsed = SearchQuery::parse(query)?;
         let mut results = Vec::new();
 
-        if let Some(ref alpm) = self.alpm {
-            match alpm.search(query).await {
-                Ok(r) => results.extend(r),
-                Err(e) => error!("Pacman search failed: {}", e),
-            }
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            match flatpak.search(query).await {
+        for source in self.registry.iter() {
+            match source.search(query).await {
                 Ok(r) => results.extend(r),
-                Err(e) => error!("Flatpak search failed: {}", e),
+                Err(e) => error!("{} search failed: {}", source.display_name(), e),
             }
         }
 
-        // Sort by name.
-        results.sort_by(|a, b| a.name.cmp(&b.name));
+        parsed.sort(&mut results);
 
         Ok(results)
     }
@@ -144,7 +197,7 @@ impl PackageManager {
     pub async fn search_backend(
         &self,
         query: &str,
-        backend: PackageBackend,
+        backend: &PackageBackend,
     ) -> Result<Vec<SearchResult>> {
         self.get_backend(backend)?.search(query).await
     }
@@ -153,17 +206,10 @@ impl PackageManager {
     pub async fn list_installed(&self) -> Result<Vec<Package>> {
         let mut packages = Vec::new();
 
-        if let Some(ref alpm) = self.alpm {
-            match alpm.list_installed().await {
+        for source in self.registry.iter() {
+            match source.list_installed().await {
                 Ok(p) => packages.extend(p),
-                Err(e) => error!("Failed to list pacman packages: {}", e),
-            }
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            match flatpak.list_installed().await {
-                Ok(p) => packages.extend(p),
-                Err(e) => error!("Failed to list flatpak packages: {}", e),
+                Err(e) => error!("Failed to list {} packages: {}", source.display_name(), e),
             }
         }
 
@@ -172,7 +218,7 @@ impl PackageManager {
     }
 
     /// Lists installed packages from a specific backend.
-    pub async fn list_installed_backend(&self, backend: PackageBackend) -> Result<Vec<Package>> {
+    pub async fn list_installed_backend(&self, backend: &PackageBackend) -> Result<Vec<Package>> {
         self.get_backend(backend)?.list_installed().await
     }
 
@@ -180,17 +226,10 @@ impl PackageManager {
     pub async fn list_updates(&self) -> Result<Vec<UpdateInfo>> {
         let mut updates = Vec::new();
 
-        if let Some(ref alpm) = self.alpm {
-            match alpm.list_updates().await {
-                Ok(u) => updates.extend(u),
-                Err(e) => error!("Failed to check pacman updates: {}", e),
-            }
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            match flatpak.list_updates().await {
+        for source in self.registry.iter() {
+            match source.list_updates().await {
                 Ok(u) => updates.extend(u),
-                Err(e) => error!("Failed to check flatpak updates: {}", e),
+                Err(e) => error!("Failed to check {} updates: {}", source.display_name(), e),
             }
         }
 
@@ -201,27 +240,88 @@ impl PackageManager {
     pub async fn get_package_info(
         &self,
         name: &str,
-        backend: PackageBackend,
+        backend: &PackageBackend,
     ) -> Result<PackageInfo> {
         self.get_backend(backend)?.get_package_info(name).await
     }
 
+    /// Previews what an operation would do without changing the system.
+    pub async fn plan(&self, operation: &Operation) -> Result<TransactionPlan> {
+        self.get_backend(&operation.backend)?.plan(operation).await
+    }
+
     /// Executes a package operation.
     pub async fn execute(&self, operation: Operation) -> Result<OperationResult> {
-        let backend = self.get_backend(operation.backend)?;
+        self.execute_with_cancel(operation, CancellationToken::new())
+            .await
+    }
+
+    /// Executes a package operation that can be stopped through `cancel`
+    /// or [`PackageManager::cancel`].
+    pub async fn execute_with_cancel(
+        &self,
+        operation: Operation,
+        cancel: CancellationToken,
+    ) -> Result<OperationResult> {
+        let backend = self.get_backend(&operation.backend)?;
         let tx = self.progress_tx.clone();
+        let started_at = Utc::now();
+        let start = std::time::Instant::now();
 
-        let progress_callback = Box::new(move |progress: OperationProgress| {
-            let _ = tx.send(ProgressMessage::Progress(progress));
+        let requested = operation.clone();
+
+        let id = self.next_operation_id.fetch_add(1, Ordering::Relaxed);
+        let mut tracker = ProgressTracker::new();
+        tracker.start(operation.packages.len(), 0);
+        self.progress_trackers.lock().unwrap().insert(id, tracker);
+
+        let trackers = self.progress_trackers.clone();
+        let events = Box::new(move |event: TransactionEvent| {
+            if let Some(tracker) = trackers.lock().unwrap().get_mut(&id) {
+                tracker.apply(&event);
+            }
+            let _ = tx.send(ProgressMessage::Event(event));
         });
 
+        let questions: Arc<dyn QuestionAnswerer> = if operation.options.no_confirm {
+            Arc::new(DefaultAnswerer)
+        } else {
+            self.answerer.clone()
+        };
+
+        self.active_cancel.lock().unwrap().insert(id, cancel.clone());
         let result = backend
-            .execute_with_progress(operation, progress_callback)
-            .await?;
+            .execute_with_progress(operation, events, questions, cancel)
+            .await;
+        self.active_cancel.lock().unwrap().remove(&id);
+        self.progress_trackers.lock().unwrap().remove(&id);
+
+        let mut result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                let _ = self.progress_tx.send(ProgressMessage::Error(e.clone()));
+                let failed =
+                    OperationResult::from_error(requested, &e, start.elapsed().as_millis() as u64);
+                // The error is returned either way; a journal failure is
+                // only logged.
+                let _ = self
+                    .record(HistoryEntry::new(started_at, &failed, &failed.old_versions))
+                    .await;
+                return Err(e);
+            }
+        };
+        if let Err(e) = self
+            .record(HistoryEntry::new(started_at, &result, &result.old_versions))
+            .await
+        {
+            result
+                .warnings
+                .push(format!("Not recorded in history: {}", e));
+        }
 
         let _ = self
             .progress_tx
-            .send(ProgressMessage::Completed(result.clone()));
+            .send(ProgressMessage::Completed(Box::new(result.clone())));
 
         // Update state.
         {
@@ -232,10 +332,59 @@ impl PackageManager {
         Ok(result)
     }
 
+    /// Appends an entry to the journal, if any. Callers report failures as
+    /// warnings: a finished operation should not fail for want of history.
+    async fn record(&self, entry: HistoryEntry) -> Result<()> {
+        let Some(journal) = self.history.clone() else {
+            return Ok(());
+        };
+
+        let path = journal.path().display().to_string();
+        let result = tokio::task::spawn_blocking(move || journal.append(&entry))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))
+            .and_then(|appended| appended);
+        if let Err(e) = &result {
+            warn!("Failed to write history to {}: {}", path, e);
+        }
+        result.map_err(|e| Error::Other(format!("{}: {}", path, e)))
+    }
+
+    /// IDs of the operations currently running, oldest first.
+    pub fn active_operations(&self) -> Vec<u64> {
+        self.active_cancel.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Requests cancellation of the running operation `id`.
+    ///
+    /// Returns false when no such operation is in progress.
+    pub fn cancel_operation(&self, id: u64) -> bool {
+        match self.active_cancel.lock().unwrap().get(&id) {
+            Some(token) => {
+                info!("Cancelling operation {}", id);
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Requests cancellation of every running operation.
+    ///
+    /// Returns false when no operation is in progress.
+    pub fn cancel(&self) -> bool {
+        let active = self.active_cancel.lock().unwrap();
+        for (id, token) in active.iter() {
+            info!("Cancelling operation {}", id);
+            token.cancel();
+        }
+        !active.is_empty()
+    }
+
     /// Syncs all databases.
     pub async fn sync_databases(&self) -> Result<()> {
-        if let Some(ref alpm) = self.alpm {
-            alpm.sync_databases().await?;
+        for source in self.registry.iter() {
+            source.sync_databases().await?;
         }
         Ok(())
     }
@@ -244,12 +393,8 @@ impl PackageManager {
     pub async fn get_cache_size(&self) -> Result<u64> {
         let mut total = 0u64;
 
-        if let Some(ref alpm) = self.alpm {
-            total += alpm.get_cache_size().await.unwrap_or(0);
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            total += flatpak.get_cache_size().await.unwrap_or(0);
+        for source in self.registry.iter() {
+            total += source.get_cache_size().await.unwrap_or(0);
         }
 
         Ok(total)
@@ -259,12 +404,8 @@ impl PackageManager {
     pub async fn clean_caches(&self, keep_versions: usize) -> Result<u64> {
         let mut freed = 0u64;
 
-        if let Some(ref alpm) = self.alpm {
-            freed += alpm.clean_cache(keep_versions).await.unwrap_or(0);
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            freed += flatpak.clean_cache(keep_versions).await.unwrap_or(0);
+        for source in self.registry.iter() {
+            freed += source.clean_cache(keep_versions).await.unwrap_or(0);
         }
 
         Ok(freed)
@@ -274,68 +415,116 @@ impl PackageManager {
     pub async fn list_orphans(&self) -> Result<Vec<Package>> {
         let mut orphans = Vec::new();
 
-        if let Some(ref alpm) = self.alpm {
-            match alpm.list_orphans().await {
+        for source in self.registry.iter() {
+            match source.list_orphans().await {
                 Ok(o) => orphans.extend(o),
-                Err(e) => error!("Failed to list orphans: {}", e),
+                Err(e) => error!("Failed to list {} orphans: {}", source.display_name(), e),
             }
         }
 
         Ok(orphans)
     }
 
+    /// Checks installed files of the named packages, or of every installed
+    /// package if `names` is empty, like `pacman -Qkk`.
+    pub async fn verify_packages(&self, names: &[String]) -> Result<Vec<PackageVerification>> {
+        self.verify_packages_with_cancel(names, CancellationToken::new())
+            .await
+    }
+
+    /// Checks installed files like [`PackageManager::verify_packages`],
+    /// reporting each package as a progress event. The check can be stopped
+    /// through `cancel` or [`PackageManager::cancel`].
+    pub async fn verify_packages_with_cancel(
+        &self,
+        names: &[String],
+        cancel: CancellationToken,
+    ) -> Result<Vec<PackageVerification>> {
+        let id = self.next_operation_id.fetch_add(1, Ordering::Relaxed);
+        self.active_cancel.lock().unwrap().insert(id, cancel.clone());
+
+        let mut reports = Vec::new();
+        let mut result = Ok(());
+        for source in self.registry.iter() {
+            let tx = self.progress_tx.clone();
+            let events = Box::new(move |event: TransactionEvent| {
+                let _ = tx.send(ProgressMessage::Event(event));
+            });
+            match source.verify_packages(names, events, cancel.clone()).await {
+                Ok(found) => reports.extend(found),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.active_cancel.lock().unwrap().remove(&id);
+        result.map(|()| reports)
+    }
+
     /// Gets statistics about installed packages.
     pub async fn get_stats(&self) -> PackageStats {
         let mut stats = PackageStats::default();
 
-        if let Some(ref alpm) = self.alpm {
-            if let Ok(packages) = alpm.list_installed().await {
-                stats.pacman_installed = packages.len();
+        for source in self.registry.iter() {
+            let mut backend_stats = BackendStats::default();
+            if let Ok(packages) = source.list_installed().await {
+                backend_stats.installed = packages.len();
             }
-            if let Ok(updates) = alpm.list_updates().await {
-                stats.pacman_updates = updates.len();
+            if let Ok(updates) = source.list_updates().await {
+                backend_stats.updates = updates.len();
             }
-            if let Ok(orphans) = alpm.list_orphans().await {
-                stats.orphans = orphans.len();
-            }
-        }
-
-        if let Some(ref flatpak) = self.flatpak {
-            if let Ok(packages) = flatpak.list_installed().await {
-                stats.flatpak_installed = packages.len();
-            }
-            if let Ok(updates) = flatpak.list_updates().await {
-                stats.flatpak_updates = updates.len();
+            if let Ok(orphans) = source.list_orphans().await {
+                backend_stats.orphans = orphans.len();
             }
+            stats.backends.push((source.backend(), backend_stats));
         }
 
         stats
     }
 }
 
+/// Statistics for a single backend.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackendStats {
+    /// Number of installed packages.
+    pub installed: usize,
+    /// Number of available updates.
+    pub updates: usize,
+    /// Number of orphan packages.
+    pub orphans: usize,
+}
+
 /// Statistics about installed packages.
 #[derive(Debug, Clone, Default)]
 pub struct PackageStats {
-    /// Number of installed pacman packages.
-    pub pacman_installed: usize,
-    /// Number of available pacman updates.
-    pub pacman_updates: usize,
-    /// Number of installed flatpak apps.
-    pub flatpak_installed: usize,
-    /// Number of available flatpak updates.
-    pub flatpak_updates: usize,
-    /// Number of orphan packages.
-    pub orphans: usize,
+    /// Per-backend statistics, in registration order.
+    pub backends: Vec<(PackageBackend, BackendStats)>,
 }
 
 impl PackageStats {
+    /// Statistics for a specific backend.
+    pub fn get(&self, backend: &PackageBackend) -> BackendStats {
+        self.backends
+            .iter()
+            .find(|(b, _)| b == backend)
+            .map(|(_, s)| *s)
+            .unwrap_or_default()
+    }
+
     /// Total installed packages.
     pub fn total_installed(&self) -> usize {
-        self.pacman_installed + self.flatpak_installed
+        self.backends.iter().map(|(_, s)| s.installed).sum()
     }
 
     /// Total available updates.
     pub fn total_updates(&self) -> usize {
-        self.pacman_updates + self.flatpak_updates
+        self.backends.iter().map(|(_, s)| s.updates).sum()
+    }
+
+    /// Total orphan packages.
+    pub fn total_orphans(&self) -> usize {
+        self.backends.iter().map(|(_, s)| s.orphans).sum()
     }
 }
@@ -1,7 +1,10 @@
 //! Progress tracking for operations.
 
 use std::time::Instant;
-use xpm_core::operation::{OperationProgress, OperationStatus};
+use xpm_core::{
+    event::TransactionEvent,
+    operation::{OperationProgress, OperationStatus},
+};
 
 /// Tracks progress of an operation.
 #[derive(Debug)]
@@ -53,6 +56,20 @@ impl ProgressTracker {
         }
     }
 
+    /// Folds a transaction event into the current progress.
+    pub fn apply(&mut self, event: &TransactionEvent) {
+        if let Some(ref mut op) = self.current {
+            op.last_progress.apply(event);
+            if matches!(event, TransactionEvent::DownloadProgress { .. }) {
+                op.history
+                    .push((Instant::now(), op.last_progress.downloaded_bytes));
+                if op.history.len() > 10 {
+                    op.history.remove(0);
+                }
+            }
+        }
+    }
+
     /// Gets the current progress.
     pub fn current(&self) -> Option<&OperationProgress> {
         self.current.as_ref().map(|op| &op.last_progress)
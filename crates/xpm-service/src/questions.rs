@@ -0,0 +1,86 @@
+//! Forwarding transaction questions to an interactive frontend.
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+use xpm_core::question::{QuestionAnswer, QuestionAnswerer, TransactionQuestion};
+
+/// A question waiting for the frontend to answer it.
+#[derive(Debug)]
+pub struct PendingQuestion {
+    /// The question being asked.
+    pub question: TransactionQuestion,
+    reply: oneshot::Sender<QuestionAnswer>,
+}
+
+impl PendingQuestion {
+    /// Sends the answer back to the waiting backend.
+    pub fn answer(self, answer: QuestionAnswer) {
+        let _ = self.reply.send(answer);
+    }
+
+    /// Answers with the question's default.
+    pub fn answer_default(self) {
+        let answer = self.question.default_answer();
+        self.answer(answer);
+    }
+}
+
+/// Answers questions by handing them to a receiver, e.g. a GUI dialog or a
+/// CLI prompt.
+///
+/// If the receiver is gone or drops a question without answering, the
+/// question's default answer is used.
+#[derive(Debug, Clone)]
+pub struct ChannelAnswerer {
+    tx: mpsc::Sender<PendingQuestion>,
+}
+
+impl ChannelAnswerer {
+    /// Creates an answerer and the receiver that questions are delivered to.
+    pub fn new() -> (Self, mpsc::Receiver<PendingQuestion>) {
+        let (tx, rx) = mpsc::channel(8);
+        (Self { tx }, rx)
+    }
+}
+
+#[async_trait]
+impl QuestionAnswerer for ChannelAnswerer {
+    async fn answer(&self, question: &TransactionQuestion) -> QuestionAnswer {
+        let (reply, answer) = oneshot::channel();
+        let pending = PendingQuestion {
+            question: question.clone(),
+            reply,
+        };
+
+        if self.tx.send(pending).await.is_err() {
+            return question.default_answer();
+        }
+        answer.await.unwrap_or_else(|_| question.default_answer())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_channel_answerer() {
+        let (answerer, mut rx) = ChannelAnswerer::new();
+        let question = TransactionQuestion::RemoveConflict {
+            package: "foo".into(),
+            conflict: "bar".into(),
+            reason: None,
+        };
+
+        let frontend = tokio::spawn(async move {
+            let pending = rx.recv().await.unwrap();
+            pending.answer(QuestionAnswer::Yes);
+            // Dropped without answering: falls back to the default.
+            drop(rx.recv().await.unwrap());
+        });
+
+        assert_eq!(answerer.answer(&question).await, QuestionAnswer::Yes);
+        assert_eq!(answerer.answer(&question).await, QuestionAnswer::No);
+        frontend.await.unwrap();
+    }
+}
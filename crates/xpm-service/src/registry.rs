@@ -0,0 +1,71 @@
+//! Registry of package sources.
+
+use std::sync::Arc;
+use xpm_core::{package::PackageBackend, source::PackageSource};
+
+/// Package sources keyed by their `source_id()`, in registration order.
+#[derive(Default, Clone)]
+pub struct BackendRegistry {
+    sources: Vec<Arc<dyn PackageSource>>,
+}
+
+impl BackendRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a source, replacing any source with the same id.
+    pub fn register(&mut self, source: Arc<dyn PackageSource>) {
+        match self
+            .sources
+            .iter_mut()
+            .find(|s| s.source_id() == source.source_id())
+        {
+            Some(existing) => *existing = source,
+            None => self.sources.push(source),
+        }
+    }
+
+    /// Removes the source with the given id, returning it if present.
+    pub fn unregister(&mut self, id: &str) -> Option<Arc<dyn PackageSource>> {
+        let index = self.sources.iter().position(|s| s.source_id() == id)?;
+        Some(self.sources.remove(index))
+    }
+
+    /// Gets the source with the given id.
+    pub fn get(&self, id: &str) -> Option<&Arc<dyn PackageSource>> {
+        self.sources.iter().find(|s| s.source_id() == id)
+    }
+
+    /// Gets the source for a backend.
+    pub fn get_backend(&self, backend: &PackageBackend) -> Option<&Arc<dyn PackageSource>> {
+        self.get(backend.id())
+    }
+
+    /// Iterates over all registered sources in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn PackageSource>> {
+        self.sources.iter()
+    }
+
+    /// Returns the ids of all registered sources.
+    pub fn ids(&self) -> Vec<&str> {
+        self.sources.iter().map(|s| s.source_id()).collect()
+    }
+
+    /// Number of registered sources.
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Whether no sources are registered.
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+impl std::fmt::Debug for BackendRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.ids()).finish()
+    }
+}
@@ -112,8 +112,8 @@ impl AppState {
                 }
 
                 // Backend filter.
-                if let Some(backend) = self.filter.backend {
-                    if p.backend != backend {
+                if let Some(backend) = &self.filter.backend {
+                    if &p.backend != backend {
                         return false;
                     }
                 }
@@ -124,10 +124,10 @@ impl AppState {
     }
 
     /// Gets the count of installed packages by backend.
-    pub fn installed_count_by_backend(&self, backend: PackageBackend) -> usize {
+    pub fn installed_count_by_backend(&self, backend: &PackageBackend) -> usize {
         self.installed_packages
             .iter()
-            .filter(|p| p.backend == backend)
+            .filter(|p| &p.backend == backend)
             .count()
     }
 }
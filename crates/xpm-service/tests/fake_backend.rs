@@ -0,0 +1,326 @@
+//! Drives the package manager against the in-memory fake backend.
+
+use std::sync::Arc;
+use xpm_core::{
+    error::Error,
+    event::{PackageAction, TransactionEvent},
+    operation::{Operation, OperationKind, OperationOptions, OperationStatus},
+    package::{InstallReason, PackageBackend},
+    CancellationToken,
+};
+use xpm_fake::{FakeBackend, Fixture};
+use xpm_service::manager::ProgressMessage;
+use xpm_service::{BackendRegistry, HistoryJournal, HistoryQuery, PackageManager};
+
+const FIXTURE: &str = r#"{
+  "source_id": "pacman",
+  "packages": [
+    { "name": "app", "version": "2.0-1", "depends": ["libfoo>=1.1", "libbar"] },
+    { "name": "libfoo", "version": "1.2-1", "installed": "1.0-1", "dependency": true },
+    { "name": "libbar", "version": "1.0-1" },
+    { "name": "editor", "version": "3.1-1", "installed": "3.0-1", "depends": ["libfoo"] },
+    { "name": "stale", "version": "0.1-1", "installed": "0.1-1", "dependency": true },
+    { "name": "tool", "version": "1.0-1", "conflicts": ["editor"] },
+    { "name": "broken", "version": "1.0-1" }
+  ],
+  "failures": [
+    {
+      "package": "broken",
+      "error": { "kind": "database_locked", "detail": { "path": "/var/lib/pacman/db.lck" } }
+    }
+  ]
+}"#;
+
+fn manager_with(fixture: Fixture) -> PackageManager {
+    let mut registry = BackendRegistry::new();
+    registry.register(Arc::new(FakeBackend::from_fixture(fixture)));
+    PackageManager::with_registry(registry)
+}
+
+fn manager() -> PackageManager {
+    manager_with(Fixture::from_json(FIXTURE).unwrap())
+}
+
+fn install(names: &[&str]) -> Operation {
+    Operation::install(
+        names.iter().map(|n| n.to_string()).collect(),
+        PackageBackend::Pacman,
+    )
+}
+
+#[tokio::test]
+async fn test_install_pulls_in_dependencies() {
+    let manager = manager();
+    let mut progress = manager.subscribe_progress();
+
+    let plan = manager.plan(&install(&["app"])).await.unwrap();
+    let actions: Vec<_> = plan
+        .packages
+        .iter()
+        .map(|p| (p.name.as_str(), p.action))
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            ("app", PackageAction::Install),
+            ("libfoo", PackageAction::Upgrade),
+            ("libbar", PackageAction::Install),
+        ]
+    );
+
+    let result = manager.execute(install(&["app"])).await.unwrap();
+    assert!(result.is_success());
+    assert_eq!(result.affected_packages.len(), 3);
+
+    let mut completed = Vec::new();
+    while let Ok(message) = progress.try_recv() {
+        if let ProgressMessage::Event(TransactionEvent::PackageCompleted { name, .. }) = message {
+            completed.push(name);
+        }
+    }
+    assert_eq!(completed, ["app", "libfoo", "libbar"]);
+
+    let installed = manager.list_installed().await.unwrap();
+    assert!(installed.iter().any(|p| p.name == "app"));
+    assert!(manager
+        .list_updates()
+        .await
+        .unwrap()
+        .iter()
+        .all(|u| u.name != "libfoo"));
+}
+
+#[tokio::test]
+async fn test_remove_and_orphans() {
+    let manager = manager();
+
+    let orphans = manager.list_orphans().await.unwrap();
+    assert_eq!(orphans.len(), 1);
+    assert_eq!(orphans[0].name, "stale");
+
+    // libfoo is still needed by editor.
+    let err = manager
+        .plan(&Operation::remove(
+            vec!["libfoo".into()],
+            PackageBackend::Pacman,
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::UnsatisfiedDependencies(ref deps) if deps[0].package == "editor"));
+
+    let mut remove = Operation::remove(vec!["editor".into()], PackageBackend::Pacman);
+    remove.kind = OperationKind::RemoveWithDeps;
+    let result = manager.execute(remove).await.unwrap();
+    assert!(result.is_success());
+    let removed: Vec<_> = result
+        .affected_packages
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
+    assert_eq!(removed, ["editor", "libfoo"]);
+
+    let mut orphans = Operation::system_upgrade(PackageBackend::Pacman);
+    orphans.kind = OperationKind::RemoveOrphans;
+    manager.execute(orphans).await.unwrap();
+    assert!(manager.list_installed().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_install_reason() {
+    let manager = manager();
+    let orphan_names = || async {
+        let mut names: Vec<String> = manager
+            .list_orphans()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        names.sort();
+        names
+    };
+
+    let mark = |reason| {
+        Operation::set_install_reason(vec!["editor".into()], reason, PackageBackend::Pacman)
+    };
+    let result = manager
+        .execute(mark(InstallReason::Dependency))
+        .await
+        .unwrap();
+    assert_eq!(result.affected_packages.len(), 1);
+    assert_eq!(orphan_names().await, ["editor", "stale"]);
+
+    manager
+        .execute(mark(InstallReason::Explicit))
+        .await
+        .unwrap();
+    assert_eq!(orphan_names().await, ["stale"]);
+
+    let as_deps = install(&["libbar"]).with_options(OperationOptions {
+        as_deps: true,
+        ..Default::default()
+    });
+    assert!(manager.execute(as_deps).await.unwrap().is_success());
+    assert_eq!(orphan_names().await, ["libbar", "stale"]);
+
+    let missing = Operation::set_install_reason(
+        vec!["app".into()],
+        InstallReason::Explicit,
+        PackageBackend::Pacman,
+    );
+    let result = manager.execute(missing).await.unwrap();
+    assert!(matches!(
+        result.error_detail,
+        Some(Error::PackageNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_failures_are_reported() {
+    let manager = manager();
+
+    let result = manager.execute(install(&["broken"])).await.unwrap();
+    assert_eq!(result.status, OperationStatus::Failed);
+    assert!(matches!(
+        result.error_detail,
+        Some(Error::DatabaseLocked { .. })
+    ));
+
+    // Conflicts are declined by default.
+    let result = manager.execute(install(&["tool"])).await.unwrap();
+    assert!(matches!(
+        result.error_detail,
+        Some(Error::PackageConflicts(_))
+    ));
+
+    let result = manager.execute(install(&["missing"])).await.unwrap();
+    assert!(matches!(result.error_detail, Some(Error::PackageNotFound(_))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_keeps_processed_packages() {
+    let mut fixture = Fixture::from_json(FIXTURE).unwrap();
+    fixture.step_delay_ms = 20;
+    let manager = manager_with(fixture);
+
+    let cancel = CancellationToken::new();
+    let mut progress = manager.subscribe_progress();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        while let Ok(message) = progress.recv().await {
+            if let ProgressMessage::Event(TransactionEvent::PackageCompleted { .. }) = message {
+                token.cancel();
+            }
+        }
+    });
+
+    let result = manager
+        .execute_with_cancel(install(&["app"]), cancel)
+        .await
+        .unwrap();
+    assert!(result.is_cancelled());
+    assert!(!result.affected_packages.is_empty());
+    assert!(result.affected_packages.len() < 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_stops_every_running_operation() {
+    let mut fixture = Fixture::from_json(FIXTURE).unwrap();
+    fixture.step_delay_ms = 20;
+    let manager = manager_with(fixture);
+
+    let canceller = async {
+        while manager.active_operations().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert!(manager.cancel());
+    };
+    let (app, libbar, ()) = tokio::join!(
+        manager.execute(install(&["app"])),
+        manager.execute(install(&["libbar"])),
+        canceller,
+    );
+
+    assert!(app.unwrap().is_cancelled());
+    assert!(libbar.unwrap().is_cancelled());
+    assert!(manager.active_operations().is_empty());
+    assert!(!manager.cancel());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_progress_is_tracked_per_operation() {
+    let mut fixture = Fixture::from_json(FIXTURE).unwrap();
+    fixture.step_delay_ms = 50;
+    let manager = manager_with(fixture);
+
+    let short = async {
+        while manager.active_operations().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let running = manager.active_operations()[0];
+        assert!(manager.operation_progress(running).is_some());
+
+        // Finishing another operation leaves the running one's progress.
+        let missing = manager.execute(install(&["missing"])).await.unwrap();
+        assert!(missing.error_detail.is_some());
+        assert_eq!(manager.active_operations(), [running]);
+        assert!(manager.operation_progress(running).is_some());
+        assert!(manager.current_progress().is_some());
+    };
+    let (app, ()) = tokio::join!(manager.execute(install(&["app"])), short);
+
+    assert_eq!(app.unwrap().status, OperationStatus::Completed);
+    assert!(manager.current_progress().is_none());
+}
+
+#[tokio::test]
+async fn test_history_is_recorded() {
+    let path = std::env::temp_dir().join(format!("xpm-fake-history-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut manager = manager();
+    manager.set_history(Some(HistoryJournal::new(&path)));
+
+    manager
+        .execute(Operation::update(
+            vec!["editor".into()],
+            PackageBackend::Pacman,
+        ))
+        .await
+        .unwrap();
+    manager.execute(install(&["broken"])).await.unwrap();
+
+    let entries = manager.history(HistoryQuery::new()).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].is_failed());
+    assert_eq!(entries[1].changes[0].action, PackageAction::Upgrade);
+    assert_eq!(
+        entries[1].changes[0]
+            .old_version
+            .as_ref()
+            .unwrap()
+            .to_string(),
+        "3.0-1"
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_history_failure_is_reported() {
+    // A file where the journal's directory should be.
+    let blocker = std::env::temp_dir().join(format!("xpm-fake-blocker-{}", std::process::id()));
+    std::fs::write(&blocker, "").unwrap();
+
+    let mut manager = manager();
+    manager.set_history(Some(HistoryJournal::new(blocker.join("history.jsonl"))));
+
+    let result = manager.execute(install(&["app"])).await.unwrap();
+    assert!(result.is_success());
+    assert!(result
+        .warnings
+        .iter()
+        .any(|w| w.starts_with("Not recorded in history")));
+
+    std::fs::remove_file(&blocker).unwrap();
+}
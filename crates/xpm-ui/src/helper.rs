@@ -0,0 +1,390 @@
+//! Privileged helper running pacman transactions for the UI.
+//!
+//! The UI starts `pkexec xpackagemanager --helper` and writes a [`Request`]
+//! to its stdin as one JSON line. The helper runs it as root through a
+//! [`PackageManager`], which records it in the system history journal, and
+//! writes [`Reply`] lines to stdout: transaction events, questions and
+//! finally the result. Further stdin lines carry [`Input`], answering
+//! questions or cancelling the operation.
+//!
+//! The helper also resolves pending configuration files, which needs write
+//! access to them.
+//!
+//! The helper is the only part running as root, and polkit only allows it
+//! with `--helper` as its first argument. It trusts nothing but the
+//! protocol: requests are limited to pacman operations and to files that
+//! really are pending configuration files, and the operation is cancelled
+//! as soon as the UI stops listening.
+
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+use xpm_alpm::AlpmBackend;
+use xpm_core::cancel::CancellationToken;
+use xpm_core::config_file::{ConfigFile, ConfigFileAction};
+use xpm_core::error::{Error, Result};
+use xpm_core::event::TransactionEvent;
+use xpm_core::operation::{Operation, OperationResult};
+use xpm_core::question::{QuestionAnswer, TransactionQuestion};
+use xpm_service::manager::ProgressMessage;
+use xpm_service::{BackendRegistry, ChannelAnswerer, HistoryJournal, PackageManager, PendingQuestion};
+
+/// Command line flag that runs the helper instead of the UI.
+pub const HELPER_FLAG: &str = "--helper";
+
+/// What the UI asks the helper to do.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    /// Run a pacman operation.
+    Execute(Operation),
+    /// Keep, replace or merge a pending `.pacnew` or `.pacsave` file.
+    ResolveConfigFile {
+        /// The pending file.
+        file: ConfigFile,
+        /// What to do with it.
+        action: ConfigFileAction,
+    },
+}
+
+/// Lines the UI sends while a request runs.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Input {
+    /// Answer the question asked last.
+    Answer(QuestionAnswer),
+    /// Stop the operation before it commits.
+    Cancel,
+}
+
+/// Lines the helper sends back.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Reply {
+    /// A transaction event.
+    Event(TransactionEvent),
+    /// A question waiting for an [`Input::Answer`].
+    Question(TransactionQuestion),
+    /// The operation finished; nothing follows.
+    Finished(Box<std::result::Result<OperationResult, Error>>),
+    /// The configuration file was resolved; nothing follows.
+    Resolved(std::result::Result<(), Error>),
+}
+
+/// Runs the helper: reads a request from stdin, carries it out and reports
+/// back on stdout. Returns the process exit code.
+pub fn serve() -> i32 {
+    let mut input = BufReader::new(std::io::stdin());
+    let mut line = String::new();
+    let request = match input.read_line(&mut line) {
+        Ok(_) => serde_json::from_str::<Request>(&line).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            error!("Invalid helper request: {}", e);
+            return 2;
+        }
+    };
+
+    let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let (reply, success) = match request {
+        Request::Execute(operation) => {
+            let result = rt.block_on(execute(operation, input));
+            let success = matches!(&result, Ok(result) if result.is_success());
+            (Reply::Finished(Box::new(result)), success)
+        }
+        Request::ResolveConfigFile { file, action } => {
+            let result = rt.block_on(resolve_config_file(&file, action));
+            let success = result.is_ok();
+            (Reply::Resolved(result), success)
+        }
+    };
+    send(&reply);
+    if success { 0 } else { 1 }
+}
+
+/// Resolves `file`, provided it really is a pending configuration file of
+/// an installed package.
+async fn resolve_config_file(file: &ConfigFile, action: ConfigFileAction) -> Result<()> {
+    let backend = AlpmBackend::new()?;
+    if !backend.config_files().await?.contains(file) {
+        return Err(Error::Other(format!("{} is not a pending configuration file", file.pending_path())));
+    }
+    backend.resolve_config_file(file, action).await
+}
+
+async fn execute(operation: Operation, input: impl BufRead + Send + 'static) -> Result<OperationResult> {
+    let mut registry = BackendRegistry::new();
+    registry.register(Arc::new(AlpmBackend::new()?));
+    let mut manager = PackageManager::with_registry(registry);
+    manager.set_history(Some(HistoryJournal::system()));
+    let (answerer, mut questions) = ChannelAnswerer::new();
+    manager.set_answerer(Arc::new(answerer));
+
+    let cancel = CancellationToken::new();
+    let pending: Arc<Mutex<Option<PendingQuestion>>> = Arc::default();
+    read_input(input, cancel.clone(), pending.clone());
+
+    let asking_cancel = cancel.clone();
+    let asking = tokio::spawn(async move {
+        while let Some(question) = questions.recv().await {
+            // Nobody is left to answer: fall back to the default
+            if asking_cancel.is_cancelled() {
+                continue;
+            }
+            let asked = question.question.clone();
+            *pending.lock().unwrap() = Some(question);
+            send(&Reply::Question(asked));
+        }
+    });
+
+    let mut progress = manager.subscribe_progress();
+    let forwarding = tokio::spawn(async move {
+        loop {
+            match progress.recv().await {
+                Ok(ProgressMessage::Event(event)) => send(&Reply::Event(event)),
+                Ok(_) | Err(RecvError::Closed) => break,
+                Err(RecvError::Lagged(_)) => {}
+            }
+        }
+    });
+
+    let result = manager.execute_with_cancel(operation, cancel).await;
+    // Closes the progress and question channels
+    drop(manager);
+    let _ = forwarding.await;
+    let _ = asking.await;
+    result
+}
+
+/// Reads answers and cancel requests from the UI on a separate thread.
+/// The operation is cancelled when the UI goes away.
+fn read_input(
+    input: impl BufRead + Send + 'static,
+    cancel: CancellationToken,
+    pending: Arc<Mutex<Option<PendingQuestion>>>,
+) {
+    thread::spawn(move || {
+        for line in input.lines() {
+            let Ok(line) = line else { break };
+            match serde_json::from_str(&line) {
+                Ok(Input::Answer(answer)) => {
+                    if let Some(question) = pending.lock().unwrap().take() {
+                        question.answer(answer);
+                    }
+                }
+                Ok(Input::Cancel) => {
+                    cancel.cancel();
+                    if let Some(question) = pending.lock().unwrap().take() {
+                        question.answer_default();
+                    }
+                }
+                Err(e) => warn!("Ignoring helper input {:?}: {}", line, e),
+            }
+        }
+        cancel.cancel();
+        if let Some(question) = pending.lock().unwrap().take() {
+            question.answer_default();
+        }
+    });
+}
+
+fn send(reply: &Reply) {
+    let Ok(line) = serde_json::to_string(reply) else { return };
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+/// A helper started through pkexec.
+pub struct Helper {
+    child: Child,
+    stdin: HelperInput,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Helper {
+    /// Starts the helper under pkexec and sends it `request`.
+    pub fn spawn(request: &Request) -> std::io::Result<Self> {
+        let exe = std::env::current_exe()?;
+        let mut child = Command::new("pkexec")
+            .arg(exe)
+            .arg(HELPER_FLAG)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = HelperInput(Arc::new(Mutex::new(child.stdin.take().expect("piped stdin"))));
+        let stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+
+        stdin.write(request)?;
+        Ok(Self { child, stdin, stdout })
+    }
+
+    /// A handle for sending input while replies are being read.
+    pub fn input(&self) -> HelperInput {
+        self.stdin.clone()
+    }
+
+    /// Reads the next reply; None once the helper has exited.
+    pub fn next_reply(&mut self) -> Option<Reply> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.stdout.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => match serde_json::from_str(&line) {
+                    Ok(reply) => return Some(reply),
+                    Err(e) => warn!("Ignoring helper output {:?}: {}", line.trim_end(), e),
+                },
+            }
+        }
+    }
+
+    /// Waits for the helper to exit.
+    pub fn wait(mut self) {
+        let _ = self.child.wait();
+    }
+
+    /// Waits for a helper that stopped without finishing and explains why.
+    pub fn exit_error(mut self) -> Error {
+        match self.child.wait() {
+            // pkexec's exit codes for a dismissed or refused authorization
+            Ok(status) if status.code() == Some(126) => {
+                Error::PermissionDenied("authentication was dismissed".to_string())
+            }
+            Ok(status) if status.code() == Some(127) => {
+                Error::PermissionDenied("not authorized to manage packages".to_string())
+            }
+            Ok(status) => Error::Other(format!("The privileged helper exited early ({})", status)),
+            Err(e) => Error::Other(format!("Failed to wait for the privileged helper: {}", e)),
+        }
+    }
+}
+
+/// Sends [`Input`] lines to a running helper.
+#[derive(Clone)]
+pub struct HelperInput(Arc<Mutex<ChildStdin>>);
+
+impl HelperInput {
+    /// Sends one line to the helper.
+    pub fn send(&self, input: &Input) {
+        if let Err(e) = self.write(input) {
+            warn!("Failed to write to the privileged helper: {}", e);
+        }
+    }
+
+    fn write(&self, message: &impl Serialize) -> std::io::Result<()> {
+        let line = serde_json::to_string(message)?;
+        let mut stdin = self.0.lock().unwrap();
+        writeln!(stdin, "{}", line)?;
+        stdin.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::os::unix::net::UnixStream;
+    use xpm_core::config_file::ConfigFileKind;
+    use xpm_core::package::PackageBackend;
+    use xpm_core::question::QuestionAnswerer;
+
+    /// Serializes `message` as one protocol line.
+    fn line(message: &impl Serialize) -> String {
+        let line = serde_json::to_string(message).unwrap();
+        assert!(!line.contains('\n'));
+        line + "\n"
+    }
+
+    fn conflict() -> TransactionQuestion {
+        TransactionQuestion::RemoveConflict {
+            package: "foo".into(),
+            conflict: "bar".into(),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_protocol_round_trip() {
+        let request = line(&Request::Execute(Operation::install(
+            vec!["foo".into()],
+            PackageBackend::Pacman,
+        )));
+        match serde_json::from_str(&request).unwrap() {
+            Request::Execute(operation) => assert_eq!(operation.packages, ["foo"]),
+            other => panic!("unexpected request {:?}", other),
+        }
+
+        let file = ConfigFile {
+            package: "foo".into(),
+            path: "/etc/foo.conf".into(),
+            kind: ConfigFileKind::Pacnew,
+        };
+        let request = line(&Request::ResolveConfigFile {
+            file: file.clone(),
+            action: ConfigFileAction::Keep,
+        });
+        match serde_json::from_str(&request).unwrap() {
+            Request::ResolveConfigFile { file: parsed, .. } => assert_eq!(parsed, file),
+            other => panic!("unexpected request {:?}", other),
+        }
+
+        let input = line(&Input::Answer(QuestionAnswer::Provider(1)));
+        assert!(matches!(
+            serde_json::from_str(&input).unwrap(),
+            Input::Answer(QuestionAnswer::Provider(1))
+        ));
+        assert!(matches!(
+            serde_json::from_str(&line(&Input::Cancel)).unwrap(),
+            Input::Cancel
+        ));
+
+        let reply = line(&Reply::Question(conflict()));
+        assert!(matches!(
+            serde_json::from_str(&reply).unwrap(),
+            Reply::Question(TransactionQuestion::RemoveConflict { .. })
+        ));
+        let reply = line(&Reply::Finished(Box::new(Err(Error::Cancelled))));
+        match serde_json::from_str(&reply).unwrap() {
+            Reply::Finished(result) => assert!(matches!(*result, Err(Error::Cancelled))),
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_input_answers_then_cancels_at_eof() {
+        let (answerer, mut questions) = ChannelAnswerer::new();
+        let asking = tokio::spawn(async move { answerer.answer(&conflict()).await });
+        let pending = Arc::new(Mutex::new(questions.recv().await));
+
+        let cancel = CancellationToken::new();
+        let input = line(&Input::Answer(QuestionAnswer::Yes));
+        read_input(Cursor::new(input), cancel.clone(), pending);
+
+        assert_eq!(asking.await.unwrap(), QuestionAnswer::Yes);
+        while !cancel.is_cancelled() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_answers_pending_question_with_default() {
+        let (answerer, mut questions) = ChannelAnswerer::new();
+        let asking = tokio::spawn(async move { answerer.answer(&conflict()).await });
+        let pending = Arc::new(Mutex::new(questions.recv().await));
+
+        // The UI keeps stdin open after cancelling.
+        let (mut ui, helper) = UnixStream::pair().unwrap();
+        ui.write_all(line(&Input::Cancel).as_bytes()).unwrap();
+        let cancel = CancellationToken::new();
+        read_input(BufReader::new(helper), cancel.clone(), pending);
+
+        assert_eq!(asking.await.unwrap(), QuestionAnswer::No);
+        assert!(cancel.is_cancelled());
+        drop(ui);
+    }
+}
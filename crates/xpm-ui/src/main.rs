@@ -1,5 +1,8 @@
 //! xPackageManager - A modern package manager for Arch Linux.
 
+mod helper;
+
+use helper::Helper;
 use slint::{Model, ModelRc, SharedString, VecModel, Timer, TimerMode};
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -8,16 +11,84 @@ use serde_json::Value;
 use std::rc::Rc;
 use std::os::unix::io::FromRawFd;
 use std::os::unix::process::CommandExt;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
 use std::thread;
 use tracing::{error, info, Level};
 use tracing_subscriber::FmtSubscriber;
 use xpm_alpm::AlpmBackend;
-use xpm_core::source::PackageSource;
+use xpm_alpm::archive::{LocalPackage, SignatureStatus};
+use xpm_alpm::cache::CachedVersion;
+use xpm_alpm::deps::DependencyNode;
+use xpm_core::cancel::CancellationToken;
+use xpm_core::config_file::{ConfigFile, ConfigFileAction, ConfigFileKind};
+use xpm_core::event::TransactionEvent;
+use xpm_core::operation::{Operation, OperationOptions, OperationProgress, OperationResult};
+use xpm_core::event::PackageAction;
+use xpm_core::package::{InstallReason, PackageBackend, SearchResult};
+use xpm_core::plan::TransactionPlan;
+use xpm_core::query::SearchQuery;
+use xpm_core::question::{QuestionAnswer, TransactionQuestion};
+use xpm_core::source::{EventCallback, PackageSource};
+use xpm_core::verify::{FileIssue, PackageVerification};
+use xpm_fake::FakeBackend;
 use xpm_flatpak::FlatpakBackend;
+use xpm_service::{ChannelAnswerer, HistoryEntry, HistoryJournal, HistoryQuery};
 
 slint::include_modules!();
 
+/// Backend id for local package files opened from disk.
+const LOCAL_BACKEND: &str = "local";
+/// Backend id for KDE Plasma widgets.
+const PLASMOID_BACKEND: &str = "plasmoid";
+/// Backend id for fwupd firmware devices.
+const FIRMWARE_BACKEND: &str = "firmware";
+
+/// In-memory backend standing in for pacman when started with `--demo`.
+static DEMO_BACKEND: OnceLock<Arc<FakeBackend>> = OnceLock::new();
+
+/// The demo backend, if running in demo mode.
+fn demo_backend() -> Option<Arc<FakeBackend>> {
+    DEMO_BACKEND.get().cloned()
+}
+
+/// Shared pacman backend, so its package index outlives individual views.
+static ALPM_BACKEND: OnceLock<Arc<AlpmBackend>> = OnceLock::new();
+
+/// The shared pacman backend, created on first use.
+fn alpm_backend() -> xpm_core::error::Result<Arc<AlpmBackend>> {
+    if let Some(backend) = ALPM_BACKEND.get() {
+        return Ok(backend.clone());
+    }
+    let backend = Arc::new(AlpmBackend::new()?);
+    Ok(ALPM_BACKEND.get_or_init(|| backend).clone())
+}
+
+/// Cancellation token of the operation shown in the progress popup.
+static RUNNING_OPERATION: Mutex<Option<CancellationToken>> = Mutex::new(None);
+
+/// Start an operation the progress popup's Cancel button can stop.
+fn track_operation() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    *RUNNING_OPERATION.lock().unwrap() = Some(cancel.clone());
+    cancel
+}
+
+/// Forget the operation once it has finished.
+fn untrack_operation() {
+    RUNNING_OPERATION.lock().unwrap().take();
+}
+
+/// Cancellation token of the running package verification.
+static RUNNING_VERIFY: Mutex<Option<CancellationToken>> = Mutex::new(None);
+
+/// Makes the next pacman query wait for a fresh index, after pacman ran
+/// outside the backend.
+fn refresh_alpm_index() {
+    if let Some(backend) = ALPM_BACKEND.get() {
+        backend.refresh_index();
+    }
+}
+
 /// Messages from backend threads to UI
 enum UiMessage {
     PackagesLoaded {
@@ -44,41 +115,46 @@ enum UiMessage {
     OperationProgress(i32, String),
     ProgressOutput(String),
     ProgressPrompt(String),
-    ProgressHidePrompt,
     OperationDone(bool),
-    ShowTerminalFallback(String),
-}
-
-/// Patterns that indicate routine pacman prompts (auto-answer "y")
-/// These are safe default-yes [Y/n] prompts that always occur during normal operations.
-const PACMAN_AUTO_CONFIRM_PATTERNS: &[&str] = &[
-    "Proceed with installation? [Y/n]",
-"Proceed with download? [Y/n]",
-":: Proceed with installation? [Y/n]",
-":: Proceed with download? [Y/n]",
-"Do you want to remove these packages? [y/N]",
-":: Do you want to remove these packages? [y/N]",
-];
-
-/// Patterns that indicate pacman is asking a question that requires user input.
-/// These are non-routine prompts where the user should decide.
-const PACMAN_USER_PROMPT_PATTERNS: &[&str] = &[
-    ":: Replace",
-":: Import",
-"Enter a number",
-"Enter a selection",
-"Terminate batch job",
-];
-
-/// Patterns that indicate a conflict or error requiring user intervention
-const CONFLICT_PATTERNS: &[&str] = &[
-    "conflicting files",
-"are in conflict",
-"exists in filesystem",
-"breaks dependency",
-"could not satisfy dependencies",
-"failed to commit transaction",
-];
+    HistoryLoaded(Vec<HistoryData>),
+    VerifyResults(Vec<VerifyData>),
+    // Verification failed or was cancelled
+    VerifyStopped,
+    // Pending .pacnew/.pacsave files for the Maintenance view
+    ConfigFiles(Vec<ConfigFileData>),
+    // Three-way view of a pending configuration file and its merge
+    ConfigFileReview(ConfigMergeData, String),
+    // Metadata of a package archive for the local install popup
+    LocalPackageInspected {
+        package: PackageData,
+        installed_version: String,
+        missing_deps: String,
+        signature: String,
+        signature_ok: bool,
+        build: String,
+    },
+    LocalPackageFailed(String),
+    // Dependency details for the package details popup
+    PackageDetails {
+        name: String,
+        dependencies: String,
+        required_by: String,
+        why: String,
+        reason: String,
+        tree: Vec<DepNodeData>,
+        files: Vec<PackageFileData>,
+        versions: Vec<CachedVersionData>,
+    },
+    // Transaction preview for the confirm popup
+    ConfirmPlan {
+        names: String,
+        version: String,
+        size: String,
+        deps: String,
+        notes: String,
+        hooks: String,
+    },
+}
 
 /// Check if the system is running XeroLinux
 fn is_xerolinux_distro() -> bool {
@@ -113,53 +189,54 @@ fn is_arch_package(path: &str) -> bool {
     extensions.iter().any(|ext| path.ends_with(ext))
 }
 
-/// Extract package info from a local package file
-fn get_local_package_info(path: &str) -> Option<PackageData> {
-    let path_obj = Path::new(path);
-    if !path_obj.exists() {
-        return None;
-    }
-
-    let filename = path_obj.file_name()?.to_str()?;
+/// Convert an inspected package archive for the local install popup.
+fn local_package_to_ui(local: &LocalPackage) -> PackageData {
+    let info = &local.info;
+    let join = |deps: &[xpm_core::dependency::Dependency]| {
+        deps.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")
+    };
 
-    let base = filename
-    .strip_suffix(".pkg.tar.zst")
-    .or_else(|| filename.strip_suffix(".pkg.tar.xz"))
-    .or_else(|| filename.strip_suffix(".pkg.tar.gz"))
-    .or_else(|| filename.strip_suffix(".pkg.tar"))?;
+    PackageData {
+        name: SharedString::from(info.package.name.as_str()),
+        display_name: SharedString::from(info.package.name.as_str()),
+        version: SharedString::from(info.package.version.to_string().as_str()),
+        description: SharedString::from(info.package.description.as_str()),
+        repository: SharedString::from("local"),
+        backend: SharedString::from(LOCAL_BACKEND),
+        installed: local.installed_version.is_some(),
+        has_update: false,
+        installed_size: SharedString::from(format_size(info.installed_size)),
+        licenses: SharedString::from(info.licenses.join(", ")),
+        url: SharedString::from(info.url.clone().unwrap_or_default()),
+        dependencies: SharedString::from(join(&info.depends)),
+        required_by: SharedString::from(""),
+        icon_name: SharedString::from("package"),
+        selected: false,
+    }
+}
 
-    let parts: Vec<&str> = base.rsplitn(4, '-').collect();
-    let (name, version) = if parts.len() >= 3 {
-        let name = parts[3..].join("-");
-        let version = format!("{}-{}", parts[2], parts[1]);
-        (name, version)
-    } else {
-        (base.to_string(), "unknown".to_string())
+/// Read a local package archive for the install popup (runs in background thread)
+async fn inspect_local_package(tx: &mpsc::Sender<UiMessage>, path: &str) {
+    let local = match alpm_backend() {
+        Ok(backend) => backend.inspect_archive(path).await,
+        Err(e) => Err(e),
     };
 
-    let size = path_obj
-    .metadata()
-    .ok()
-    .map(|m| format_size(m.len()))
-    .unwrap_or_else(|| "Unknown".to_string());
-
-    Some(PackageData {
-        name: SharedString::from(&name),
-         display_name: SharedString::from(&name),
-         version: SharedString::from(&version),
-         description: SharedString::from(format!("Local package: {}", filename)),
-         repository: SharedString::from("local"),
-         backend: 2,
-         installed: false,
-         has_update: false,
-         installed_size: SharedString::from(&size),
-         licenses: SharedString::from(""),
-         url: SharedString::from(""),
-         dependencies: SharedString::from(""),
-         required_by: SharedString::from(""),
-         icon_name: SharedString::from("package"),
-         selected: false,
-    })
+    let message = match local {
+        Ok(local) => UiMessage::LocalPackageInspected {
+            package: local_package_to_ui(&local),
+            installed_version: local.installed_version.map(|v| v.to_string()).unwrap_or_default(),
+            missing_deps: local.unsatisfied.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", "),
+            signature_ok: local.signature == SignatureStatus::Valid,
+            signature: local.signature.to_string(),
+            build: local.build.and_then(|b| b.build_tool).unwrap_or_default(),
+        },
+        Err(e) => {
+            error!("Failed to read {}: {}", path, e);
+            UiMessage::LocalPackageFailed(format!("Could not read package: {}", e))
+        }
+    };
+    let _ = tx.send(message);
 }
 
 /// Format bytes into human readable size
@@ -175,6 +252,370 @@ fn format_size(bytes: u64) -> String {
     }
 }
 
+/// Format a signed size change, e.g. "+1.2 MB".
+fn format_size_delta(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(bytes.unsigned_abs()))
+}
+
+/// Build the operation a confirm popup action stands for.
+fn confirm_operation(action: &str, names: &[String], backend: PackageBackend) -> Operation {
+    let packages = names.to_vec();
+    match action {
+        "install" | "bulk-install" => Operation::install(packages, backend),
+        "install-asdeps" => Operation::install(packages, backend).with_options(OperationOptions {
+            as_deps: true,
+            ..Default::default()
+        }),
+        "mark-explicit" => Operation::set_install_reason(packages, InstallReason::Explicit, backend),
+        "mark-deps" => Operation::set_install_reason(packages, InstallReason::Dependency, backend),
+        "downgrade" => Operation::downgrade(packages, backend),
+        "downgrade-ignore" => Operation::downgrade(packages, backend).with_options(OperationOptions {
+            ignore_upgrades: true,
+            ..Default::default()
+        }),
+        "remove" | "bulk-remove" => Operation::remove(packages, backend),
+        "update-all" => Operation::system_upgrade(backend),
+        _ => Operation::update(packages, backend),
+    }
+}
+
+/// Render a transaction plan into the confirm popup's version, size,
+/// dependency, notes and hooks lines.
+fn describe_plan(plan: &TransactionPlan) -> (String, String, String, String, String) {
+    let version = match plan.packages.iter().filter(|p| p.explicit).collect::<Vec<_>>()[..] {
+        [pkg] => match (&pkg.old_version, &pkg.new_version) {
+            (Some(old), Some(new)) if old != new => format!("{} → {}", old, new),
+            (_, Some(new)) => new.to_string(),
+            (Some(old), None) => old.to_string(),
+            (None, None) => String::new(),
+        },
+        _ => String::new(),
+    };
+
+    let mut size = format_size_delta(plan.installed_size_delta());
+    if plan.download_size() > 0 {
+        size = format!("{} download, {} installed", format_size(plan.download_size()), size);
+    }
+
+    let deps = [
+        PackageAction::Install,
+        PackageAction::Upgrade,
+        PackageAction::Downgrade,
+        PackageAction::Reinstall,
+        PackageAction::Remove,
+    ]
+    .iter()
+    .filter_map(|action| {
+        let names: Vec<&str> = plan
+            .dependencies()
+            .filter(|p| p.action == *action)
+            .map(|p| p.name.as_str())
+            .collect();
+        (!names.is_empty()).then(|| format!("{}: {}", action, names.join(", ")))
+    })
+    .collect::<Vec<_>>()
+    .join("; ");
+
+    let mut notes: Vec<String> = plan
+        .conflicts
+        .iter()
+        .map(|c| format!("{} conflicts with {}", c.package, c.conflicts_with))
+        .collect();
+    notes.extend(plan.replacements.iter().map(|r| format!("{} replaces {}", r.new, r.old)));
+    notes.extend(plan.warnings.iter().cloned());
+
+    // Hook descriptions read like "Updating linux initcpios..."
+    let hooks = plan
+        .hooks
+        .iter()
+        .map(|hook| {
+            let text = hook.description.as_deref().unwrap_or(&hook.name).trim_end_matches(['.', '…']);
+            if hook.abort_on_fail {
+                format!("{} (cancels the transaction if it fails)", text)
+            } else {
+                text.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (version, size, deps, notes.join("\n"), hooks)
+}
+
+/// Compute the transaction plan for a confirm popup in the background.
+/// Returns false if the backend cannot be previewed.
+fn request_confirm_plan(tx: &mpsc::Sender<UiMessage>, action: &str, names: &str, backend: &str) -> bool {
+    let backend = PackageBackend::from_id(backend);
+    if !matches!(backend, PackageBackend::Pacman | PackageBackend::Flatpak) {
+        return false;
+    }
+
+    let tx = tx.clone();
+    let action = action.to_string();
+    let names = names.to_string();
+    thread::spawn(move || {
+        let name_list: Vec<String> = names.split('\n').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect();
+        let operation = confirm_operation(&action, &name_list, backend.clone());
+
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+        let plan = rt.block_on(async {
+            match (backend, demo_backend()) {
+                (PackageBackend::Flatpak, _) => FlatpakBackend::new()?.plan(&operation).await,
+                (_, Some(demo)) => demo.plan(&operation).await,
+                _ => alpm_backend()?.preview(&operation).await,
+            }
+        });
+
+        let (version, size, deps, notes, hooks) = match plan {
+            Ok(plan) => describe_plan(&plan),
+            Err(e) => (String::new(), String::new(), String::new(), format!("Could not preview: {}", e), String::new()),
+        };
+        let _ = tx.send(UiMessage::ConfirmPlan { names, version, size, deps, notes, hooks });
+    });
+    true
+}
+
+/// Convert a journal entry for the History view.
+fn history_to_data(entry: &HistoryEntry) -> HistoryData {
+    let targets = if entry.operation.packages.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", entry.operation.packages.join(", "))
+    };
+
+    let changes = entry
+        .changes
+        .iter()
+        .map(|c| match (&c.old_version, &c.new_version) {
+            (Some(old), Some(new)) if old != new => format!("{} {} → {}", c.name, old, new),
+            (None, Some(new)) => format!("{} {} (new)", c.name, new),
+            (Some(old), None) => format!("{} {} (removed)", c.name, old),
+            (_, Some(new)) => format!("{} {}", c.name, new),
+            (None, None) => c.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    HistoryData {
+        time: SharedString::from(entry.started_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()),
+        title: SharedString::from(format!("{}{}", entry.operation.kind, targets)),
+        backend: SharedString::from(entry.operation.backend.id()),
+        status: SharedString::from(entry.status.to_string()),
+        failed: entry.is_failed(),
+        duration: SharedString::from(format!("{:.1}s", entry.duration_ms as f64 / 1000.0)),
+        changes: SharedString::from(changes),
+        error: SharedString::from(entry.error.clone().unwrap_or_default()),
+    }
+}
+
+/// Load the History view, optionally filtered by package name. Pacman
+/// operations are in the system journal, the ones run without root in the
+/// user's own.
+fn load_history(tx: &mpsc::Sender<UiMessage>, package: &str, failed_only: bool) {
+    const LIMIT: usize = 500;
+    let mut query = HistoryQuery::new().limit(LIMIT);
+    if !package.is_empty() {
+        query = query.package(package);
+    }
+    if failed_only {
+        query = query.failed_only();
+    }
+
+    let mut journals = vec![HistoryJournal::system()];
+    let user = HistoryJournal::for_current_user();
+    if user.path() != journals[0].path() {
+        journals.push(user);
+    }
+
+    let mut entries = Vec::new();
+    for journal in &journals {
+        match journal.query(&query) {
+            Ok(found) => entries.extend(found),
+            Err(e) => error!("Failed to read history from {}: {}", journal.path().display(), e),
+        }
+    }
+    entries.sort_by_key(|e| std::cmp::Reverse(e.started_at));
+    entries.truncate(LIMIT);
+    let _ = tx.send(UiMessage::HistoryLoaded(entries.iter().map(history_to_data).collect()));
+}
+
+/// Convert a verification report for the Maintenance view.
+fn verification_to_data(report: &PackageVerification) -> VerifyData {
+    let lines = |issues: &[FileIssue]| {
+        issues
+            .iter()
+            .map(|issue| format!("{}: {}", issue.path, issue.problem))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let mut summary = Vec::new();
+    if !report.corrupted.is_empty() {
+        summary.push(format!("{} damaged", report.corrupted.len()));
+    }
+    if !report.modified_config.is_empty() {
+        summary.push(format!("{} config modified", report.modified_config.len()));
+    }
+    if !report.unverified.is_empty() {
+        summary.push(format!("{} could not be verified", report.unverified.len()));
+    }
+
+    VerifyData {
+        package: SharedString::from(report.package.as_str()),
+        summary: SharedString::from(summary.join(", ")),
+        corrupted: SharedString::from(lines(&report.corrupted)),
+        modified: SharedString::from(lines(&report.modified_config)),
+        unverified: SharedString::from(lines(&report.unverified)),
+        intact: report.is_intact(),
+    }
+}
+
+/// Verify every installed package and list those with changed or
+/// unreadable files, damaged ones first. Progress is shown in the status bar.
+async fn verify_installed_packages(tx: &mpsc::Sender<UiMessage>, cancel: CancellationToken) {
+    let tx_events = tx.clone();
+    let events: EventCallback = Box::new(move |event: TransactionEvent| {
+        if let TransactionEvent::PackageStarted { index, total, .. } = &event {
+            let percent = (index - 1) * 100 / total;
+            let _ = tx_events.send(UiMessage::SetProgress(percent as i32));
+            let _ = tx_events.send(UiMessage::SetProgressText(event.message()));
+        }
+    });
+
+    let reports = match demo_backend() {
+        Some(demo) => demo.verify_packages(&[], events, cancel).await,
+        None => match alpm_backend() {
+            Ok(alpm) => alpm.verify_packages(&[], events, cancel).await,
+            Err(e) => Err(e),
+        },
+    };
+    let _ = tx.send(UiMessage::SetProgress(0));
+    let _ = tx.send(UiMessage::SetProgressText(String::new()));
+
+    let reports = match reports {
+        Ok(reports) => reports,
+        Err(xpm_core::error::Error::Cancelled) => {
+            let _ = tx.send(UiMessage::SetStatus("Verification cancelled".to_string()));
+            let _ = tx.send(UiMessage::VerifyStopped);
+            return;
+        }
+        Err(e) => {
+            error!("Failed to verify packages: {}", e);
+            let _ = tx.send(UiMessage::SetStatus(format!("Verification failed: {}", e)));
+            let _ = tx.send(UiMessage::VerifyStopped);
+            return;
+        }
+    };
+
+    let checked = reports.len();
+    let mut results: Vec<&PackageVerification> = reports
+        .iter()
+        .filter(|r| !r.corrupted.is_empty() || !r.modified_config.is_empty() || !r.is_complete())
+        .collect();
+    results.sort_by_key(|r| (r.is_intact(), r.package.clone()));
+
+    let damaged = results.iter().filter(|r| !r.is_intact()).count();
+    let incomplete = results.iter().filter(|r| !r.is_complete()).count();
+    let mut status = format!("Verified {} packages: {} damaged", checked, damaged);
+    if incomplete > 0 {
+        status.push_str(&format!(", {} not fully checked", incomplete));
+    }
+    let _ = tx.send(UiMessage::SetStatus(status));
+    let _ = tx.send(UiMessage::VerifyResults(results.into_iter().map(verification_to_data).collect()));
+}
+
+/// Convert a pending configuration file for the Maintenance view.
+fn config_file_to_data(file: &ConfigFile) -> ConfigFileData {
+    ConfigFileData {
+        path: SharedString::from(file.path.as_str()),
+        package: SharedString::from(file.package.as_str()),
+        kind: SharedString::from(file.kind.to_string()),
+    }
+}
+
+fn config_file_from_data(data: &ConfigFileData) -> ConfigFile {
+    ConfigFile {
+        package: data.package.to_string(),
+        path: data.path.to_string(),
+        kind: if data.kind == "pacsave" { ConfigFileKind::Pacsave } else { ConfigFileKind::Pacnew },
+    }
+}
+
+/// List .pacnew and .pacsave files left beside installed configuration files.
+async fn find_config_files(tx: &mpsc::Sender<UiMessage>) {
+    // The demo backend has no files on disk
+    if demo_backend().is_some() {
+        let _ = tx.send(UiMessage::ConfigFiles(Vec::new()));
+        return;
+    }
+
+    let files = match alpm_backend() {
+        Ok(alpm) => alpm.config_files().await,
+        Err(e) => Err(e),
+    };
+    match files {
+        Ok(files) => {
+            let _ = tx.send(UiMessage::SetStatus(format!("{} configuration files to review", files.len())));
+            let _ = tx.send(UiMessage::ConfigFiles(files.iter().map(config_file_to_data).collect()));
+        }
+        Err(e) => {
+            error!("Failed to find configuration files: {}", e);
+            let _ = tx.send(UiMessage::SetStatus(format!("Could not look for .pacnew files: {}", e)));
+            let _ = tx.send(UiMessage::ConfigFiles(Vec::new()));
+        }
+    }
+}
+
+/// Load the original, current and new contents of a pending configuration file.
+async fn review_config_file(tx: &mpsc::Sender<UiMessage>, file: ConfigFile) {
+    let view = match alpm_backend() {
+        Ok(alpm) => alpm.config_file_view(&file).await,
+        Err(e) => Err(e),
+    };
+    match view {
+        Ok(view) => {
+            let data = ConfigMergeData {
+                path: SharedString::from(view.file.path.as_str()),
+                package: SharedString::from(view.file.package.as_str()),
+                kind: SharedString::from(view.file.kind.to_string()),
+                original: SharedString::from(view.original.as_deref().unwrap_or_default()),
+                current: SharedString::from(view.current.as_deref().unwrap_or_default()),
+                new: SharedString::from(view.new.as_deref().unwrap_or_default()),
+                has_original: view.original.is_some(),
+                conflicts: view.conflicts as i32,
+            };
+            let _ = tx.send(UiMessage::ConfigFileReview(data, view.merged));
+        }
+        Err(e) => {
+            error!("Failed to read {}: {}", file.pending_path(), e);
+            let _ = tx.send(UiMessage::SetStatus(format!("Could not read {}: {}", file.pending_path(), e)));
+        }
+    }
+}
+
+/// Keep, replace or merge a pending configuration file as root through the
+/// privileged helper.
+fn resolve_config_file(file: &ConfigFile, action: ConfigFileAction) -> xpm_core::error::Result<()> {
+    let request = helper::Request::ResolveConfigFile { file: file.clone(), action };
+    let mut helper = Helper::spawn(&request)
+        .map_err(|e| xpm_core::error::Error::Other(format!("Failed to start pkexec: {}", e)))?;
+
+    let mut resolved = None;
+    while let Some(reply) = helper.next_reply() {
+        if let helper::Reply::Resolved(result) = reply {
+            resolved = Some(result);
+        }
+    }
+    match resolved {
+        Some(result) => {
+            helper.wait();
+            result
+        }
+        None => Err(helper.exit_error()),
+    }
+}
+
 /// Strip ANSI escape sequences for clean display.
 /// Handles CSI sequences (ESC[...), OSC (ESC]...), and simple ESC+char sequences.
 /// Normalizes PTY line endings (\r\n → \n). Bare \r (progress bars) is preserved.
@@ -398,323 +839,293 @@ fn run_in_terminal(
     let _ = tx.send(UiMessage::TerminalDone(success));
 }
 
-/// Build command and args for a package operation
-fn build_pacman_command(action: &str, names: &[String], backend: i32) -> (String, Vec<String>) {
-    match (action, backend) {
-        ("install", 1) | ("bulk-install", 1) => {
-            ("flatpak".to_string(), {
-                let mut args = vec!["install".to_string(), "-y".to_string()];
-                args.extend(names.iter().cloned());
-                args
-            })
+/// Run a Flatpak operation through the Flatpak backend.
+fn run_flatpak_operation(
+    tx: &mpsc::Sender<UiMessage>,
+    title: &str,
+    action: &str,
+    names: &[String],
+    input_sender: &Arc<Mutex<Option<mpsc::Sender<String>>>>,
+) {
+    match FlatpakBackend::new() {
+        Ok(backend) => {
+            let history = HistoryJournal::for_current_user();
+            run_backend_operation(tx, title, action, names, &backend, Some(history), input_sender)
         }
-        ("remove", 1) | ("bulk-remove", 1) => {
-            ("flatpak".to_string(), {
-                let mut args = vec!["uninstall".to_string(), "-y".to_string()];
-                args.extend(names.iter().cloned());
-                args
-            })
+        Err(e) => {
+            let _ = tx.send(UiMessage::ShowProgressPopup(title.to_string()));
+            let _ = tx.send(UiMessage::OperationProgress(0, format!("Error: {}", e)));
+            let _ = tx.send(UiMessage::OperationDone(false));
         }
-        ("update", 1) => {
-            ("flatpak".to_string(), {
-                let mut args = vec!["update".to_string(), "-y".to_string()];
-                args.extend(names.iter().cloned());
-                args
-            })
+    }
+}
+
+/// Forward transaction events to the progress popup: scriptlet output and
+/// warnings to its output pane, everything else to the progress bar.
+fn progress_events(tx: &mpsc::Sender<UiMessage>, total: usize) -> EventCallback {
+    let tx = tx.clone();
+    let progress = Mutex::new(OperationProgress::new(total, 0));
+    let output = Mutex::new(String::new());
+    Box::new(move |event: TransactionEvent| {
+        match &event {
+            TransactionEvent::ScriptletOutput(_) | TransactionEvent::Warning(_) => {
+                let mut output = output.lock().unwrap();
+                output.push_str(&event.message());
+                output.push('\n');
+                let _ = tx.send(UiMessage::ProgressOutput(output.clone()));
+            }
+            _ => {
+                let mut progress = progress.lock().unwrap();
+                progress.apply(&event);
+                let _ = tx.send(UiMessage::OperationProgress(
+                    progress.overall_percent() as i32,
+                    progress.message.clone(),
+                ));
+            }
         }
-        ("remove", _) | ("bulk-remove", _) => {
-            ("pkexec".to_string(), {
-                let mut args = vec!["pacman".to_string(), "-R".to_string()];
-                args.extend(names.iter().cloned());
-                args
-            })
+    })
+}
+
+/// Show the outcome of an operation in the progress popup.
+fn report_result(tx: &mpsc::Sender<UiMessage>, result: xpm_core::error::Result<OperationResult>) {
+    // Point out configuration files the transaction left for review, and
+    // anything else that went wrong along the way
+    if let Ok(result) = &result {
+        let mut notes = Vec::new();
+        if !result.config_files.is_empty() {
+            let paths: Vec<String> = result.config_files.iter().map(|f| f.pending_path()).collect();
+            notes.push(format!("New configuration files to review: {}", paths.join(", ")));
         }
-        ("update-all", _) => {
-            ("pkexec".to_string(), vec!["pacman".to_string(), "-Syu".to_string()])
+        notes.extend(result.warnings.iter().map(|w| format!("Warning: {}", w)));
+        if !notes.is_empty() {
+            let _ = tx.send(UiMessage::SetStatus(notes.join("; ")));
         }
-        _ => {
-            // install, bulk-install, update for pacman
-            ("pkexec".to_string(), {
-                let mut args = vec!["pacman".to_string(), "-S".to_string()];
-                args.extend(names.iter().cloned());
-                args
-            })
+    }
+
+    match result {
+        Ok(result) if result.is_success() => {
+            let stage = match result.config_files.len() {
+                0 => "Done".to_string(),
+                1 => "Done. 1 new .pacnew/.pacsave file to review in Maintenance".to_string(),
+                n => format!("Done. {} new .pacnew/.pacsave files to review in Maintenance", n),
+            };
+            let _ = tx.send(UiMessage::OperationProgress(100, stage));
+            let _ = tx.send(UiMessage::OperationDone(true));
+        }
+        Ok(result) => {
+            let message = result.error.unwrap_or_else(|| "Operation failed".to_string());
+            let _ = tx.send(UiMessage::OperationProgress(0, message));
+            let _ = tx.send(UiMessage::OperationDone(false));
+        }
+        Err(e) => {
+            let _ = tx.send(UiMessage::OperationProgress(0, format!("Error: {}", e)));
+            let _ = tx.send(UiMessage::OperationDone(false));
         }
     }
 }
 
-/// Run a managed operation with progress tracking and auto-confirmation.
-/// Falls back to full terminal on conflict/error.
-fn run_managed_operation(
+/// Run an operation through a package source, driving the progress popup
+/// from its transaction events and asking its questions there. The outcome
+/// is recorded in `history`, if given.
+fn run_backend_operation(
     tx: &mpsc::Sender<UiMessage>,
     title: &str,
     action: &str,
     names: &[String],
-    backend: i32,
+    backend: &dyn PackageSource,
+    history: Option<HistoryJournal>,
     input_sender: &Arc<Mutex<Option<mpsc::Sender<String>>>>,
-    pid_holder: &Arc<Mutex<Option<u32>>>,
 ) {
     let _ = tx.send(UiMessage::ShowProgressPopup(title.to_string()));
 
-    let (cmd, args) = build_pacman_command(action, names, backend);
-    let args_str: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let cancel = track_operation();
 
-    let (master_fd, child_pid) = match spawn_in_pty(&cmd, &args_str) {
-        Ok(pair) => pair,
-        Err(e) => {
-            let _ = tx.send(UiMessage::OperationProgress(0, format!("Error: {}", e)));
-            let _ = tx.send(UiMessage::OperationDone(false));
-            return;
-        }
-    };
-
-    *pid_holder.lock().unwrap() = Some(child_pid);
-
-    // Create input channel for auto-confirm
+    // Each question waits for the next response typed into the popup
+    let (answerer, mut questions) = ChannelAnswerer::new();
     let (in_tx, in_rx) = mpsc::channel::<String>();
     *input_sender.lock().unwrap() = Some(in_tx.clone());
+    // Cancelling gives up on a question still waiting for a response
+    cancel.on_cancel(move || {
+        let _ = in_tx.send(String::new());
+    });
+    let tx_asking = tx.clone();
+    let asking_cancel = cancel.clone();
+    let asking_handle = thread::spawn(move || {
+        while let Some(pending) = questions.blocking_recv() {
+            if asking_cancel.is_cancelled() {
+                pending.answer_default();
+                continue;
+            }
+            while in_rx.try_recv().is_ok() {}
+            let _ = tx_asking.send(UiMessage::ProgressPrompt(question_prompt(&pending.question)));
+            match in_rx.recv() {
+                Ok(text) => {
+                    let answer = parse_answer(&pending.question, &text);
+                    pending.answer(answer);
+                }
+                Err(_) => pending.answer_default(),
+            }
+        }
+    });
 
-    // Shared state for escalation
-    let escalated = Arc::new(Mutex::new(false));
-    let output_buffer = Arc::new(Mutex::new(String::new()));
+    let operation = confirm_operation(action, names, backend.backend());
+    let started_at = chrono::Utc::now();
+    let start = std::time::Instant::now();
+    let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let mut result = rt.block_on(backend.execute_with_progress(
+        operation.clone(),
+        progress_events(tx, names.len()),
+        Arc::new(answerer),
+        cancel,
+    ));
+
+    untrack_operation();
+    *input_sender.lock().unwrap() = None;
+    let _ = asking_handle.join();
+
+    if let Some(journal) = history {
+        let recorded = match &result {
+            Ok(result) => journal.append(&HistoryEntry::new(started_at, result, &result.old_versions)),
+            Err(e) => {
+                let failed = OperationResult::from_error(operation, e, start.elapsed().as_millis() as u64);
+                journal.append(&HistoryEntry::new(started_at, &failed, &failed.old_versions))
+            }
+        };
+        if let Err(e) = recorded {
+            error!("Failed to write history to {}: {}", journal.path().display(), e);
+            if let Ok(result) = &mut result {
+                result.warnings.push(format!("Not recorded in history: {}", e));
+            }
+        }
+    }
+    report_result(tx, result);
+}
 
-    // Reader thread: parse output for progress/conflicts
-    let tx_reader = tx.clone();
-    let master_fd_reader = master_fd;
-    let escalated_r = escalated.clone();
-    let output_buffer_r = output_buffer.clone();
-    let in_tx_r = in_tx;
-    let total_packages = names.len().max(1);
+/// The prompt shown in the progress popup for a backend question.
+fn question_prompt(question: &TransactionQuestion) -> String {
+    match question {
+        TransactionQuestion::SelectProvider { providers, .. } => {
+            format!("{} Enter a number (1-{}):", question, providers.len())
+        }
+        _ if question.default_answer().is_yes() => format!("{} [Y/n]", question),
+        _ => format!("{} [y/N]", question),
+    }
+}
 
-    let reader_handle = thread::spawn(move || {
-        use std::io::Read;
-        let mut file = unsafe { std::fs::File::from_raw_fd(master_fd_reader) };
-        let mut buf = [0u8; 4096];
-        let mut current_percent: i32 = 0;
-        // Throttle UI output updates to avoid flooding the renderer
-        let mut pending_output = String::new();
-        let mut last_output_flush = std::time::Instant::now();
-        const OUTPUT_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(150);
-        const MAX_OUTPUT_LINES: usize = 80;
+/// Read a typed response to a question; anything unrecognised takes the
+/// question's default.
+fn parse_answer(question: &TransactionQuestion, input: &str) -> QuestionAnswer {
+    let input = input.trim().to_lowercase();
+    match question {
+        TransactionQuestion::SelectProvider { providers, .. } => match input.parse::<usize>() {
+            Ok(n) if (1..=providers.len()).contains(&n) => QuestionAnswer::Provider(n - 1),
+            _ => question.default_answer(),
+        },
+        _ => match input.as_str() {
+            "y" | "yes" => QuestionAnswer::Yes,
+            "n" | "no" => QuestionAnswer::No,
+            _ => question.default_answer(),
+        },
+    }
+}
 
-        loop {
-            match file.read(&mut buf) {
-                Ok(0) => break,
-                                      Ok(n) => {
-                                          let text = String::from_utf8_lossy(&buf[..n]);
-                                          let cleaned = strip_ansi(&text);
-                                          if cleaned.is_empty() {
-                                              continue;
-                                          }
+/// Run a pacman operation as root through the privileged helper, driving
+/// the progress popup from its events and asking its questions there.
+fn run_helper_operation(
+    tx: &mpsc::Sender<UiMessage>,
+    title: &str,
+    action: &str,
+    names: &[String],
+    input_sender: &Arc<Mutex<Option<mpsc::Sender<String>>>>,
+) {
+    let _ = tx.send(UiMessage::ShowProgressPopup(title.to_string()));
 
-                                          // Accumulate output (cap at 64KB to prevent memory issues with large updates)
-                                          {
-                                              let mut buf = output_buffer_r.lock().unwrap();
-                                              if buf.len() < 65536 {
-                                                  buf.push_str(&cleaned);
-                                              }
-                                          }
+    let operation = confirm_operation(action, names, PackageBackend::Pacman);
+    let result = match Helper::spawn(&helper::Request::Execute(operation)) {
+        Ok(helper) => run_helper(tx, helper, names.len(), input_sender),
+        Err(e) => Err(xpm_core::error::Error::Other(format!("Failed to start pkexec: {}", e))),
+    };
+    report_result(tx, result);
+}
 
-                                          let is_escalated = *escalated_r.lock().unwrap();
+/// Follow a running helper until it finishes.
+fn run_helper(
+    tx: &mpsc::Sender<UiMessage>,
+    mut helper: Helper,
+    total: usize,
+    input_sender: &Arc<Mutex<Option<mpsc::Sender<String>>>>,
+) -> xpm_core::error::Result<OperationResult> {
+    let events = progress_events(tx, total);
+    let asked: Arc<Mutex<Option<TransactionQuestion>>> = Arc::default();
 
-                                          if is_escalated {
-                                              // Already escalated — forward to terminal
-                                              let _ = tx_reader.send(UiMessage::TerminalOutput(cleaned));
-                                          } else {
-                                              // Buffer output and flush at throttled intervals
-                                              // Filter out noisy progress bar lines (contain lots of # or %)
-                                              for line in cleaned.lines() {
-                                                  let trimmed = line.trim();
-                                                  if trimmed.is_empty() { continue; }
-                                                  // Skip raw progress bar fragments (e.g. "####..." or repeated dots)
-                                                  if trimmed.len() > 5 && (trimmed.chars().filter(|&c| c == '#').count() > trimmed.len() / 2
-                                                      || trimmed.chars().filter(|&c| c == '.').count() > trimmed.len() / 2) {
-                                                      continue;
-                                                      }
-                                                      pending_output.push_str(trimmed);
-                                                  pending_output.push('\n');
-                                              }
-
-                                              // Flush pending output to UI at throttled rate
-                                              let now = std::time::Instant::now();
-                                              if !pending_output.is_empty() && now.duration_since(last_output_flush) >= OUTPUT_FLUSH_INTERVAL {
-                                                  // Trim to last N lines before sending
-                                                  let lines: Vec<&str> = pending_output.lines().collect();
-                                                  if lines.len() > MAX_OUTPUT_LINES {
-                                                      pending_output = lines[lines.len() - MAX_OUTPUT_LINES..].join("\n");
-                                                      pending_output.push('\n');
-                                                  }
-                                                  let _ = tx_reader.send(UiMessage::ProgressOutput(pending_output.clone()));
-                                                  last_output_flush = now;
-                                                  // Don't clear — keep as rolling buffer so next flush has context
-                                              }
-
-                                              // Check for conflicts/errors
-                                              let lower = cleaned.to_lowercase();
-                                              let has_conflict = CONFLICT_PATTERNS.iter().any(|p| lower.contains(&p.to_lowercase()));
-
-                                              if has_conflict {
-                                                  // Escalate to terminal
-                                                  *escalated_r.lock().unwrap() = true;
-                                                  let accumulated = output_buffer_r.lock().unwrap().clone();
-                                                  let _ = tx_reader.send(UiMessage::ShowTerminalFallback(accumulated));
-                                                  continue;
-                                              }
-
-                                              // Check for routine auto-confirm prompts
-                                              let is_auto_confirm = PACMAN_AUTO_CONFIRM_PATTERNS.iter().any(|p| cleaned.contains(p));
-                                              if is_auto_confirm {
-                                                  let _ = in_tx_r.send("y".to_string());
-                                                  let _ = tx_reader.send(UiMessage::ProgressHidePrompt);
-                                              } else {
-                                                  // Check for prompts that need user input
-                                                  let needs_user_input = PACMAN_USER_PROMPT_PATTERNS.iter().any(|p| cleaned.contains(p))
-                                                  || (cleaned.contains("[y/N]") && !is_auto_confirm);
-                                                  if needs_user_input {
-                                                      // Extract the prompt text (last non-empty line)
-                                                      let prompt_text = cleaned.lines()
-                                                      .filter(|l| !l.trim().is_empty())
-                                                      .last()
-                                                      .unwrap_or(&cleaned)
-                                                      .trim()
-                                                      .to_string();
-                                                      let _ = tx_reader.send(UiMessage::ProgressPrompt(prompt_text));
-                                                  }
-                                              }
-
-                                              // Stage detection for progress
-                                              for line in cleaned.lines() {
-                                                  let lower_line = line.to_lowercase();
-                                                  let trimmed = line.trim().to_string();
-                                                  let new_percent = if lower_line.contains("resolving dependencies") {
-                                                      10
-                                                  } else if lower_line.contains("looking for conflicting") {
-                                                      15
-                                                  } else if lower_line.contains("downloading") {
-                                                      parse_progress_fraction(line, 20, 50, total_packages)
-                                                      .unwrap_or(35)
-                                                  } else if lower_line.contains("checking keyring") || lower_line.contains("checking integrity") {
-                                                      52
-                                                  } else if lower_line.contains("checking package integrity") {
-                                                      55
-                                                  } else if lower_line.contains("loading package files") {
-                                                      58
-                                                  } else if lower_line.contains("installing") || lower_line.contains("upgrading") || lower_line.contains("removing") || lower_line.contains("reinstalling") {
-                                                      parse_progress_fraction(line, 60, 85, total_packages)
-                                                      .unwrap_or(72)
-                                                  } else if lower_line.contains("running post-transaction hooks") {
-                                                      88
-                                                  } else if lower_line.contains("arming conditionpathexists") || lower_line.contains("updating linux module") || lower_line.contains("dkms") {
-                                                      90
-                                                  } else if lower_line.contains("updating linux initcpios") || lower_line.contains("mkinitcpio") {
-                                                      92
-                                                  } else if lower_line.contains("updating grub") || lower_line.contains("grub-mkconfig") {
-                                                      95
-                                                  } else if lower_line.contains("updating the info") || lower_line.contains("updating the desktop") || lower_line.contains("updating mime") {
-                                                      97
-                                                  } else {
-                                                      current_percent
-                                                  };
-
-                                                  if new_percent > current_percent {
-                                                      current_percent = new_percent;
-                                                      let _ = tx_reader.send(UiMessage::OperationProgress(current_percent, trimmed));
-                                                  } else if new_percent == current_percent && current_percent >= 88 && !trimmed.is_empty() {
-                                                      // During post-transaction hooks, keep updating the stage text
-                                                      let _ = tx_reader.send(UiMessage::OperationProgress(current_percent, trimmed));
-                                                  }
-                                              }
-                                          }
-                                      }
-                                      Err(_) => break,
-            }
-        }
-        // Final flush of any remaining output
-        if !pending_output.is_empty() {
-            let lines: Vec<&str> = pending_output.lines().collect();
-            if lines.len() > MAX_OUTPUT_LINES {
-                pending_output = lines[lines.len() - MAX_OUTPUT_LINES..].join("\n");
-                pending_output.push('\n');
-            }
-            let _ = tx_reader.send(UiMessage::ProgressOutput(pending_output));
-        }
-        std::mem::forget(file);
-    });
+    let cancel = track_operation();
+    let input = helper.input();
+    cancel.on_cancel(move || input.send(&helper::Input::Cancel));
 
-    // Writer thread
-    let master_fd_writer = master_fd;
+    // Responses typed into the popup answer the question asked last
+    let (in_tx, in_rx) = mpsc::channel::<String>();
+    *input_sender.lock().unwrap() = Some(in_tx);
+    let input = helper.input();
+    let asked_w = asked.clone();
     let writer_handle = thread::spawn(move || {
-        use std::io::Write;
-        let dup_fd = unsafe { libc::dup(master_fd_writer) };
-        if dup_fd < 0 {
-            return;
-        }
-        let mut file = unsafe { std::fs::File::from_raw_fd(dup_fd) };
-        while let Ok(input) = in_rx.recv() {
-            let data = format!("{}\n", input);
-            if file.write_all(data.as_bytes()).is_err() {
-                break;
+        while let Ok(text) = in_rx.recv() {
+            if let Some(question) = asked_w.lock().unwrap().take() {
+                input.send(&helper::Input::Answer(parse_answer(&question, &text)));
             }
-            let _ = file.flush();
         }
     });
 
-    // Wait for child
-    let status = unsafe {
-        let mut wstatus: libc::c_int = 0;
-        libc::waitpid(child_pid as libc::pid_t, &mut wstatus, 0);
-        wstatus
-    };
-
-    let success = libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0;
+    let mut finished = None;
+    while let Some(reply) = helper.next_reply() {
+        match reply {
+            helper::Reply::Event(event) => events(event),
+            helper::Reply::Question(question) => {
+                let prompt = question_prompt(&question);
+                *asked.lock().unwrap() = Some(question);
+                let _ = tx.send(UiMessage::ProgressPrompt(prompt));
+            }
+            helper::Reply::Finished(result) => finished = Some(*result),
+            helper::Reply::Resolved(_) => {}
+        }
+    }
 
-    *pid_holder.lock().unwrap() = None;
+    untrack_operation();
     *input_sender.lock().unwrap() = None;
-
-    unsafe { libc::close(master_fd); }
-
-    let _ = reader_handle.join();
     let _ = writer_handle.join();
 
-    let was_escalated = *escalated.lock().unwrap();
-    if was_escalated {
-        // Already in terminal mode
-        let _ = tx.send(UiMessage::TerminalDone(success));
-    } else {
-        let _ = tx.send(UiMessage::OperationDone(success));
-    }
-}
-
-/// Parse "(X/Y)" fraction from a line and map it to a progress range
-fn parse_progress_fraction(line: &str, range_start: i32, range_end: i32, _total_packages: usize) -> Option<i32> {
-    // Look for pattern like "(1/5)" or "( 1/ 5)"
-    if let Some(start) = line.find('(') {
-        if let Some(end) = line[start..].find(')') {
-            let inner = &line[start + 1..start + end];
-            let parts: Vec<&str> = inner.split('/').collect();
-            if parts.len() == 2 {
-                if let (Ok(current), Ok(total)) = (
-                    parts[0].trim().parse::<i32>(),
-                                                   parts[1].trim().parse::<i32>(),
-                ) {
-                    if total > 0 {
-                        let fraction = current as f64 / total as f64;
-                        return Some(range_start + ((range_end - range_start) as f64 * fraction) as i32);
-                    }
-                }
-            }
+    match finished {
+        Some(result) => {
+            helper.wait();
+            result
         }
+        None => Err(helper.exit_error()),
     }
-    None
+}
+
+/// Run a managed operation with progress tracking. Flatpak and demo
+/// operations run in-process; pacman operations run as root in the
+/// privileged helper.
+fn run_managed_operation(
+    tx: &mpsc::Sender<UiMessage>,
+    title: &str,
+    action: &str,
+    names: &[String],
+    backend: &str,
+    input_sender: &Arc<Mutex<Option<mpsc::Sender<String>>>>,
+) {
+    if backend == PackageBackend::Flatpak.id() {
+        run_flatpak_operation(tx, title, action, names, input_sender);
+        return;
+    }
+    if let Some(demo) = demo_backend().filter(|demo| demo.source_id() == backend) {
+        run_backend_operation(tx, title, action, names, demo.as_ref(), None, input_sender);
+        return;
+    }
+    run_helper_operation(tx, title, action, names, input_sender);
 }
 
 /// Convert a Package to PackageData for the UI
 fn package_to_ui(pkg: &xpm_core::package::Package, has_update: bool, desktop_map: &HashMap<String, String>) -> PackageData {
-    let backend = match pkg.backend {
-        xpm_core::package::PackageBackend::Pacman => 0,
-        xpm_core::package::PackageBackend::Flatpak => 1,
-    };
-
     let display_name = humanize_package_name(&pkg.name, desktop_map);
 
     PackageData {
@@ -723,7 +1134,7 @@ fn package_to_ui(pkg: &xpm_core::package::Package, has_update: bool, desktop_map
         version: SharedString::from(pkg.version.to_string().as_str()),
         description: SharedString::from(pkg.description.as_str()),
         repository: SharedString::from(pkg.repository.as_str()),
-        backend,
+        backend: SharedString::from(pkg.backend.id()),
         installed: matches!(
             pkg.status,
             xpm_core::package::PackageStatus::Installed | xpm_core::package::PackageStatus::Orphan
@@ -741,11 +1152,6 @@ fn package_to_ui(pkg: &xpm_core::package::Package, has_update: bool, desktop_map
 
 /// Convert UpdateInfo to PackageData for the UI
 fn update_to_ui(update: &xpm_core::package::UpdateInfo) -> PackageData {
-    let backend = match update.backend {
-        xpm_core::package::PackageBackend::Pacman => 0,
-        xpm_core::package::PackageBackend::Flatpak => 1,
-    };
-
     let version_str = format!(
         "{} → {}",
         update.current_version.to_string(),
@@ -758,7 +1164,7 @@ fn update_to_ui(update: &xpm_core::package::UpdateInfo) -> PackageData {
         version: SharedString::from(version_str.as_str()),
         description: SharedString::from(version_str.as_str()),
         repository: SharedString::from(update.repository.as_str()),
-        backend,
+        backend: SharedString::from(update.backend.id()),
         installed: true,
         has_update: true,
         installed_size: SharedString::from(format_size(update.download_size).as_str()),
@@ -771,13 +1177,223 @@ fn update_to_ui(update: &xpm_core::package::UpdateInfo) -> PackageData {
     }
 }
 
+/// Levels of the dependency tree resolved for the details popup
+const DETAILS_TREE_DEPTH: usize = 12;
+
+/// Flatten a dependency tree into rows for the details popup. The root
+/// package itself is skipped; its direct dependencies start collapsed.
+fn dependency_rows(root: &DependencyNode) -> Vec<DepNodeData> {
+    fn push_rows(node: &DependencyNode, depth: i32, parent: i32, rows: &mut Vec<DepNodeData>) {
+        let mut notes = Vec::new();
+        match &node.provider {
+            Some(provider) if *provider != node.dependency.name => notes.push(format!("provided by {}", provider)),
+            Some(_) => {}
+            None => notes.push("not found".to_string()),
+        }
+        if node.provider.is_some() && !node.installed {
+            notes.push("not installed".to_string());
+        }
+        if node.optional {
+            notes.push("optional".to_string());
+        }
+        if node.repeated {
+            notes.push("see above".to_string());
+        }
+        if node.truncated {
+            notes.push("…".to_string());
+        }
+
+        let index = rows.len() as i32;
+        rows.push(DepNodeData {
+            label: SharedString::from(node.dependency.to_string()),
+            note: SharedString::from(notes.join(", ")),
+            depth,
+            parent,
+            has_children: !node.children.is_empty(),
+            expanded: false,
+            visible: depth == 0,
+            installed: node.installed,
+            missing: node.provider.is_none(),
+        });
+        for child in &node.children {
+            push_rows(child, depth + 1, index, rows);
+        }
+    }
+
+    let mut rows = Vec::new();
+    for child in &root.children {
+        push_rows(child, 0, -1, &mut rows);
+    }
+    rows
+}
+
+/// Expand or collapse a dependency row, then show exactly the rows whose
+/// ancestors are all expanded. Parents always precede their children.
+fn toggle_dependency_row(rows: &mut [DepNodeData], index: usize) {
+    if let Some(row) = rows.get_mut(index) {
+        row.expanded = !row.expanded;
+    }
+    for i in 0..rows.len() {
+        let parent = rows[i].parent;
+        rows[i].visible = parent < 0 || (rows[parent as usize].visible && rows[parent as usize].expanded);
+    }
+}
+
+/// Describe why-installed chains, one "a → b → c" chain per line
+fn describe_why_installed(name: &str, chains: &[Vec<String>]) -> String {
+    match chains {
+        [] => "Nothing depends on it (orphan)".to_string(),
+        [chain] if chain.len() == 1 && chain[0] == name => "Installed explicitly".to_string(),
+        _ => chains.iter().map(|chain| chain.join(" → ")).collect::<Vec<_>>().join("\n"),
+    }
+}
+
+/// UI id of an install reason; empty for packages that are not installed
+fn install_reason_id(reason: Option<InstallReason>) -> String {
+    match reason {
+        Some(InstallReason::Explicit) => "explicit".to_string(),
+        Some(InstallReason::Dependency) => "dependency".to_string(),
+        None => String::new(),
+    }
+}
+
+/// Resolve dependencies, reverse dependencies, install reason and files for the details popup
+async fn load_package_details(tx: &mpsc::Sender<UiMessage>, name: &str) {
+    if let Some(demo) = demo_backend() {
+        // The demo backend has no dependency index; list direct dependencies only
+        let info = demo.get_package_info(name).await;
+        let reason = info.as_ref().ok().and_then(|info| info.reason);
+        let tree = info
+            .map(|info| {
+                info.depends
+                    .iter()
+                    .map(|dep| DepNodeData {
+                        label: SharedString::from(dep.to_string()),
+                        note: SharedString::from(""),
+                        depth: 0,
+                        parent: -1,
+                        has_children: false,
+                        expanded: false,
+                        visible: true,
+                        installed: true,
+                        missing: false,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let _ = tx.send(UiMessage::PackageDetails {
+            name: name.to_string(),
+            dependencies: String::new(),
+            required_by: String::new(),
+            why: String::new(),
+            reason: install_reason_id(reason),
+            tree,
+            files: Vec::new(),
+            versions: Vec::new(),
+        });
+        return;
+    }
+
+    let backend = match alpm_backend() {
+        Ok(backend) => backend,
+        Err(e) => {
+            error!("Failed to open pacman backend: {}", e);
+            let _ = tx.send(UiMessage::PackageDetails {
+                name: name.to_string(),
+                dependencies: String::new(),
+                required_by: String::new(),
+                why: format!("Could not load details: {}", e),
+                reason: String::new(),
+                tree: Vec::new(),
+                files: Vec::new(),
+                versions: Vec::new(),
+            });
+            return;
+        }
+    };
+
+    let (dependencies, tree) = match backend.dependency_tree(name, DETAILS_TREE_DEPTH, true).await {
+        Ok(root) => {
+            let direct: Vec<String> = root.children.iter().filter(|c| !c.optional).map(|c| c.dependency.name.clone()).collect();
+            (direct.join(", "), dependency_rows(&root))
+        }
+        Err(e) => {
+            error!("Failed to resolve dependencies of {}: {}", name, e);
+            (String::new(), Vec::new())
+        }
+    };
+    let required_by = backend
+        .reverse_dependencies(name)
+        .await
+        .map(|rev| rev.required_by.join(", "))
+        .unwrap_or_default();
+    // Only installed packages have an install reason
+    let why = backend
+        .why_installed(name)
+        .await
+        .map(|chains| describe_why_installed(name, &chains))
+        .unwrap_or_default();
+
+    let reason = backend.get_package_info(name).await.ok().and_then(|info| info.reason);
+    // Only installed packages have a file list
+    let files = match reason {
+        Some(_) => backend
+            .package_files(name)
+            .await
+            .map(|files| {
+                files
+                    .into_iter()
+                    .map(|file| PackageFileData { path: SharedString::from(file.path), backup: file.backup })
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                error!("Failed to list files of {}: {}", name, e);
+                Vec::new()
+            }),
+        None => Vec::new(),
+    };
+    // Only installed packages can be downgraded
+    let versions = match reason {
+        Some(_) => backend
+            .cached_versions(name)
+            .await
+            .map(|versions| versions.into_iter().map(cached_version_to_data).collect())
+            .unwrap_or_else(|e| {
+                error!("Failed to list cached versions of {}: {}", name, e);
+                Vec::new()
+            }),
+        None => Vec::new(),
+    };
+
+    let _ = tx.send(UiMessage::PackageDetails {
+        name: name.to_string(),
+        dependencies,
+        required_by,
+        why,
+        reason: install_reason_id(reason),
+        tree,
+        files,
+        versions,
+    });
+}
+
+/// Convert a cached package version into a row of the details popup.
+fn cached_version_to_data(version: CachedVersion) -> CachedVersionData {
+    CachedVersionData {
+        version: SharedString::from(version.package.version.to_string()),
+        path: SharedString::from(version.package.path.to_string_lossy().to_string()),
+        signature: SharedString::from(version.signature.to_string()),
+        signature_ok: version.signature == SignatureStatus::Valid,
+    }
+}
+
 /// Helper to update the `selected` field in a VecModel
-fn update_selection_in_model(model: &ModelRc<PackageData>, name: &str, backend: i32, selected: bool) {
+fn update_selection_in_model(model: &ModelRc<PackageData>, name: &str, backend: &str, selected: bool) {
     let model = model.as_any().downcast_ref::<VecModel<PackageData>>();
     if let Some(vec_model) = model {
         for i in 0..vec_model.row_count() {
             if let Some(mut row) = vec_model.row_data(i) {
-                if row.name.as_str() == name && row.backend == backend {
+                if row.name.as_str() == name && row.backend.as_str() == backend {
                     row.selected = selected;
                     vec_model.set_row_data(i, row);
                     break;
@@ -788,7 +1404,7 @@ fn update_selection_in_model(model: &ModelRc<PackageData>, name: &str, backend:
 }
 
 /// Look up whether a package is installed by searching all models
-fn find_package_installed(window: &MainWindow, name: &str, backend: i32) -> bool {
+fn find_package_installed(window: &MainWindow, name: &str, backend: &str) -> bool {
     let models: Vec<ModelRc<PackageData>> = vec![
         window.get_installed_packages(),
         window.get_update_packages(),
@@ -802,7 +1418,7 @@ fn find_package_installed(window: &MainWindow, name: &str, backend: i32) -> bool
         if let Some(vec_model) = model.as_any().downcast_ref::<VecModel<PackageData>>() {
             for i in 0..vec_model.row_count() {
                 if let Some(row) = vec_model.row_data(i) {
-                    if row.name.as_str() == name && row.backend == backend {
+                    if row.name.as_str() == name && row.backend.as_str() == backend {
                         return row.installed;
                     }
                 }
@@ -813,7 +1429,7 @@ fn find_package_installed(window: &MainWindow, name: &str, backend: i32) -> bool
 }
 
 /// Update selection state across all package models in the window
-fn update_selection_in_models(window: &MainWindow, name: &str, backend: i32, selected: bool) {
+fn update_selection_in_models(window: &MainWindow, name: &str, backend: &str, selected: bool) {
     update_selection_in_model(&window.get_installed_packages(), name, backend, selected);
     update_selection_in_model(&window.get_update_packages(), name, backend, selected);
     update_selection_in_model(&window.get_search_packages(), name, backend, selected);
@@ -824,6 +1440,16 @@ fn update_selection_in_models(window: &MainWindow, name: &str, backend: i32, sel
 }
 
 fn main() {
+    // The privileged helper answers on stdout, so it logs to stderr.
+    if std::env::args().nth(1).as_deref() == Some(helper::HELPER_FLAG) {
+        let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .with_writer(std::io::stderr)
+        .finish();
+        tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
+        std::process::exit(helper::serve());
+    }
+
     // Initialize logging.
     let subscriber = FmtSubscriber::builder()
     .with_max_level(Level::INFO)
@@ -834,14 +1460,27 @@ fn main() {
 
     // Check for command line arguments (file to install)
     let args: Vec<String> = std::env::args().collect();
-    let local_package_path = args.get(1).filter(|arg| is_arch_package(arg)).cloned();
+    let local_package_path = args.iter().skip(1).find(|arg| is_arch_package(arg)).cloned();
+
+    // --demo runs against an in-memory backend; --demo=FILE loads a fixture.
+    if let Some(arg) = args.iter().skip(1).find(|arg| arg.starts_with("--demo")) {
+        let demo = match arg.strip_prefix("--demo=") {
+            Some(path) => FakeBackend::load(path).unwrap_or_else(|e| {
+                error!("Failed to load demo fixture {}: {}", path, e);
+                std::process::exit(1);
+            }),
+            None => FakeBackend::demo(),
+        };
+        info!("Running in demo mode");
+        let _ = DEMO_BACKEND.set(Arc::new(demo));
+    }
 
     if let Some(ref path) = local_package_path {
         info!("Opening local package: {}", path);
     }
 
     // Check if running on XeroLinux
-    if !is_xerolinux_distro() {
+    if demo_backend().is_none() && !is_xerolinux_distro() {
         let warning = DistroWarning::new().expect("Failed to create warning window");
         warning.on_dismiss(move || {
             std::process::exit(0);
@@ -862,7 +1501,7 @@ fn main() {
     let terminal_child_pid: Arc<Mutex<Option<u32>>> = Arc::new(Mutex::new(None));
 
     // Selection tracking: Vec of (name, backend, installed)
-    let selected_packages: Rc<RefCell<Vec<(String, i32, bool)>>> = Rc::new(RefCell::new(Vec::new()));
+    let selected_packages: Rc<RefCell<Vec<(String, SharedString, bool)>>> = Rc::new(RefCell::new(Vec::new()));
 
     // Create backend thread sender
     let tx_load = tx.clone();
@@ -942,6 +1581,7 @@ fn main() {
                             thread::spawn(move || {
                                 let rt = tokio::runtime::Runtime::new().expect("Runtime");
                                 rt.block_on(async {
+                                    refresh_alpm_index();
                                     load_packages_async(&tx, false).await;
                                     // Re-run search so Install/Remove buttons update
                                     if !search_query.is_empty() {
@@ -979,10 +1619,6 @@ fn main() {
                         window.set_progress_popup_prompt(SharedString::from(&prompt));
                         window.set_progress_popup_show_input(true);
                     }
-                    UiMessage::ProgressHidePrompt => {
-                        window.set_progress_popup_show_input(false);
-                        window.set_progress_popup_prompt(SharedString::from(""));
-                    }
                     UiMessage::OperationProgress(percent, stage) => {
                         window.set_progress_popup_percent(percent);
                         window.set_progress_popup_stage(SharedString::from(&stage));
@@ -1006,6 +1642,7 @@ fn main() {
                             thread::spawn(move || {
                                 let rt = tokio::runtime::Runtime::new().expect("Runtime");
                                 rt.block_on(async {
+                                    refresh_alpm_index();
                                     load_packages_async(&tx, false).await;
                                     if !search_query.is_empty() {
                                         search_packages_async(&tx, &search_query).await;
@@ -1017,17 +1654,68 @@ fn main() {
                             });
                         }
                     }
-                    UiMessage::ShowTerminalFallback(accumulated) => {
-                        // Switch from progress popup to terminal
-                        window.set_show_progress_popup(false);
-                        window.set_terminal_title(window.get_progress_popup_title());
-                        window.set_terminal_output(SharedString::from(""));
-                        window.set_terminal_done(false);
-                        window.set_terminal_success(false);
-                        window.set_show_terminal(true);
-                        pending_terminal.clear();
-                        pending_terminal.push_str(&accumulated);
-                        flush_now = true;
+                    UiMessage::HistoryLoaded(entries) => {
+                        window.set_history_entries(ModelRc::new(VecModel::from(entries)));
+                        window.set_loading(false);
+                    }
+                    UiMessage::LocalPackageInspected { package, installed_version, missing_deps, signature, signature_ok, build } => {
+                        window.set_local_package(package);
+                        window.set_local_installed_version(SharedString::from(installed_version));
+                        window.set_local_missing_deps(SharedString::from(missing_deps));
+                        window.set_local_signature(SharedString::from(signature));
+                        window.set_local_signature_ok(signature_ok);
+                        window.set_local_build(SharedString::from(build));
+                        window.set_local_loading(false);
+                    }
+                    UiMessage::LocalPackageFailed(message) => {
+                        window.set_local_error(SharedString::from(message));
+                        window.set_local_loading(false);
+                    }
+                    UiMessage::ConfigFiles(files) => {
+                        window.set_config_files(ModelRc::new(VecModel::from(files)));
+                        window.set_config_files_done(true);
+                    }
+                    UiMessage::ConfigFileReview(data, merged) => {
+                        window.set_config_merge(data);
+                        window.set_config_merged(SharedString::from(merged));
+                        window.set_show_config_merge(true);
+                    }
+                    UiMessage::VerifyResults(results) => {
+                        window.set_verify_results(ModelRc::new(VecModel::from(results)));
+                        window.set_verify_done(true);
+                        window.set_verifying(false);
+                        window.set_loading(false);
+                    }
+                    UiMessage::VerifyStopped => {
+                        window.set_verifying(false);
+                        window.set_loading(false);
+                    }
+                    UiMessage::PackageDetails { name, dependencies, required_by, why, reason, tree, files, versions } => {
+                        // Ignore details for a popup that has since changed
+                        let mut package = window.get_details_package();
+                        if window.get_show_details() && package.name == name.as_str() {
+                            package.dependencies = SharedString::from(dependencies);
+                            package.required_by = SharedString::from(required_by);
+                            window.set_details_package(package);
+                            window.set_details_why(SharedString::from(why));
+                            window.set_details_reason(SharedString::from(reason));
+                            window.set_details_tree(ModelRc::new(VecModel::from(tree)));
+                            window.set_details_files(ModelRc::new(VecModel::from(files)));
+                            window.set_details_versions(ModelRc::new(VecModel::from(versions)));
+                            window.set_details_loading(false);
+                        }
+                    }
+                    UiMessage::ConfirmPlan { names, version, size, deps, notes, hooks } => {
+                        // Ignore previews for a popup that has since changed
+                        if window.get_show_confirm_popup() && window.get_confirm_package_names() == names.as_str() {
+                            window.set_confirm_version(SharedString::from(version));
+                            window.set_confirm_size(SharedString::from(size));
+                            window.set_confirm_deps(SharedString::from(deps));
+                            window.set_confirm_notes(SharedString::from(notes));
+                            window.set_confirm_hook_count(hooks.lines().count() as i32);
+                            window.set_confirm_hooks(SharedString::from(hooks));
+                            window.set_confirm_planning(false);
+                        }
                     }
                 }
             }
@@ -1064,11 +1752,23 @@ fn main() {
 
     // Handle local package file if passed via command line
     if let Some(ref path) = local_package_path {
-        if let Some(pkg_info) = get_local_package_info(path) {
-            window.set_local_package(pkg_info);
+        if Path::new(path).exists() {
+            let file_name = Path::new(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            window.set_local_package(PackageData {
+                display_name: SharedString::from(file_name),
+                ..Default::default()
+            });
             window.set_local_package_path(SharedString::from(path.as_str()));
+            window.set_local_loading(true);
             window.set_show_local_install(true);
             window.set_view(4);
+
+            let tx = tx.clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+                rt.block_on(inspect_local_package(&tx, &path));
+            });
         }
     }
 
@@ -1080,6 +1780,7 @@ fn main() {
             let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
             rt.block_on(async {
                 let _ = tx.send(UiMessage::SetLoading(true));
+                refresh_alpm_index();
                 load_packages_async(&tx, false).await;
             });
         });
@@ -1119,6 +1820,104 @@ fn main() {
     let repo_model: Vec<SharedString> = repos.iter().map(|r| SharedString::from(r.as_str())).collect();
     window.set_repos(ModelRc::new(VecModel::from(repo_model)));
 
+    // Load history callback
+    let tx_history = tx.clone();
+    window.on_load_history(move |package, failed_only| {
+        let tx = tx_history.clone();
+        let package = package.to_string();
+        thread::spawn(move || {
+            let _ = tx.send(UiMessage::SetLoading(true));
+            load_history(&tx, &package, failed_only);
+        });
+    });
+
+    // Verify installed packages callback
+    let tx_verify = tx.clone();
+    let window_weak_vp = window.as_weak();
+    window.on_verify_packages(move || {
+        let tx = tx_verify.clone();
+        if let Some(window) = window_weak_vp.upgrade() {
+            window.set_verifying(true);
+        }
+        let cancel = CancellationToken::new();
+        *RUNNING_VERIFY.lock().unwrap() = Some(cancel.clone());
+        thread::spawn(move || {
+            let _ = tx.send(UiMessage::SetLoading(true));
+            let _ = tx.send(UiMessage::SetStatus("Verifying installed files...".to_string()));
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+            rt.block_on(verify_installed_packages(&tx, cancel));
+            RUNNING_VERIFY.lock().unwrap().take();
+        });
+    });
+
+    window.on_cancel_verify(move || {
+        if let Some(cancel) = RUNNING_VERIFY.lock().unwrap().as_ref() {
+            info!("Cancelling package verification");
+            cancel.cancel();
+        }
+    });
+
+    let tx_config = tx.clone();
+    window.on_find_config_files(move || {
+        let tx = tx_config.clone();
+        thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+            rt.block_on(find_config_files(&tx));
+        });
+    });
+
+    let tx_review = tx.clone();
+    window.on_review_config_file(move |data| {
+        let tx = tx_review.clone();
+        let file = config_file_from_data(&data);
+        thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+            rt.block_on(review_config_file(&tx, file));
+        });
+    });
+
+    let tx_resolve = tx.clone();
+    let window_weak_rc = window.as_weak();
+    window.on_resolve_config_file(move |action| {
+        let Some(window) = window_weak_rc.upgrade() else { return };
+        let merge = window.get_config_merge();
+        let file = config_file_from_data(&ConfigFileData {
+            path: merge.path.clone(),
+            package: merge.package.clone(),
+            kind: merge.kind.clone(),
+        });
+        let merged = window.get_config_merged().to_string();
+        window.set_show_config_merge(false);
+
+        let tx = tx_resolve.clone();
+        let action = action.to_string();
+        thread::spawn(move || {
+            let resolution = match action.as_str() {
+                "keep" => ConfigFileAction::Keep,
+                "replace" => ConfigFileAction::Replace,
+                _ => ConfigFileAction::Merge(merged),
+            };
+            let message = match resolve_config_file(&file, resolution) {
+                Ok(()) => match action.as_str() {
+                    "keep" => format!("Kept {}", file.path),
+                    "replace" => format!("Replaced {} with its {}", file.path, file.kind),
+                    _ => format!("Merged {} into {}", file.pending_path(), file.path),
+                },
+                Err(e) => format!("Could not update {}: {}", file.path, e),
+            };
+            let _ = tx.send(UiMessage::SetStatus(message));
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+            rt.block_on(find_config_files(&tx));
+        });
+    });
+
+    let window_weak_cm = window.as_weak();
+    window.on_close_config_merge(move || {
+        if let Some(window) = window_weak_cm.upgrade() {
+            window.set_show_config_merge(false);
+        }
+    });
+
     // Load repo packages callback
     let tx_repo = tx.clone();
     window.on_load_repo(move |repo| {
@@ -1134,70 +1933,63 @@ fn main() {
     // Install package callback (called by confirm-operation)
     let tx_install = tx.clone();
     let install_input = terminal_input_sender.clone();
-    let install_pid = terminal_child_pid.clone();
     window.on_install_package(move |name, backend| {
         info!("Install: {} (backend: {})", name, backend);
         let tx = tx_install.clone();
         let name = name.to_string();
         let input = install_input.clone();
-        let pid = install_pid.clone();
 
         thread::spawn(move || {
             let title = format!("Installing {}", name);
-            run_managed_operation(&tx, &title, "install", &[name], backend, &input, &pid);
+            run_managed_operation(&tx, &title, "install", &[name], &backend, &input);
         });
     });
 
     // Remove package callback (called by confirm-operation)
     let tx_remove = tx.clone();
     let remove_input = terminal_input_sender.clone();
-    let remove_pid = terminal_child_pid.clone();
     window.on_remove_package(move |name, backend| {
         info!("Remove: {} (backend: {})", name, backend);
         let tx = tx_remove.clone();
         let name = name.to_string();
         let input = remove_input.clone();
-        let pid = remove_pid.clone();
 
         thread::spawn(move || {
             let title = format!("Removing {}", name);
-            run_managed_operation(&tx, &title, "remove", &[name], backend, &input, &pid);
+            run_managed_operation(&tx, &title, "remove", &[name], &backend, &input);
         });
     });
 
     // Update package callback (called by confirm-operation)
     let tx_upd = tx.clone();
     let upd_input = terminal_input_sender.clone();
-    let upd_pid = terminal_child_pid.clone();
     window.on_update_package(move |name, backend| {
         info!("Update: {} (backend: {})", name, backend);
         let tx = tx_upd.clone();
         let name = name.to_string();
         let input = upd_input.clone();
-        let pid = upd_pid.clone();
 
         thread::spawn(move || {
             let title = format!("Updating {}", name);
-            run_managed_operation(&tx, &title, "update", &[name], backend, &input, &pid);
+            run_managed_operation(&tx, &title, "update", &[name], &backend, &input);
         });
     });
 
     // Update all callback - route through managed operation
     let tx_update = tx.clone();
     let update_all_input = terminal_input_sender.clone();
-    let update_all_pid = terminal_child_pid.clone();
     window.on_update_all(move || {
         info!("Update all packages");
         let tx = tx_update.clone();
         let input = update_all_input.clone();
-        let pid = update_all_pid.clone();
 
         thread::spawn(move || {
-            run_managed_operation(&tx, "System Update", "update-all", &[], 0, &input, &pid);
+            run_managed_operation(&tx, "System Update", "update-all", &[], PackageBackend::Pacman.id(), &input);
         });
     });
 
     // Request install — show confirmation popup
+    let tx_ri = tx.clone();
     let window_weak_ri = window.as_weak();
     window.on_request_install(move |name, backend| {
         if let Some(window) = window_weak_ri.upgrade() {
@@ -1207,13 +1999,18 @@ fn main() {
             window.set_confirm_version(SharedString::from(""));
             window.set_confirm_size(SharedString::from(""));
             window.set_confirm_deps(SharedString::from(""));
-            window.set_confirm_backend(backend);
+            window.set_confirm_notes(SharedString::from(""));
+            window.set_confirm_hooks(SharedString::from(""));
+            window.set_confirm_backend(backend.clone());
             window.set_confirm_package_count(1);
+            window.set_confirm_as_deps(false);
             window.set_show_confirm_popup(true);
+            window.set_confirm_planning(request_confirm_plan(&tx_ri, "install", &name, &backend));
         }
     });
 
     // Request remove — show confirmation popup
+    let tx_rr = tx.clone();
     let window_weak_rr = window.as_weak();
     window.on_request_remove(move |name, backend| {
         if let Some(window) = window_weak_rr.upgrade() {
@@ -1223,13 +2020,18 @@ fn main() {
             window.set_confirm_version(SharedString::from(""));
             window.set_confirm_size(SharedString::from(""));
             window.set_confirm_deps(SharedString::from(""));
-            window.set_confirm_backend(backend);
+            window.set_confirm_notes(SharedString::from(""));
+            window.set_confirm_hooks(SharedString::from(""));
+            window.set_confirm_backend(backend.clone());
             window.set_confirm_package_count(1);
+            window.set_confirm_as_deps(false);
             window.set_show_confirm_popup(true);
+            window.set_confirm_planning(request_confirm_plan(&tx_rr, "remove", &name, &backend));
         }
     });
 
     // Request update — show confirmation popup
+    let tx_ru = tx.clone();
     let window_weak_ru = window.as_weak();
     window.on_request_update(move |name, backend| {
         if let Some(window) = window_weak_ru.upgrade() {
@@ -1239,16 +2041,19 @@ fn main() {
             window.set_confirm_version(SharedString::from(""));
             window.set_confirm_size(SharedString::from(""));
             window.set_confirm_deps(SharedString::from(""));
-            window.set_confirm_backend(backend);
+            window.set_confirm_notes(SharedString::from(""));
+            window.set_confirm_hooks(SharedString::from(""));
+            window.set_confirm_backend(backend.clone());
             window.set_confirm_package_count(1);
+            window.set_confirm_as_deps(false);
             window.set_show_confirm_popup(true);
+            window.set_confirm_planning(request_confirm_plan(&tx_ru, "update", &name, &backend));
         }
     });
 
     // Confirm operation — dispatch to actual operation via run_managed_operation
     let tx_confirm = tx.clone();
     let confirm_input = terminal_input_sender.clone();
-    let confirm_pid = terminal_child_pid.clone();
     let window_weak_co = window.as_weak();
     window.on_confirm_operation(move || {
         if let Some(window) = window_weak_co.upgrade() {
@@ -1260,7 +2065,6 @@ fn main() {
             let name_list: Vec<String> = names_str.split('\n').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect();
             let tx = tx_confirm.clone();
             let input = confirm_input.clone();
-            let pid = confirm_pid.clone();
 
             match action.as_str() {
                 "install" | "bulk-install" => {
@@ -1269,8 +2073,9 @@ fn main() {
                     } else {
                         format!("Installing {}", name_list.first().map(|s| s.as_str()).unwrap_or(""))
                     };
+                    let install_action = if window.get_confirm_as_deps() { "install-asdeps" } else { "install" };
                     thread::spawn(move || {
-                        run_managed_operation(&tx, &title, "install", &name_list, backend, &input, &pid);
+                        run_managed_operation(&tx, &title, install_action, &name_list, &backend, &input);
                     });
                 }
                 "remove" | "bulk-remove" => {
@@ -1280,18 +2085,18 @@ fn main() {
                         format!("Removing {}", name_list.first().map(|s| s.as_str()).unwrap_or(""))
                     };
                     thread::spawn(move || {
-                        run_managed_operation(&tx, &title, "remove", &name_list, backend, &input, &pid);
+                        run_managed_operation(&tx, &title, "remove", &name_list, &backend, &input);
                     });
                 }
                 "update" => {
                     let title = format!("Updating {}", names_str);
                     thread::spawn(move || {
-                        run_managed_operation(&tx, &title, "update", &name_list, backend, &input, &pid);
+                        run_managed_operation(&tx, &title, "update", &name_list, &backend, &input);
                     });
                 }
                 "update-all" => {
                     thread::spawn(move || {
-                        run_managed_operation(&tx, "System Update", "update-all", &[], 0, &input, &pid);
+                        run_managed_operation(&tx, "System Update", "update-all", &[], PackageBackend::Pacman.id(), &input);
                     });
                 }
                 _ => {}
@@ -1307,6 +2112,101 @@ fn main() {
         }
     });
 
+    // Package details popup
+    let tx_details = tx.clone();
+    let window_weak_pd = window.as_weak();
+    window.on_show_package_details(move |package| {
+        let Some(window) = window_weak_pd.upgrade() else { return };
+        let name = package.name.to_string();
+        // Only pacman packages have a dependency index
+        let resolve = package.backend == PackageBackend::Pacman.id();
+        window.set_details_package(package);
+        window.set_details_tree(ModelRc::new(VecModel::from(Vec::<DepNodeData>::new())));
+        window.set_details_files(ModelRc::new(VecModel::from(Vec::<PackageFileData>::new())));
+        window.set_details_versions(ModelRc::new(VecModel::from(Vec::<CachedVersionData>::new())));
+        window.set_details_ignore_upgrades(false);
+        window.set_details_tab(0);
+        window.set_details_why(SharedString::from(""));
+        window.set_details_reason(SharedString::from(""));
+        window.set_details_loading(resolve);
+        window.set_show_details(true);
+
+        if resolve {
+            let tx = tx_details.clone();
+            thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+                rt.block_on(load_package_details(&tx, &name));
+            });
+        }
+    });
+
+    let window_weak_dt = window.as_weak();
+    window.on_toggle_dep_node(move |index| {
+        if let Some(window) = window_weak_dt.upgrade() {
+            let mut rows: Vec<DepNodeData> = window.get_details_tree().iter().collect();
+            toggle_dependency_row(&mut rows, index as usize);
+            window.set_details_tree(ModelRc::new(VecModel::from(rows)));
+        }
+    });
+
+    let tx_reason = tx.clone();
+    let reason_input = terminal_input_sender.clone();
+    let window_weak_sr = window.as_weak();
+    window.on_set_install_reason(move |name, explicit| {
+        if let Some(window) = window_weak_sr.upgrade() {
+            window.set_show_details(false);
+        }
+        let (title, action) = if explicit {
+            (format!("Marking {} as explicitly installed", name), "mark-explicit")
+        } else {
+            (format!("Marking {} as dependency", name), "mark-deps")
+        };
+        let names = vec![name.to_string()];
+        let tx = tx_reason.clone();
+        let input = reason_input.clone();
+        thread::spawn(move || {
+            run_managed_operation(&tx, &title, action, &names, PackageBackend::Pacman.id(), &input);
+        });
+    });
+
+    let tx_downgrade = tx.clone();
+    let downgrade_input = terminal_input_sender.clone();
+    let window_weak_dg = window.as_weak();
+    window.on_downgrade_package(move |name, version, path, ignore| {
+        if let Some(window) = window_weak_dg.upgrade() {
+            window.set_show_details(false);
+        }
+        let title = format!("Downgrading {} to {}", name, version);
+        let action = if ignore { "downgrade-ignore" } else { "downgrade" };
+        let names = vec![path.to_string()];
+        let tx = tx_downgrade.clone();
+        let input = downgrade_input.clone();
+        thread::spawn(move || {
+            run_managed_operation(&tx, &title, action, &names, PackageBackend::Pacman.id(), &input);
+        });
+    });
+
+    let window_weak_cd = window.as_weak();
+    window.on_close_details(move || {
+        if let Some(window) = window_weak_cd.upgrade() {
+            window.set_show_details(false);
+        }
+    });
+
+    // Cancel the operation in the progress popup
+    let window_weak_cop = window.as_weak();
+    window.on_cancel_operation(move || {
+        if let Some(cancel) = RUNNING_OPERATION.lock().unwrap().as_ref() {
+            info!("Cancelling the running operation");
+            cancel.cancel();
+        }
+        if let Some(window) = window_weak_cop.upgrade() {
+            window.set_progress_popup_stage(SharedString::from("Cancelling…"));
+            window.set_progress_popup_show_input(false);
+            window.set_progress_popup_prompt(SharedString::from(""));
+        }
+    });
+
     // Close progress popup
     let window_weak_cp = window.as_weak();
     window.on_close_progress_popup(move || {
@@ -1338,11 +2238,11 @@ fn main() {
 
         if let Some(window) = window_weak_tps.upgrade() {
             // Look up installed state from models
-            let is_installed = find_package_installed(&window, &name_str, backend);
+            let is_installed = find_package_installed(&window, &name_str, &backend);
 
             if selected {
                 if !sel.iter().any(|(n, b, _)| n == &name_str && *b == backend) {
-                    sel.push((name_str.clone(), backend, is_installed));
+                    sel.push((name_str.clone(), backend.clone(), is_installed));
                 }
             } else {
                 sel.retain(|(n, b, _)| !(n == &name_str && *b == backend));
@@ -1352,7 +2252,7 @@ fn main() {
             let installed_count = sel.iter().filter(|(_, _, inst)| *inst).count() as i32;
             window.set_selected_installed_count(installed_count);
             window.set_selected_uninstalled_count(sel.len() as i32 - installed_count);
-            update_selection_in_models(&window, &name_str, backend, selected);
+            update_selection_in_models(&window, &name_str, &backend, selected);
         }
     });
 
@@ -1361,27 +2261,28 @@ fn main() {
     let window_weak_cs = window.as_weak();
     window.on_clear_selection(move || {
         let mut sel = selected_pkgs_clear.borrow_mut();
-        let old_sel: Vec<(String, i32, bool)> = sel.drain(..).collect();
+        let old_sel: Vec<(String, SharedString, bool)> = sel.drain(..).collect();
         if let Some(window) = window_weak_cs.upgrade() {
             window.set_selected_count(0);
             window.set_selected_installed_count(0);
             window.set_selected_uninstalled_count(0);
             for (name, backend, _) in &old_sel {
-                update_selection_in_models(&window, name, *backend, false);
+                update_selection_in_models(&window, name, backend, false);
             }
         }
     });
 
     // Bulk install — collect only uninstalled packages, show confirm
     let selected_pkgs_bi = selected_packages.clone();
+    let tx_bi = tx.clone();
     let window_weak_bi = window.as_weak();
     window.on_bulk_install(move || {
         let sel = selected_pkgs_bi.borrow();
         // Only include uninstalled packages
-        let uninstalled: Vec<&(String, i32, bool)> = sel.iter().filter(|(_, _, inst)| !inst).collect();
+        let uninstalled: Vec<&(String, SharedString, bool)> = sel.iter().filter(|(_, _, inst)| !inst).collect();
         if uninstalled.is_empty() { return; }
         let names: Vec<String> = uninstalled.iter().map(|(n, _, _)| n.clone()).collect();
-        let backend = uninstalled[0].1;
+        let backend = uninstalled[0].1.clone();
         let names_str = names.join("\n");
 
         if let Some(window) = window_weak_bi.upgrade() {
@@ -1391,22 +2292,27 @@ fn main() {
             window.set_confirm_version(SharedString::from(""));
             window.set_confirm_size(SharedString::from(""));
             window.set_confirm_deps(SharedString::from(""));
-            window.set_confirm_backend(backend);
+            window.set_confirm_notes(SharedString::from(""));
+            window.set_confirm_hooks(SharedString::from(""));
+            window.set_confirm_backend(backend.clone());
             window.set_confirm_package_count(names.len() as i32);
+            window.set_confirm_as_deps(false);
             window.set_show_confirm_popup(true);
+            window.set_confirm_planning(request_confirm_plan(&tx_bi, "bulk-install", &names_str, &backend));
         }
     });
 
     // Bulk remove — collect only installed packages, show confirm
     let selected_pkgs_br = selected_packages.clone();
+    let tx_br = tx.clone();
     let window_weak_br = window.as_weak();
     window.on_bulk_remove(move || {
         let sel = selected_pkgs_br.borrow();
         // Only include installed packages
-        let installed: Vec<&(String, i32, bool)> = sel.iter().filter(|(_, _, inst)| *inst).collect();
+        let installed: Vec<&(String, SharedString, bool)> = sel.iter().filter(|(_, _, inst)| *inst).collect();
         if installed.is_empty() { return; }
         let names: Vec<String> = installed.iter().map(|(n, _, _)| n.clone()).collect();
-        let backend = installed[0].1;
+        let backend = installed[0].1.clone();
         let names_str = names.join("\n");
 
         if let Some(window) = window_weak_br.upgrade() {
@@ -1416,9 +2322,13 @@ fn main() {
             window.set_confirm_version(SharedString::from(""));
             window.set_confirm_size(SharedString::from(""));
             window.set_confirm_deps(SharedString::from(""));
-            window.set_confirm_backend(backend);
+            window.set_confirm_notes(SharedString::from(""));
+            window.set_confirm_hooks(SharedString::from(""));
+            window.set_confirm_backend(backend.clone());
             window.set_confirm_package_count(names.len() as i32);
+            window.set_confirm_as_deps(false);
             window.set_show_confirm_popup(true);
+            window.set_confirm_planning(request_confirm_plan(&tx_br, "bulk-remove", &names_str, &backend));
         }
     });
 
@@ -1513,6 +2423,7 @@ fn main() {
             let rt = tokio::runtime::Runtime::new().expect("Runtime");
             rt.block_on(async {
                 let _ = tx.send(UiMessage::SetLoading(true));
+                refresh_alpm_index();
                 load_packages_async(&tx, true).await;
             });
 
@@ -1636,8 +2547,13 @@ fn main() {
 
 /// Load packages from backends (runs in background thread)
 async fn load_packages_async(tx: &mpsc::Sender<UiMessage>, check_updates: bool) {
+    if let Some(demo) = demo_backend() {
+        load_demo_packages(tx, &demo).await;
+        return;
+    }
+
     // Initialize backends
-    let alpm = match AlpmBackend::new() {
+    let alpm = match alpm_backend() {
         Ok(b) => b,
         Err(e) => {
             error!("Failed to initialize ALPM: {}", e);
@@ -1658,18 +2574,12 @@ async fn load_packages_async(tx: &mpsc::Sender<UiMessage>, check_updates: bool)
     let cache_fut = alpm.get_cache_size();
     let orphans_fut = alpm.list_orphans();
     let flatpak_avail_fut = flatpak.list_available();
-    let desktop_map_fut = tokio::task::spawn_blocking(build_desktop_name_map);
+    let desktop_map_fut = build_desktop_name_map();
     let flatpak_map_fut = tokio::task::spawn_blocking(build_flatpak_name_map);
 
     // Only check for updates when explicitly requested
     let flatpak_updates_fut = if check_updates { Some(flatpak.list_updates()) } else { None };
-    let checkupdates_fut = if check_updates {
-        Some(tokio::task::spawn_blocking(|| {
-            std::process::Command::new("checkupdates")
-            .output()
-            .or_else(|_| std::process::Command::new("pacman").args(["-Qu"]).output())
-        }))
-    } else { None };
+    let pacman_updates_fut = if check_updates { Some(alpm.check_updates(xpm_alpm::updates::DEFAULT_MAX_AGE)) } else { None };
     let plasmoid_fut = if check_updates { Some(tokio::task::spawn_blocking(list_plasmoids_with_updates)) } else { None };
     let firmware_fut = if check_updates { Some(tokio::task::spawn_blocking(list_firmware)) } else { None };
 
@@ -1694,7 +2604,7 @@ async fn load_packages_async(tx: &mpsc::Sender<UiMessage>, check_updates: bool)
     let flatpak_updates = if let Some(fut) = flatpak_updates_fut {
         fut.await.unwrap_or_else(|e| { error!("Failed to list flatpak updates: {}", e); Vec::new() })
     } else { Vec::new() };
-    let checkupdates_res = if let Some(fut) = checkupdates_fut { Some(fut.await) } else { None };
+    let pacman_updates_res = if let Some(fut) = pacman_updates_fut { Some(fut.await) } else { None };
     let (_installed_plasmoids, plasmoid_updates) = if let Some(fut) = plasmoid_fut {
         fut.await.unwrap_or_else(|_| (Vec::new(), Vec::new()))
     } else { (Vec::new(), Vec::new()) };
@@ -1709,38 +2619,18 @@ async fn load_packages_async(tx: &mpsc::Sender<UiMessage>, check_updates: bool)
 
     let flatpak_packages = flatpak_avail_res.unwrap_or_else(|e| { error!("Failed to list flatpak: {}", e); Vec::new() });
 
-    let desktop_map = desktop_map_res.unwrap_or_default();
+    let desktop_map = desktop_map_res;
     let flatpak_name_map = flatpak_map_res.unwrap_or_default();
 
-    // Parse checkupdates output
-    let mut updates: Vec<xpm_core::package::UpdateInfo> = Vec::new();
-    if let Some(Ok(Ok(result))) = checkupdates_res {
-        if result.status.success() {
-            let stdout = String::from_utf8_lossy(&result.stdout);
-            for line in stdout.lines() {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 4 {
-                    updates.push(xpm_core::package::UpdateInfo {
-                        name: parts[0].to_string(),
-                                 current_version: xpm_core::package::Version::new(parts[1]),
-                                 new_version: xpm_core::package::Version::new(parts[3]),
-                                 backend: xpm_core::package::PackageBackend::Pacman,
-                                 repository: String::new(),
-                                 download_size: 0,
-                    });
-                } else if parts.len() >= 2 {
-                    updates.push(xpm_core::package::UpdateInfo {
-                        name: parts[0].to_string(),
-                                 current_version: xpm_core::package::Version::new(""),
-                                 new_version: xpm_core::package::Version::new(parts[1]),
-                                 backend: xpm_core::package::PackageBackend::Pacman,
-                                 repository: String::new(),
-                                 download_size: 0,
-                    });
-                }
-            }
+    let updates = match pacman_updates_res {
+        Some(Ok(check)) => check.updates,
+        Some(Err(e)) => {
+            error!("Failed to check for updates: {}", e);
+            let _ = tx.send(UiMessage::SetStatus(format!("Update check failed: {}", e)));
+            Vec::new()
         }
-    }
+        None => Vec::new(),
+    };
 
     let update_names: std::collections::HashSet<String> =
     updates.iter().map(|u| u.name.clone()).collect();
@@ -1780,7 +2670,7 @@ async fn load_packages_async(tx: &mpsc::Sender<UiMessage>, check_updates: bool)
          version: SharedString::from(p.version.to_string().as_str()),
          description: SharedString::from(&summary),
          repository: SharedString::from(p.repository.as_str()),
-         backend: 1, // Flatpak
+         backend: SharedString::from(PackageBackend::Flatpak.id()),
          installed: matches!(
              p.status,
              xpm_core::package::PackageStatus::Installed | xpm_core::package::PackageStatus::Orphan
@@ -1824,6 +2714,35 @@ async fn load_packages_async(tx: &mpsc::Sender<UiMessage>, check_updates: bool)
     });
 }
 
+/// Load the package lists from the demo backend.
+async fn load_demo_packages(tx: &mpsc::Sender<UiMessage>, demo: &FakeBackend) {
+    let installed = demo.list_installed().await.unwrap_or_default();
+    let updates = demo.list_updates().await.unwrap_or_default();
+    let orphans = demo.list_orphans().await.unwrap_or_default();
+    let cache_size = demo.get_cache_size().await.unwrap_or(0);
+    let desktop_map = HashMap::new();
+
+    let update_names: std::collections::HashSet<&str> = updates.iter().map(|u| u.name.as_str()).collect();
+    let stats = StatsData {
+        pacman_count: installed.len() as i32,
+        flatpak_count: 0,
+        orphan_count: orphans.len() as i32,
+        update_count: updates.len() as i32,
+        cache_size: SharedString::from(format_size(cache_size)),
+    };
+
+    let _ = tx.send(UiMessage::PackagesLoaded {
+        installed: installed
+            .iter()
+            .map(|p| package_to_ui(p, update_names.contains(p.name.as_str()), &desktop_map))
+            .collect(),
+        updates: updates.iter().map(update_to_ui).collect(),
+        flatpak: Vec::new(),
+        firmware: Vec::new(),
+        stats,
+    });
+}
+
 /// List installed plasmoids and return those with updates separately
 fn list_plasmoids_with_updates() -> (Vec<PackageData>, Vec<PackageData>) {
     let mut plasmoids = Vec::new();
@@ -1881,7 +2800,7 @@ fn list_plasmoids_with_updates() -> (Vec<PackageData>, Vec<PackageData>) {
                         version: SharedString::from(&info.version),
                         description: SharedString::from(&info.description),
                         repository: SharedString::from(if is_user { "kde-store" } else { "system" }),
-                        backend: 3, // 3 = plasmoid
+                        backend: SharedString::from(PLASMOID_BACKEND),
                         installed: true,
                         has_update,
                         installed_size: SharedString::from(""),
@@ -2014,7 +2933,7 @@ fn list_firmware() -> Vec<PackageData> {
                                           version: SharedString::from(&version),
                                           description: SharedString::from(&description),
                                           repository: SharedString::from("fwupd"),
-                                          backend: 4, // 4 = firmware
+                                          backend: SharedString::from(FIRMWARE_BACKEND),
                                           installed: true,
                                           has_update: false, // Will check separately
                                           installed_size: SharedString::from(""),
@@ -2134,7 +3053,38 @@ fn parse_plasmoid_desktop(path: &std::path::Path) -> PlasmoidInfo {
 
 /// Search packages (runs in background thread)
 async fn search_packages_async(tx: &mpsc::Sender<UiMessage>, query: &str) {
-    let alpm = match AlpmBackend::new() {
+    let parsed = match SearchQuery::parse(query) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let _ = tx.send(UiMessage::SetStatus(e.to_string()));
+            let _ = tx.send(UiMessage::SearchResults(Vec::new()));
+            return;
+        }
+    };
+
+    if let Some(demo) = demo_backend() {
+        let results = demo.search(query).await.unwrap_or_default();
+        let _ = tx.send(UiMessage::SearchResults(results.iter().map(|r| PackageData {
+            name: SharedString::from(r.name.as_str()),
+            display_name: SharedString::from(r.name.as_str()),
+            version: SharedString::from(r.version.to_string().as_str()),
+            description: SharedString::from(r.description.as_str()),
+            repository: SharedString::from(r.repository.as_str()),
+            backend: SharedString::from(r.backend.id()),
+            installed: r.installed,
+            has_update: r.installed_version.as_ref().is_some_and(|v| v.vercmp(&r.version).is_lt()),
+            installed_size: SharedString::from(""),
+            licenses: SharedString::from(""),
+            url: SharedString::from(""),
+            dependencies: SharedString::from(""),
+            required_by: SharedString::from(""),
+            icon_name: SharedString::from(""),
+            selected: false,
+        }).collect()));
+        return;
+    }
+
+    let alpm = match alpm_backend() {
         Ok(b) => b,
         Err(e) => {
             error!("Failed to initialize ALPM: {}", e);
@@ -2171,20 +3121,29 @@ async fn search_packages_async(tx: &mpsc::Sender<UiMessage>, query: &str) {
     };
 
     // Build desktop name map for humanization
-    let desktop_map = build_desktop_name_map();
+    let desktop_map = build_desktop_name_map().await;
+
+    // Rank both backends' results together so the best matches survive
+    // the limit below.
+    let mut combined: Vec<SearchResult> = pacman_results.into_iter().chain(flatpak_results).collect();
+    parsed.sort(&mut combined);
 
     // Convert to UI types
-    let mut results: Vec<PackageData> = pacman_results
+    let mut results: Vec<PackageData> = combined
     .iter()
     .map(|r| {
-        let display_name = humanize_package_name(&r.name, &desktop_map);
+        let display_name = if r.backend == PackageBackend::Pacman {
+            humanize_package_name(&r.name, &desktop_map)
+        } else {
+            r.name.clone()
+        };
         PackageData {
             name: SharedString::from(r.name.as_str()),
          display_name: SharedString::from(&display_name),
          version: SharedString::from(r.version.to_string().as_str()),
          description: SharedString::from(r.description.as_str()),
          repository: SharedString::from(r.repository.as_str()),
-         backend: 0,
+         backend: SharedString::from(r.backend.id()),
          installed: r.installed,
          has_update: false,
          installed_size: SharedString::from(""),
@@ -2198,24 +3157,6 @@ async fn search_packages_async(tx: &mpsc::Sender<UiMessage>, query: &str) {
     })
     .collect();
 
-    results.extend(flatpak_results.iter().map(|r| PackageData {
-        name: SharedString::from(r.name.as_str()),
-                                              display_name: SharedString::from(r.name.as_str()),
-                                              version: SharedString::from(r.version.to_string().as_str()),
-                                              description: SharedString::from(r.description.as_str()),
-                                              repository: SharedString::from(r.repository.as_str()),
-                                              backend: 1,
-                                              installed: r.installed,
-                                              has_update: false,
-                                              installed_size: SharedString::from(""),
-                                              licenses: SharedString::from(""),
-                                              url: SharedString::from(""),
-                                              dependencies: SharedString::from(""),
-                                              required_by: SharedString::from(""),
-                                              icon_name: SharedString::from(""),
-                                              selected: false,
-    }));
-
     // Limit results
     results.truncate(100);
 
@@ -2314,7 +3255,7 @@ fn parse_desktop_file(path: &std::path::Path, target_category: &str) -> Option<P
 
     // Determine if it's a Flatpak (by path)
     let is_flatpak = path.to_string_lossy().contains("flatpak");
-    let backend = if is_flatpak { 1 } else { 0 };
+    let backend = if is_flatpak { PackageBackend::Flatpak } else { PackageBackend::Pacman };
     let repo = if is_flatpak { "flathub" } else { "native" };
 
     // Get package name from filename
@@ -2326,7 +3267,7 @@ fn parse_desktop_file(path: &std::path::Path, target_category: &str) -> Option<P
          version: SharedString::from(""),
          description: SharedString::from(&description),
          repository: SharedString::from(repo),
-         backend,
+         backend: SharedString::from(backend.id()),
          installed: true,
          has_update: false,
          installed_size: SharedString::from(""),
@@ -2488,7 +3429,7 @@ fn parse_appstream_xml<R: std::io::Read>(reader: R, target_category: &str, packa
                               version: SharedString::from(""),
                               description: SharedString::from(&current_summary),
                               repository: SharedString::from("flathub"),
-                              backend: 1,
+                              backend: SharedString::from(PackageBackend::Flatpak.id()),
                               installed: false, // Will check later
                               has_update: false,
                               installed_size: SharedString::from(""),
@@ -2529,7 +3470,23 @@ fn parse_appstream_xml<R: std::io::Read>(reader: R, target_category: &str, packa
 }
 
 /// Build a mapping of package names to human-readable names from desktop files
-fn build_desktop_name_map() -> HashMap<String, String> {
+async fn build_desktop_name_map() -> HashMap<String, String> {
+    // Map packages to the desktop files they own, from the package index
+    let entries = match demo_backend() {
+        Some(_) => Vec::new(),
+        None => match alpm_backend() {
+            Ok(backend) => backend.desktop_entries().await.unwrap_or_default(),
+            Err(_) => Vec::new(),
+        },
+    };
+    tokio::task::spawn_blocking(move || desktop_name_map(entries))
+    .await
+    .unwrap_or_default()
+}
+
+/// Reads the desktop files, then names each package in `entries` (package
+/// name and desktop file path) after its desktop file
+fn desktop_name_map(entries: Vec<(String, String)>) -> HashMap<String, String> {
     let mut map = HashMap::new();
 
     // Scan desktop files to find human-readable names
@@ -2571,35 +3528,19 @@ fn build_desktop_name_map() -> HashMap<String, String> {
         }
     }
 
-    // Also use pacman -Ql to map packages to desktop files they own
-    if let Ok(output) = std::process::Command::new("pacman")
-        .args(["-Ql"])
-        .output()
-        {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                for line in stdout.lines() {
-                    if line.contains("/usr/share/applications/") && line.ends_with(".desktop") {
-                        let parts: Vec<&str> = line.splitn(2, ' ').collect();
-                        if parts.len() == 2 {
-                            let pkg_name = parts[0];
-                            let desktop_path = parts[1].trim();
-                            let file_stem = std::path::Path::new(desktop_path)
-                            .file_stem()
-                            .and_then(|s| s.to_str())
-                            .unwrap_or("")
-                            .to_lowercase();
-                            // If we have a human name for this desktop file, map the package name to it
-                            if let Some(human_name) = map.get(&file_stem) {
-                                map.insert(pkg_name.to_lowercase(), human_name.clone());
-                            }
-                        }
-                    }
-                }
-            }
+    for (pkg_name, desktop_path) in entries {
+        let file_stem = std::path::Path::new(&desktop_path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+        // If we have a human name for this desktop file, map the package name to it
+        if let Some(human_name) = map.get(&file_stem) {
+            map.insert(pkg_name.to_lowercase(), human_name.clone());
         }
+    }
 
-        map
+    map
 }
 
 /// Humanize a package name - lookup desktop file names, fallback to title-case
@@ -2650,7 +3591,8 @@ fn load_repo_packages(tx: &std::sync::mpsc::Sender<UiMessage>, repo: &str) {
     .unwrap_or_default();
 
     // Build desktop name map for humanization
-    let desktop_map = build_desktop_name_map();
+    let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let desktop_map = rt.block_on(build_desktop_name_map());
 
     // Use expac -S with %r to get repo, then filter. Format: repo\tname\tversion\tdesc
     let output = std::process::Command::new("expac")
@@ -2676,7 +3618,7 @@ fn load_repo_packages(tx: &std::sync::mpsc::Sender<UiMessage>, repo: &str) {
                          version: SharedString::from(version),
                          description: SharedString::from(description),
                          repository: SharedString::from(repo),
-                         backend: 0,
+                         backend: SharedString::from(PackageBackend::Pacman.id()),
                          installed: is_installed,
                          has_update: false,
                          installed_size: SharedString::from(""),